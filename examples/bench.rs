@@ -0,0 +1,136 @@
+//! Times the `Runner`, so changes which affect how long it takes can be
+//! compared before and after.
+//!
+//! Run `cargo run --release --example bench --features std`. It prints the
+//! mean and worst time for:
+//!
+//! * typing one byte, with echo, at line lengths from 1 to 128
+//! * running a command with 10 arguments
+//! * printing `help` for a menu with 50 items
+//!
+//! Everything is written to a `String`, so no time goes on a real terminal.
+
+use menu::*;
+use std::time::{Duration, Instant};
+
+/// How many times each thing is timed.
+const RUNS: usize = 2000;
+
+fn noop(
+    _menu: &Menu<String, ()>,
+    _item: &Item<String, ()>,
+    _args: &[&str],
+    _interface: &mut CommandContext<String, ()>,
+    _context: &mut (),
+) {
+}
+
+const TEN: &[Parameter] = &[
+    Parameter::mandatory("a"),
+    Parameter::mandatory("b"),
+    Parameter::mandatory("c"),
+    Parameter::mandatory("d"),
+    Parameter::mandatory("e"),
+    Parameter::optional("f"),
+    Parameter::optional("g"),
+    Parameter::optional("h"),
+    Parameter::named("i"),
+    Parameter::named_value("j", "J"),
+];
+
+const TEN_MENU: Menu<String, ()> = Menu::new("root", &[&Item::new("ten", noop, TEN)]);
+
+/// The mean and worst of some timings.
+struct Timings {
+    total: Duration,
+    worst: Duration,
+}
+
+impl Timings {
+    /// Time `f`, `RUNS` times, calling `reset` before each.
+    fn measure(mut reset: impl FnMut(), mut f: impl FnMut()) -> Timings {
+        let mut timings = Timings {
+            total: Duration::ZERO,
+            worst: Duration::ZERO,
+        };
+        for _ in 0..RUNS {
+            reset();
+            let start = Instant::now();
+            f();
+            let elapsed = start.elapsed();
+            timings.total += elapsed;
+            timings.worst = timings.worst.max(elapsed);
+        }
+        timings
+    }
+
+    fn print(&self, what: &str) {
+        println!(
+            "{:<32} {:>10.2?} {:>10.2?}",
+            what,
+            self.total / RUNS as u32,
+            self.worst
+        );
+    }
+}
+
+/// Typing a byte onto a line which then has `len` bytes.
+fn typed_byte(len: usize) -> Timings {
+    let mut buffer = [0u8; 256];
+    let mut r = Runner::new(TEN_MENU, &mut buffer, String::new(), &mut ());
+    for _ in 0..len {
+        r.input_byte(b'x', &mut ());
+    }
+    // `measure` needs both closures to borrow the runner, so take turns
+    let r = std::cell::RefCell::new(r);
+    Timings::measure(
+        || {
+            let mut r = r.borrow_mut();
+            // Rub out the last byte, to type it again
+            r.input_byte(0x08, &mut ());
+            r.interface.clear();
+        },
+        || r.borrow_mut().input_byte(b'y', &mut ()),
+    )
+}
+
+fn ten_arguments() -> Timings {
+    const LINE: &str = "ten 1 2 3 4 5 6 7 8 --i --j=10";
+    let mut buffer = [0u8; 64];
+    let mut r = Runner::new(TEN_MENU, &mut buffer, String::new(), &mut ());
+    assert!(r.run_command(LINE, &mut ()).is_success());
+    let r = std::cell::RefCell::new(r);
+    Timings::measure(
+        || r.borrow_mut().interface.clear(),
+        || {
+            r.borrow_mut().run_command(LINE, &mut ());
+        },
+    )
+}
+
+fn help_for_fifty_items() -> Timings {
+    let names: Vec<String> = (0..50).map(|i| format!("item{}", i)).collect();
+    let items: Vec<Item<String, ()>> = names
+        .iter()
+        .map(|name| Item::new(name, noop, &TEN[..2]).help("Does something"))
+        .collect();
+    let item_refs: Vec<&Item<String, ()>> = items.iter().collect();
+    let menu = Menu::new("root", &item_refs);
+    let mut buffer = [0u8; 64];
+    let r = std::cell::RefCell::new(Runner::new(menu, &mut buffer, String::new(), &mut ()));
+    Timings::measure(
+        || r.borrow_mut().interface.clear(),
+        || {
+            r.borrow_mut().run_command("help", &mut ());
+        },
+    )
+}
+
+fn main() {
+    println!("{:<32} {:>10} {:>10}", "", "mean", "worst");
+    for len in [1, 2, 4, 8, 16, 32, 64, 128] {
+        typed_byte(len).print(&format!("typed byte, line length {}", len));
+    }
+    ten_arguments().print("command with 10 arguments");
+    help_for_fifty_items().print("help for 50 items");
+}
@@ -0,0 +1,202 @@
+//! Two consoles, a UART and a USB serial port, sharing one menu and one
+//! state struct.
+//!
+//! Each console has its own `Runner`, with its own buffer, so a line half
+//! typed on one console doesn't get mixed up with a line on the other. They
+//! share:
+//!
+//! * the menu, which is a `static`. `Menu` is `Copy`, so each `Runner` gets
+//!   its own copy of the `static`'s few pointers.
+//! * the interface type. A menu is built for one interface type, so both
+//!   consoles use `Port`, an enum of the two transports which writes to
+//!   whichever one it holds.
+//! * the state, which is the context of both `Runner`s. A `Runner` only
+//!   borrows its context while it's handling a byte, so each byte can be
+//!   handed over with the state borrowed from a `Mutex`.
+//!
+//! On a microcontroller, where the bytes arrive in two interrupt handlers,
+//! the `Mutex` would be a `critical_section::Mutex<RefCell<State>>`, and each
+//! handler would do something like:
+//!
+//! ```rust,ignore
+//! critical_section::with(|cs| {
+//!     let mut state = STATE.borrow_ref_mut(cs);
+//!     UART_RUNNER.input_byte(byte, &mut state);
+//! });
+//! ```
+//!
+//! A command which takes a long time then holds up the other console, as it
+//! holds the state until it returns.
+//!
+//! Prompts don't collide, as each `Runner` only ever writes to its own
+//! console, and only while handling a byte. If one console needs to tell
+//! the other about a change, such as `led on`, write the news to the other
+//! console between bytes, then call its `Runner::prompt(true)` to put its
+//! prompt back. Any line the user was part way through typing is kept, but
+//! isn't shown again until they type another character.
+//!
+//! Run `cargo run --example dual_console` to see both consoles' output.
+
+use menu::*;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// A pretend UART, which sends one byte at a time.
+#[derive(Default)]
+struct Uart {
+    sent: String,
+}
+
+/// A pretend USB serial port, which sends whole packets.
+#[derive(Default)]
+struct Usb {
+    packets: Vec<String>,
+}
+
+/// Whichever console a `Runner` is on.
+enum Port {
+    Uart(Uart),
+    Usb(Usb),
+}
+
+impl Write for Port {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        match self {
+            Port::Uart(uart) => uart.sent.push_str(s),
+            Port::Usb(usb) => usb.packets.push(s.to_owned()),
+        }
+        Ok(())
+    }
+}
+
+impl Port {
+    /// Everything sent so far.
+    fn sent(&self) -> String {
+        match self {
+            Port::Uart(uart) => uart.sent.clone(),
+            Port::Usb(usb) => usb.packets.concat(),
+        }
+    }
+}
+
+/// What both consoles control.
+struct State {
+    led: bool,
+    presses: u32,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    led: false,
+    presses: 0,
+});
+
+static ROOT_MENU: Menu<Port, State> = Menu::new(
+    "root",
+    &[
+        &Item::new("press", press, &[]).help("Count a button press"),
+        &Item::new("led", led, &[Parameter::mandatory("on|off")]).help("Turn the LED on or off"),
+        &Item::new("status", status, &[]).help("Show the LED and the count"),
+    ],
+);
+
+fn press(
+    _menu: &Menu<Port, State>,
+    _item: &Item<Port, State>,
+    _args: &[&str],
+    interface: &mut CommandContext<Port, State>,
+    context: &mut State,
+) {
+    context.presses += 1;
+    writeln!(interface, "{} presses", context.presses).unwrap();
+}
+
+fn led(
+    _menu: &Menu<Port, State>,
+    _item: &Item<Port, State>,
+    args: &[&str],
+    interface: &mut CommandContext<Port, State>,
+    context: &mut State,
+) {
+    match args {
+        ["on"] => context.led = true,
+        ["off"] => context.led = false,
+        _ => interface.fail("Say on or off"),
+    }
+}
+
+fn status(
+    _menu: &Menu<Port, State>,
+    _item: &Item<Port, State>,
+    _args: &[&str],
+    interface: &mut CommandContext<Port, State>,
+    context: &mut State,
+) {
+    let led = if context.led { "on" } else { "off" };
+    writeln!(interface, "LED {}, {} presses", led, context.presses).unwrap();
+}
+
+/// Type `uart_input` and `usb_input` at the same time, a byte from each in
+/// turn, and return what each console shows.
+fn run(uart_input: &str, usb_input: &str) -> (String, String) {
+    let mut uart_buffer = [0u8; 32];
+    let mut usb_buffer = [0u8; 64];
+    let mut uart = Runner::new_deferred(ROOT_MENU, &mut uart_buffer, Port::Uart(Uart::default()));
+    let mut usb = Runner::new_deferred(ROOT_MENU, &mut usb_buffer, Port::Usb(Usb::default()));
+    let mut uart_bytes = uart_input.bytes();
+    let mut usb_bytes = usb_input.bytes();
+    loop {
+        let (a, b) = (uart_bytes.next(), usb_bytes.next());
+        if a.is_none() && b.is_none() {
+            break;
+        }
+        // Each byte borrows the state just while it's being handled, as an
+        // interrupt handler would
+        if let Some(byte) = a {
+            uart.input_byte(byte, &mut STATE.lock().unwrap());
+        }
+        if let Some(byte) = b {
+            usb.input_byte(byte, &mut STATE.lock().unwrap());
+        }
+    }
+    (uart.interface.sent(), usb.interface.sent())
+}
+
+/// What a terminal would show, given that each `\r` goes back to the start
+/// of the line.
+fn screen(sent: &str) -> String {
+    sent.split('\n')
+        .map(|line| line.rsplit('\r').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn main() {
+    let (uart, usb) = run("press\rled on\r", "press\rpress\rstatus\r");
+    println!("UART:{}\n", screen(&uart));
+    println!("USB:{}", screen(&usb));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interleaved() {
+        // The USB console's `status` ends after the UART console's `led on`,
+        // so it sees everything the UART console did
+        let (uart, usb) = run("press\rled on\r", "press\rpress\rstatus\r");
+        let state = STATE.lock().unwrap();
+        assert!(state.led);
+        assert_eq!(state.presses, 3);
+        // Each console only shows its own commands' output
+        let lines = |sent: &str| {
+            screen(sent)
+                .lines()
+                .filter(|line| line.ends_with("presses"))
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines(&uart), ["1 presses"]);
+        assert_eq!(lines(&usb), ["2 presses", "3 presses", "LED on, 3 presses"]);
+    }
+}
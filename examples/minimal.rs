@@ -0,0 +1,177 @@
+//! The smallest console this crate makes: commands and `help`, and nothing
+//! else, for something like a bootloader.
+//!
+//! Run it with `cargo run --example minimal --no-default-features`, which
+//! leaves out echo, line editing, history, completion, escape sequences and
+//! the extra built-in commands. Type a command and press Enter.
+
+use menu::*;
+use std::fmt::Write;
+use std::io::Read;
+
+#[derive(Default)]
+struct Bootloader {
+    /// Which image we'll boot
+    image: u8,
+    booted: bool,
+}
+
+fn select(
+    _menu: &Menu<String, Bootloader>,
+    item: &Item<String, Bootloader>,
+    args: &[&str],
+    interface: &mut CommandContext<String, Bootloader>,
+    context: &mut Bootloader,
+) {
+    let Ok(Some(image)) = argument_finder(item, args, "image") else {
+        return;
+    };
+    match image.parse() {
+        Ok(image @ 1..=2) => {
+            context.image = image;
+            writeln!(interface, "Image {} selected", image).ok();
+        }
+        _ => interface.fail("There are images 1 and 2"),
+    }
+}
+
+fn boot(
+    _menu: &Menu<String, Bootloader>,
+    _item: &Item<String, Bootloader>,
+    _args: &[&str],
+    interface: &mut CommandContext<String, Bootloader>,
+    context: &mut Bootloader,
+) {
+    writeln!(interface, "Booting image {}", context.image).ok();
+    context.booted = true;
+}
+
+/// The output is kept in a `String`, which `main` prints after each byte.
+const ROOT_MENU: Menu<String, Bootloader> = Menu::new(
+    "root",
+    &[
+        &Item::new("select", select, &[Parameter::mandatory("image")])
+            .help("Choose the image to boot"),
+        &Item::new("boot", boot, &[]).help("Boot the chosen image"),
+    ],
+);
+
+fn main() {
+    let mut buffer = [0u8; 32];
+    let mut context = Bootloader {
+        image: 1,
+        booted: false,
+    };
+    let mut r = Runner::new(ROOT_MENU, &mut buffer, String::new(), &mut context);
+    print!("{}", r.interface);
+    r.interface.clear();
+    for byte in std::io::stdin().lock().bytes() {
+        let Ok(byte) = byte else {
+            break;
+        };
+        // A terminal sends `\n` for Enter, rather than `\r`
+        let byte = if byte == b'\n' { b'\r' } else { byte };
+        r.input_byte(byte, &mut context);
+        print!("{}", r.interface);
+        r.interface.clear();
+        if context.booted {
+            break;
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Type `script`, with `\n` for Enter, and give back what's on the
+    /// screen.
+    fn run(script: &str, context: &mut Bootloader) -> String {
+        let mut buffer = [0u8; 32];
+        let mut r = Runner::new(ROOT_MENU, &mut buffer, String::new(), context);
+        for byte in script.bytes() {
+            let byte = if byte == b'\n' { b'\r' } else { byte };
+            r.input_byte(byte, context);
+        }
+        screen(&r.interface)
+    }
+
+    /// What's left on each line once carriage returns and backspaces have
+    /// moved the cursor back, and later characters have been written over
+    /// earlier ones.
+    fn screen(output: &str) -> String {
+        let mut lines = vec![Vec::new()];
+        let mut column = 0;
+        for c in output.chars() {
+            let line = lines.last_mut().unwrap();
+            match c {
+                '\n' => {
+                    lines.push(Vec::new());
+                    column = 0;
+                }
+                '\r' => column = 0,
+                '\x08' => column -= 1,
+                c if column < line.len() => {
+                    line[column] = c;
+                    column += 1;
+                }
+                c => {
+                    line.push(c);
+                    column += 1;
+                }
+            }
+        }
+        lines
+            .iter()
+            .map(|line| line.iter().collect::<String>().trim_end().to_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Without echo, the line typed is printed over the prompt when Enter
+    /// is pressed.
+    const PROMPT: &str = if cfg!(feature = "echo") { "> " } else { "" };
+
+    #[test]
+    fn commands_and_help() {
+        let mut context = Bootloader::default();
+        assert_eq!(
+            run("help\nselect 2\nboot\n", &mut context),
+            format!(
+                "
+{PROMPT}help
+AVAILABLE ITEMS:
+  select <image>
+  boot
+  help [ <command> ]
+
+{PROMPT}select 2
+Image 2 selected
+
+{PROMPT}boot
+Booting image 2
+
+>"
+            )
+        );
+        assert!(context.booted);
+    }
+
+    #[test]
+    fn mistakes() {
+        let mut context = Bootloader::default();
+        let screen = run("selcet\nselect\nselect 3\nselect 1x\x08\n", &mut context);
+        for expected in [
+            "selcet\nCommand \"selcet\" not found. Try 'help'.\n",
+            "select\nError: Missing argument <image>\n",
+            "select 3\nError: There are images 1 and 2\n",
+            "select 1\nImage 1 selected\n",
+        ] {
+            let expected = format!("\n{PROMPT}{expected}");
+            assert!(screen.contains(&expected), "{expected:?} in {screen:?}");
+        }
+        assert_eq!(context.image, 1);
+        assert!(!context.booted);
+    }
+}
@@ -0,0 +1,124 @@
+//! Turns a CSV list of registers into Rust source for a menu, with one item
+//! per register built by `menu::regmap::register`.
+//!
+//! Run `cargo run --example regmap_gen examples/registers.csv > registers.rs`
+//! then `include!("registers.rs")` in a module which has `Console` and `Bus`
+//! types in scope, for the menu's interface and context. `Bus` must
+//! implement `menu::regmap::RegBus`.
+//!
+//! Each line of the CSV is `name,address,width,access,help`, where `width`
+//! is 8, 16 or 32, `access` is `ro`, `wo` or `rw`, and `help` may be empty.
+//! Blank lines, and lines starting with `#`, are skipped.
+
+use std::fmt::Write;
+
+/// Write the source for the registers in `csv` to `out`.
+fn generate(csv: &str, out: &mut impl Write) -> Result<(), String> {
+    writeln!(out, "// Generated by the regmap_gen example. Don't edit.").unwrap();
+    writeln!(
+        out,
+        "use menu::regmap::{{register, Access, RegisterDesc, Width}};"
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "pub const REGISTERS: &[&menu::Item<Console, Bus>] = &["
+    )
+    .unwrap();
+    for (i, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fail = |why: &str| format!("line {}: {}", i + 1, why);
+        let fields: Vec<&str> = line.splitn(5, ',').map(str::trim).collect();
+        let [name, address, width, access, help] = fields[..] else {
+            return Err(fail("expected name,address,width,access,help"));
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(fail("bad name"));
+        }
+        let address = address.replace('_', "");
+        let address = match address.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => address.parse(),
+        }
+        .map_err(|_| fail("bad address"))?;
+        let width = match width {
+            "8" => "Bits8",
+            "16" => "Bits16",
+            "32" => "Bits32",
+            _ => return Err(fail("width must be 8, 16 or 32")),
+        };
+        let access = match access {
+            "ro" => "ReadOnly",
+            "wo" => "WriteOnly",
+            "rw" => "ReadWrite",
+            _ => return Err(fail("access must be ro, wo or rw")),
+        };
+        write!(
+            out,
+            "    &register({:?}, &RegisterDesc::new({:#010x}, Width::{}, Access::{}))",
+            name, address, width, access
+        )
+        .unwrap();
+        if !help.is_empty() {
+            write!(out, ".help({:?})", help).unwrap();
+        }
+        writeln!(out, ",").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+    Ok(())
+}
+
+fn main() {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: regmap_gen <registers.csv>");
+        std::process::exit(2);
+    };
+    let csv = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("Can't read {}: {}", path, e);
+        std::process::exit(1);
+    });
+    let mut source = String::new();
+    match generate(&csv, &mut source) {
+        Ok(()) => print!("{}", source),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample() {
+        let mut source = String::new();
+        generate(include_str!("registers.csv"), &mut source).unwrap();
+        assert!(source.contains(
+            "    &register(\"status\", &RegisterDesc::new(0x40010004, Width::Bits16, \
+             Access::ReadOnly)).help(\"What the peripheral is doing\"),\n"
+        ));
+        assert!(source.contains(
+            "    &register(\"data\", &RegisterDesc::new(0x40010008, Width::Bits8, \
+             Access::ReadWrite)),\n"
+        ));
+        assert!(source.ends_with("];\n"));
+    }
+
+    #[test]
+    fn mistakes() {
+        let check = |csv: &str| generate(csv, &mut String::new()).unwrap_err();
+        assert_eq!(
+            check("a,1,8,rw"),
+            "line 1: expected name,address,width,access,help"
+        );
+        assert_eq!(check("\na,0xZZ,8,rw,"), "line 2: bad address");
+        assert_eq!(check("a,1,12,rw,"), "line 1: width must be 8, 16 or 32");
+        assert_eq!(check("a,1,8,r,"), "line 1: access must be ro, wo or rw");
+    }
+}
@@ -9,81 +9,45 @@ struct Context {
     _inner: u32,
 }
 
-const ROOT_MENU: Menu<Output, Context> = Menu {
-    label: "root",
-    items: &[
-        &Item {
-            item_type: ItemType::Callback {
-                function: select_foo,
-                parameters: &[
-                    Parameter::Mandatory {
-                        parameter_name: "a",
-                        help: Some("This is the help text for 'a'"),
-                    },
-                    Parameter::Optional {
-                        parameter_name: "b",
-                        help: None,
-                    },
-                    Parameter::Named {
-                        parameter_name: "verbose",
-                        help: None,
-                    },
-                    Parameter::NamedValue {
-                        parameter_name: "level",
-                        argument_name: "INT",
-                        help: Some("Set the level of the dangle"),
-                    },
-                ],
-            },
-            command: "foo",
-            help: Some(
-                "Makes a foo appear.
+const ROOT_MENU: Menu<Output, Context> = Menu::new(
+    "root",
+    &[
+        &Item::new(
+            "foo",
+            select_foo,
+            &[
+                Parameter::mandatory("a").help("This is the help text for 'a'"),
+                Parameter::optional("b"),
+                Parameter::named("verbose"),
+                Parameter::named_value("level", "INT").help("Set the level of the dangle"),
+            ],
+        )
+        .help(
+            "Makes a foo appear.
 
 This is some extensive help text.
 
 It contains multiple paragraphs and should be preceeded by the parameter list.
 ",
-            ),
-        },
-        &Item {
-            item_type: ItemType::Callback {
-                function: select_bar,
-                parameters: &[],
-            },
-            command: "bar",
-            help: Some("fandoggles a bar"),
-        },
-        &Item {
-            item_type: ItemType::Menu(&Menu {
-                label: "sub",
-                items: &[
-                    &Item {
-                        item_type: ItemType::Callback {
-                            function: select_baz,
-                            parameters: &[],
-                        },
-                        command: "baz",
-                        help: Some("thingamobob a baz"),
-                    },
-                    &Item {
-                        item_type: ItemType::Callback {
-                            function: select_quux,
-                            parameters: &[],
-                        },
-                        command: "quux",
-                        help: Some("maximum quux"),
-                    },
+        ),
+        &Item::new("bar", select_bar, &[]).help("fandoggles a bar"),
+        &Item::new_menu(
+            "sub",
+            &Menu::new(
+                "sub",
+                &[
+                    &Item::new("baz", select_baz, &[]).help("thingamobob a baz"),
+                    &Item::new("quux", select_quux, &[]).help("maximum quux"),
                 ],
-                entry: Some(enter_sub),
-                exit: Some(exit_sub),
-            }),
-            command: "sub",
-            help: Some("enter sub-menu"),
-        },
+            )
+            .entry(enter_sub)
+            .exit(exit_sub),
+        )
+        .help("enter sub-menu"),
     ],
-    entry: Some(enter_root),
-    exit: Some(exit_root),
-};
+)
+.entry(enter_root)
+.exit(exit_root);
 
 struct Output(pancurses::Window);
 
@@ -114,7 +78,7 @@ fn main() {
             }
             Some(Input::KeyDC) => break,
             Some(input) => {
-                r.interface.0.addstr(&format!("{:?}", input));
+                r.interface.0.addstr(format!("{:?}", input));
             }
             None => (),
         }
@@ -134,7 +98,7 @@ fn select_foo(
     _menu: &Menu<Output, Context>,
     item: &Item<Output, Context>,
     args: &[&str],
-    interface: &mut Output,
+    interface: &mut CommandContext<Output, Context>,
     _context: &mut Context,
 ) {
     writeln!(interface, "In select_foo. Args = {:?}", args).unwrap();
@@ -174,7 +138,7 @@ fn select_bar(
     _menu: &Menu<Output, Context>,
     _item: &Item<Output, Context>,
     args: &[&str],
-    interface: &mut Output,
+    interface: &mut CommandContext<Output, Context>,
     _context: &mut Context,
 ) {
     writeln!(interface, "In select_bar. Args = {:?}", args).unwrap();
@@ -192,7 +156,7 @@ fn select_baz(
     _menu: &Menu<Output, Context>,
     _item: &Item<Output, Context>,
     args: &[&str],
-    interface: &mut Output,
+    interface: &mut CommandContext<Output, Context>,
     _context: &mut Context,
 ) {
     writeln!(interface, "In select_baz: Args = {:?}", args).unwrap();
@@ -202,7 +166,7 @@ fn select_quux(
     _menu: &Menu<Output, Context>,
     _item: &Item<Output, Context>,
     args: &[&str],
-    interface: &mut Output,
+    interface: &mut CommandContext<Output, Context>,
     _context: &mut Context,
 ) {
     writeln!(interface, "In select_quux: Args = {:?}", args).unwrap();
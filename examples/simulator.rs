@@ -0,0 +1,414 @@
+//! A pretend device, with a menu you can try out on your own terminal.
+//!
+//! Run `cargo run --example simulator --features test-util` and type `help`.
+//! Press Ctrl-D to quit.
+//!
+//! With `--script <file>`, the commands typed at each prompt in the
+//! transcript `<file>` are replayed, and the program fails if what appears on
+//! the screen differs from the transcript. See `examples/simulator.txt`.
+
+use menu::test_util::{normalise, script_bytes, transcript_diff};
+use menu::*;
+use std::fmt::Write;
+use std::io::{Read, Write as _};
+
+struct Device {
+    name: String,
+    baud: u32,
+    leds: [bool; 4],
+    level: u8,
+    commands: u32,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Device {
+            name: String::from("sim-1"),
+            baud: 115200,
+            leds: [false; 4],
+            level: 2,
+            commands: 0,
+        }
+    }
+}
+
+/// Where the menu's output goes.
+enum Console {
+    /// Straight to the terminal
+    Terminal(std::io::Stdout),
+    /// Kept, so we can compare it with a transcript
+    Recorder(String),
+}
+
+impl std::fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        match self {
+            Console::Terminal(stdout) => {
+                stdout.write_all(s.as_bytes()).map_err(|_| std::fmt::Error)
+            }
+            Console::Recorder(output) => output.write_str(s),
+        }
+    }
+}
+
+const LEVELS: [&str; 4] = ["error", "warn", "info", "debug"];
+
+const SETTINGS_MENU: Menu<Console, Device> = Menu::new(
+    "settings",
+    &[
+        &Item::new("show", settings_show, &[]).help("Show all the settings."),
+        &Item::new(
+            "baud",
+            settings_baud,
+            &[Parameter::mandatory("rate").help("Bits per second, from 300 to 921600")],
+        )
+        .help("Set the serial port speed."),
+        &Item::new(
+            "name",
+            settings_name,
+            &[Parameter::mandatory("name").help("Up to 16 characters")],
+        )
+        .help("Set the device name."),
+        &Item::new(
+            "log",
+            settings_log,
+            &[Parameter::named_value("level", "LEVEL").help("One of error, warn, info or debug")],
+        )
+        .help("Show or set how much gets logged."),
+    ],
+)
+.entry(enter_settings)
+.exit(exit_settings);
+
+const ROOT_MENU: Menu<Console, Device> = Menu::new(
+    "root",
+    &[
+        &Item::new("status", status, &[]).help("Show how the device is doing."),
+        &Item::new(
+            "led",
+            led,
+            &[
+                Parameter::mandatory("index").help("Which LED, from 0 to 3"),
+                Parameter::optional("state").help("on or off. Toggles the LED if not given."),
+            ],
+        )
+        .help("Turn an LED on or off."),
+        &Item::new(
+            "hello",
+            hello,
+            &[
+                Parameter::optional("name").help("Who to greet"),
+                Parameter::named("shout").help("Greet them loudly"),
+            ],
+        )
+        .help("Say hello."),
+        &Item::new_menu("settings", &SETTINGS_MENU).help("Change the device settings."),
+        &Item::new("debug", debug, &[])
+            .help("Dump the device state. Not for customers.")
+            .hidden(),
+    ],
+);
+
+fn status(
+    _menu: &Menu<Console, Device>,
+    _item: &Item<Console, Device>,
+    _args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    writeln!(interface, "{:<12} {:>10}", "NAME", device.name).unwrap();
+    writeln!(interface, "{:<12} {:>10}", "BAUD", device.baud).unwrap();
+    writeln!(
+        interface,
+        "{:<12} {:>10}",
+        "LEVEL", LEVELS[device.level as usize]
+    )
+    .unwrap();
+    writeln!(interface, "{:<12} {:>10}", "COMMANDS", device.commands).unwrap();
+    write!(interface, "{:<12} ", "LEDS").unwrap();
+    for (i, on) in device.leds.iter().enumerate() {
+        write!(interface, " {}:{}", i, if *on { '*' } else { '.' }).unwrap();
+    }
+    writeln!(interface).unwrap();
+}
+
+fn led(
+    _menu: &Menu<Console, Device>,
+    item: &Item<Console, Device>,
+    args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    let index = argument_finder(item, args, "index").unwrap().unwrap();
+    let Some(led) = index
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| device.leds.get_mut(i))
+    else {
+        writeln!(interface, "Error: No LED {:?}", index).unwrap();
+        return;
+    };
+    match argument_finder(item, args, "state").unwrap() {
+        None => *led = !*led,
+        Some("on") => *led = true,
+        Some("off") => *led = false,
+        Some(other) => {
+            writeln!(interface, "Error: {:?} isn't on or off", other).unwrap();
+            return;
+        }
+    }
+    writeln!(
+        interface,
+        "LED {} is {}",
+        index,
+        if *led { "on" } else { "off" }
+    )
+    .unwrap();
+}
+
+fn hello(
+    _menu: &Menu<Console, Device>,
+    item: &Item<Console, Device>,
+    args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    let name = argument_finder(item, args, "name")
+        .unwrap()
+        .unwrap_or("world");
+    if argument_finder(item, args, "shout").unwrap().is_some() {
+        writeln!(interface, "HELLO, {}!", name.to_uppercase()).unwrap();
+    } else {
+        writeln!(interface, "Hello, {}.", name).unwrap();
+    }
+}
+
+fn debug(
+    _menu: &Menu<Console, Device>,
+    _item: &Item<Console, Device>,
+    _args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    writeln!(
+        interface,
+        "name={:?} baud={} leds={:?} level={} commands={}",
+        device.name, device.baud, device.leds, device.level, device.commands
+    )
+    .unwrap();
+}
+
+fn enter_settings(_menu: &Menu<Console, Device>, interface: &mut Console, _device: &mut Device) {
+    writeln!(interface, "Changes take effect immediately.").unwrap();
+}
+
+fn exit_settings(_menu: &Menu<Console, Device>, interface: &mut Console, device: &mut Device) {
+    writeln!(interface, "Settings saved for {}.", device.name).unwrap();
+}
+
+fn settings_show(
+    _menu: &Menu<Console, Device>,
+    _item: &Item<Console, Device>,
+    _args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    writeln!(interface, "{:<8} {:<10} DESCRIPTION", "SETTING", "VALUE").unwrap();
+    writeln!(interface, "{:<8} {:<10} Device name", "name", device.name).unwrap();
+    writeln!(
+        interface,
+        "{:<8} {:<10} Serial port speed",
+        "baud", device.baud
+    )
+    .unwrap();
+    writeln!(
+        interface,
+        "{:<8} {:<10} Logging level",
+        "log", LEVELS[device.level as usize]
+    )
+    .unwrap();
+}
+
+fn settings_baud(
+    _menu: &Menu<Console, Device>,
+    item: &Item<Console, Device>,
+    args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    let rate = argument_finder(item, args, "rate").unwrap().unwrap();
+    match rate.parse::<u32>() {
+        Ok(rate) if (300..=921600).contains(&rate) => {
+            device.baud = rate;
+            writeln!(interface, "Baud rate is {}", rate).unwrap();
+        }
+        _ => writeln!(interface, "Error: Bad baud rate {:?}", rate).unwrap(),
+    }
+}
+
+fn settings_name(
+    _menu: &Menu<Console, Device>,
+    item: &Item<Console, Device>,
+    args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    let name = argument_finder(item, args, "name").unwrap().unwrap();
+    if name.chars().count() > 16 {
+        writeln!(interface, "Error: Name too long").unwrap();
+    } else {
+        device.name = String::from(name);
+        writeln!(interface, "Name is {}", name).unwrap();
+    }
+}
+
+fn settings_log(
+    _menu: &Menu<Console, Device>,
+    item: &Item<Console, Device>,
+    args: &[&str],
+    interface: &mut CommandContext<Console, Device>,
+    device: &mut Device,
+) {
+    device.commands += 1;
+    if let Some(level) = argument_finder(item, args, "level").unwrap() {
+        match LEVELS.iter().position(|l| *l == level) {
+            Some(idx) => device.level = idx as u8,
+            None => {
+                writeln!(interface, "Error: Unknown level {:?}", level).unwrap();
+                return;
+            }
+        }
+    }
+    writeln!(interface, "Logging at {}", LEVELS[device.level as usize]).unwrap();
+}
+
+/// Puts the terminal into raw mode, and back again when dropped.
+#[cfg(unix)]
+struct RawMode(Option<libc::termios>);
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> RawMode {
+        // Safety: we only pass valid pointers to a `termios` we own.
+        unsafe {
+            let mut original = core::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                // Not a terminal
+                return RawMode(None);
+            }
+            let mut raw = original;
+            // Give us every key as it is pressed, without echoing it, and
+            // without turning Enter into a line feed.
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_iflag &= !libc::ICRNL;
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw);
+            RawMode(Some(original))
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(original) = &self.0 {
+            // Safety: `original` came from `tcgetattr`.
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+            }
+        }
+    }
+}
+
+fn interactive() {
+    #[cfg(unix)]
+    let _raw = RawMode::enable();
+    let mut buffer = [0u8; 64];
+    let mut device = Device::default();
+    let console = Console::Terminal(std::io::stdout());
+    let mut r = Runner::new(ROOT_MENU, &mut buffer, console, &mut device);
+    let _ = std::io::stdout().flush();
+    let mut stdin = std::io::stdin();
+    let mut byte = [0u8; 1];
+    // Read one key at a time, so each is handled as soon as it's pressed
+    while let Ok(1) = stdin.read(&mut byte) {
+        match byte[0] {
+            // Ctrl-D
+            0x04 => break,
+            // Lets you pipe commands in, when we're not on a terminal
+            b'\n' => r.input_byte(b'\r', &mut device),
+            b => r.input_byte(b, &mut device),
+        }
+        let _ = std::io::stdout().flush();
+    }
+    println!();
+}
+
+/// If this line of a transcript has a prompt, what was typed after it?
+fn typed_input(line: &str) -> Option<&str> {
+    let (prompt, rest) = line.split_once('>')?;
+    if prompt.contains(' ') || !(prompt.is_empty() || prompt.starts_with('/')) {
+        return None;
+    }
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+fn replay(path: &str) -> bool {
+    let expected = match std::fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Can't read {}: {}", path, e);
+            return false;
+        }
+    };
+    let expected = expected.strip_suffix('\n').unwrap_or(&expected);
+    let mut buffer = [0u8; 64];
+    let mut device = Device::default();
+    let console = Console::Recorder(String::new());
+    let mut r = Runner::new(ROOT_MENU, &mut buffer, console, &mut device);
+    // The last prompt is still waiting for input
+    let lines: Vec<&str> = expected.lines().filter_map(typed_input).collect();
+    for line in lines.iter().take(lines.len().saturating_sub(1)) {
+        for b in script_bytes(line).into_iter().chain([b'\r']) {
+            r.input_byte(b, &mut device);
+        }
+    }
+    let Console::Recorder(output) = &r.interface else {
+        unreachable!();
+    };
+    // Skip the blank line before the first prompt
+    let actual = normalise(output);
+    match transcript_diff(actual.trim_start_matches('\n'), expected) {
+        Some(diff) => {
+            eprintln!("{}", diff);
+            false
+        }
+        None => {
+            println!("{} matches", path);
+            true
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => interactive(),
+        [flag, path] if flag == "--script" => {
+            if !replay(path) {
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            eprintln!("Usage: simulator [--script <file>]");
+            std::process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Throws arbitrary command lines at a `Runner`.
+//!
+//! Run with `cargo +nightly fuzz run run_command` from this directory.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use menu::test_util::MockConsole;
+use menu::*;
+
+fn check_args(
+    _menu: &Menu<MockConsole, u32>,
+    item: &Item<MockConsole, u32>,
+    args: &[&str],
+    _interface: &mut CommandContext<MockConsole, u32>,
+    context: &mut u32,
+) {
+    if let ItemType::Callback { parameters, .. } = item.item_type {
+        for param in parameters {
+            let name = match param {
+                Parameter::Mandatory { parameter_name, .. }
+                | Parameter::Optional { parameter_name, .. }
+                | Parameter::Named { parameter_name, .. }
+                | Parameter::NamedValue { parameter_name, .. } => parameter_name,
+            };
+            let _ = argument_finder(item, args, name);
+        }
+    }
+    let _ = argument_finder(item, args, "not-a-parameter");
+    *context += 1;
+}
+
+const SUB_MENU: Menu<MockConsole, u32> = Menu::new(
+    "sub",
+    &[&Item::new("é", check_args, &[Parameter::optional("ü")])],
+);
+
+const ROOT_MENU: Menu<MockConsole, u32> = Menu::new(
+    "root",
+    &[
+        &Item::new(
+            "foo",
+            check_args,
+            &[
+                Parameter::mandatory("a"),
+                Parameter::optional("b"),
+                Parameter::named("verbose"),
+                Parameter::named_value("level", "INT"),
+                Parameter::named_value("", ""),
+                Parameter::named_value("ß=", "x"),
+            ],
+        ),
+        &Item::new("\u{2000}", check_args, &[]),
+        &Item::new_menu("sub", &SUB_MENU),
+    ],
+);
+
+fuzz_target!(|data: &[u8]| {
+    let mut context = 0;
+    let mut buffer = [0u8; 64];
+    let mut runner = Runner::new(ROOT_MENU, &mut buffer, MockConsole::new(), &mut context);
+    // Each line of the input is run as a command, so the fuzzer can move
+    // between menus.
+    for line in data.split(|b| *b == b'\n') {
+        if let Ok(line) = core::str::from_utf8(line) {
+            runner.run_command(line, &mut context);
+        }
+    }
+    // And the same bytes again, as keystrokes.
+    for b in data {
+        runner.input_byte(*b, &mut context);
+    }
+});
@@ -0,0 +1,554 @@
+//! # Menu Derive
+//!
+//! Derive macros for the `menu` crate. You probably want to use these through
+//! `menu`'s `derive` feature rather than depending on this crate directly.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{
+    spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit, Fields, GenericArgument,
+    Lit, LitStr, Path, PathArguments, Type,
+};
+
+/// Generate a menu from an enum.
+///
+/// Each variant becomes an item in the menu, and each field of a struct
+/// variant becomes a parameter. Doc comments become help text.
+///
+/// * A field of type `bool` becomes a named parameter (`--name`), which is
+///   `true` if it was given.
+/// * A field of type `Option<V>` becomes an optional positional parameter,
+///   or a named value (`--name=VALUE`) if marked `#[menu(named)]`.
+/// * Any other field becomes a mandatory positional parameter, and can't be
+///   marked `#[menu(named)]`.
+///
+/// Values are parsed with `core::str::FromStr`. If one can't be parsed, the
+/// handler isn't called and the command fails, as if with
+/// `CommandContext::fail`, with a reason like `Invalid value for "level"`.
+///
+/// The enum must be annotated with the interface and context types of the
+/// menu, and a handler function with the signature `fn(Self, &mut
+/// CommandContext<Interface, Context>, &mut Context)` which is called when
+/// any of the commands are entered:
+///
+/// ```rust,ignore
+/// #[derive(MenuCommands)]
+/// #[menu(interface = Output, context = Context, handler = handle_command)]
+/// enum Command {
+///     /// Show the status
+///     Status,
+///     /// Change the level
+///     SetLevel {
+///         /// The new level
+///         level: u8,
+///     },
+///     #[menu(name = "reboot-now")]
+///     Reboot,
+/// }
+/// ```
+///
+/// The command name for each variant is the variant name in kebab-case,
+/// unless you set it with `#[menu(name = "...")]`. Similarly, each parameter
+/// is named after its field. `#[menu(help = "...")]` overrides the doc
+/// comment and `#[menu(value_name = "...")]` sets the placeholder shown in
+/// help for a named value.
+///
+/// The enum gains an `ITEMS` associated constant, a `const fn menu(label)`
+/// which makes a `Menu` from those items and a `parse_command` function which
+/// turns an item and its arguments back into the enum.
+#[proc_macro_derive(MenuCommands, attributes(menu))]
+pub fn derive_menu_commands(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The settings from `#[menu(...)]` on the enum itself.
+struct EnumSettings {
+    interface: Type,
+    context: Type,
+    handler: Path,
+}
+
+/// The settings from `#[menu(...)]` on a variant or field.
+#[derive(Default)]
+struct ItemSettings {
+    name: Option<LitStr>,
+    help: Option<LitStr>,
+    value_name: Option<LitStr>,
+    /// Where `named` was given, if it was
+    named: Option<Span>,
+}
+
+/// What sort of parameter a field becomes.
+enum FieldKind<'a> {
+    Flag,
+    Mandatory(&'a Type),
+    Optional(&'a Type),
+    NamedValue(&'a Type),
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream, Error> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new(
+            input.span(),
+            "MenuCommands can only be derived for enums",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "MenuCommands can't be derived for generic enums",
+        ));
+    }
+    let settings = enum_settings(&input)?;
+    let ident = &input.ident;
+    let interface = &settings.interface;
+    let context = &settings.context;
+    let handler = &settings.handler;
+
+    let mut items = Vec::new();
+    let mut parsers = Vec::new();
+    // The reason given for each parameter that fails to parse
+    let mut reasons: Vec<(String, String)> = Vec::new();
+    for variant in &data.variants {
+        let variant_settings = item_settings(&variant.attrs)?;
+        let variant_ident = &variant.ident;
+        let command = variant_settings
+            .name
+            .map(|n| n.value())
+            .unwrap_or_else(|| kebab_case(&variant_ident.to_string()));
+        let help = variant_settings
+            .help
+            .map(|h| h.value())
+            .or_else(|| doc_comment(&variant.attrs));
+
+        let mut parameters = Vec::new();
+        let parser = match &variant.fields {
+            Fields::Unit => quote! { #ident::#variant_ident },
+            Fields::Unnamed(fields) => {
+                return Err(Error::new(
+                    fields.span(),
+                    "MenuCommands needs unit or struct variants",
+                ))
+            }
+            Fields::Named(fields) => {
+                let mut field_parsers = Vec::new();
+                for field in &fields.named {
+                    let field_settings = item_settings(&field.attrs)?;
+                    let field_ident = field.ident.as_ref().expect("named field");
+                    let parameter_name = field_settings
+                        .name
+                        .as_ref()
+                        .map(LitStr::value)
+                        .unwrap_or_else(|| kebab_case(&field_ident.to_string()));
+                    let help = option_tokens(
+                        field_settings
+                            .help
+                            .as_ref()
+                            .map(LitStr::value)
+                            .or_else(|| doc_comment(&field.attrs)),
+                    );
+                    let kind = field_kind(&field.ty, field_settings.named)?;
+                    let (parameter, value) = match kind {
+                        FieldKind::Flag => (
+                            quote! {
+                                ::menu::Parameter::Named {
+                                    parameter_name: #parameter_name,
+                                    help: #help,
+                                    short: None,
+                                }
+                            },
+                            quote! {
+                                ::core::matches!(::menu::argument_finder(item, args, #parameter_name), Ok(Some(_)))
+                            },
+                        ),
+                        FieldKind::Mandatory(ty) => (
+                            quote! {
+                                ::menu::Parameter::Mandatory {
+                                    parameter_name: #parameter_name,
+                                    help: #help,
+                                    display: None,
+                                }
+                            },
+                            quote! {
+                                match ::menu::argument_finder(item, args, #parameter_name) {
+                                    Ok(Some(arg)) => arg.parse::<#ty>().map_err(|_| #parameter_name)?,
+                                    _ => return Err(#parameter_name),
+                                }
+                            },
+                        ),
+                        FieldKind::Optional(ty) => (
+                            quote! {
+                                ::menu::Parameter::Optional {
+                                    parameter_name: #parameter_name,
+                                    help: #help,
+                                    display: None,
+                                    default: None,
+                                }
+                            },
+                            optional_value(&parameter_name, ty),
+                        ),
+                        FieldKind::NamedValue(ty) => {
+                            let argument_name = field_settings
+                                .value_name
+                                .as_ref()
+                                .map(LitStr::value)
+                                .unwrap_or_else(|| parameter_name.to_uppercase());
+                            (
+                                quote! {
+                                    ::menu::Parameter::NamedValue {
+                                        parameter_name: #parameter_name,
+                                        argument_name: #argument_name,
+                                        help: #help,
+                                        short: None,
+                                        default: None,
+                                        allowed_values: None,
+                                    }
+                                },
+                                optional_value(&parameter_name, ty),
+                            )
+                        }
+                    };
+                    parameters.push(parameter);
+                    if !reasons.iter().any(|(name, _)| *name == parameter_name) {
+                        let reason = format!("Invalid value for {:?}", parameter_name);
+                        reasons.push((parameter_name.clone(), reason));
+                    }
+                    field_parsers.push(quote! { #field_ident: #value });
+                }
+                quote! { #ident::#variant_ident { #(#field_parsers),* } }
+            }
+        };
+        let set_help = help.map(|h| quote! { .help(#h) });
+        items.push(quote! {
+            &::menu::Item::new(
+                #command,
+                #ident::__menu_dispatch,
+                &[#(#parameters),*],
+            )
+            #set_help
+        });
+        parsers.push(quote! { #command => Ok(#parser), });
+    }
+
+    let (reason_names, reason_texts): (Vec<_>, Vec<_>) = reasons.into_iter().unzip();
+    Ok(quote! {
+        impl #ident {
+            /// The menu items generated from this enum.
+            pub const ITEMS: &'static [&'static ::menu::Item<'static, #interface, #context>] =
+                &[#(#items),*];
+
+            /// Make a menu containing the items generated from this enum.
+            pub const fn menu(label: &'static str) -> ::menu::Menu<'static, #interface, #context> {
+                ::menu::Menu::new(label, Self::ITEMS)
+            }
+
+            /// Turn one of the generated items, and the arguments given to
+            /// it, back into a value of this enum.
+            ///
+            /// If an argument is missing or can't be parsed, you get the name
+            /// of its parameter as the error. If the item didn't come from
+            /// this enum, you get an empty string.
+            pub fn parse_command(
+                item: &::menu::Item<#interface, #context>,
+                args: &[&str],
+            ) -> Result<Self, &'static str> {
+                match item.command {
+                    #(#parsers)*
+                    _ => Err(""),
+                }
+            }
+
+            fn __menu_dispatch(
+                _menu: &::menu::Menu<#interface, #context>,
+                item: &::menu::Item<#interface, #context>,
+                args: &[&str],
+                interface: &mut ::menu::CommandContext<#interface, #context>,
+                context: &mut #context,
+            ) {
+                match Self::parse_command(item, args) {
+                    Ok(command) => #handler(command, interface, context),
+                    Err(name) => interface.fail(match name {
+                        #(#reason_names => #reason_texts,)*
+                        _ => "Invalid arguments",
+                    }),
+                }
+            }
+        }
+    })
+}
+
+/// Parse an optional value, failing only if it was given but is invalid.
+fn optional_value(parameter_name: &str, ty: &Type) -> TokenStream {
+    quote! {
+        match ::menu::argument_finder(item, args, #parameter_name) {
+            Ok(Some(arg)) => Some(arg.parse::<#ty>().map_err(|_| #parameter_name)?),
+            _ => None,
+        }
+    }
+}
+
+fn enum_settings(input: &DeriveInput) -> Result<EnumSettings, Error> {
+    let mut interface = None;
+    let mut context = None;
+    let mut handler = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("menu")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("interface") {
+                interface = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("context") {
+                context = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("handler") {
+                handler = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `interface`, `context` or `handler`"));
+            }
+            Ok(())
+        })?;
+    }
+    let missing = |what| {
+        Error::new(
+            input.ident.span(),
+            format!("missing `#[menu({} = ...)]` on enum", what),
+        )
+    };
+    Ok(EnumSettings {
+        interface: interface.ok_or_else(|| missing("interface"))?,
+        context: context.ok_or_else(|| missing("context"))?,
+        handler: handler.ok_or_else(|| missing("handler"))?,
+    })
+}
+
+fn item_settings(attrs: &[Attribute]) -> Result<ItemSettings, Error> {
+    let mut settings = ItemSettings::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("menu")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                settings.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("help") {
+                settings.help = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("value_name") {
+                settings.value_name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("named") {
+                settings.named = Some(meta.path.span());
+            } else {
+                return Err(meta.error("expected `name`, `help`, `value_name` or `named`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(settings)
+}
+
+fn field_kind(ty: &Type, named: Option<Span>) -> Result<FieldKind<'_>, Error> {
+    if is_bool(ty) {
+        Ok(FieldKind::Flag)
+    } else if let Some(inner) = option_inner(ty) {
+        if named.is_some() {
+            Ok(FieldKind::NamedValue(inner))
+        } else {
+            Ok(FieldKind::Optional(inner))
+        }
+    } else if let Some(span) = named {
+        Err(Error::new(
+            span,
+            "`#[menu(named)]` needs a field of type `Option<_>`",
+        ))
+    } else {
+        Ok(FieldKind::Mandatory(ty))
+    }
+}
+
+fn is_bool(ty: &Type) -> bool {
+    matches!(ty, Type::Path(p) if p.qself.is_none() && p.path.is_ident("bool"))
+}
+
+/// If this is `Option<V>`, get `V`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(p) = ty else {
+        return None;
+    };
+    let segment = p.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Join up the `///` lines on an item.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let mut lines = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("doc")) {
+        if let Ok(nv) = attr.meta.require_name_value() {
+            if let Expr::Lit(ExprLit {
+                lit: Lit::Str(s), ..
+            }) = &nv.value
+            {
+                let line = s.value();
+                lines.push(line.strip_prefix(' ').unwrap_or(&line).to_owned());
+            }
+        }
+    }
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+fn option_tokens(value: Option<String>) -> TokenStream {
+    match value {
+        Some(s) => quote! { Some(#s) },
+        None => quote! { None },
+    }
+}
+
+/// `SetLevel` becomes `set-level`.
+fn kebab_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c == '_' {
+            result.push('-');
+        } else if c.is_uppercase() {
+            if i != 0 && !result.ends_with('-') {
+                result.push('-');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(input: &str) -> String {
+        let input: DeriveInput = syn::parse_str(input).unwrap();
+        match expand(input) {
+            Ok(tokens) => tokens.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn kebab() {
+        assert_eq!(kebab_case("Status"), "status");
+        assert_eq!(kebab_case("SetLevel"), "set-level");
+        assert_eq!(kebab_case("max_speed"), "max-speed");
+    }
+
+    #[test]
+    fn unit_variant() {
+        let out = expand_str(
+            "#[menu(interface = Out, context = Ctx, handler = handle)]
+            enum Cmd {
+                /// Show the status
+                Status,
+            }",
+        );
+        assert!(
+            out.contains(":: menu :: Item :: new (\"status\" , Cmd :: __menu_dispatch , & [] ,) . help (\"Show the status\")"),
+            "{}",
+            out
+        );
+        assert!(out.contains("\"status\" => Ok (Cmd :: Status)"), "{}", out);
+    }
+
+    #[test]
+    fn struct_variant() {
+        let out = expand_str(
+            "#[menu(interface = Out, context = Ctx, handler = handle)]
+            enum Cmd {
+                SetLevel {
+                    /// The level
+                    level: u8,
+                    verbose: bool,
+                    speed: Option<u32>,
+                    #[menu(named, value_name = \"NAME\")]
+                    mode: Option<Mode>,
+                },
+            }",
+        );
+        assert!(
+            out.contains(":: menu :: Item :: new (\"set-level\""),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains(
+                ":: menu :: Parameter :: Mandatory { parameter_name : \"level\" , help : Some (\"The level\") , display : None , }"
+            ),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains(":: menu :: Parameter :: Named { parameter_name : \"verbose\""),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains(":: menu :: Parameter :: Optional { parameter_name : \"speed\""),
+            "{}",
+            out
+        );
+        assert!(
+            out.contains(
+                ":: menu :: Parameter :: NamedValue { parameter_name : \"mode\" , argument_name : \"NAME\""
+            ),
+            "{}",
+            out
+        );
+        assert!(out.contains("parse :: < u8 >"), "{}", out);
+        assert!(out.contains("parse :: < Mode >"), "{}", out);
+    }
+
+    #[test]
+    fn renamed() {
+        let out = expand_str(
+            "#[menu(interface = Out, context = Ctx, handler = handle)]
+            enum Cmd {
+                #[menu(name = \"reboot-now\", help = \"Reboot\")]
+                Reboot,
+            }",
+        );
+        assert!(
+            out.contains(":: menu :: Item :: new (\"reboot-now\" , Cmd :: __menu_dispatch , & [] ,) . help (\"Reboot\")"),
+            "{}",
+            out
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            expand_str("#[menu(interface = Out, context = Ctx, handler = h)] struct Cmd;"),
+            "MenuCommands can only be derived for enums"
+        );
+        assert_eq!(
+            expand_str("#[menu(interface = Out, handler = h)] enum Cmd { A }"),
+            "missing `#[menu(context = ...)]` on enum"
+        );
+        assert_eq!(
+            expand_str("#[menu(interface = Out, context = Ctx, handler = h)] enum Cmd { A(u8) }"),
+            "MenuCommands needs unit or struct variants"
+        );
+        assert_eq!(
+            expand_str(
+                "#[menu(interface = Out, context = Ctx, handler = h)]
+                enum Cmd { A { #[menu(named)] level: u8 } }"
+            ),
+            "`#[menu(named)]` needs a field of type `Option<_>`"
+        );
+    }
+}
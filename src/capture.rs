@@ -0,0 +1,109 @@
+//! Keeps a copy of what a command writes, for the next command to use.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::ConsoleWriter;
+
+/// The buffer given to [`Runner::set_capture_buffer`](crate::Runner::set_capture_buffer).
+///
+/// Each half holds one capture. The last capture stays in one half while the
+/// next command captures into the other, then they swap over.
+pub(crate) struct CaptureBuffer<'a> {
+    buffer: &'a mut [u8],
+    /// Which half holds the last capture
+    last_half: usize,
+    last_len: usize,
+    last_truncated: bool,
+}
+
+impl<'a> CaptureBuffer<'a> {
+    pub(crate) fn new(buffer: &'a mut [u8]) -> Self {
+        CaptureBuffer {
+            buffer,
+            last_half: 0,
+            last_len: 0,
+            last_truncated: false,
+        }
+    }
+
+    /// Get ready for a command to capture its output.
+    pub(crate) fn start(&mut self) -> Capture<'_> {
+        let half = self.buffer.len() / 2;
+        let (first, second) = self.buffer.split_at_mut(half);
+        let (last, next) = if self.last_half == 0 {
+            (first, &mut second[..half])
+        } else {
+            (&mut second[..half], first)
+        };
+        Capture {
+            // We only ever store whole characters
+            last: core::str::from_utf8(&last[..self.last_len]).unwrap_or(""),
+            last_truncated: self.last_truncated,
+            next,
+            used: 0,
+            truncated: false,
+            active: false,
+        }
+    }
+
+    /// Keep what a command captured, in place of the last capture.
+    pub(crate) fn finish(&mut self, used: usize, truncated: bool) {
+        self.last_half ^= 1;
+        self.last_len = used;
+        self.last_truncated = truncated;
+    }
+}
+
+/// The last capture, and room for the command being run to capture into.
+pub(crate) struct Capture<'c> {
+    pub(crate) last: &'c str,
+    pub(crate) last_truncated: bool,
+    next: &'c mut [u8],
+    used: usize,
+    truncated: bool,
+    /// The command has asked to capture
+    pub(crate) active: bool,
+}
+
+impl Capture<'_> {
+    /// How much was captured, and whether some was dropped.
+    pub(crate) fn result(&self) -> (usize, bool) {
+        (self.used, self.truncated)
+    }
+
+    /// Keep as much of `s` as fits, stopping before the first character
+    /// which doesn't. Once something has been dropped, nothing more is kept.
+    fn keep(&mut self, s: &str) {
+        if self.truncated {
+            return;
+        }
+        let room = self.next.len() - self.used;
+        let mut len = s.len().min(room);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.next[self.used..self.used + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.used += len;
+        self.truncated = len < s.len();
+    }
+}
+
+/// Writes to the console, and keeps a copy for the next command. See
+/// [`CommandContext::capture`](crate::CommandContext::capture).
+pub struct CaptureWriter<'w, 'c, I> {
+    pub(crate) writer: &'w mut ConsoleWriter<'c, I>,
+    pub(crate) capture: Option<&'w mut Capture<'c>>,
+}
+
+impl<I> Write for CaptureWriter<'_, '_, I>
+where
+    I: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if let Some(capture) = &mut self.capture {
+            capture.keep(s);
+        }
+        self.writer.write_str(s)
+    }
+}
@@ -0,0 +1,652 @@
+//! The handle given to item callbacks, through which they can ask the
+//! `Runner` to do things once they have returned.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+use core::ops::Range;
+
+use super::capture::{Capture, CaptureWriter};
+use super::menu_manager::{MenuManager, MAX_DEPTH};
+use super::{BusyHookFn, ConsoleWriter, Echo, InputSource, InputTapFn, KeyPollFn, MenuError};
+
+/// The type of function which is given the bytes typed while passing
+/// through. See [`CommandContext::request_passthrough`].
+pub type PassthroughFn<T> = fn(bytes: &[u8], context: &mut T);
+
+/// What a [`RawModeFn`] wants to happen after it has handled a byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawModeAction {
+    /// Keep sending me every byte
+    Continue,
+    /// Go back to normal line editing, and print a prompt
+    Finish,
+}
+
+/// Handles every byte of input while the `Runner` is in raw mode. See
+/// [`CommandContext::request_raw_mode`].
+pub type RawModeFn<I, T> =
+    fn(input: u8, writer: &mut ConsoleWriter<I>, context: &mut T) -> RawModeAction;
+
+/// Called with the answer to [`CommandContext::confirm`].
+pub type ConfirmFn<I, T> =
+    fn(confirmed: bool, interface: &mut CommandContext<I, T>, context: &mut T);
+
+/// Called with the line typed in answer to [`CommandContext::read_line`].
+pub type LineFn<I, T> = fn(line: &str, interface: &mut CommandContext<I, T>, context: &mut T);
+
+/// A line the `Runner` should read for a callback, rather than a command.
+pub(crate) struct LineRequest<I, T> {
+    pub(crate) prompt: &'static str,
+    /// Echo a `*` for each character, rather than the character
+    pub(crate) masked: bool,
+    pub(crate) then: LineFn<I, T>,
+    /// Where in the `Runner`'s buffer the line to start with is
+    pub(crate) template: Range<usize>,
+}
+
+/// The room left in the `Runner`'s buffer after the line being run, where a
+/// template for the next line can wait until the callback returns.
+pub(crate) struct Spare<'s> {
+    /// Where in the buffer the room starts
+    pub(crate) start: usize,
+    pub(crate) bytes: &'s mut [u8],
+}
+
+impl Spare<'_> {
+    /// No room at all.
+    pub(crate) fn none() -> Self {
+        Spare {
+            start: 0,
+            bytes: &mut [],
+        }
+    }
+}
+
+/// How callbacks get keys, and who's told about them.
+pub(crate) struct Keys<I> {
+    pub(crate) poll: Option<KeyPollFn<I>>,
+    pub(crate) tap: Option<InputTapFn<I>>,
+}
+
+impl<I> Keys<I> {
+    pub(crate) const NONE: Self = Keys {
+        poll: None,
+        tap: None,
+    };
+}
+
+// Derived, these would need `I: Copy`
+impl<I> Clone for Keys<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for Keys<I> {}
+
+/// Which lines of a callback's output [`CommandContext::pager`] shows, and
+/// how far through them it is.
+#[derive(Clone, Copy)]
+#[cfg(feature = "paging")]
+pub(crate) struct Page {
+    /// How long the command line is, in the `Runner`'s buffer, so it can be
+    /// run again for the next page
+    pub(crate) line_len: usize,
+    /// Lines already shown, which aren't shown again
+    pub(crate) skip: usize,
+    /// Lines to show after those
+    pub(crate) height: usize,
+    /// Lines written so far
+    pub(crate) line: usize,
+    /// Something was written past the end of the page
+    pub(crate) overflowed: bool,
+}
+
+#[cfg(feature = "paging")]
+impl Page {
+    pub(crate) const fn new(line_len: usize, skip: usize, height: usize) -> Self {
+        Page {
+            line_len,
+            skip,
+            height,
+            line: 0,
+            overflowed: false,
+        }
+    }
+
+    /// Is the line being written past the end of the page?
+    const fn past_end(&self) -> bool {
+        self.line >= self.skip + self.height
+    }
+}
+
+/// Something a callback wants to handle the input once it has returned.
+pub(crate) enum InputRequest<I, T> {
+    Raw(RawModeFn<I, T>),
+    Passthrough(PassthroughFn<T>, &'static [u8]),
+    /// Ask this question, then call the function with the answer
+    Confirm(&'static str, ConfirmFn<I, T>),
+    Line(LineRequest<I, T>),
+    /// Start the next command line with what's in this part of the buffer
+    Prefill(Range<usize>),
+    /// Show `--More--`, then the page after this one
+    #[cfg(feature = "paging")]
+    More(Page),
+}
+
+/// Given to item callbacks, for writing to the interface and for asking the
+/// `Runner` to do things.
+///
+/// Nothing you ask for happens until your callback returns. Only one thing
+/// can take the input at a time, so of [`CommandContext::request_raw_mode`],
+/// [`CommandContext::request_passthrough`], [`CommandContext::confirm`],
+/// [`CommandContext::read_line`], [`CommandContext::read_secret`],
+/// [`CommandContext::prefill`] and [`CommandContext::edit_line`], only the
+/// last one called counts. Output cut short by `CommandContext::pager`,
+/// with the `paging` feature, counts too.
+pub struct CommandContext<'c, I, T> {
+    writer: ConsoleWriter<'c, I>,
+    menu_mgr: MenuManager<'c, I, T>,
+    busy_hook: Option<BusyHookFn<T>>,
+    keys: Keys<I>,
+    capture: Option<Capture<'c>>,
+    #[cfg(feature = "paging")]
+    page: Option<Page>,
+    spare: Spare<'c>,
+    requests: Requests<I, T>,
+}
+
+/// Everything a callback asked for.
+pub(crate) struct Requests<I, T> {
+    /// Where the `Runner` should be in the menu tree
+    pub(crate) menu: Option<[Option<usize>; MAX_DEPTH]>,
+    /// What should take the input next, if not the command line
+    pub(crate) input: Option<InputRequest<I, T>>,
+    pub(crate) prompt_prefix: Option<&'static str>,
+    pub(crate) machine_mode: Option<bool>,
+    pub(crate) echo: Option<Echo>,
+    /// Why the command failed, if it did
+    pub(crate) failure: Option<&'static str>,
+    /// How much the command captured, and whether some was dropped
+    pub(crate) captured: Option<(usize, bool)>,
+    /// The command asked for a failure to count against its throttle group
+    pub(crate) throttle_failure: bool,
+}
+
+impl<'c, I, T> CommandContext<'c, I, T> {
+    pub(crate) fn new(
+        writer: ConsoleWriter<'c, I>,
+        menu_mgr: &MenuManager<'c, I, T>,
+        busy_hook: Option<BusyHookFn<T>>,
+        keys: Keys<I>,
+    ) -> Self {
+        CommandContext {
+            writer,
+            menu_mgr: menu_mgr.clone(),
+            busy_hook,
+            keys,
+            capture: None,
+            #[cfg(feature = "paging")]
+            page: None,
+            spare: Spare::none(),
+            requests: Requests {
+                menu: None,
+                input: None,
+                prompt_prefix: None,
+                machine_mode: None,
+                echo: None,
+                failure: None,
+                captured: None,
+                throttle_failure: false,
+            },
+        }
+    }
+
+    /// Let the callback capture its output, and see the last capture.
+    pub(crate) fn with_capture(mut self, capture: Capture<'c>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// Let the callback page its output.
+    #[cfg(feature = "paging")]
+    pub(crate) fn with_page(mut self, page: Option<Page>) -> Self {
+        self.page = page;
+        self
+    }
+
+    /// Let the callback keep a template for the next line in `spare`.
+    pub(crate) fn with_spare(mut self, spare: Spare<'c>) -> Self {
+        self.spare = spare;
+        self
+    }
+
+    pub(crate) fn into_requests(self) -> Requests<I, T> {
+        let mut requests = self.requests;
+        requests.captured = self
+            .capture
+            .filter(|capture| capture.active)
+            .map(|capture| capture.result());
+        requests
+    }
+
+    /// Get at the interface, for anything other than writing text.
+    ///
+    /// Anything you write directly to the interface isn't tracked by the
+    /// [`ConsoleWriter`].
+    pub fn interface(&mut self) -> &mut I {
+        self.writer.interface()
+    }
+
+    /// Get the writer that this `CommandContext` writes through.
+    pub fn writer(&mut self) -> &mut ConsoleWriter<'c, I> {
+        &mut self.writer
+    }
+
+    /// Get a writer which writes to the console, like this `CommandContext`,
+    /// but also keeps a copy for the next command to find with
+    /// [`CommandContext::last_capture`]. See
+    /// [`Runner::set_capture_buffer`](crate::Runner::set_capture_buffer).
+    ///
+    /// Everything written through capture writers while your callback runs
+    /// is kept, up to half the size of the capture buffer. Anything past that
+    /// is still written to the console, but not kept. Without a capture
+    /// buffer, nothing is kept.
+    pub fn capture(&mut self) -> CaptureWriter<'_, 'c, I> {
+        if let Some(capture) = &mut self.capture {
+            capture.active = true;
+        }
+        let capture = self.capture.as_mut();
+        CaptureWriter {
+            writer: &mut self.writer,
+            capture,
+        }
+    }
+
+    /// What was captured by the last command which called
+    /// [`CommandContext::capture`], or an empty string if there's no capture
+    /// buffer or nothing has been captured.
+    ///
+    /// This is cut short, at the end of a character, if there wasn't room for
+    /// all of it. See [`CommandContext::last_capture_truncated`].
+    pub fn last_capture(&self) -> &'c str {
+        self.capture.as_ref().map_or("", |capture| capture.last)
+    }
+
+    /// Was [`CommandContext::last_capture`] cut short?
+    pub fn last_capture_truncated(&self) -> bool {
+        self.capture
+            .as_ref()
+            .is_some_and(|capture| capture.last_truncated)
+    }
+
+    /// Call the `Runner`'s busy hook, if it has one. See
+    /// [`Runner::set_busy_hook`](crate::Runner::set_busy_hook).
+    ///
+    /// Call this every so often from a callback which takes a long time, so
+    /// the hook can feed a watchdog or the like.
+    pub fn keep_alive(&mut self, context: &mut T) {
+        if let Some(hook) = self.busy_hook {
+            hook(context);
+        }
+    }
+
+    /// Take a key which is waiting to be read, if there is one, using the
+    /// `Runner`'s key poll. See
+    /// [`Runner::set_key_poll`](crate::Runner::set_key_poll).
+    ///
+    /// This doesn't wait, so a command which streams output can call it
+    /// between lines and stop when it gets a key. The key doesn't go into
+    /// the command line, and isn't echoed. Without a key poll, there's never
+    /// a key.
+    pub fn poll_key(&mut self) -> Option<u8> {
+        let poll = self.keys.poll?;
+        let key = poll(self.writer.interface())?;
+        if let Some(tap) = self.keys.tap {
+            tap(self.writer.interface(), key, InputSource::Polled);
+        }
+        Some(key)
+    }
+
+    /// Go to another menu.
+    ///
+    /// The path is a list of sub-menu commands separated by `/`, such as
+    /// `settings/wifi`. It starts from the current menu, unless it starts with
+    /// a `/`, in which case it starts from the root menu. A `..` goes up a
+    /// level.
+    ///
+    /// Nothing changes if any part of the path isn't a sub-menu.
+    pub fn enter_menu(&mut self, path: &str) -> Result<(), MenuError<'static>> {
+        let mut menu_mgr = self.menu_mgr.clone();
+        let relative_path = match path.strip_prefix('/') {
+            Some(rest) => {
+                while menu_mgr.depth() != 0 {
+                    menu_mgr.pop();
+                }
+                rest
+            }
+            None => path,
+        };
+        for part in relative_path.split('/').filter(|p| !p.is_empty()) {
+            if part == ".." {
+                menu_mgr.pop();
+                continue;
+            }
+            let index = menu_mgr
+                .current()
+                .items
+                .iter()
+                .position(|item| item.command == part && item.is_menu())
+                .ok_or(MenuError::NotFound)?;
+            menu_mgr.push(index)?;
+        }
+        self.requests.menu = Some(menu_mgr.path());
+        self.menu_mgr = menu_mgr;
+        Ok(())
+    }
+
+    /// Fills in the command of each sub-menu we're in, outermost first, and
+    /// returns how many there are.
+    pub(crate) fn menu_path(&self, path: &mut [&'c str; MAX_DEPTH]) -> usize {
+        let depth = self.menu_mgr.depth();
+        for (level, (command, index)) in path.iter_mut().zip(self.menu_mgr.path()).enumerate() {
+            let Some(index) = index else {
+                break;
+            };
+            *command = self
+                .menu_mgr
+                .at_depth(level)
+                .and_then(|menu| menu.items.get(index))
+                .map_or("", |item| item.command);
+        }
+        depth
+    }
+
+    /// Leave the current menu, as if the user had typed `exit`.
+    ///
+    /// Does nothing in the root menu.
+    pub fn exit_menu(&mut self) {
+        self.menu_mgr.pop();
+        self.requests.menu = Some(self.menu_mgr.path());
+    }
+
+    /// Send every byte of input to `handler`, rather than treating it as a
+    /// command line, until `handler` returns [`RawModeAction::Finish`].
+    ///
+    /// There is no prompt until raw mode finishes.
+    pub fn request_raw_mode(&mut self, handler: RawModeFn<I, T>) {
+        self.requests.input = Some(InputRequest::Raw(handler));
+    }
+
+    /// Pass everything typed to `forward`, rather than treating it as a
+    /// command line, until `escape` is typed, such as `b"+++"` or Ctrl-]
+    /// (`b"\x1d"`).
+    ///
+    /// This is for talking to something with its own console. `forward`
+    /// sends the bytes on, and the application writes whatever comes back
+    /// to the interface while [`Runner::in_passthrough`](crate::Runner::in_passthrough).
+    /// Bytes which might be the start of `escape` are held back until the
+    /// next byte shows whether they are, so `escape` can be split across
+    /// reads. It isn't passed on, and the prompt comes back after it.
+    pub fn request_passthrough(&mut self, forward: PassthroughFn<T>, escape: &'static [u8]) {
+        self.requests.input = Some(InputRequest::Passthrough(forward, escape));
+    }
+
+    /// Read a line of text, such as a new setting, once your callback has
+    /// returned.
+    ///
+    /// The `Runner` shows `prompt` rather than its usual prompt, and the
+    /// line is edited just like a command line, in the `Runner`'s buffer.
+    /// When Enter is pressed, `then` is called with the line. It can ask for
+    /// another line, and the usual prompt comes back once it doesn't.
+    pub fn read_line(&mut self, prompt: &'static str, then: LineFn<I, T>) {
+        self.requests.input = Some(InputRequest::Line(LineRequest {
+            prompt,
+            masked: false,
+            then,
+            template: 0..0,
+        }));
+    }
+
+    /// Read a line like [`CommandContext::read_line`], but echo a `*` for
+    /// each character typed, for passwords and the like.
+    pub fn read_secret(&mut self, prompt: &'static str, then: LineFn<I, T>) {
+        self.requests.input = Some(InputRequest::Line(LineRequest {
+            prompt,
+            masked: true,
+            then,
+            template: 0..0,
+        }));
+    }
+
+    /// Start the next command line with `template` already typed, once
+    /// your callback has returned, such as the command to change a setting
+    /// followed by its current value.
+    ///
+    /// The template is shown after the prompt with the cursor at its end,
+    /// and can be edited like anything typed, or run as it is with Enter.
+    /// Ctrl-C throws it away.
+    ///
+    /// The template is kept in the `Runner`'s buffer, in the room left after
+    /// the command line being run. Gives [`MenuError::TooLong`] if it won't
+    /// fit. There's no room at all unless the command line was typed in, so
+    /// it always does when your callback is run by
+    /// [`Runner::run_command`](crate::Runner::run_command), from a macro
+    /// being played back, or again by the pager for the next page.
+    pub fn prefill(&mut self, template: &str) -> Result<(), MenuError<'static>> {
+        let template = self.keep_template(template)?;
+        self.requests.input = Some(InputRequest::Prefill(template));
+        Ok(())
+    }
+
+    /// Read a line like [`CommandContext::read_line`], but start it with
+    /// `template` already typed, as [`CommandContext::prefill`] does for a
+    /// command line.
+    ///
+    /// Gives [`MenuError::TooLong`] if there isn't room for the template,
+    /// as `prefill` does, such as from [`Runner::run_command`](crate::Runner::run_command).
+    pub fn edit_line(
+        &mut self,
+        prompt: &'static str,
+        template: &str,
+        then: LineFn<I, T>,
+    ) -> Result<(), MenuError<'static>> {
+        let template = self.keep_template(template)?;
+        self.requests.input = Some(InputRequest::Line(LineRequest {
+            prompt,
+            masked: false,
+            then,
+            template,
+        }));
+        Ok(())
+    }
+
+    /// Copy a template into the spare room in the buffer, and say where it
+    /// is.
+    fn keep_template(&mut self, template: &str) -> Result<Range<usize>, MenuError<'static>> {
+        let bytes = self
+            .spare
+            .bytes
+            .get_mut(..template.len())
+            .ok_or(MenuError::TooLong)?;
+        bytes.copy_from_slice(template.as_bytes());
+        let start = self.spare.start;
+        Ok(start..start + template.len())
+    }
+
+    /// Print `prefix` at the start of every prompt.
+    pub fn set_prompt_prefix(&mut self, prefix: &'static str) {
+        self.requests.prompt_prefix = Some(prefix);
+    }
+
+    /// Turn the `Runner`'s machine mode on or off. See
+    /// [`Runner::set_machine_mode`](crate::Runner::set_machine_mode).
+    ///
+    /// The command which changes the mode is reported as it would have been
+    /// before the change.
+    pub fn set_machine_mode(&mut self, machine_mode: bool) {
+        self.requests.machine_mode = Some(machine_mode);
+    }
+
+    /// Change how what's typed is shown, such as turning it off before
+    /// reading a passphrase with [`CommandContext::read_line`]. See
+    /// [`Runner::set_echo`](crate::Runner::set_echo).
+    pub fn set_echo(&mut self, echo: Echo) {
+        self.requests.echo = Some(echo);
+    }
+
+    /// Report that the command failed, and why.
+    ///
+    /// The `Runner` prints `Error: ` and the reason once your callback has
+    /// returned, or reports it in machine mode, and
+    /// [`Runner::run_command`](crate::Runner::run_command) returns it as
+    /// [`CommandOutcome::Failed`](crate::CommandOutcome::Failed).
+    pub fn fail(&mut self, reason: &'static str) {
+        self.requests.failure = Some(reason);
+    }
+
+    /// Count a failure, such as a wrong password, against the item's
+    /// throttle group. See [`Runner::set_throttle`](crate::Runner::set_throttle).
+    ///
+    /// Once your callback has returned, the `Runner` passes the group to the
+    /// failure function, which can keep count in the context. Does nothing
+    /// if the item has no throttle group.
+    pub fn report_failure(&mut self) {
+        self.requests.throttle_failure = true;
+    }
+}
+
+impl<'c, I, T> CommandContext<'c, I, T>
+where
+    I: Write,
+{
+    /// Ask the user a yes or no question, such as "Are you sure?".
+    ///
+    /// This returns straight away, as the answer hasn't been typed yet. Once
+    /// your callback returns, the `Runner` prints the question followed by
+    /// `[y/N]`, takes the next `y` or `n` it's given, or Enter for no, echoes
+    /// it, and calls `then` with `true` for yes. Anything else typed is ignored, and nothing
+    /// typed goes into the command line.
+    ///
+    /// So, the rest of a destructive command belongs in `then`:
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// # use core::fmt::Write;
+    /// fn factory_reset(
+    ///     _menu: &Menu<String, ()>,
+    ///     _item: &Item<String, ()>,
+    ///     _args: &[&str],
+    ///     interface: &mut CommandContext<String, ()>,
+    ///     _context: &mut (),
+    /// ) {
+    ///     interface.confirm("Erase all settings?", |confirmed, interface, _context| {
+    ///         if confirmed {
+    ///             // Erase everything
+    ///         } else {
+    ///             writeln!(interface, "Cancelled").unwrap();
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn confirm(&mut self, prompt: &'static str, then: ConfirmFn<I, T>) {
+        self.requests.input = Some(InputRequest::Confirm(prompt, then));
+    }
+
+    /// Get a writer which stops at the end of each screenful, for output too
+    /// long to read all at once. See
+    /// [`Runner::set_page_height`](crate::Runner::set_page_height).
+    ///
+    /// Once a page has been written, anything more is thrown away, and
+    /// when your callback returns the `Runner` shows `--More--`, like
+    /// [`CommandContext::confirm`] shows its question. Space shows the next
+    /// page, Enter one more line, and `q` or Ctrl-C none.
+    ///
+    /// To show more, the `Runner` calls your callback again, with the same
+    /// arguments, and the pager throwing away what's already been shown.
+    /// Only the callback runs again, not the whole command line, so the
+    /// throttle function isn't asked again. But the callback should write
+    /// the same thing each time, and not change anything. It can stop
+    /// early once [`Pager::cancelled`] is true, as nothing more will be
+    /// shown.
+    ///
+    /// Without a page height, or when the callback isn't run for a command
+    /// line, everything is written.
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// # use core::fmt::Write;
+    /// fn dump(
+    ///     _menu: &Menu<String, Vec<u32>>,
+    ///     _item: &Item<String, Vec<u32>>,
+    ///     _args: &[&str],
+    ///     interface: &mut CommandContext<String, Vec<u32>>,
+    ///     log: &mut Vec<u32>,
+    /// ) {
+    ///     let mut pager = interface.pager();
+    ///     for entry in log.iter() {
+    ///         if pager.cancelled() {
+    ///             break;
+    ///         }
+    ///         writeln!(pager, "{}", entry).ok();
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "paging")]
+    pub fn pager(&mut self) -> Pager<'_, 'c, I, T> {
+        Pager { context: self }
+    }
+}
+
+/// Writes to the console a page at a time. See [`CommandContext::pager`].
+#[cfg(feature = "paging")]
+pub struct Pager<'p, 'c, I, T> {
+    context: &'p mut CommandContext<'c, I, T>,
+}
+
+#[cfg(feature = "paging")]
+impl<I, T> Pager<'_, '_, I, T> {
+    /// Has something been thrown away, because it's past the end of the
+    /// page? If so, nothing more written will be shown either.
+    pub fn cancelled(&self) -> bool {
+        self.context.page.is_some_and(|page| page.overflowed)
+    }
+}
+
+#[cfg(feature = "paging")]
+impl<I, T> Write for Pager<'_, '_, I, T>
+where
+    I: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let Some(page) = &mut self.context.page else {
+            return self.context.writer.write_str(s);
+        };
+        for piece in s.split_inclusive('\n') {
+            if page.past_end() {
+                if !page.overflowed {
+                    page.overflowed = true;
+                    let next = Page::new(page.line_len, page.skip + page.height, page.height);
+                    self.context.requests.input = Some(InputRequest::More(next));
+                }
+                return Ok(());
+            }
+            if page.line >= page.skip {
+                self.context.writer.write_str(piece)?;
+            }
+            if piece.ends_with('\n') {
+                page.line += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I, T> Write for CommandContext<'_, I, T>
+where
+    I: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.writer.write_str(s)
+    }
+}
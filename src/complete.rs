@@ -0,0 +1,454 @@
+//! Works out what to add to the command line when Tab is pressed.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::parser::{self, Argument, OptionMatch, Token};
+use super::{Menu, Parameter};
+
+/// The most candidates listed when Tab can't add anything. Any more are
+/// shown as `...`.
+pub(crate) const MAX_LISTED: usize = 16;
+
+/// Which parameter's value is being typed at the end of `args`, the part of
+/// the command line after the command, and what's been typed of it so far.
+///
+/// Returns `None` if an option's name, or an argument the command doesn't
+/// take, is being typed.
+pub(crate) fn value_position<'p, 'l>(
+    parameters: &'p [Parameter<'p>],
+    args: &'l str,
+    allow_prefixes: bool,
+) -> Option<(&'p Parameter<'p>, &'l str)> {
+    // Still typing the command
+    if args.is_empty() {
+        return None;
+    }
+    let (before, last) = parser::split_last_word(args);
+    let token = match options_ended(before) {
+        true => Token::Positional(last),
+        false => parser::classify(last),
+    };
+    match token {
+        Token::Positional(partial) => {
+            // It may be the value of an option before it, as in `--level 3`
+            let words = parser::tokenize(before).chain(core::iter::once(last));
+            let mut given = 0;
+            let mut current = None;
+            for argument in parser::read_arguments(parameters, words, allow_prefixes) {
+                if let Some(Argument::Positional(_)) = current.replace(argument) {
+                    given += 1;
+                }
+            }
+            match current? {
+                Argument::Positional(_) => parameters
+                    .iter()
+                    .filter(|p| {
+                        matches!(p, Parameter::Mandatory { .. } | Parameter::Optional { .. })
+                    })
+                    .nth(given)
+                    .map(|p| (p, partial)),
+                Argument::Option {
+                    resolved: OptionMatch::Exact(name) | OptionMatch::Prefix(name),
+                    value: Some(_),
+                    ..
+                } => named_value(parameters, name).map(|p| (p, partial)),
+                Argument::Option { .. } => None,
+            }
+        }
+        Token::Named(_) | Token::Short(_) | Token::EndOfOptions => None,
+        Token::NamedValue(name, partial) => {
+            let name = match parser::resolve_option(parameters, name, true) {
+                OptionMatch::Exact(name) => name,
+                OptionMatch::Prefix(name) if allow_prefixes => name,
+                _ => return None,
+            };
+            named_value(parameters, name).map(|p| (p, partial))
+        }
+        Token::ShortValue(shorts, partial) => {
+            // Only the last of several short options can take a value
+            let short = shorts.chars().next_back()?;
+            match parser::resolve_short(parameters, short, true) {
+                OptionMatch::Exact(name) => named_value(parameters, name).map(|p| (p, partial)),
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Is there a `--` on its own in `before`, so everything after it is
+/// positional?
+fn options_ended(before: &str) -> bool {
+    parser::tokenize(before).any(|word| parser::classify(word) == Token::EndOfOptions)
+}
+
+/// The `NamedValue` parameter called `name`.
+fn named_value<'p>(parameters: &'p [Parameter<'p>], name: &str) -> Option<&'p Parameter<'p>> {
+    parameters.iter().find(
+        |p| matches!(p, Parameter::NamedValue { parameter_name, .. } if *parameter_name == name),
+    )
+}
+
+/// Find what the last word of `line` could be, for an application which
+/// draws its own console, such as a GUI, from the same menus as the
+/// `Runner`.
+///
+/// While the first word is being typed, the candidates are the commands of
+/// the items in `menu` which start with it. Once a sub-menu's command has
+/// been typed, followed by a space, the rest of the line is completed in
+/// that sub-menu. Once a command's been typed, the candidates are the names
+/// of its options, without their `--`, which start with what's been typed
+/// after `--`. While an option's value is being typed, they're its
+/// [allowed values](Parameter::allowed_values), if it has them. There are
+/// none while anything else is being typed.
+///
+/// Candidates are in the order they're declared, and hidden items are left
+/// out, as are built-in commands like `help`. As many as fit are put in
+/// `out`, and the number put there is returned.
+///
+/// ```rust
+/// # use menu::*;
+/// # fn noop(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+/// const SETTINGS: Menu<(), ()> = Menu::new(
+///     "settings",
+///     &[&Item::new("baud", noop, &[Parameter::named("save")])],
+/// );
+/// const ROOT: Menu<(), ()> = Menu::new(
+///     "root",
+///     &[
+///         &Item::new("status", noop, &[]),
+///         &Item::new_menu("settings", &SETTINGS),
+///     ],
+/// );
+/// let mut out = [""; 4];
+/// let count = complete(&ROOT, "s", &mut out);
+/// assert_eq!(out[..count], ["status", "settings"]);
+/// let count = complete(&ROOT, "settings baud --s", &mut out);
+/// assert_eq!(out[..count], ["save"]);
+/// ```
+pub fn complete<'a, I, T>(menu: &'a Menu<'a, I, T>, line: &str, out: &mut [&'a str]) -> usize {
+    let mut menu = menu;
+    let mut line = line;
+    loop {
+        let (cmd, rest) = parser::split_command(line);
+        if rest.is_empty() {
+            return fill(
+                out,
+                menu.items
+                    .iter()
+                    .filter(|item| !item.hidden)
+                    .map(|item| item.command)
+                    .filter(|command| command.starts_with(cmd)),
+            );
+        }
+        let Some((_, item)) = menu.find(cmd) else {
+            return 0;
+        };
+        let Some(submenu) = item.submenu() else {
+            let allowed = value_position(item.parameters(), rest, false)
+                .and_then(|(parameter, partial)| Some((parameter.allowed()?, partial)));
+            if let Some((allowed, partial)) = allowed {
+                return fill(
+                    out,
+                    allowed.iter().copied().filter(|c| c.starts_with(partial)),
+                );
+            }
+            let (before, last) = parser::split_last_word(rest);
+            if options_ended(before) {
+                return 0;
+            }
+            let partial = match parser::classify(last) {
+                Token::Named(partial) => partial,
+                Token::Positional("") | Token::EndOfOptions => "",
+                _ => return 0,
+            };
+            return fill(
+                out,
+                options(item.parameters())
+                    .map(|(name, _)| name)
+                    .filter(|name| name.starts_with(partial)),
+            );
+        };
+        menu = submenu;
+        line = rest;
+    }
+}
+
+/// Put as many of `candidates` in `out` as fit, and say how many.
+fn fill<'a>(out: &mut [&'a str], candidates: impl Iterator<Item = &'a str>) -> usize {
+    out.iter_mut()
+        .zip(candidates)
+        .map(|(slot, candidate)| *slot = candidate)
+        .count()
+}
+
+/// What's been typed of the option name at the end of `args`, after its
+/// `--`, if an option's name is being typed.
+pub(crate) fn option_position(args: &str) -> Option<&str> {
+    if args.is_empty() {
+        return None;
+    }
+    let (before, last) = parser::split_last_word(args);
+    if options_ended(before) {
+        return None;
+    }
+    match parser::classify(last) {
+        Token::Named(partial) => Some(partial),
+        // It may be the start of an option's name
+        Token::EndOfOptions => Some(""),
+        _ => None,
+    }
+}
+
+/// The names of the options in `parameters`, each with what's typed after
+/// it: `=` before a value, or a space.
+pub(crate) fn options<'p>(
+    parameters: &'p [Parameter<'p>],
+) -> impl Iterator<Item = (&'p str, char)> + 'p {
+    parameters.iter().filter_map(|parameter| match parameter {
+        Parameter::Named { parameter_name, .. } => Some((*parameter_name, ' ')),
+        Parameter::NamedValue { parameter_name, .. } => Some((*parameter_name, '=')),
+        _ => None,
+    })
+}
+
+/// Writes the candidates on one line, two spaces apart, up to
+/// [`MAX_LISTED`] of them.
+#[derive(Default)]
+pub(crate) struct Lister {
+    listed: usize,
+}
+
+impl Lister {
+    pub(crate) fn add(&mut self, out: &mut dyn Write, candidate: core::fmt::Arguments) {
+        let separator = if self.listed == 0 { "" } else { "  " };
+        if self.listed < MAX_LISTED {
+            write!(out, "{}{}", separator, candidate).ok();
+        } else if self.listed == MAX_LISTED {
+            write!(out, "{}...", separator).ok();
+        }
+        self.listed += 1;
+    }
+}
+
+/// Collects the candidates for the word being completed, keeping only what
+/// they all add to it.
+pub(crate) struct Candidates<'p, 'b> {
+    partial: &'p str,
+    /// What all the candidates so far add to `partial`, in the first `len`
+    /// bytes
+    common: &'b mut [u8],
+    len: usize,
+    count: usize,
+    /// The only candidate so far didn't fit in `common`
+    cut_short: bool,
+}
+
+impl<'p, 'b> Candidates<'p, 'b> {
+    pub(crate) fn new(partial: &'p str, room: &'b mut [u8]) -> Self {
+        Candidates {
+            partial,
+            common: room,
+            len: 0,
+            count: 0,
+            cut_short: false,
+        }
+    }
+
+    /// Consider one candidate. Candidates which don't start with what's been
+    /// typed are ignored.
+    pub(crate) fn add(&mut self, candidate: &str) {
+        let Some(tail) = candidate.strip_prefix(self.partial) else {
+            return;
+        };
+        self.count += 1;
+        let mut len = if self.count == 1 {
+            let len = tail.len().min(self.common.len());
+            self.cut_short = len < tail.len();
+            len
+        } else {
+            self.common[..self.len]
+                .iter()
+                .zip(tail.as_bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        while !tail.is_char_boundary(len) {
+            len -= 1;
+        }
+        if self.count == 1 {
+            self.common[..len].copy_from_slice(&tail.as_bytes()[..len]);
+        }
+        self.len = len;
+    }
+
+    /// How many candidates there were, and how many bytes they all add,
+    /// which are at the start of the room given to [`Candidates::new`].
+    pub(crate) fn result(&self) -> (usize, usize) {
+        (self.count, self.len)
+    }
+
+    /// There was only one candidate, and all of it was added.
+    pub(crate) fn is_whole(&self) -> bool {
+        self.count == 1 && !self.cut_short
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMETERS: &[Parameter] = &[
+        Parameter::mandatory("file"),
+        Parameter::optional("ssid"),
+        Parameter::named("verbose"),
+        Parameter::named_value("level", "INT"),
+    ];
+
+    #[test]
+    fn positions() {
+        let position = |args| value_position(PARAMETERS, args, false);
+        assert_eq!(position(""), None);
+        assert_eq!(position(" "), Some((&PARAMETERS[0], "")));
+        assert_eq!(position(" lo"), Some((&PARAMETERS[0], "lo")));
+        assert_eq!(
+            position(" log.txt --verbose ho"),
+            Some((&PARAMETERS[1], "ho"))
+        );
+        assert_eq!(position(" a b c"), None);
+        assert_eq!(position(" --verb"), None);
+        assert_eq!(position(" --level=4"), Some((&PARAMETERS[3], "4")));
+        assert_eq!(position(" --lev=4"), None);
+        assert_eq!(position(" --level 4"), Some((&PARAMETERS[3], "4")));
+        assert_eq!(position(" --level "), Some((&PARAMETERS[3], "")));
+        assert_eq!(position(" --level 4 lo"), Some((&PARAMETERS[0], "lo")));
+        assert_eq!(position(" -- --level="), Some((&PARAMETERS[0], "--level=")));
+        assert_eq!(position(" --"), None);
+        assert_eq!(
+            value_position(PARAMETERS, " --lev=4", true),
+            Some((&PARAMETERS[3], "4"))
+        );
+    }
+
+    #[test]
+    fn option_positions() {
+        assert_eq!(option_position(""), None);
+        assert_eq!(option_position(" --"), Some(""));
+        assert_eq!(option_position(" log.txt --ver"), Some("ver"));
+        assert_eq!(option_position(" --level="), None);
+        assert_eq!(option_position(" --verbose "), None);
+        assert_eq!(option_position(" -- --ver"), None);
+        assert_eq!(
+            options(PARAMETERS).collect::<std::vec::Vec<_>>(),
+            [("verbose", ' '), ("level", '=')]
+        );
+    }
+
+    fn noop(
+        _menu: &Menu<(), ()>,
+        _item: &crate::Item<(), ()>,
+        _args: &[&str],
+        _interface: &mut crate::CommandContext<(), ()>,
+        _context: &mut (),
+    ) {
+    }
+
+    const INNER: Menu<(), ()> = Menu::new(
+        "inner",
+        &[
+            &crate::Item::new("reset", noop, &[]),
+            &crate::Item::new("read", noop, PARAMETERS),
+        ],
+    );
+    const SETTINGS: Menu<(), ()> = Menu::new(
+        "settings",
+        &[
+            &crate::Item::new("baud", noop, &[Parameter::named("save")]),
+            &crate::Item::new_menu("inner", &INNER),
+        ],
+    );
+    const ROOT: Menu<(), ()> = Menu::new(
+        "root",
+        &[
+            &crate::Item::new("status", noop, &[]),
+            &crate::Item::new_menu("settings", &SETTINGS),
+            &crate::Item::new("secret", noop, &[]).hidden(),
+            &crate::Item::new("stop", noop, PARAMETERS),
+            &crate::Item::new("go", noop, &[]),
+        ],
+    );
+
+    fn candidates(line: &str) -> std::vec::Vec<&'static str> {
+        let mut out = [""; 8];
+        let count = complete(&ROOT, line, &mut out);
+        out[..count].to_vec()
+    }
+
+    #[test]
+    fn commands() {
+        // In the order they're declared, without hidden ones
+        assert_eq!(candidates(""), ["status", "settings", "stop", "go"]);
+        assert_eq!(candidates("  s"), ["status", "settings", "stop"]);
+        assert_eq!(candidates("st"), ["status", "stop"]);
+        assert_eq!(candidates("stop"), ["stop"]);
+        assert_eq!(candidates("sec"), [] as [&str; 0]);
+        assert_eq!(candidates("x"), [] as [&str; 0]);
+        // Case matters
+        assert_eq!(candidates("S"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn sub_menus() {
+        assert_eq!(candidates("settings "), ["baud", "inner"]);
+        assert_eq!(candidates("settings  b"), ["baud"]);
+        assert_eq!(candidates("settings inner re"), ["reset", "read"]);
+        assert_eq!(candidates("settings inner read --l"), ["level"]);
+        assert_eq!(candidates("settings nope "), [] as [&str; 0]);
+        assert_eq!(candidates("nope x"), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn options_of_a_command() {
+        assert_eq!(candidates("stop "), ["verbose", "level"]);
+        assert_eq!(candidates("stop --"), ["verbose", "level"]);
+        assert_eq!(candidates("stop log.txt --v"), ["verbose"]);
+        assert_eq!(candidates("settings baud --"), ["save"]);
+        // Values, and commands with no options, have no candidates
+        assert_eq!(candidates("stop lo"), [] as [&str; 0]);
+        assert_eq!(candidates("stop --level="), [] as [&str; 0]);
+        assert_eq!(candidates("go "), [] as [&str; 0]);
+    }
+
+    #[test]
+    fn out_is_filled() {
+        let mut out = [""; 2];
+        assert_eq!(complete(&ROOT, "", &mut out), 2);
+        assert_eq!(out, ["status", "settings"]);
+        assert_eq!(complete(&ROOT, "", &mut []), 0);
+    }
+
+    #[test]
+    fn common_part() {
+        let mut room = [0u8; 8];
+        let mut candidates = Candidates::new("lo", &mut room);
+        for c in ["log.txt", "boot.bin", "logo.png"] {
+            candidates.add(c);
+        }
+        assert_eq!(candidates.result(), (2, 1));
+        assert!(!candidates.is_whole());
+        assert_eq!(&room[..1], b"g");
+
+        // Only whole characters are added
+        let mut room = [0u8; 8];
+        let mut candidates = Candidates::new("caf", &mut room);
+        candidates.add("café");
+        candidates.add("cafè");
+        assert_eq!(candidates.result(), (2, 0));
+
+        let mut room = [0u8; 3];
+        let mut candidates = Candidates::new("", &mut room);
+        candidates.add("abcd");
+        assert_eq!(candidates.result(), (1, 3));
+        assert!(!candidates.is_whole());
+    }
+}
@@ -0,0 +1,357 @@
+//! Walks a menu tree one item at a time, for driving a menu from something
+//! other than a command line, such as a display and a rotary encoder.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::command::Keys;
+use super::menu_manager::{MenuManager, MAX_DEPTH};
+use super::output::OutputState;
+use super::{CommandContext, CommandId, ConsoleWriter, Handler, Item, ItemType, Menu, Parameter};
+
+/// What happened when an item was selected with [`MenuCursor::select`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectResult {
+    /// The item's callback was called, and didn't report a failure
+    Called,
+    /// The item's callback was called, and reported this failure with
+    /// [`CommandContext::fail`]
+    Failed(&'static str),
+    /// The item was a sub-menu, which is now the current menu
+    Entered,
+    /// The item has `Mandatory` parameters, so can't be run without
+    /// arguments
+    NeedsArguments,
+    /// The item is a `Dispatch` item with this ID, which the caller should
+    /// handle itself
+    Dispatch(CommandId),
+    /// The item is a sub-menu, but we're already `MAX_DEPTH` menus deep
+    TooDeep,
+    /// The current menu has nothing to select
+    Nothing,
+}
+
+/// Remembers which menu we're in and which item of it is selected, without
+/// reading or printing any lines.
+///
+/// Items marked with [`Item::hidden`] are skipped over. Moving past the last
+/// item goes back to the first, and the other way around.
+///
+/// Callbacks are called with no arguments, so only items without
+/// `Mandatory` parameters can be run. Apart from changing menu, anything a
+/// callback asks its [`CommandContext`] for, such as raw mode, is ignored.
+pub struct MenuCursor<'a, I, T> {
+    menu_mgr: MenuManager<'a, I, T>,
+    /// The index of the selected item in each menu we're in, outermost first
+    positions: [usize; MAX_DEPTH + 1],
+    output: OutputState,
+}
+
+impl<'a, I, T> MenuCursor<'a, I, T> {
+    /// Start at the first item of the root menu.
+    ///
+    /// The root menu's entry callbacks aren't called.
+    pub fn new(menu: Menu<'a, I, T>) -> Self {
+        let mut cursor = MenuCursor {
+            menu_mgr: MenuManager::new(menu),
+            positions: [0; MAX_DEPTH + 1],
+            output: OutputState::default(),
+        };
+        cursor.first_visible();
+        cursor
+    }
+
+    /// The menu we're in.
+    pub fn menu(&self) -> &Menu<'a, I, T> {
+        self.menu_mgr.current()
+    }
+
+    /// How many sub-menus deep we are.
+    pub fn depth(&self) -> usize {
+        self.menu_mgr.depth()
+    }
+
+    /// The index in [`MenuCursor::menu`]'s items of the selected item.
+    pub fn position(&self) -> usize {
+        self.positions[self.depth()]
+    }
+
+    /// The selected item, or `None` if the menu has no items which aren't
+    /// hidden.
+    pub fn item(&self) -> Option<&'a Item<'a, I, T>> {
+        self.menu()
+            .items
+            .get(self.position())
+            .copied()
+            .filter(|item| !item.hidden)
+    }
+
+    /// The selected item's command, for showing it.
+    pub fn command(&self) -> Option<&'a str> {
+        self.item().map(|item| item.command)
+    }
+
+    /// The selected item's help, for showing it.
+    pub fn help(&self) -> Option<&'a str> {
+        self.item().and_then(|item| item.help)
+    }
+
+    /// Select the next item.
+    pub fn move_next(&mut self) {
+        let items = self.menu().items;
+        let start = self.position();
+        let found = (1..=items.len())
+            .map(|step| (start + step) % items.len())
+            .find(|i| !items[*i].hidden);
+        if let Some(i) = found {
+            let depth = self.depth();
+            self.positions[depth] = i;
+        }
+    }
+
+    /// Select the previous item.
+    pub fn move_prev(&mut self) {
+        let items = self.menu().items;
+        let start = self.position();
+        let found = (1..=items.len())
+            .map(|step| (start + items.len() - step) % items.len())
+            .find(|i| !items[*i].hidden);
+        if let Some(i) = found {
+            let depth = self.depth();
+            self.positions[depth] = i;
+        }
+    }
+
+    /// Select the first item in the current menu which isn't hidden.
+    fn first_visible(&mut self) {
+        let depth = self.depth();
+        self.positions[depth] = first_visible(self.menu());
+    }
+}
+
+impl<I, T> MenuCursor<'_, I, T>
+where
+    I: Write,
+{
+    /// Run the selected item, or enter it if it's a sub-menu.
+    ///
+    /// Anything the callback, or a menu's entry callback, writes goes to
+    /// `interface`.
+    pub fn select(&mut self, interface: &mut I, context: &mut T) -> SelectResult {
+        let Some(item) = self.item() else {
+            return SelectResult::Nothing;
+        };
+        let (handler, parameters): (&dyn Handler<I, T>, _) = match &item.item_type {
+            ItemType::Callback {
+                function,
+                parameters,
+            } => (function, *parameters),
+            ItemType::ArgsCallback {
+                function,
+                parameters,
+            } => (function, *parameters),
+            ItemType::Handler {
+                handler,
+                parameters,
+            } => (*handler, *parameters),
+            ItemType::Dispatch { id, parameters } => {
+                if needs_arguments(parameters) {
+                    return SelectResult::NeedsArguments;
+                }
+                return SelectResult::Dispatch(*id);
+            }
+            ItemType::Menu(_) => {
+                let mut target = self.menu_mgr.clone();
+                if target.push(self.position()).is_err() {
+                    return SelectResult::TooDeep;
+                }
+                self.change_menu(target.path(), interface, context);
+                return SelectResult::Entered;
+            }
+        };
+        if needs_arguments(parameters) {
+            return SelectResult::NeedsArguments;
+        }
+        let menu = *self.menu();
+        let writer = ConsoleWriter::new(interface, &mut self.output);
+        let mut command_context = CommandContext::new(writer, &self.menu_mgr, None, Keys::NONE);
+        handler.handle(&menu, item, &[], &mut command_context, context);
+        let requests = command_context.into_requests();
+        if let Some(target) = requests.menu {
+            self.change_menu(target, interface, context);
+        }
+        match requests.failure {
+            Some(reason) => SelectResult::Failed(reason),
+            None => SelectResult::Called,
+        }
+    }
+
+    /// Go back up to the menu above, calling the current menu's exit
+    /// callback.
+    ///
+    /// Returns `false`, and does nothing, in the root menu.
+    pub fn back(&mut self, interface: &mut I, context: &mut T) -> bool {
+        if self.depth() == 0 {
+            return false;
+        }
+        let mut target = self.menu_mgr.clone();
+        target.pop();
+        self.change_menu(target.path(), interface, context);
+        true
+    }
+
+    /// Go to another menu, calling the exit and entry callbacks on the way,
+    /// just as the `Runner` does.
+    fn change_menu(
+        &mut self,
+        target: [Option<usize>; MAX_DEPTH],
+        interface: &mut I,
+        context: &mut T,
+    ) {
+        let positions = &mut self.positions;
+        self.menu_mgr
+            .change_menu(target, interface, context, |depth, index, menu| {
+                // Come back to the sub-menu's item when we leave it
+                positions[depth] = index;
+                positions[depth + 1] = first_visible(menu);
+            });
+    }
+}
+
+/// The index of the first item in `menu` which isn't hidden.
+fn first_visible<I, T>(menu: &Menu<I, T>) -> usize {
+    menu.items.iter().position(|item| !item.hidden).unwrap_or(0)
+}
+
+fn needs_arguments(parameters: &[Parameter]) -> bool {
+    parameters
+        .iter()
+        .any(|p| matches!(p, Parameter::Mandatory { .. }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    fn bump(
+        _menu: &Menu<String, u32>,
+        item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        context: &mut u32,
+    ) {
+        *context += 1;
+        writeln!(interface, "{} {}", item.command, context).unwrap();
+    }
+
+    fn broken(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        interface.fail("Broken");
+    }
+
+    fn go_home(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        interface.enter_menu("/").unwrap();
+    }
+
+    fn entered(menu: &Menu<String, u32>, interface: &mut String, _context: &mut u32) {
+        interface.push_str("> ");
+        interface.push_str(menu.label);
+        interface.push('\n');
+    }
+
+    fn left(menu: &Menu<String, u32>, interface: &mut String, _context: &mut u32) {
+        interface.push_str("< ");
+        interface.push_str(menu.label);
+        interface.push('\n');
+    }
+
+    const MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("secret", bump, &[]).hidden(),
+            &Item::new("bump", bump, &[Parameter::optional("by")]).help("Add one"),
+            &Item::new("set", bump, &[Parameter::mandatory("value")]),
+            &Item::new_dispatch("reset", 7, &[]),
+            &Item::new_menu(
+                "settings",
+                &Menu::new(
+                    "settings",
+                    &[
+                        &Item::new("broken", broken, &[]),
+                        &Item::new("home", go_home, &[]),
+                        &Item::new_menu("empty", &Menu::new("empty", &[])),
+                    ],
+                )
+                .entry(entered)
+                .exit(left),
+            ),
+        ],
+    );
+
+    #[test]
+    fn walk() {
+        let mut cursor = MenuCursor::new(MENU);
+        let mut out = String::new();
+        let mut context = 0;
+        // The hidden item is skipped
+        assert_eq!(cursor.command(), Some("bump"));
+        assert_eq!(cursor.help(), Some("Add one"));
+        assert_eq!(cursor.select(&mut out, &mut context), SelectResult::Called);
+        assert_eq!((out.as_str(), context), ("bump 1\n", 1));
+        cursor.move_next();
+        assert_eq!(
+            cursor.select(&mut out, &mut context),
+            SelectResult::NeedsArguments
+        );
+        cursor.move_next();
+        assert_eq!(
+            cursor.select(&mut out, &mut context),
+            SelectResult::Dispatch(7)
+        );
+        cursor.move_next();
+        assert_eq!(cursor.command(), Some("settings"));
+        // Wraps around, past the hidden item
+        cursor.move_next();
+        assert_eq!(cursor.command(), Some("bump"));
+        cursor.move_prev();
+        assert_eq!(cursor.command(), Some("settings"));
+
+        out.clear();
+        assert_eq!(cursor.select(&mut out, &mut context), SelectResult::Entered);
+        assert_eq!(out, "> settings\n");
+        assert_eq!((cursor.depth(), cursor.menu().label), (1, "settings"));
+        assert_eq!(
+            cursor.select(&mut out, &mut context),
+            SelectResult::Failed("Broken")
+        );
+        cursor.move_prev();
+        assert_eq!(cursor.command(), Some("empty"));
+        assert_eq!(cursor.select(&mut out, &mut context), SelectResult::Entered);
+        assert_eq!(cursor.item().map(|i| i.command), None);
+        cursor.move_next();
+        assert_eq!(cursor.select(&mut out, &mut context), SelectResult::Nothing);
+
+        // Going back comes back to the sub-menu's item
+        out.clear();
+        assert!(cursor.back(&mut out, &mut context));
+        assert_eq!(cursor.command(), Some("empty"));
+        cursor.move_prev();
+        assert_eq!(cursor.command(), Some("home"));
+        assert_eq!(cursor.select(&mut out, &mut context), SelectResult::Called);
+        assert_eq!(out, "< settings\n");
+        assert_eq!((cursor.depth(), cursor.command()), (0, Some("settings")));
+        assert!(!cursor.back(&mut out, &mut context));
+    }
+}
@@ -0,0 +1,69 @@
+//! Routes `Dispatch` items to a [`CommandHandler`], so a menu can be pure
+//! data with all of its behaviour in one `impl` block.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::menu_manager::MAX_DEPTH;
+use super::parser::{ArgumentParser, ParsedArgs};
+use super::{CommandContext, Handler, Item, ItemType, Menu};
+
+/// Identifies the command of a `Dispatch` item. See [`CommandHandler`].
+///
+/// An enum with `#[repr(u32)]` makes a good set of command IDs.
+pub type CommandId = u32;
+
+/// Handles the commands of every `Dispatch` item in a menu, usually
+/// implemented by the context.
+///
+/// Call [`Runner::enable_dispatch`](crate::Runner::enable_dispatch) to have
+/// the `Runner` route `Dispatch` items here.
+pub trait CommandHandler<I>: Sized {
+    /// Called when the command of a `Dispatch` item has been entered, once
+    /// its arguments have been checked against its parameters.
+    ///
+    /// `path` holds the command of each sub-menu we're in, outermost first,
+    /// and `cmd` is the item's ID. An `Err` is printed as an error.
+    fn dispatch(
+        &mut self,
+        path: &[&str],
+        cmd: CommandId,
+        args: &ParsedArgs,
+        interface: &mut CommandContext<I, Self>,
+    ) -> Result<(), &'static str>;
+}
+
+/// The [`Handler`] which the `Runner` calls for `Dispatch` items.
+pub(crate) struct Dispatcher;
+
+impl<I, T> Handler<I, T> for Dispatcher
+where
+    I: Write,
+    T: CommandHandler<I>,
+{
+    fn handle(
+        &self,
+        _menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &[&str],
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    ) {
+        let ItemType::Dispatch { id, parameters } = item.item_type else {
+            return;
+        };
+        // The `Runner` has already checked the arguments, perhaps allowing
+        // prefixes of option names
+        let Ok(parsed) = ArgumentParser::new(parameters)
+            .allow_prefixes(true)
+            .parse_arguments(args)
+        else {
+            return;
+        };
+        let mut path = [""; MAX_DEPTH];
+        let depth = interface.menu_path(&mut path);
+        if let Err(e) = context.dispatch(&path[..depth], id, &parsed, interface) {
+            interface.fail(e);
+        }
+    }
+}
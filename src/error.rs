@@ -0,0 +1,144 @@
+//! The errors the API can give.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::parser::ArgError;
+use super::regmap::BusError;
+
+/// Describes the ways in which the API can fail.
+///
+/// More kinds of error may be added, so match with a `_` arm. Each kind has
+/// a number, from [`MenuError::code`], for host tools which only see a
+/// number. The numbers never change, and aren't reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MenuError<'a> {
+    /// Tried to find arguments on an item that was a sub-menu. Code 1.
+    NotACallbackItem,
+    /// The argument, item or menu you asked for was not found. Code 2.
+    NotFound,
+    /// Tried to go more than `menu_manager::MAX_DEPTH` sub-menus deep. Code 3.
+    TooDeep,
+    /// The arguments didn't suit the item's parameters. Code 4.
+    Arguments(ArgError<'a>),
+    /// A register couldn't be read or written. Code 5.
+    Bus(BusError),
+    /// The output couldn't be written. Code 6.
+    Write,
+    /// There wasn't room in a buffer for something. Code 7.
+    TooLong,
+}
+
+impl MenuError<'_> {
+    /// The number of this kind of error. See [`MenuError`].
+    pub const fn code(&self) -> u8 {
+        match self {
+            MenuError::NotACallbackItem => 1,
+            MenuError::NotFound => 2,
+            MenuError::TooDeep => 3,
+            MenuError::Arguments(_) => 4,
+            MenuError::Bus(_) => 5,
+            MenuError::Write => 6,
+            MenuError::TooLong => 7,
+        }
+    }
+
+    /// Write the error as a message for the user, like `Not found`.
+    pub fn write_to(&self, w: &mut dyn Write) -> core::fmt::Result {
+        match self {
+            MenuError::NotACallbackItem => w.write_str("That's a menu, not a command"),
+            MenuError::NotFound => w.write_str("Not found"),
+            MenuError::TooDeep => w.write_str("Menus are nested too deep"),
+            MenuError::Arguments(e) => write!(w, "{}", e),
+            MenuError::Bus(BusError) => w.write_str("Bus error"),
+            MenuError::Write => w.write_str("Couldn't write output"),
+            MenuError::TooLong => w.write_str("Too long to fit"),
+        }
+    }
+}
+
+impl core::fmt::Display for MenuError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.write_to(f)
+    }
+}
+
+impl<'a> From<ArgError<'a>> for MenuError<'a> {
+    fn from(e: ArgError<'a>) -> Self {
+        MenuError::Arguments(e)
+    }
+}
+
+impl From<BusError> for MenuError<'_> {
+    fn from(e: BusError) -> Self {
+        MenuError::Bus(e)
+    }
+}
+
+impl From<core::fmt::Error> for MenuError<'_> {
+    fn from(_: core::fmt::Error) -> Self {
+        MenuError::Write
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parameter;
+    use std::string::{String, ToString};
+
+    fn message(e: MenuError) -> String {
+        let mut s = String::new();
+        e.write_to(&mut s).unwrap();
+        assert_eq!(s, e.to_string());
+        s
+    }
+
+    #[test]
+    fn conversions() {
+        const PARAMETERS: &[Parameter] = &[Parameter::named("verbose")];
+        let e = MenuError::from(ArgError::UnknownOption("--x", PARAMETERS));
+        assert_eq!(
+            e,
+            MenuError::Arguments(ArgError::UnknownOption("--x", PARAMETERS))
+        );
+        assert_eq!(e.code(), 4);
+        assert_eq!(
+            message(e),
+            "Did not understand \"--x\". Options are: --verbose"
+        );
+
+        let e = MenuError::from(BusError);
+        assert_eq!(e.code(), 5);
+        assert_eq!(message(e), "Bus error");
+
+        let e = MenuError::from(core::fmt::Error);
+        assert_eq!(e.code(), 6);
+        assert_eq!(message(e), "Couldn't write output");
+
+        let codes = [
+            MenuError::NotACallbackItem,
+            MenuError::NotFound,
+            MenuError::TooDeep,
+            MenuError::TooLong,
+        ]
+        .map(|e| (e.code(), message(e)));
+        assert_eq!(
+            codes,
+            [
+                (1, "That's a menu, not a command".into()),
+                (2, "Not found".into()),
+                (3, "Menus are nested too deep".into()),
+                (7, "Too long to fit".into()),
+            ]
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn old_name() {
+        let e: crate::Error = MenuError::NotFound;
+        assert_eq!(e, MenuError::NotFound);
+    }
+}
@@ -0,0 +1,382 @@
+//! Comparing two menu trees, such as the ones in two versions of some
+//! firmware, to catch commands which were removed or changed by accident.
+//!
+//! Only available with the `std` feature.
+//!
+//! ```rust
+//! # use menu::*;
+//! # use menu::export::{diff, DiffEntry};
+//! fn noop(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+//! const OLD: Menu<(), ()> = Menu::new("root", &[&Item::new("reset", noop, &[])]);
+//! const NEW: Menu<(), ()> = Menu::new("root", &[&Item::new("reboot", noop, &[])]);
+//! assert_eq!(
+//!     diff(&OLD, &NEW),
+//!     [
+//!         DiffEntry::Added("reboot".into()),
+//!         DiffEntry::Removed("reset".into()),
+//!     ]
+//! );
+//! ```
+#![deny(missing_docs)]
+
+use super::{Item, Menu, Parameter};
+use std::{format, string::String, vec::Vec};
+
+/// One difference between two menu trees, found by [`diff`].
+///
+/// Each names an item by its path, which is the commands to type to reach
+/// it, separated by `/`, like `settings/baud`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffEntry {
+    /// The item is only in the new tree. A new sub-menu's items aren't
+    /// listed too.
+    Added(String),
+    /// The item is only in the old tree. A removed sub-menu's items aren't
+    /// listed too.
+    Removed(String),
+    /// The item was a command and is now a sub-menu, or the other way round
+    KindChanged(String),
+    /// The command takes different parameters. Each is written as `help`
+    /// would, like `<file> [ -f, --force ]`, but with the names
+    /// [`argument_finder`](crate::argument_finder) looks them up by, and
+    /// any default and choices, like `[ --baud=RATE (default: 9600) ]`.
+    ParametersChanged {
+        /// Where the item is
+        path: String,
+        /// The old parameters
+        old: String,
+        /// The new parameters
+        new: String,
+    },
+    /// The help text changed, for the item or any parameter in both trees,
+    /// including the names `help` shows for them
+    HelpChanged(String),
+}
+
+impl DiffEntry {
+    /// Where the item is.
+    pub fn path(&self) -> &str {
+        match self {
+            DiffEntry::Added(path)
+            | DiffEntry::Removed(path)
+            | DiffEntry::KindChanged(path)
+            | DiffEntry::ParametersChanged { path, .. }
+            | DiffEntry::HelpChanged(path) => path,
+        }
+    }
+}
+
+/// Find the differences between two menu trees.
+///
+/// Only what's typed and what `help` shows are compared, so the menus can
+/// have different interface and context types. Items are matched up by
+/// their commands, so moving an item within a menu isn't a change.
+///
+/// The entries are sorted by path, so the same trees always give the same
+/// list. An item whose parameters and help both changed has both entries,
+/// parameters first.
+pub fn diff<I, T, J, U>(old: &Menu<I, T>, new: &Menu<J, U>) -> Vec<DiffEntry> {
+    let mut entries = Vec::new();
+    diff_menus(&mut entries, "", old, new);
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    entries
+}
+
+fn diff_menus<I, T, J, U>(
+    entries: &mut Vec<DiffEntry>,
+    prefix: &str,
+    old: &Menu<I, T>,
+    new: &Menu<J, U>,
+) {
+    for old_item in old.items {
+        let path = format!("{}{}", prefix, old_item.command);
+        match new
+            .items
+            .iter()
+            .find(|item| item.command == old_item.command)
+        {
+            Some(new_item) => diff_items(entries, path, old_item, new_item),
+            None => entries.push(DiffEntry::Removed(path)),
+        }
+    }
+    for new_item in new.items {
+        if !old
+            .items
+            .iter()
+            .any(|item| item.command == new_item.command)
+        {
+            entries.push(DiffEntry::Added(format!("{}{}", prefix, new_item.command)));
+        }
+    }
+}
+
+fn diff_items<I, T, J, U>(
+    entries: &mut Vec<DiffEntry>,
+    path: String,
+    old: &Item<I, T>,
+    new: &Item<J, U>,
+) {
+    match (old.submenu(), new.submenu()) {
+        (Some(old_menu), Some(new_menu)) => {
+            if old.help != new.help {
+                entries.push(DiffEntry::HelpChanged(path.clone()));
+            }
+            diff_menus(entries, &format!("{}/", path), old_menu, new_menu);
+        }
+        (None, None) => {
+            let (old_signature, new_signature) =
+                (signature(old.parameters()), signature(new.parameters()));
+            // Parameters are matched up by name, so adding one doesn't
+            // change the help of the ones after it
+            let help_changed = old.help != new.help
+                || old.parameters().iter().any(|old_parameter| {
+                    new.parameters()
+                        .iter()
+                        .find(|new_parameter| new_parameter.name() == old_parameter.name())
+                        .is_some_and(|new_parameter| {
+                            help_of(new_parameter) != help_of(old_parameter)
+                        })
+                });
+            if old_signature != new_signature {
+                entries.push(DiffEntry::ParametersChanged {
+                    path: path.clone(),
+                    old: old_signature,
+                    new: new_signature,
+                });
+            }
+            if help_changed {
+                entries.push(DiffEntry::HelpChanged(path));
+            }
+        }
+        _ => entries.push(DiffEntry::KindChanged(path)),
+    }
+}
+
+/// The parameters as they're typed, and what they default to.
+fn signature(parameters: &[Parameter]) -> String {
+    let words: Vec<String> = parameters
+        .iter()
+        .map(|parameter| {
+            let mut word = match parameter {
+                Parameter::Mandatory { parameter_name, .. } => format!("<{}>", parameter_name),
+                Parameter::Optional { parameter_name, .. } => format!("[ <{}>", parameter_name),
+                Parameter::Named { parameter_name, .. } => format!("[ --{}", parameter_name),
+                Parameter::NamedValue {
+                    parameter_name,
+                    argument_name,
+                    ..
+                } => format!("[ --{}={}", parameter_name, argument_name),
+            };
+            if let Some(short) = parameter.short_name() {
+                word.insert_str(2, &format!("-{}, ", short));
+            }
+            if let Some(default) = parameter.default_value() {
+                word += &format!(" (default: {})", default);
+            }
+            if let Some(allowed) = parameter.allowed() {
+                word += &format!(" (choices: {})", allowed.join(" "));
+            }
+            if !matches!(parameter, Parameter::Mandatory { .. }) {
+                word += " ]";
+            }
+            word
+        })
+        .collect();
+    words.join(" ")
+}
+
+/// What `help` shows for a parameter, apart from its signature.
+fn help_of<'a>(parameter: &Parameter<'a>) -> (Option<&'a str>, &'a str) {
+    let help = match parameter {
+        Parameter::Mandatory { help, .. }
+        | Parameter::Optional { help, .. }
+        | Parameter::Named { help, .. }
+        | Parameter::NamedValue { help, .. } => *help,
+    };
+    (help, parameter.display_name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommandContext, Item};
+
+    fn noop<I, T>(
+        _menu: &Menu<I, T>,
+        _item: &Item<I, T>,
+        _args: &[&str],
+        _interface: &mut CommandContext<I, T>,
+        _context: &mut T,
+    ) {
+    }
+
+    const OLD: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("status", noop, &[]).help("Show the status"),
+            &Item::new(
+                "copy",
+                noop,
+                &[
+                    Parameter::mandatory("from"),
+                    Parameter::mandatory("to"),
+                    Parameter::named("force"),
+                ],
+            ),
+            &Item::new("reset", noop, &[]),
+            &Item::new_menu(
+                "settings",
+                &Menu::new(
+                    "settings",
+                    &[
+                        &Item::new("baud", noop, &[Parameter::mandatory("rate")]),
+                        &Item::new("name", noop, &[Parameter::mandatory("name")]),
+                    ],
+                ),
+            ),
+            &Item::new("tools", noop, &[]),
+        ],
+    );
+
+    /// Every kind of change to `OLD`, with a different context type
+    const NEW: Menu<String, ()> = Menu::new(
+        "root",
+        &[
+            // Moved, and the help changed
+            &Item::new_menu(
+                "settings",
+                &Menu::new(
+                    "settings",
+                    &[
+                        &Item::new("name", noop, &[Parameter::mandatory("name")]),
+                        // Only a parameter's help changed
+                        &Item::new(
+                            "baud",
+                            noop,
+                            &[Parameter::mandatory("rate").help("Bits per second")],
+                        ),
+                        &Item::new("parity", noop, &[]),
+                    ],
+                ),
+            )
+            .help("Change the settings"),
+            &Item::new("status", noop, &[]).help("Show the status"),
+            // Optional now, and a new option
+            &Item::new(
+                "copy",
+                noop,
+                &[
+                    Parameter::mandatory("from"),
+                    Parameter::optional("to").display("dest"),
+                    Parameter::named("force"),
+                    Parameter::named_value("retries", "N"),
+                ],
+            ),
+            &Item::new_menu("tools", &Menu::new("tools", &[])),
+            &Item::new("reboot", noop, &[]),
+        ],
+    );
+
+    #[test]
+    fn every_change() {
+        assert_eq!(
+            diff(&OLD, &NEW),
+            [
+                DiffEntry::ParametersChanged {
+                    path: "copy".into(),
+                    old: "<from> <to> [ --force ]".into(),
+                    new: "<from> [ <to> ] [ --force ] [ --retries=N ]".into(),
+                },
+                DiffEntry::HelpChanged("copy".into()),
+                DiffEntry::Added("reboot".into()),
+                DiffEntry::Removed("reset".into()),
+                DiffEntry::HelpChanged("settings".into()),
+                DiffEntry::HelpChanged("settings/baud".into()),
+                DiffEntry::Added("settings/parity".into()),
+                DiffEntry::KindChanged("tools".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parameters_matched_by_name() {
+        const BEFORE: Menu<(), ()> = Menu::new(
+            "root",
+            &[&Item::new(
+                "send",
+                noop,
+                &[Parameter::mandatory("data").help("What to send")],
+            )],
+        );
+        const AFTER: Menu<(), ()> = Menu::new(
+            "root",
+            &[&Item::new(
+                "send",
+                noop,
+                &[
+                    Parameter::mandatory("port"),
+                    Parameter::mandatory("data").help("What to send"),
+                ],
+            )],
+        );
+        // Only the parameters changed, as `data` has the same help
+        assert_eq!(
+            diff(&BEFORE, &AFTER),
+            [DiffEntry::ParametersChanged {
+                path: "send".into(),
+                old: "<data>".into(),
+                new: "<port> <data>".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn short_default_and_choices() {
+        const BEFORE: Menu<(), ()> = Menu::new(
+            "root",
+            &[&Item::new(
+                "open",
+                noop,
+                &[
+                    Parameter::optional("port"),
+                    Parameter::named("force"),
+                    Parameter::named_value("baud", "RATE").allowed_values(&["9600", "115200"]),
+                ],
+            )],
+        );
+        const AFTER: Menu<(), ()> = Menu::new(
+            "root",
+            &[&Item::new(
+                "open",
+                noop,
+                &[
+                    Parameter::optional("port").default("/dev/ttyS0"),
+                    Parameter::named("force").short('f'),
+                    Parameter::named_value("baud", "RATE")
+                        .default("9600")
+                        .allowed_values(&["9600"]),
+                ],
+            )],
+        );
+        assert_eq!(
+            diff(&BEFORE, &AFTER),
+            [DiffEntry::ParametersChanged {
+                path: "open".into(),
+                old: "[ <port> ] [ --force ] [ --baud=RATE (choices: 9600 115200) ]".into(),
+                new: "[ <port> (default: /dev/ttyS0) ] [ -f, --force ] \
+                      [ --baud=RATE (default: 9600) (choices: 9600) ]"
+                    .into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn no_change() {
+        assert_eq!(diff(&OLD, &OLD), []);
+        assert_eq!(diff(&NEW, &NEW), []);
+        // The other way round, what was added was removed
+        let backwards = diff(&NEW, &OLD);
+        assert!(backwards.contains(&DiffEntry::Removed("settings/parity".into())));
+        assert!(backwards.contains(&DiffEntry::Added("reset".into())));
+    }
+}
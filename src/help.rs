@@ -0,0 +1,468 @@
+//! Renders help text, both for the `help` command and for documentation.
+
+use core::fmt::{Result, Write};
+
+use super::output::write_heading;
+use super::regmap::RegisterDesc;
+use super::{menu_manager, Item, Menu, Parameter};
+
+/// Options for [`render_help`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HelpRenderOptions {
+    include_hidden: bool,
+    wrap_width: Option<usize>,
+}
+
+impl HelpRenderOptions {
+    /// The same help a user would see: no hidden items, and no wrapping.
+    pub const fn new() -> Self {
+        HelpRenderOptions {
+            include_hidden: false,
+            wrap_width: None,
+        }
+    }
+
+    /// Include items marked with [`Item::hidden`].
+    pub const fn include_hidden(mut self, include_hidden: bool) -> Self {
+        self.include_hidden = include_hidden;
+        self
+    }
+
+    /// Wrap help text to fit within `width` columns, where possible.
+    pub const fn wrap_width(mut self, width: usize) -> Self {
+        self.wrap_width = Some(width);
+        self
+    }
+}
+
+/// Writes the help for a whole menu tree, without needing a `Runner`.
+///
+/// This is exactly what a user would see if they typed `help`, then `help
+/// <command>` for each item, in every menu. Each of those is preceded by the
+/// prompt and command, like this:
+///
+/// ```text
+/// > help
+/// AVAILABLE ITEMS:
+///   foo <a>
+///   sub
+///   help [ <command> ]
+///
+/// > help foo
+/// SUMMARY:
+///   foo <a>
+/// ...
+/// /sub> help
+/// ...
+/// ```
+pub fn render_help<I, T>(
+    menu: &Menu<I, T>,
+    out: &mut impl Write,
+    opts: &HelpRenderOptions,
+) -> Result {
+    render_menu(menu, None, 0, out, opts)
+}
+
+/// A list of menu labels, for printing prompts.
+struct Path<'p> {
+    label: &'p str,
+    parent: Option<&'p Path<'p>>,
+}
+
+impl core::fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result {
+        if let Some(parent) = self.parent {
+            write!(f, "{}", parent)?;
+        }
+        write!(f, "/{}", self.label)
+    }
+}
+
+/// Displays a command as an OSC 8 hyperlink, to the URL template with each
+/// `{}` replaced by the command.
+struct Link<'l>(&'l str, &'l str);
+
+impl core::fmt::Display for Link<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result {
+        let Link(template, command) = self;
+        f.write_str("\x1b]8;;")?;
+        let mut parts = template.split("{}");
+        if let Some(first) = parts.next() {
+            f.write_str(first)?;
+        }
+        for part in parts {
+            write!(f, "{}{}", command, part)?;
+        }
+        write!(f, "\x1b\\{}\x1b]8;;\x1b\\", command)
+    }
+}
+
+fn render_menu<I, T>(
+    menu: &Menu<I, T>,
+    path: Option<&Path>,
+    depth: usize,
+    out: &mut impl Write,
+    opts: &HelpRenderOptions,
+) -> Result {
+    let prompt = |out: &mut dyn Write| match path {
+        Some(path) => write!(out, "{}> help", path),
+        None => write!(out, "> help"),
+    };
+    let visible = |item: &&&Item<I, T>| opts.include_hidden || !item.hidden;
+    prompt(out)?;
+    writeln!(out)?;
+    write_item_list(
+        out,
+        menu.items.iter().filter(visible),
+        depth != 0,
+        &Builtins::default(),
+        None,
+        false,
+    )?;
+    for item in menu.items.iter().filter(visible) {
+        writeln!(out)?;
+        prompt(out)?;
+        writeln!(out, " {}", item.command)?;
+        write_long_help(out, item, opts.wrap_width, false)?;
+    }
+    if depth < menu_manager::MAX_DEPTH {
+        for item in menu.items.iter().filter(visible) {
+            if let Some(sub_menu) = item.submenu() {
+                let sub_path = Path {
+                    label: sub_menu.label,
+                    parent: path,
+                };
+                writeln!(out)?;
+                render_menu(sub_menu, Some(&sub_path), depth + 1, out, opts)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The commands the `Runner` has set up, beyond `help` and `exit`, for the
+/// list shown by `help`.
+#[derive(Default)]
+pub(crate) struct Builtins<'b> {
+    /// The `about` command, unless it's hidden
+    pub(crate) about: Option<&'b str>,
+    /// `history`, if there's a history buffer
+    pub(crate) history: bool,
+    /// `show`, if it's been enabled and no item has the same command
+    pub(crate) show: bool,
+    /// `record`, `stop` and `play`, if there are macro buffers. Each is
+    /// left out if an item has the same command.
+    pub(crate) record: bool,
+    pub(crate) stop: bool,
+    pub(crate) play: bool,
+}
+
+/// Writes the list of items, as shown by `help`, followed by the `builtins`,
+/// with each item's command linked to `links` if given, and the heading in
+/// bold if `colour`. See
+/// [`Runner::set_help_links`](crate::Runner::set_help_links).
+pub(crate) fn write_item_list<'i, I, T>(
+    out: &mut impl Write,
+    items: impl Iterator<Item = &'i &'i Item<'i, I, T>>,
+    in_sub_menu: bool,
+    builtins: &Builtins,
+    links: Option<&str>,
+    colour: bool,
+) -> Result
+where
+    I: 'i,
+    T: 'i,
+{
+    write_heading(out, colour, "AVAILABLE ITEMS")?;
+    for item in items {
+        write_short_help(out, item, links)?;
+    }
+    if in_sub_menu {
+        writeln!(out, "  exit")?;
+    }
+    if let Some(about) = builtins.about {
+        writeln!(out, "  {}", about)?;
+    }
+    if builtins.history {
+        writeln!(out, "  history [ clear ]")?;
+    }
+    if builtins.show {
+        writeln!(out, "  show")?;
+    }
+    if builtins.record {
+        writeln!(out, "  record <slot>")?;
+    }
+    if builtins.stop {
+        writeln!(out, "  stop")?;
+    }
+    if builtins.play {
+        writeln!(out, "  play <slot>")?;
+    }
+    writeln!(out, "  help [ <command> ]")
+}
+
+/// Writes one line about an item, for the list shown by `help`.
+pub(crate) fn write_short_help<I, T>(
+    out: &mut impl Write,
+    item: &Item<I, T>,
+    links: Option<&str>,
+) -> Result {
+    let mut has_options = false;
+    match links {
+        Some(template) => write!(out, "  {}", Link(template, item.command))?,
+        None => write!(out, "  {}", item.command)?,
+    }
+    for param in item.parameters() {
+        match param {
+            Parameter::Mandatory { .. } => {
+                write!(out, " <{}>", param.display_name())?;
+            }
+            Parameter::Optional { .. } => {
+                write!(out, " [ <{}> ]", param.display_name())?;
+            }
+            Parameter::Named { .. } | Parameter::NamedValue { .. } => {
+                has_options = true;
+            }
+        }
+    }
+    if has_options {
+        write!(out, " [OPTIONS...]")?;
+    }
+    writeln!(out)
+}
+
+/// Writes everything about an item, as shown by `help <command>`, with the
+/// headings in bold if `colour`.
+pub(crate) fn write_long_help<I, T>(
+    out: &mut impl Write,
+    item: &Item<I, T>,
+    wrap_width: Option<usize>,
+    colour: bool,
+) -> Result {
+    write_heading(out, colour, "SUMMARY")?;
+    write!(out, "  {}", item.command)?;
+    let mut at_line_start = false;
+    let parameters = item.parameters();
+    if !parameters.is_empty() {
+        at_line_start = true;
+        for param in parameters.iter() {
+            match param {
+                Parameter::Mandatory { .. } => {
+                    write!(out, " <{}>", param.display_name())?;
+                }
+                Parameter::Optional { .. } => {
+                    write!(out, " [ <{}> ]", param.display_name())?;
+                }
+                Parameter::Named { parameter_name, .. } => {
+                    write!(out, " [ --{} ]", parameter_name)?;
+                }
+                Parameter::NamedValue {
+                    parameter_name,
+                    argument_name,
+                    ..
+                } => {
+                    write!(out, " [ --{}={} ]", parameter_name, argument_name)?;
+                }
+            }
+        }
+        write!(out, "\n\n")?;
+        write_heading(out, colour, "PARAMETERS")?;
+        let default_help = "Undocumented option";
+        for param in parameters.iter() {
+            let help = match param {
+                Parameter::Mandatory { help, .. } | Parameter::Optional { help, .. } => {
+                    writeln!(out, "  <{}>", param.display_name())?;
+                    help
+                }
+                Parameter::Named {
+                    parameter_name,
+                    help,
+                    short,
+                } => {
+                    write_short(out, *short)?;
+                    writeln!(out, "--{}", parameter_name)?;
+                    help
+                }
+                Parameter::NamedValue {
+                    parameter_name,
+                    argument_name,
+                    help,
+                    short,
+                    ..
+                } => {
+                    write_short(out, *short)?;
+                    writeln!(out, "--{}={}", parameter_name, argument_name)?;
+                    help
+                }
+            };
+            write_wrapped(out, help.unwrap_or(default_help), "    ", wrap_width)?;
+            if let Some(default) = param.default_value() {
+                write!(out, " (default: {})", default)?;
+            }
+            if let Some(allowed) = param.allowed() {
+                write!(out, "\n    Choices:")?;
+                for choice in allowed {
+                    write!(out, " {}", choice)?;
+                }
+            }
+            writeln!(out, "\n")?;
+        }
+    }
+    if let Some(register) = item.data_as::<RegisterDesc>() {
+        write!(out, "\n\n")?;
+        write_heading(out, colour, "REGISTER")?;
+        write!(out, "  {}", register)?;
+        at_line_start = false;
+    }
+    if let Some(help) = item.help {
+        write!(out, "\n\n")?;
+        write_heading(out, colour, "DESCRIPTION")?;
+        write_wrapped(out, help, "", wrap_width)?;
+        writeln!(out)?;
+    } else if !at_line_start {
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// Writes the indent before an option in the list of parameters, and its
+/// short form if it has one, like `-v, `.
+fn write_short(out: &mut impl Write, short: Option<char>) -> Result {
+    match short {
+        Some(short) => write!(out, "  -{}, ", short),
+        None => write!(out, "  "),
+    }
+}
+
+/// Writes some text with every line indented, breaking lines between words
+/// to fit in `width` columns if given.
+///
+/// There is no newline after the last line.
+fn write_wrapped(out: &mut impl Write, text: &str, indent: &str, width: Option<usize>) -> Result {
+    for (i, line) in text.split('\n').enumerate() {
+        if i != 0 {
+            writeln!(out)?;
+        }
+        let Some(width) = width else {
+            write!(out, "{}{}", indent, line)?;
+            continue;
+        };
+        write!(out, "{}", indent)?;
+        let mut column = indent.chars().count();
+        let mut first = true;
+        for word in line.split(' ').filter(|w| !w.is_empty()) {
+            let len = word.chars().count();
+            if !first && column + 1 + len > width {
+                write!(out, "\n{}", indent)?;
+                column = indent.chars().count();
+                first = true;
+            }
+            if !first {
+                write!(out, " ")?;
+                column += 1;
+            }
+            write!(out, "{}", word)?;
+            column += len;
+            first = false;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    fn wrapped(text: &str, indent: &str, width: Option<usize>) -> String {
+        let mut s = String::new();
+        write_wrapped(&mut s, text, indent, width).unwrap();
+        s
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(wrapped("a b\nc", "  ", None), "  a b\n  c");
+        assert_eq!(
+            wrapped("the quick brown fox", "  ", Some(12)),
+            "  the quick\n  brown fox"
+        );
+        assert_eq!(
+            wrapped("antidisestablishment", "", Some(5)),
+            "antidisestablishment"
+        );
+        assert_eq!(wrapped("one\n\ntwo", "", Some(5)), "one\n\ntwo");
+    }
+
+    #[test]
+    fn display_names() {
+        const PARAMETERS: &[Parameter] = &[
+            Parameter::mandatory("ip")
+                .display("ip-address")
+                .help("Where to"),
+            Parameter::optional("n").help("How many").display("count"),
+            Parameter::named("quiet").display("ignored"),
+            Parameter::named_value("ttl", "HOPS").short('t'),
+        ];
+        const _: () = assert!(crate::assert_unique_parameters(PARAMETERS));
+        let item: Item<(), ()> = Item::new("ping", |_, _, _, _, _| {}, PARAMETERS);
+
+        let mut short = String::new();
+        write_short_help(&mut short, &item, None).unwrap();
+        assert_eq!(short, "  ping <ip-address> [ <count> ] [OPTIONS...]\n");
+        assert!(!crate::assert_unique_parameters(&[
+            Parameter::named("quiet").short('q'),
+            Parameter::named("quick").short('q'),
+        ]));
+        let mut long = String::new();
+        write_long_help(&mut long, &item, None, false).unwrap();
+        assert!(long.starts_with(
+            "SUMMARY:\n  ping <ip-address> [ <count> ] [ --quiet ] [ --ttl=HOPS ]\n\n\
+             PARAMETERS:\n  <ip-address>\n    Where to\n\n  <count>\n    How many\n\n  --quiet\n"
+        ));
+        assert!(long.ends_with("  -t, --ttl=HOPS\n    Undocumented option\n\n"));
+
+        // Arguments are still found by the parameter's name
+        let args = ["10.0.0.1", "3"];
+        assert_eq!(
+            crate::argument_finder(&item, &args, "ip"),
+            Ok(Some("10.0.0.1"))
+        );
+        assert_eq!(crate::argument_finder(&item, &args, "n"), Ok(Some("3")));
+        assert!(crate::argument_finder(&item, &args, "count").is_err());
+    }
+
+    #[test]
+    fn defaults() {
+        const PARAMETERS: &[Parameter] = &[
+            Parameter::optional("port").default("/dev/ttyS0"),
+            Parameter::named_value("rate", "BAUD")
+                .help("Bits per second")
+                .default("115200")
+                .allowed_values(&["9600", "115200"]),
+        ];
+        let item: Item<(), ()> = Item::new("serial", |_, _, _, _, _| {}, PARAMETERS);
+        let mut long = String::new();
+        write_long_help(&mut long, &item, None, false).unwrap();
+        assert!(long.ends_with(
+            "  <port>\n    Undocumented option (default: /dev/ttyS0)\n\n\
+             \x20 --rate=BAUD\n    Bits per second (default: 115200)\n    Choices: 9600 115200\n\n"
+        ));
+
+        let find = crate::argument_finder;
+        assert_eq!(find(&item, &[], "rate"), Ok(Some("115200")));
+        assert_eq!(find(&item, &[], "port"), Ok(Some("/dev/ttyS0")));
+        let args = ["--rate=9600", "COM1"];
+        assert_eq!(find(&item, &args, "rate"), Ok(Some("9600")));
+        assert_eq!(find(&item, &args, "port"), Ok(Some("COM1")));
+        // Given but empty isn't the same as not given
+        assert_eq!(find(&item, &["--rate="], "rate"), Ok(Some("")));
+        assert_eq!(crate::argument_count(&item, &[], "rate"), Ok(0));
+        let parser = crate::parser::ArgumentParser::new(PARAMETERS);
+        assert!(parser.parse_arguments(&["--rate", "9600"]).is_ok());
+        assert_eq!(
+            parser.parse_arguments(&["--rate=300"]).unwrap_err(),
+            crate::parser::ArgError::NotAllowed("rate", "300", &["9600", "115200"])
+        );
+    }
+}
@@ -0,0 +1,274 @@
+//! Remembers the command lines typed into the `Runner`.
+#![deny(missing_docs)]
+
+use super::storage::{Buffer, Bytes};
+
+/// A search of the history, after Ctrl-R. What's being looked for is kept
+/// in the line buffer, after the line being typed.
+#[cfg(feature = "ansi")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Search {
+    /// How long what's being looked for is, in bytes
+    pub(crate) len: usize,
+    /// The number of the line found, if one has been
+    pub(crate) found: Option<usize>,
+    /// Nothing matches what's being looked for, apart from lines newer than
+    /// the one found
+    pub(crate) failed: bool,
+}
+
+/// Command lines kept one after another in a buffer, each ending with a NUL.
+///
+/// When the buffer is full, the oldest lines are dropped to make room, unless
+/// it can grow. Lines are numbered from 1 and keep their number until they are
+/// dropped.
+///
+/// While lines are being recalled with [`History::older`] and
+/// [`History::newer`], the line that was being typed is kept after the last
+/// NUL, if there's room, so it can be given back.
+pub(crate) struct History<'a> {
+    buffer: Buffer<'a>,
+    used: usize,
+    /// The number of the oldest line we still have
+    first: usize,
+    /// The number of the line being recalled, if one is
+    recalled: Option<usize>,
+    /// How long the line that was being typed is
+    draft: usize,
+}
+
+impl<'a> History<'a> {
+    pub(crate) fn new(buffer: impl Into<Buffer<'a>>) -> Self {
+        History {
+            buffer: buffer.into(),
+            used: 0,
+            first: 1,
+            recalled: None,
+            draft: 0,
+        }
+    }
+
+    /// Remember a line, unless it's blank or could never fit.
+    pub(crate) fn push(&mut self, line: &str) {
+        self.stop_recalling();
+        if super::parser::tokenize(line).next().is_none() || line.contains('\0') {
+            return;
+        }
+        if !self.buffer.make_room(self.used + line.len() + 1) {
+            let room = self.buffer.bytes().len();
+            if line.len() >= room {
+                return;
+            }
+            while self.used + line.len() + 1 > room {
+                self.drop_oldest();
+            }
+        }
+        let buffer = self.buffer.bytes_mut();
+        buffer[self.used..self.used + line.len()].copy_from_slice(line.as_bytes());
+        buffer[self.used + line.len()] = 0;
+        self.used += line.len() + 1;
+    }
+
+    fn drop_oldest(&mut self) {
+        let buffer = self.buffer.bytes_mut();
+        let Some(end) = buffer[..self.used].iter().position(|b| *b == 0) else {
+            self.used = 0;
+            return;
+        };
+        buffer.copy_within(end + 1..self.used, 0);
+        self.used -= end + 1;
+        self.first += 1;
+    }
+
+    /// Forget everything, and start numbering from 1 again.
+    pub(crate) fn clear(&mut self) {
+        self.used = 0;
+        self.first = 1;
+        self.stop_recalling();
+    }
+
+    /// How many lines we have.
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.bytes()[..self.used]
+            .iter()
+            .filter(|b| **b == 0)
+            .count()
+    }
+
+    /// Every line we have with its number, oldest first.
+    pub(crate) fn entries(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.buffer.bytes()[..self.used]
+            .split(|b| *b == 0)
+            // There's an empty slice after the last NUL
+            .take(self.len())
+            .map(|line| core::str::from_utf8(line).unwrap_or(""))
+            .enumerate()
+            .map(|(i, line)| (self.first + i, line))
+    }
+
+    /// The line with the given number, if we still have it.
+    pub(crate) fn get(&self, number: usize) -> Option<&str> {
+        self.entries()
+            .find(|(n, _)| *n == number)
+            .map(|(_, line)| line)
+    }
+
+    /// The number of the newest line containing `text`, and no newer than
+    /// line `from` if that's given.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn find(&self, text: &str, from: Option<usize>) -> Option<usize> {
+        let from = from.unwrap_or(usize::MAX);
+        self.entries()
+            .filter(|(n, line)| *n <= from && line.contains(text))
+            .last()
+            .map(|(n, _)| n)
+    }
+
+    /// The line before the one being recalled, or the newest line if none
+    /// is. `typed` is what's on the command line, which is kept when we
+    /// start recalling.
+    ///
+    /// Returns `None`, and changes nothing, if there's no older line.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn older(&mut self, typed: &str) -> Option<&str> {
+        let number = match self.recalled {
+            Some(number) if number > self.first => number - 1,
+            Some(_) => return None,
+            None if self.used == 0 => return None,
+            None => {
+                self.keep_draft(typed);
+                self.first + self.len() - 1
+            }
+        };
+        self.recalled = Some(number);
+        self.get(number)
+    }
+
+    /// The line after the one being recalled. After the newest line, gives
+    /// back the line that was being typed and stops recalling.
+    ///
+    /// Returns `None` if no line is being recalled.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn newer(&mut self) -> Option<&str> {
+        let number = self.recalled? + 1;
+        if number < self.first + self.len() {
+            self.recalled = Some(number);
+            return self.get(number);
+        }
+        self.recalled = None;
+        let draft = &self.buffer.bytes()[self.used..self.used + self.draft];
+        Some(core::str::from_utf8(draft).unwrap_or(""))
+    }
+
+    /// Forget the line that was being typed, and which line was recalled.
+    pub(crate) fn stop_recalling(&mut self) {
+        self.recalled = None;
+        self.draft = 0;
+    }
+
+    /// Keep `typed` in the room after the last line. Lines are never
+    /// dropped to make room: if it doesn't fit, it's forgotten.
+    #[cfg(feature = "ansi")]
+    fn keep_draft(&mut self, typed: &str) {
+        self.draft = if self.buffer.make_room(self.used + typed.len()) {
+            self.buffer.bytes_mut()[self.used..self.used + typed.len()]
+                .copy_from_slice(typed.as_bytes());
+            typed.len()
+        } else {
+            0
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn entries<'h>(history: &'h History) -> Vec<(usize, &'h str)> {
+        history.entries().collect()
+    }
+
+    #[test]
+    fn drops_oldest() {
+        let mut buffer = [0u8; 12];
+        let mut history = History::new(&mut buffer[..]);
+        history.push("one");
+        history.push("two");
+        history.push("  ");
+        assert_eq!(entries(&history), [(1, "one"), (2, "two")]);
+        history.push("three");
+        assert_eq!(entries(&history), [(2, "two"), (3, "three")]);
+        history.push("twelve chars");
+        assert_eq!(entries(&history), [(2, "two"), (3, "three")]);
+        history.push("ten chars!");
+        assert_eq!(entries(&history), [(4, "ten chars!")]);
+        assert_eq!(history.get(4), Some("ten chars!"));
+        assert_eq!(history.get(3), None);
+        history.clear();
+        assert_eq!(entries(&history), []);
+        history.push("a");
+        assert_eq!(entries(&history), [(1, "a")]);
+    }
+
+    #[test]
+    #[cfg(feature = "ansi")]
+    fn recall() {
+        let mut buffer = [0u8; 16];
+        let mut history = History::new(&mut buffer[..]);
+        assert_eq!(history.older("half"), None);
+        assert_eq!(history.newer(), None);
+        history.push("one");
+        history.push("two");
+        assert_eq!(history.older("half"), Some("two"));
+        assert_eq!(history.older("ignored"), Some("one"));
+        assert_eq!(history.older("ignored"), None);
+        assert_eq!(history.newer(), Some("two"));
+        assert_eq!(history.newer(), Some("half"));
+        assert_eq!(history.newer(), None);
+
+        // No line is dropped to make room for what was being typed, so it's
+        // forgotten if it doesn't fit
+        assert_eq!(history.older("much too long"), Some("two"));
+        assert_eq!(entries(&history), [(1, "one"), (2, "two")]);
+        assert_eq!(history.newer(), Some(""));
+        assert_eq!(history.older("fits now"), Some("two"));
+        assert_eq!(history.newer(), Some("fits now"));
+
+        // Running a line stops recalling
+        history.older("");
+        history.push("three");
+        assert_eq!(history.newer(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "ansi")]
+    fn find() {
+        let mut buffer = [0u8; 32];
+        let mut history = History::new(&mut buffer[..]);
+        assert_eq!(history.find("", None), None);
+        history.push("led on");
+        history.push("status");
+        history.push("led off");
+        assert_eq!(history.find("led", None), Some(3));
+        assert_eq!(history.find("led", Some(2)), Some(1));
+        assert_eq!(history.find("led", Some(0)), None);
+        assert_eq!(history.find("stat", Some(3)), Some(2));
+        assert_eq!(history.find("reset", None), None);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn unbounded() {
+        let mut history = History::new(Buffer::Growing(Vec::new()));
+        for i in 0..1000 {
+            history.push(&std::format!("line {}", i));
+        }
+        assert_eq!(history.len(), 1000);
+        assert_eq!(history.get(1), Some("line 0"));
+        assert_eq!(history.get(1000), Some("line 999"));
+        history.push(&"x".repeat(5000));
+        assert_eq!(history.get(1), Some("line 0"));
+        assert_eq!(history.get(1001).map(str::len), Some(5000));
+    }
+}
@@ -0,0 +1,704 @@
+//! Decides what each byte of input does, without doing any of it.
+//!
+//! The `Runner` works out which [`InputState`] it's in, asks [`step`] what
+//! to do with the byte, then does it. Keeping the decision here, apart from
+//! the I/O, means every state has to say what happens to every kind of
+//! byte.
+#![deny(missing_docs)]
+
+/// What the `Runner` is doing with its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum InputState {
+    /// Typing a command line, or a line a callback asked for
+    Normal,
+    /// Typing a line a callback asked for, which is echoed as `*`s
+    Masked,
+    /// After an ESC, while typing a line
+    #[cfg(feature = "ansi")]
+    Escape,
+    /// After the ESC which finished a history search
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    LeftSearch,
+    /// In a control sequence, after `ESC [` and the numbers so far, while
+    /// typing a line
+    #[cfg(feature = "ansi")]
+    Csi(Params),
+    /// After `ESC O`, which comes before one more byte, while typing a line
+    #[cfg(feature = "ansi")]
+    Ss3,
+    /// Answering a yes or no question from a callback
+    Confirm,
+    /// Showing `--More--`, part way through a callback's paged output
+    #[cfg(feature = "paging")]
+    More,
+    /// Searching the history, after Ctrl-R
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    Search,
+    /// Giving every byte to a raw mode handler
+    RawPassthrough,
+    /// Forwarding bytes elsewhere, watching for the escape sequence
+    Passthrough,
+    /// Keeping what's pasted, until the end of a bracketed paste
+    #[cfg(feature = "ansi")]
+    Paste,
+}
+
+/// The kinds of byte which are treated differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ByteClass {
+    /// Carriage return, which ends a line
+    Enter,
+    /// Line feed, which is ignored after a carriage return
+    LineFeed,
+    /// Tab
+    #[cfg(feature = "completion")]
+    Tab,
+    /// Backspace or delete
+    Erase,
+    /// Ctrl-C
+    Interrupt,
+    /// Ctrl-D
+    EndOfInput,
+    /// Ctrl-L
+    Redraw,
+    /// Ctrl-R
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    Search,
+    /// Ctrl-W
+    #[cfg(feature = "line-editing")]
+    EraseWord,
+    /// Ctrl-U
+    #[cfg(feature = "line-editing")]
+    EraseLine,
+    /// ESC, which starts an escape sequence
+    #[cfg(feature = "ansi")]
+    Escape,
+    /// Anything else
+    Other,
+}
+
+impl ByteClass {
+    pub(crate) const fn of(byte: u8) -> Self {
+        match byte {
+            0x0D => ByteClass::Enter,
+            0x0A => ByteClass::LineFeed,
+            #[cfg(feature = "completion")]
+            0x09 => ByteClass::Tab,
+            0x08 | 0x7F => ByteClass::Erase,
+            0x03 => ByteClass::Interrupt,
+            0x04 => ByteClass::EndOfInput,
+            0x0C => ByteClass::Redraw,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            0x12 => ByteClass::Search,
+            #[cfg(feature = "line-editing")]
+            0x17 => ByteClass::EraseWord,
+            #[cfg(feature = "line-editing")]
+            0x15 => ByteClass::EraseLine,
+            #[cfg(feature = "ansi")]
+            0x1B => ByteClass::Escape,
+            _ => ByteClass::Other,
+        }
+    }
+}
+
+/// What to do with a byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    /// Nothing
+    Ignore,
+    /// Give it to the raw mode handler
+    Raw,
+    /// Forward it, unless it finishes the escape sequence
+    Forward,
+    /// Keep it, unless it finishes the paste
+    #[cfg(feature = "ansi")]
+    Paste,
+    /// Answer the question, yes or no
+    Answer(bool),
+    /// Carry on with, or stop, the paged output
+    #[cfg(feature = "paging")]
+    More(MoreKey),
+    /// Start searching the history
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    StartSearch,
+    /// Change or finish the history search
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    Search(SearchKey),
+    /// Finish the line, and run it or give it to the callback waiting for it
+    Submit,
+    /// Complete the word at the end of the line, or add the byte to the line
+    /// if nothing can complete it
+    #[cfg(feature = "completion")]
+    Complete,
+    /// Remove the character before the cursor
+    Erase,
+    /// Abandon the line
+    Cancel,
+    /// End the session if the line is empty, or remove the character at
+    /// the cursor
+    EndOfInput,
+    /// Clear the screen and show the line again
+    Redraw,
+    /// Remove the word before the cursor
+    #[cfg(feature = "line-editing")]
+    EraseWord,
+    /// Throw away the whole line
+    #[cfg(feature = "line-editing")]
+    EraseLine,
+    /// Add the byte to the line, at the cursor
+    Insert,
+    /// Add this character to the line, at the cursor, for a key bound to it
+    InsertChar(char),
+    /// Start an escape sequence
+    #[cfg(feature = "ansi")]
+    StartEscape,
+    /// Throw away the line, after an ESC which didn't start an escape
+    /// sequence, then do what the byte after it does
+    #[cfg(feature = "ansi")]
+    LoneEscape,
+    /// Start a control sequence, after `ESC [`
+    #[cfg(feature = "ansi")]
+    StartCsi,
+    /// Start a single shift sequence, after `ESC O`
+    #[cfg(feature = "ansi")]
+    StartSs3,
+    /// Carry on with a control sequence, with these numbers so far
+    #[cfg(feature = "ansi")]
+    ContinueCsi(Params),
+    /// Move the cursor one character left, after `ESC [ D`
+    #[cfg(feature = "line-editing")]
+    CursorLeft,
+    /// Move the cursor one character right, after `ESC [ C`
+    #[cfg(feature = "line-editing")]
+    CursorRight,
+    /// Move the cursor to the start of the word before it, after `ESC b`
+    /// or Ctrl-Left
+    #[cfg(feature = "line-editing")]
+    WordLeft,
+    /// Move the cursor to the end of the word after it, after `ESC f` or
+    /// Ctrl-Right
+    #[cfg(feature = "line-editing")]
+    WordRight,
+    /// Remove the character at the cursor, after `ESC [ 3 ~`
+    #[cfg(feature = "line-editing")]
+    Delete,
+    /// Switch between inserting and overwriting, after `ESC [ 2 ~`
+    #[cfg(feature = "line-editing")]
+    ToggleOverwrite,
+    /// Replace the line with the one before it in the history, after
+    /// `ESC [ A`
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    HistoryOlder,
+    /// Replace the line with the one after it in the history, after
+    /// `ESC [ B`
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    HistoryNewer,
+    /// Finish a control sequence, or single shift sequence, which does
+    /// nothing here
+    #[cfg(feature = "ansi")]
+    EndCsi,
+}
+
+/// Where we are in an escape sequence typed on a line.
+#[cfg(feature = "ansi")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Escape {
+    /// Not in one
+    None,
+    /// After ESC
+    Started,
+    /// After the ESC which finished a history search
+    #[cfg(feature = "history")]
+    LeftSearch,
+    /// After `ESC [`, with the numbers so far
+    Csi(Params),
+    /// After `ESC O`
+    Ss3,
+}
+
+#[cfg(feature = "ansi")]
+impl Escape {
+    /// Where we are after `action`.
+    pub(crate) const fn after(action: Action) -> Self {
+        match action {
+            Action::StartEscape => Escape::Started,
+            #[cfg(feature = "history")]
+            Action::Search(SearchKey::Leave) => Escape::LeftSearch,
+            Action::StartCsi => Escape::Csi(Params::NONE),
+            Action::ContinueCsi(params) => Escape::Csi(params),
+            Action::StartSs3 => Escape::Ss3,
+            _ => Escape::None,
+        }
+    }
+}
+
+/// The numbers in a control sequence, such as the 3 in `ESC [ 3 ~` for
+/// Delete, or the 1 and 5 in `ESC [ 1 ; 5 D` for Ctrl-Left.
+#[cfg(feature = "ansi")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Params {
+    /// The key, and which modifier keys were held down. A number which
+    /// is too big, or isn't a number, is [`u8::MAX`].
+    numbers: [u8; 2],
+    /// Which of them is being typed
+    index: usize,
+}
+
+#[cfg(feature = "ansi")]
+impl Params {
+    const NONE: Params = Params {
+        numbers: [0; 2],
+        index: 0,
+    };
+    /// Anything we don't understand
+    const OTHER: Params = Params {
+        numbers: [u8::MAX; 2],
+        index: 2,
+    };
+
+    /// Take in a parameter byte.
+    const fn push(self, byte: u8) -> Params {
+        let Params { mut numbers, index } = self;
+        match byte {
+            b'0'..=b'9' if index < 2 && numbers[index] < 25 => {
+                numbers[index] = numbers[index] * 10 + (byte - b'0');
+                Params { numbers, index }
+            }
+            b';' if index < 1 => Params {
+                numbers,
+                index: index + 1,
+            },
+            _ => Params::OTHER,
+        }
+    }
+
+    /// Is this `key`, without any modifier keys?
+    #[cfg(feature = "line-editing")]
+    const fn is(self, key: u8) -> bool {
+        self.numbers[0] == key && matches!(self.numbers[1], 0 | 1)
+    }
+
+    /// Was Alt or Ctrl held down, to move by a word?
+    #[cfg(feature = "line-editing")]
+    const fn by_word(self) -> bool {
+        matches!(self.numbers[0], 0 | 1) && matches!(self.numbers[1], 3 | 5)
+    }
+}
+
+/// What a key pressed at `--More--` asks for.
+#[cfg(feature = "paging")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MoreKey {
+    /// Show the next page, after space
+    Page,
+    /// Show one more line, after Enter
+    Line,
+    /// Show no more, after `q` or Ctrl-C
+    Quit,
+}
+
+/// What a key pressed while searching the history asks for.
+#[cfg(all(feature = "ansi", feature = "history"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchKey {
+    /// Add the byte to what's being looked for
+    Type,
+    /// Remove the last character of what's being looked for
+    Erase,
+    /// Look for an older match, after Ctrl-R again
+    Older,
+    /// Run the line found, after Enter
+    Accept,
+    /// Go back to the line being typed, after Ctrl-C
+    Abort,
+    /// Go back to the line being typed and start an escape sequence, after
+    /// ESC, so the arrow keys work on the line straight away
+    Leave,
+}
+
+/// What to do with `byte` in `state`.
+pub(crate) const fn step(state: InputState, byte: u8) -> Action {
+    match (state, ByteClass::of(byte)) {
+        (InputState::RawPassthrough, _) => Action::Raw,
+        (InputState::Passthrough, _) => Action::Forward,
+        #[cfg(feature = "ansi")]
+        (InputState::Paste, _) => Action::Paste,
+        (InputState::Confirm, _) => match byte {
+            b'y' | b'Y' => Action::Answer(true),
+            b'n' | b'N' | b'\r' => Action::Answer(false),
+            _ => Action::Ignore,
+        },
+        #[cfg(feature = "paging")]
+        (InputState::More, _) => match byte {
+            b' ' => Action::More(MoreKey::Page),
+            b'\r' => Action::More(MoreKey::Line),
+            b'q' | b'Q' | 0x03 => Action::More(MoreKey::Quit),
+            _ => Action::Ignore,
+        },
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        (InputState::Search, class) => match class {
+            ByteClass::Other => Action::Search(SearchKey::Type),
+            ByteClass::Erase => Action::Search(SearchKey::Erase),
+            ByteClass::Search => Action::Search(SearchKey::Older),
+            ByteClass::Enter => Action::Search(SearchKey::Accept),
+            ByteClass::Interrupt => Action::Search(SearchKey::Abort),
+            ByteClass::Escape => Action::Search(SearchKey::Leave),
+            _ => Action::Ignore,
+        },
+        (InputState::Normal | InputState::Masked, class) => match class {
+            ByteClass::Enter => Action::Submit,
+            ByteClass::LineFeed => Action::Ignore,
+            #[cfg(feature = "completion")]
+            ByteClass::Tab => Action::Complete,
+            ByteClass::Erase => Action::Erase,
+            ByteClass::Interrupt => Action::Cancel,
+            ByteClass::EndOfInput => Action::EndOfInput,
+            ByteClass::Redraw => Action::Redraw,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            ByteClass::Search => Action::StartSearch,
+            #[cfg(feature = "line-editing")]
+            ByteClass::EraseWord => Action::EraseWord,
+            #[cfg(feature = "line-editing")]
+            ByteClass::EraseLine => Action::EraseLine,
+            #[cfg(feature = "ansi")]
+            ByteClass::Escape => Action::StartEscape,
+            ByteClass::Other => Action::Insert,
+        },
+        // Only control sequences, single shifts, and Alt-B and Alt-F are
+        // understood. Anything else after ESC means it was pressed on its
+        // own, which throws the line away
+        #[cfg(feature = "ansi")]
+        (InputState::Escape, _) if byte == b'[' => Action::StartCsi,
+        #[cfg(feature = "ansi")]
+        (InputState::Escape, _) if byte == b'O' => Action::StartSs3,
+        #[cfg(feature = "line-editing")]
+        (InputState::Escape, _) if byte == b'b' => Action::WordLeft,
+        #[cfg(feature = "line-editing")]
+        (InputState::Escape, _) if byte == b'f' => Action::WordRight,
+        #[cfg(feature = "ansi")]
+        (InputState::Escape, _) => Action::LoneEscape,
+        // The line found is kept, so the ESC is as if it wasn't there,
+        // unless it starts an escape sequence
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        (InputState::LeftSearch, _) => match step(InputState::Escape, byte) {
+            Action::LoneEscape => step(InputState::Normal, byte),
+            action => action,
+        },
+        #[cfg(feature = "ansi")]
+        (InputState::Csi(params), _) => match byte {
+            #[cfg(feature = "line-editing")]
+            b'D' if params.by_word() => Action::WordLeft,
+            #[cfg(feature = "line-editing")]
+            b'C' if params.by_word() => Action::WordRight,
+            #[cfg(feature = "line-editing")]
+            b'D' => Action::CursorLeft,
+            #[cfg(feature = "line-editing")]
+            b'C' => Action::CursorRight,
+            #[cfg(feature = "history")]
+            b'A' => Action::HistoryOlder,
+            #[cfg(feature = "history")]
+            b'B' => Action::HistoryNewer,
+            #[cfg(feature = "line-editing")]
+            b'~' if params.is(2) => Action::ToggleOverwrite,
+            #[cfg(feature = "line-editing")]
+            b'~' if params.is(3) => Action::Delete,
+            0x40..=0x7E => Action::EndCsi,
+            // A control character abandons the sequence
+            0x00..=0x1F => step(InputState::Normal, byte),
+            _ => Action::ContinueCsi(params.push(byte)),
+        },
+        // The function keys, and the arrow keys on some terminals, are
+        // sent as `ESC O` and one byte, which means what it would at the
+        // end of a control sequence
+        #[cfg(feature = "ansi")]
+        (InputState::Ss3, _) => match step(InputState::Csi(Params::NONE), byte) {
+            Action::ContinueCsi(_) => Action::EndCsi,
+            action => action,
+        },
+    }
+}
+
+/// Watches bytes being passed through for an escape sequence, holding
+/// back any which might be the start of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EscapeMatcher {
+    escape: &'static [u8],
+    /// How much of the escape sequence has been held back
+    matched: usize,
+}
+
+impl EscapeMatcher {
+    pub(crate) const fn new(escape: &'static [u8]) -> Self {
+        EscapeMatcher { escape, matched: 0 }
+    }
+
+    /// Look at the next byte. Bytes which turn out not to be part of the
+    /// escape sequence are given to `forward`, in order. Returns `true` once
+    /// the whole escape sequence has been seen, and not forwarded.
+    pub(crate) fn feed(&mut self, byte: u8, mut forward: impl FnMut(&[u8])) -> bool {
+        let escape = self.escape;
+        let matched = self.matched;
+        if escape.get(matched) == Some(&byte) {
+            self.matched += 1;
+            if self.matched == escape.len() {
+                self.matched = 0;
+                return true;
+            }
+            return false;
+        }
+        // Keep the longest tail of what's held back, and this byte, which
+        // could still be the start of the escape sequence
+        for kept in (1..=matched).rev() {
+            let dropped = matched + 1 - kept;
+            if escape[..kept - 1] == escape[dropped..matched] && escape[kept - 1] == byte {
+                forward(&escape[..dropped]);
+                self.matched = kept;
+                return false;
+            }
+        }
+        if matched > 0 {
+            forward(&escape[..matched]);
+        }
+        forward(&[byte]);
+        self.matched = 0;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    const STATES: &[InputState] = &[
+        InputState::Normal,
+        InputState::Masked,
+        #[cfg(feature = "ansi")]
+        InputState::Escape,
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        InputState::LeftSearch,
+        #[cfg(feature = "ansi")]
+        InputState::Csi(Params::NONE),
+        #[cfg(feature = "ansi")]
+        InputState::Csi(Params::NONE.push(b'3')),
+        #[cfg(feature = "ansi")]
+        InputState::Ss3,
+        InputState::Confirm,
+        #[cfg(feature = "paging")]
+        InputState::More,
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        InputState::Search,
+        InputState::RawPassthrough,
+        InputState::Passthrough,
+        #[cfg(feature = "ansi")]
+        InputState::Paste,
+    ];
+
+    #[test]
+    fn enter_ends_the_line() {
+        for &state in STATES {
+            let action = step(state, b'\r');
+            match state {
+                InputState::RawPassthrough => assert_eq!(action, Action::Raw),
+                InputState::Passthrough => assert_eq!(action, Action::Forward),
+                #[cfg(feature = "ansi")]
+                InputState::Paste => assert_eq!(action, Action::Paste),
+                #[cfg(all(feature = "ansi", feature = "history"))]
+                InputState::Search => assert_eq!(action, Action::Search(SearchKey::Accept)),
+                // After throwing the line away
+                #[cfg(feature = "ansi")]
+                InputState::Escape => assert_eq!(action, Action::LoneEscape),
+                #[cfg(feature = "paging")]
+                InputState::More => assert_eq!(action, Action::More(MoreKey::Line)),
+                _ => assert!(matches!(action, Action::Submit | Action::Answer(_))),
+            }
+        }
+    }
+
+    #[test]
+    fn every_byte() {
+        for &state in STATES {
+            for byte in 0..=u8::MAX {
+                let action = step(state, byte);
+                match (state, ByteClass::of(byte)) {
+                    (InputState::RawPassthrough, _) => assert_eq!(action, Action::Raw),
+                    (InputState::Passthrough, _) => assert_eq!(action, Action::Forward),
+                    #[cfg(feature = "ansi")]
+                    (InputState::Paste, _) => assert_eq!(action, Action::Paste),
+                    // Only y, n and Enter answer a question
+                    (InputState::Confirm, _) => assert_eq!(
+                        action == Action::Ignore,
+                        !b"yYnN\r".contains(&byte),
+                        "{byte:#x}"
+                    ),
+                    // Only space, Enter, q and Ctrl-C do anything at --More--
+                    #[cfg(feature = "paging")]
+                    (InputState::More, _) => assert_eq!(
+                        action == Action::Ignore,
+                        !b" \rqQ\x03".contains(&byte),
+                        "{byte:#x}"
+                    ),
+                    // Only printable bytes are looked for, and only Ctrl-R
+                    // looks further back
+                    #[cfg(all(feature = "ansi", feature = "history"))]
+                    (InputState::Search, ByteClass::Other) => {
+                        assert_eq!(action, Action::Search(SearchKey::Type))
+                    }
+                    #[cfg(all(feature = "ansi", feature = "history"))]
+                    (InputState::Search, class) => assert_eq!(
+                        action == Action::Search(SearchKey::Older),
+                        class == ByteClass::Search,
+                        "{byte:#x}"
+                    ),
+                    // Lines are edited the same whether or not they're masked
+                    (InputState::Masked, _) => {
+                        assert_eq!(action, step(InputState::Normal, byte))
+                    }
+                    (InputState::Normal, ByteClass::Other) => assert_eq!(action, Action::Insert),
+                    (InputState::Normal, _) => assert_ne!(action, Action::Insert),
+                    #[cfg(feature = "ansi")]
+                    (InputState::Escape, _) if byte == b'[' => {
+                        assert_eq!(action, Action::StartCsi)
+                    }
+                    #[cfg(feature = "ansi")]
+                    (InputState::Escape, _) if byte == b'O' => {
+                        assert_eq!(action, Action::StartSs3)
+                    }
+                    #[cfg(feature = "line-editing")]
+                    (InputState::Escape, _) if b"bf".contains(&byte) => {
+                        assert!(matches!(action, Action::WordLeft | Action::WordRight))
+                    }
+                    #[cfg(feature = "ansi")]
+                    (InputState::Escape, _) => assert_eq!(action, Action::LoneEscape),
+                    #[cfg(all(feature = "ansi", feature = "history"))]
+                    (InputState::LeftSearch, _) => match step(InputState::Escape, byte) {
+                        Action::LoneEscape => assert_eq!(action, step(InputState::Normal, byte)),
+                        escape => assert_eq!(action, escape),
+                    },
+                    // A control sequence ends with its final byte, or is
+                    // abandoned for a control character
+                    #[cfg(feature = "ansi")]
+                    (InputState::Csi(_), _) if byte < 0x20 => {
+                        assert_eq!(action, step(InputState::Normal, byte))
+                    }
+                    #[cfg(feature = "ansi")]
+                    (InputState::Csi(_), _) => assert_eq!(
+                        Escape::after(action) == Escape::None,
+                        (0x40..=0x7E).contains(&byte),
+                        "{byte:#x}"
+                    ),
+                    // A single shift always ends with the byte after it
+                    #[cfg(feature = "ansi")]
+                    (InputState::Ss3, _) if byte < 0x20 => {
+                        assert_eq!(action, step(InputState::Normal, byte))
+                    }
+                    #[cfg(feature = "ansi")]
+                    (InputState::Ss3, _) => assert_eq!(Escape::after(action), Escape::None),
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "line-editing", feature = "history"))]
+    fn arrow_keys() {
+        let mut state = InputState::Normal;
+        let mut actions = [Action::Ignore; 6];
+        for (action, byte) in actions.iter_mut().zip(b"\x1b[D\x1b[C") {
+            *action = step(state, *byte);
+            state = match Escape::after(*action) {
+                Escape::None => InputState::Normal,
+                Escape::Started => InputState::Escape,
+                #[cfg(feature = "history")]
+                Escape::LeftSearch => InputState::LeftSearch,
+                Escape::Csi(params) => InputState::Csi(params),
+                Escape::Ss3 => InputState::Ss3,
+            };
+        }
+        assert_eq!(
+            actions,
+            [
+                Action::StartEscape,
+                Action::StartCsi,
+                Action::CursorLeft,
+                Action::StartEscape,
+                Action::StartCsi,
+                Action::CursorRight,
+            ]
+        );
+        assert_eq!(
+            step(InputState::Csi(Params::NONE), b'A'),
+            Action::HistoryOlder
+        );
+        assert_eq!(
+            step(InputState::Csi(Params::NONE), b'B'),
+            Action::HistoryNewer
+        );
+        assert_eq!(step(InputState::Csi(Params::NONE), b'~'), Action::EndCsi);
+        assert_eq!(step(InputState::Escape, b'x'), Action::LoneEscape);
+        assert_eq!(step(InputState::Ss3, b'D'), Action::CursorLeft);
+        assert_eq!(step(InputState::Ss3, b'P'), Action::EndCsi);
+        assert_eq!(step(InputState::Ss3, b'3'), Action::EndCsi);
+    }
+
+    #[test]
+    #[cfg(feature = "line-editing")]
+    fn editing_keys() {
+        let run = |bytes: &[u8]| {
+            let mut state = InputState::Normal;
+            let mut action = Action::Ignore;
+            for &byte in bytes {
+                action = step(state, byte);
+                state = match Escape::after(action) {
+                    Escape::None => InputState::Normal,
+                    Escape::Started => InputState::Escape,
+                    #[cfg(feature = "history")]
+                    Escape::LeftSearch => InputState::LeftSearch,
+                    Escape::Csi(params) => InputState::Csi(params),
+                    Escape::Ss3 => InputState::Ss3,
+                };
+            }
+            action
+        };
+        assert_eq!(run(b"\x1b[3~"), Action::Delete);
+        assert_eq!(run(b"\x1b[2~"), Action::ToggleOverwrite);
+        // With a modifier, or another number, it's some other key
+        assert_eq!(run(b"\x1b[3;5~"), Action::EndCsi);
+        assert_eq!(run(b"\x1b[23~"), Action::EndCsi);
+        assert_eq!(run(b"\x1b[2003~"), Action::EndCsi);
+        assert_eq!(run(b"\x1bO3"), Action::EndCsi);
+        // Alt or Ctrl and an arrow key moves by a word
+        assert_eq!(run(b"\x1bb"), Action::WordLeft);
+        assert_eq!(run(b"\x1bf"), Action::WordRight);
+        assert_eq!(run(b"\x1b[1;5D"), Action::WordLeft);
+        assert_eq!(run(b"\x1b[1;3C"), Action::WordRight);
+        assert_eq!(run(b"\x1b[1;2C"), Action::CursorRight);
+        assert_eq!(run(b"\x1b[1;5;1D"), Action::CursorLeft);
+    }
+
+    #[test]
+    fn escape_matcher() {
+        let run = |escape: &'static [u8], input: &[u8]| {
+            let mut matcher = EscapeMatcher::new(escape);
+            let mut forwarded = Vec::new();
+            let mut end = None;
+            for (i, byte) in input.iter().enumerate() {
+                if matcher.feed(*byte, |bytes| forwarded.extend_from_slice(bytes)) {
+                    end = Some(i);
+                    break;
+                }
+            }
+            (forwarded, end)
+        };
+        assert_eq!(
+            run(b"+++", b"AT+X\r++a+++b"),
+            (b"AT+X\r++a".to_vec(), Some(10))
+        );
+        assert_eq!(run(b"+++", b"++++"), (b"".to_vec(), Some(2)));
+        assert_eq!(run(b"\x1d", b"ab\x1d"), (b"ab".to_vec(), Some(2)));
+        // A tail of what's held back can start the sequence again
+        assert_eq!(run(b"aab", b"aaab"), (b"a".to_vec(), Some(3)));
+        assert_eq!(run(b"abac", b"ababac"), (b"ab".to_vec(), Some(5)));
+        assert_eq!(run(b"abc", b"abd"), (b"abd".to_vec(), None));
+    }
+}
@@ -0,0 +1,205 @@
+//! Binding bytes, or sequences of them, to what they do on the command line,
+//! for keypads and front panels which don't send what a terminal would.
+#![deny(missing_docs)]
+
+use super::input::Action;
+
+/// What a key bound with
+/// [`Runner::set_key_bindings`](crate::Runner::set_key_bindings) does.
+///
+/// An action which needs a feature that's turned off does nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Nothing, to stop a key doing what it usually does
+    Ignore,
+    /// Finish the line, like Enter
+    Submit,
+    /// Complete the word at the end of the line, like Tab, with the
+    /// `completion` feature
+    Complete,
+    /// Remove the character before the cursor, like Backspace
+    Backspace,
+    /// Remove the character at the cursor, like Delete, with the
+    /// `line-editing` feature
+    Delete,
+    /// Abandon the line, like Ctrl-C
+    Cancel,
+    /// End the session if the line is empty, like Ctrl-D
+    EndOfInput,
+    /// Clear the screen and show the line again, like Ctrl-L
+    Redraw,
+    /// Search the history, like Ctrl-R, with the `ansi` and `history`
+    /// features
+    Search,
+    /// Remove the word before the cursor, like Ctrl-W, with the
+    /// `line-editing` feature
+    DeleteWord,
+    /// Throw away the whole line, like Ctrl-U, with the `line-editing`
+    /// feature
+    KillLine,
+    /// Show the line before in the history, like the up arrow key, with
+    /// the `ansi` and `history` features
+    HistoryPrev,
+    /// Show the line after in the history, like the down arrow key, with
+    /// the `ansi` and `history` features
+    HistoryNext,
+    /// Move the cursor one character left, with the `line-editing` feature
+    CursorLeft,
+    /// Move the cursor one character right, with the `line-editing` feature
+    CursorRight,
+    /// Move the cursor to the start of the word before it, like Alt-B,
+    /// with the `line-editing` feature
+    WordLeft,
+    /// Move the cursor to the end of the word after it, like Alt-F, with
+    /// the `line-editing` feature
+    WordRight,
+    /// Switch between inserting and overwriting, like Insert, with the
+    /// `line-editing` feature
+    ToggleOverwrite,
+    /// Type this character
+    Insert(char),
+}
+
+impl KeyAction {
+    /// What the `Runner` does for it.
+    pub(crate) const fn action(self) -> Action {
+        match self {
+            KeyAction::Ignore => Action::Ignore,
+            KeyAction::Submit => Action::Submit,
+            #[cfg(feature = "completion")]
+            KeyAction::Complete => Action::Complete,
+            KeyAction::Backspace => Action::Erase,
+            #[cfg(feature = "line-editing")]
+            KeyAction::Delete => Action::Delete,
+            KeyAction::Cancel => Action::Cancel,
+            KeyAction::EndOfInput => Action::EndOfInput,
+            KeyAction::Redraw => Action::Redraw,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            KeyAction::Search => Action::StartSearch,
+            #[cfg(feature = "line-editing")]
+            KeyAction::DeleteWord => Action::EraseWord,
+            #[cfg(feature = "line-editing")]
+            KeyAction::KillLine => Action::EraseLine,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            KeyAction::HistoryPrev => Action::HistoryOlder,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            KeyAction::HistoryNext => Action::HistoryNewer,
+            #[cfg(feature = "line-editing")]
+            KeyAction::CursorLeft => Action::CursorLeft,
+            #[cfg(feature = "line-editing")]
+            KeyAction::CursorRight => Action::CursorRight,
+            #[cfg(feature = "line-editing")]
+            KeyAction::WordLeft => Action::WordLeft,
+            #[cfg(feature = "line-editing")]
+            KeyAction::WordRight => Action::WordRight,
+            #[cfg(feature = "line-editing")]
+            KeyAction::ToggleOverwrite => Action::ToggleOverwrite,
+            KeyAction::Insert(c) => Action::InsertChar(c),
+            #[allow(unreachable_patterns)]
+            _ => Action::Ignore,
+        }
+    }
+}
+
+/// A key, and what it does when it's typed on the command line. See
+/// [`Runner::set_key_bindings`](crate::Runner::set_key_bindings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding<'a> {
+    /// The bytes the key sends, as given to
+    /// [`Runner::input_byte`](crate::Runner::input_byte). One byte, or a
+    /// sequence such as `b"\x1b[11~"`
+    pub keys: &'a [u8],
+    /// What it does
+    pub action: KeyAction,
+}
+
+impl<'a> KeyBinding<'a> {
+    /// Bind the bytes in `keys` to `action`.
+    pub const fn new(keys: &'a [u8], action: KeyAction) -> Self {
+        KeyBinding { keys, action }
+    }
+}
+
+/// What a byte typed did to the sequence being matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Fed<'k> {
+    /// It finished a binding's sequence
+    Bound(KeyAction),
+    /// It carried on at least one binding's sequence, which isn't finished
+    Partial,
+    /// It didn't carry on any binding's sequence. The bytes before it,
+    /// which did, were held back and do what they usually do
+    Unbound(&'k [u8]),
+}
+
+/// Follows what's typed through the bindings' sequences.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeyMatcher {
+    /// A binding which starts with what's been typed so far
+    binding: usize,
+    /// How much of it has been typed
+    len: usize,
+}
+
+impl KeyMatcher {
+    /// Follow `byte` through `bindings`. The first binding it finishes wins,
+    /// even if a longer one starts the same way.
+    pub(crate) fn feed<'k>(&mut self, bindings: &'k [KeyBinding<'k>], byte: u8) -> Fed<'k> {
+        let typed = bindings
+            .get(self.binding)
+            .and_then(|binding| binding.keys.get(..self.len))
+            .unwrap_or(&[]);
+        let continues = |binding: &KeyBinding| {
+            binding.keys.starts_with(typed) && binding.keys.get(typed.len()) == Some(&byte)
+        };
+        if let Some(binding) = bindings
+            .iter()
+            .find(|binding| continues(binding) && binding.keys.len() == typed.len() + 1)
+        {
+            *self = KeyMatcher::default();
+            return Fed::Bound(binding.action);
+        }
+        match bindings.iter().position(continues) {
+            Some(binding) => {
+                self.binding = binding;
+                self.len = typed.len() + 1;
+                Fed::Partial
+            }
+            None => {
+                *self = KeyMatcher::default();
+                Fed::Unbound(typed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences() {
+        const BINDINGS: &[KeyBinding] = &[
+            KeyBinding::new(b"\x1b[11~", KeyAction::Submit),
+            KeyBinding::new(b"\x1b[12~", KeyAction::Cancel),
+            KeyBinding::new(b"ab", KeyAction::Redraw),
+            KeyBinding::new(b"a", KeyAction::Ignore),
+            KeyBinding::new(b"abc", KeyAction::Complete),
+        ];
+        let mut matcher = KeyMatcher::default();
+        let mut feed = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|byte| matcher.feed(BINDINGS, *byte))
+                .last()
+        };
+        assert_eq!(feed(b"\x1b[11"), Some(Fed::Partial));
+        assert_eq!(feed(b"~"), Some(Fed::Bound(KeyAction::Submit)));
+        assert_eq!(feed(b"\x1b[12~"), Some(Fed::Bound(KeyAction::Cancel)));
+        assert_eq!(feed(b"\x1b[A"), Some(Fed::Unbound(b"\x1b[")));
+        assert_eq!(feed(b"x"), Some(Fed::Unbound(b"")));
+        // The shorter binding wins
+        assert_eq!(feed(b"a"), Some(Fed::Bound(KeyAction::Ignore)));
+        assert_eq!(feed(b"b"), Some(Fed::Unbound(b"")));
+    }
+}
@@ -3,32 +3,307 @@
 //! A basic command-line interface for `#![no_std]` Rust programs. Peforms
 //! zero heap allocation.
 #![no_std]
+// The `Runner` runs on devices which must never stop, so it must never panic
+#![cfg_attr(
+    not(test),
+    deny(
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::panic,
+        clippy::unreachable
+    )
+)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+mod capture;
+mod command;
+#[cfg(feature = "completion")]
+mod complete;
+mod cursor;
+mod dispatch;
+mod error;
+#[cfg(any(test, feature = "std"))]
+pub mod export;
+mod help;
+#[cfg(feature = "history")]
+mod history;
+mod input;
+mod keymap;
+#[cfg(feature = "builtins-extra")]
+mod macros;
 pub mod menu_manager;
+mod output;
+pub mod parser;
+#[cfg(feature = "ansi")]
+mod paste;
+pub mod regmap;
+mod sink;
+#[cfg(feature = "stats")]
+pub mod stats;
+mod storage;
+#[cfg(feature = "telnet")]
+mod telnet;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod value;
+
+pub use capture::CaptureWriter;
+use command::InputRequest;
+#[cfg(feature = "paging")]
+pub use command::Pager;
+pub use command::{CommandContext, ConfirmFn, LineFn, PassthroughFn, RawModeAction, RawModeFn};
+#[cfg(feature = "completion")]
+pub use complete::complete;
+use core::any::Any;
+use core::fmt::Write;
+use core::ops::Range;
+pub use cursor::{MenuCursor, SelectResult};
+pub use dispatch::{CommandHandler, CommandId};
+pub use error::MenuError;
+pub use help::{render_help, HelpRenderOptions};
+#[cfg(feature = "paging")]
+use input::MoreKey;
+use input::{Action, EscapeMatcher, InputState};
+pub use keymap::{KeyAction, KeyBinding};
+#[cfg(feature = "derive")]
+pub use menu_derive::MenuCommands;
+pub use output::ConsoleWriter;
+use output::{KeepAlive, Output, OutputState};
+pub use sink::{BackoffFn, ByteSink, SinkWriter};
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use storage::MAX_ARGUMENTS;
 
 /// The type of function we call when we enter/exit a menu.
 pub type MenuCallbackFn<I, T> = fn(menu: &Menu<I, T>, interface: &mut I, context: &mut T);
 
+/// The type of function which sends bytes back to a telnet client. See
+/// [`Runner::enable_telnet`].
+#[cfg(feature = "telnet")]
+pub type TelnetReplyFn<T> = fn(bytes: &[u8], context: &mut T);
+
+/// The type of function the `Runner` calls every so often while it's busy.
+/// See [`Runner::set_busy_hook`].
+pub type BusyHookFn<T> = fn(context: &mut T);
+
+/// The type of function which checks for a key waiting to be read from the
+/// interface, without waiting. See [`Runner::set_key_poll`].
+pub type KeyPollFn<I> = fn(interface: &mut I) -> Option<u8>;
+
+/// The type of function which draws the prompt, instead of the `Runner`.
+/// See [`PromptRendering::External`].
+pub type PromptFn<T> = fn(info: &PromptInfo, context: &mut T);
+
+/// The type of function which writes the prompt for a command, in place of
+/// the sub-menus it's in. See [`PromptRendering::Custom`].
+pub type PromptWriteFn<T> = fn(info: &PromptInfo, out: &mut dyn Write, context: &mut T);
+
+/// How a byte reached the `Runner`, for an [`InputTapFn`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    /// Given to [`Runner::input_byte`]
+    Typed,
+    /// Read by a callback with [`CommandContext::poll_key`]
+    Polled,
+}
+
+/// The type of function which is shown every byte of input, however it
+/// arrived. See [`Runner::set_input_tap`].
+pub type InputTapFn<I> = fn(interface: &mut I, byte: u8, source: InputSource);
+
+/// The type of function which suggests values for one of an item's
+/// parameters, when Tab is pressed while typing it. See
+/// [`Runner::set_value_completer`].
+///
+/// Call `emit` once for each candidate. Candidates which don't start with
+/// `partial`, what's been typed so far, are ignored.
+pub type ValueCompleterFn<I, T> = fn(
+    item: &Item<I, T>,
+    parameter: &Parameter,
+    partial: &str,
+    context: &mut T,
+    emit: &mut dyn FnMut(&str),
+);
+
+/// The type of function which decides whether a command in a throttle group
+/// may run. See [`Runner::set_throttle`].
+pub type ThrottleFn<T> = fn(group: u8, context: &mut T) -> ThrottleDecision;
+
+/// The type of function told about a failure reported with
+/// [`CommandContext::report_failure`]. See [`Runner::set_throttle`].
+pub type ThrottleFailureFn<T> = fn(group: u8, context: &mut T);
+
+/// The type of function called when the session times out. See
+/// [`Runner::set_session_timeout`].
+pub type SessionTimeoutFn<T> = fn(context: &mut T);
+
+/// Whether a command in a throttle group may run. Returned by a
+/// [`ThrottleFn`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleDecision {
+    /// Run the command
+    Allow,
+    /// Don't run the command, and print this message instead
+    Deny(&'static str),
+    /// Don't run the command, and don't say why
+    DenyQuiet,
+}
+
+/// Like a [`MenuCallbackFn`] for entering a menu, but told more about how
+/// the menu was entered.
+pub type MenuEntryFn<I, T> = fn(entry: &EntryContext<I, T>, interface: &mut I, context: &mut T);
+
+/// Where a menu was entered from. Given to a [`MenuEntryFn`].
+pub struct EntryContext<'e, 'a, I, T> {
+    /// The menu being entered
+    pub entered: &'e Menu<'a, I, T>,
+    /// The menu which `entered` is an item of, or `None` for the root menu
+    pub parent: Option<&'e Menu<'a, I, T>>,
+    /// The menu we were in before, or `None` if the `Runner` is just starting
+    pub previous: Option<&'e Menu<'a, I, T>>,
+}
+
 /// The type of function we call when we a valid command has been entered.
-pub type ItemCallbackFn<I, T> =
-    fn(menu: &Menu<I, T>, item: &Item<I, T>, args: &[&str], interface: &mut I, context: &mut T);
+///
+/// Write to the interface through the [`CommandContext`], which can also ask
+/// the `Runner` to change menus and so on once the callback returns.
+pub type ItemCallbackFn<I, T> = fn(
+    menu: &Menu<I, T>,
+    item: &Item<I, T>,
+    args: &[&str],
+    interface: &mut CommandContext<I, T>,
+    context: &mut T,
+);
+
+/// The arguments given to an [`ArgsCallbackFn`], checked against the item's
+/// parameters, to look up by name or position.
+pub type Args<'p, 'a> = parser::ParsedArgs<'p, 'a>;
+
+/// The type of function we call when a command made with [`Item::new_args`]
+/// has been entered. It's like an [`ItemCallbackFn`], but finds its
+/// arguments in [`Args`] rather than with [`argument_finder`].
+pub type ArgsCallbackFn<I, T> = fn(
+    menu: &Menu<I, T>,
+    item: &Item<I, T>,
+    args: &Args,
+    interface: &mut CommandContext<I, T>,
+    context: &mut T,
+);
+
+impl<I, T> Handler<I, T> for ArgsCallbackFn<I, T>
+where
+    I: Write,
+{
+    fn handle(
+        &self,
+        menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &[&str],
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    ) {
+        // Called without the `Runner`, which would have checked them
+        match parser::ArgumentParser::new(item.parameters()).parse_arguments(args) {
+            Ok(parsed) => self(menu, item, &parsed, interface, context),
+            Err(e) => {
+                writeln!(interface, "{}", e).ok();
+                interface.fail("Bad arguments");
+            }
+        }
+    }
 
-#[derive(Debug)]
+    fn handle_parsed(
+        &self,
+        menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &Args,
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    ) {
+        self(menu, item, args, interface, context)
+    }
+}
+
+/// Something that can handle a command, like an [`ItemCallbackFn`] but able
+/// to carry its own state.
+///
+/// Closures with the right signature, and `fn`s, are `Handler`s already. For
+/// a `static` or `const` menu the handler must be `Sync` and live for
+/// `'static`: a closure which captures nothing, or a reference to a `static`
+/// value, is fine. Any state inside a shared handler needs interior
+/// mutability, such as an atomic.
+pub trait Handler<I, T> {
+    /// Called when the item's command has been entered.
+    fn handle(
+        &self,
+        menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &[&str],
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    );
+
+    /// Called by the `Runner` instead of [`Handler::handle`], with the
+    /// arguments it has already checked against the item's parameters.
+    ///
+    /// By default, this calls `handle` with the arguments as they were
+    /// typed.
+    fn handle_parsed(
+        &self,
+        menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &Args,
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    ) {
+        self.handle(menu, item, args.arguments(), interface, context)
+    }
+}
+
+impl<I, T, F> Handler<I, T> for F
+where
+    F: Fn(&Menu<I, T>, &Item<I, T>, &[&str], &mut CommandContext<I, T>, &mut T),
+{
+    fn handle(
+        &self,
+        menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &[&str],
+        interface: &mut CommandContext<I, T>,
+        context: &mut T,
+    ) {
+        self(menu, item, args, interface, context)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Describes a parameter to the command
 pub enum Parameter<'a> {
     /// A mandatory positional parameter
     Mandatory {
-        /// A name for this mandatory positional parameter
+        /// A name for this mandatory positional parameter, which
+        /// [`argument_finder`] looks it up by
         parameter_name: &'a str,
         /// Help text
         help: Option<&'a str>,
+        /// What `help` calls it, if not `parameter_name`
+        display: Option<&'a str>,
     },
     /// An optional positional parameter. Must come after the mandatory positional arguments.
     Optional {
-        /// A name for this optional positional parameter
+        /// A name for this optional positional parameter, which
+        /// [`argument_finder`] looks it up by
         parameter_name: &'a str,
         /// Help text
         help: Option<&'a str>,
+        /// What `help` calls it, if not `parameter_name`
+        display: Option<&'a str>,
+        /// What [`argument_finder`] gives if it isn't given
+        default: Option<&'a str>,
     },
     /// An optional named parameter with no argument (e.g. `--verbose` or `--dry-run`)
     Named {
@@ -36,8 +311,12 @@ pub enum Parameter<'a> {
         parameter_name: &'a str,
         /// Help text
         help: Option<&'a str>,
+        /// The character which can come after a `-` instead, like `v` for `-v`
+        short: Option<char>,
     },
-    /// A optional named parameter with argument (e.g. `--mode=foo` or `--level=3`)
+    /// A optional named parameter with argument (e.g. `--mode=foo` or `--level=3`).
+    /// The argument can also be the next word, as in `--level 3`, unless
+    /// it starts with `--` or there's a `Named` parameter of the same name.
     NamedValue {
         /// The bit that comes after the `--`
         parameter_name: &'a str,
@@ -45,9 +324,282 @@ pub enum Parameter<'a> {
         argument_name: &'a str,
         /// Help text
         help: Option<&'a str>,
+        /// The character which can come after a `-` instead, like `l` for
+        /// `-l=3` or `-l 3`
+        short: Option<char>,
+        /// What [`argument_finder`] gives if it isn't given
+        default: Option<&'a str>,
+        /// The only values it can be given, if not any
+        allowed_values: Option<&'a [&'a str]>,
     },
 }
 
+impl<'a> Parameter<'a> {
+    /// Make a mandatory positional parameter.
+    pub const fn mandatory(parameter_name: &'a str) -> Self {
+        Parameter::Mandatory {
+            parameter_name,
+            help: None,
+            display: None,
+        }
+    }
+
+    /// Make an optional positional parameter.
+    pub const fn optional(parameter_name: &'a str) -> Self {
+        Parameter::Optional {
+            parameter_name,
+            help: None,
+            display: None,
+            default: None,
+        }
+    }
+
+    /// Make a named parameter with no argument, like `--verbose`.
+    pub const fn named(parameter_name: &'a str) -> Self {
+        Parameter::Named {
+            parameter_name,
+            help: None,
+            short: None,
+        }
+    }
+
+    /// Make a named parameter with an argument, like `--level=INT`.
+    pub const fn named_value(parameter_name: &'a str, argument_name: &'a str) -> Self {
+        Parameter::NamedValue {
+            parameter_name,
+            argument_name,
+            help: None,
+            short: None,
+            default: None,
+            allowed_values: None,
+        }
+    }
+
+    /// Set the help text for this parameter.
+    pub const fn help(self, help: &'a str) -> Self {
+        match self {
+            Parameter::Mandatory {
+                parameter_name,
+                display,
+                ..
+            } => Parameter::Mandatory {
+                parameter_name,
+                help: Some(help),
+                display,
+            },
+            Parameter::Optional {
+                parameter_name,
+                display,
+                default,
+                ..
+            } => Parameter::Optional {
+                parameter_name,
+                help: Some(help),
+                display,
+                default,
+            },
+            Parameter::Named {
+                parameter_name,
+                short,
+                ..
+            } => Parameter::Named {
+                parameter_name,
+                help: Some(help),
+                short,
+            },
+            Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                short,
+                default,
+                allowed_values,
+                ..
+            } => Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help: Some(help),
+                short,
+                default,
+                allowed_values,
+            },
+        }
+    }
+
+    /// Let this option be given as a `-` and one character too, like `-v`
+    /// for `--verbose`. Positional parameters can't be, so this only
+    /// changes options. It shouldn't be a digit, as `-4` is a number.
+    pub const fn short(self, short: char) -> Self {
+        match self {
+            Parameter::Named {
+                parameter_name,
+                help,
+                ..
+            } => Parameter::Named {
+                parameter_name,
+                help,
+                short: Some(short),
+            },
+            Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                default,
+                allowed_values,
+                ..
+            } => Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                short: Some(short),
+                default,
+                allowed_values,
+            },
+            other => other,
+        }
+    }
+
+    /// The character given to [`Parameter::short`], if any.
+    pub const fn short_name(&self) -> Option<char> {
+        match self {
+            Parameter::Named { short, .. } | Parameter::NamedValue { short, .. } => *short,
+            _ => None,
+        }
+    }
+
+    /// Set what `help` calls this parameter, like `<ip-address>` for a
+    /// parameter looked up as `ip`. Options are always shown as they're
+    /// typed, so this only changes positional parameters.
+    pub const fn display(self, display: &'a str) -> Self {
+        match self {
+            Parameter::Mandatory {
+                parameter_name,
+                help,
+                ..
+            } => Parameter::Mandatory {
+                parameter_name,
+                help,
+                display: Some(display),
+            },
+            Parameter::Optional {
+                parameter_name,
+                help,
+                default,
+                ..
+            } => Parameter::Optional {
+                parameter_name,
+                help,
+                display: Some(display),
+                default,
+            },
+            other => other,
+        }
+    }
+
+    /// Set what [`argument_finder`] gives for this parameter when it isn't
+    /// given, like `115200` for a baud rate, which `help` also shows. Only
+    /// `Optional` and `NamedValue` parameters can have one, so this doesn't
+    /// change others. An empty value given as `--rate=` is still empty.
+    pub const fn default(self, default: &'a str) -> Self {
+        match self {
+            Parameter::Optional {
+                parameter_name,
+                help,
+                display,
+                ..
+            } => Parameter::Optional {
+                parameter_name,
+                help,
+                display,
+                default: Some(default),
+            },
+            Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                short,
+                allowed_values,
+                ..
+            } => Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                short,
+                default: Some(default),
+                allowed_values,
+            },
+            other => other,
+        }
+    }
+
+    /// Only let this option be given one of `values`, like `fast`, `slow`
+    /// or `auto` for `--mode`. Any other value is an error before the
+    /// command runs. They're compared exactly, as commands are. The long
+    /// help lists them, and Tab completes them. Only `NamedValue`
+    /// parameters can have them, so this doesn't change others.
+    pub const fn allowed_values(self, values: &'a [&'a str]) -> Self {
+        match self {
+            Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                short,
+                default,
+                ..
+            } => Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                help,
+                short,
+                default,
+                allowed_values: Some(values),
+            },
+            other => other,
+        }
+    }
+
+    /// The values given to [`Parameter::allowed_values`], if any.
+    pub const fn allowed(&self) -> Option<&'a [&'a str]> {
+        match self {
+            Parameter::NamedValue { allowed_values, .. } => *allowed_values,
+            _ => None,
+        }
+    }
+
+    /// The value given to [`Parameter::default`], if any.
+    pub const fn default_value(&self) -> Option<&'a str> {
+        match self {
+            Parameter::Optional { default, .. } | Parameter::NamedValue { default, .. } => *default,
+            _ => None,
+        }
+    }
+
+    /// The name [`argument_finder`] looks this parameter up by.
+    pub const fn name(&self) -> &'a str {
+        match self {
+            Parameter::Mandatory { parameter_name, .. }
+            | Parameter::Optional { parameter_name, .. }
+            | Parameter::Named { parameter_name, .. }
+            | Parameter::NamedValue { parameter_name, .. } => parameter_name,
+        }
+    }
+
+    /// The name `help` shows for this parameter: the one given to
+    /// [`Parameter::display`], or else [`Parameter::name`].
+    pub const fn display_name(&self) -> &'a str {
+        match self {
+            Parameter::Mandatory {
+                display: Some(display),
+                ..
+            }
+            | Parameter::Optional {
+                display: Some(display),
+                ..
+            } => display,
+            _ => self.name(),
+        }
+    }
+}
+
 /// Do we enter a sub-menu when this command is entered, or call a specific
 /// function?
 pub enum ItemType<'a, I, T>
@@ -61,15 +613,39 @@ where
         /// The list of parameters for this function. Pass an empty list if there aren't any.
         parameters: &'a [Parameter<'a>],
     },
+    /// Call a function with the [`Args`] when this command is entered
+    ArgsCallback {
+        /// The function to call
+        function: ArgsCallbackFn<I, T>,
+        /// The list of parameters for this function. Pass an empty list if there aren't any.
+        parameters: &'a [Parameter<'a>],
+    },
+    /// Call a [`Handler`] when this command is entered
+    Handler {
+        /// The handler to call
+        handler: &'a (dyn Handler<I, T> + Sync),
+        /// The list of parameters for this handler. Pass an empty list if there aren't any.
+        parameters: &'a [Parameter<'a>],
+    },
+    /// Call the context's [`CommandHandler`] when this command is entered. See
+    /// [`Runner::enable_dispatch`].
+    Dispatch {
+        /// Passed to [`CommandHandler::dispatch`], to say which command this is
+        id: CommandId,
+        /// The list of parameters for this command. Pass an empty list if there aren't any.
+        parameters: &'a [Parameter<'a>],
+    },
     /// This item is a sub-menu you can enter
     Menu(&'a Menu<'a, I, T>),
-    /// Internal use only - do not use
-    _Dummy,
 }
 
 /// An `Item` is a what our menus are made from. Each item has a `name` which
 /// you have to enter to select this item. Each item can also have zero or
 /// more parameters, and some optional help text.
+///
+/// Prefer building an `Item` with [`Item::new`] or [`Item::new_menu`] over
+/// filling in the fields yourself - new fields may be added in future, and
+/// the builder gives them sensible defaults.
 pub struct Item<'a, I, T>
 where
     T: 'a,
@@ -81,9 +657,254 @@ where
     pub help: Option<&'a str>,
     /// The type of this item - menu, callback, etc.
     pub item_type: ItemType<'a, I, T>,
+    /// Hidden items work as usual but aren't listed by `help`.
+    pub hidden: bool,
+    /// Anything you like, for the callback to find with [`Item::data_as`].
+    /// Handy when many items share one callback.
+    pub data: Option<&'a (dyn Any + Sync)>,
+    /// Don't print a prompt after this item's callback returns.
+    pub suppress_prompt_after: bool,
+    /// Ask the throttle function before running this item. See
+    /// [`Runner::set_throttle`].
+    pub throttle_group: Option<u8>,
+}
+
+impl<'a, I, T> Item<'a, I, T> {
+    /// Make an item which calls `function` when `command` is entered.
+    pub const fn new(
+        command: &'a str,
+        function: ItemCallbackFn<I, T>,
+        parameters: &'a [Parameter<'a>],
+    ) -> Self {
+        Item {
+            command,
+            help: None,
+            item_type: ItemType::Callback {
+                function,
+                parameters,
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        }
+    }
+
+    /// Make an item which calls `function` with its [`Args`] when `command`
+    /// is entered.
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// use core::fmt::Write;
+    ///
+    /// fn dim(
+    ///     _menu: &Menu<String, ()>,
+    ///     _item: &Item<String, ()>,
+    ///     args: &Args,
+    ///     interface: &mut CommandContext<String, ()>,
+    ///     _context: &mut (),
+    /// ) {
+    ///     let level: u8 = args.parse("level").unwrap().unwrap();
+    ///     let slowly = args.contains("slowly");
+    ///     writeln!(interface, "Dimming to {} (slowly: {})", level, slowly).unwrap();
+    /// }
+    ///
+    /// static ITEM: Item<String, ()> = Item::new_args(
+    ///     "dim",
+    ///     dim,
+    ///     &[Parameter::mandatory("level"), Parameter::named("slowly")],
+    /// );
+    /// ```
+    pub const fn new_args(
+        command: &'a str,
+        function: ArgsCallbackFn<I, T>,
+        parameters: &'a [Parameter<'a>],
+    ) -> Self {
+        Item {
+            command,
+            help: None,
+            item_type: ItemType::ArgsCallback {
+                function,
+                parameters,
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        }
+    }
+
+    /// Make an item which calls `handler` when `command` is entered.
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// use core::fmt::Write;
+    /// use core::sync::atomic::{AtomicU32, Ordering};
+    ///
+    /// struct Counter(AtomicU32);
+    ///
+    /// impl<I: core::fmt::Write, T> Handler<I, T> for Counter {
+    ///     fn handle(
+    ///         &self,
+    ///         _menu: &Menu<I, T>,
+    ///         _item: &Item<I, T>,
+    ///         _args: &[&str],
+    ///         interface: &mut CommandContext<I, T>,
+    ///         _context: &mut T,
+    ///     ) {
+    ///         let count = self.0.fetch_add(1, Ordering::Relaxed) + 1;
+    ///         writeln!(interface, "Called {} times", count).unwrap();
+    ///     }
+    /// }
+    ///
+    /// static COUNTER: Counter = Counter(AtomicU32::new(0));
+    /// static ITEM: Item<String, ()> = Item::new_handler("count", &COUNTER, &[]);
+    /// ```
+    pub const fn new_handler(
+        command: &'a str,
+        handler: &'a (dyn Handler<I, T> + Sync),
+        parameters: &'a [Parameter<'a>],
+    ) -> Self {
+        Item {
+            command,
+            help: None,
+            item_type: ItemType::Handler {
+                handler,
+                parameters,
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        }
+    }
+
+    /// Make an item which calls the context's [`CommandHandler`] with `id`
+    /// when `command` is entered.
+    pub const fn new_dispatch(
+        command: &'a str,
+        id: CommandId,
+        parameters: &'a [Parameter<'a>],
+    ) -> Self {
+        Item {
+            command,
+            help: None,
+            item_type: ItemType::Dispatch { id, parameters },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        }
+    }
+
+    /// Make an item which enters `menu` when `command` is entered.
+    pub const fn new_menu(command: &'a str, menu: &'a Menu<'a, I, T>) -> Self {
+        Item {
+            command,
+            help: None,
+            item_type: ItemType::Menu(menu),
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        }
+    }
+
+    /// Set the help text for this item.
+    pub const fn help(mut self, help: &'a str) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    /// Leave this item out of the list printed by `help`.
+    pub const fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    /// Don't print a prompt after this item's callback returns, just that
+    /// once.
+    ///
+    /// This suits a command which streams output until a key is pressed,
+    /// perhaps polling for it with [`CommandContext::poll_key`], and which
+    /// doesn't want a prompt in the middle of its output.
+    pub const fn suppress_prompt_after(mut self) -> Self {
+        self.suppress_prompt_after = true;
+        self
+    }
+
+    /// Put this item in a throttle group, so the function given to
+    /// [`Runner::set_throttle`] can stop it running, such as after too many
+    /// wrong passwords.
+    pub const fn throttle_group(mut self, group: u8) -> Self {
+        self.throttle_group = Some(group);
+        self
+    }
+
+    /// Attach some data to this item, for the callback to find with
+    /// [`Item::data_as`].
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// # fn read_register(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+    /// struct Register {
+    ///     address: u32,
+    /// }
+    ///
+    /// const ITEMS: &[&Item<(), ()>] = &[
+    ///     &Item::new("ctrl", read_register, &[]).data(&Register { address: 0x40 }),
+    ///     &Item::new("status", read_register, &[]).data(&Register { address: 0x44 }),
+    /// ];
+    /// ```
+    pub const fn data<D>(mut self, data: &'a D) -> Self
+    where
+        D: Any + Sync,
+    {
+        self.data = Some(data);
+        self
+    }
+
+    /// The item's parameters, or none for a sub-menu.
+    pub const fn parameters(&self) -> &'a [Parameter<'a>] {
+        match self.item_type {
+            ItemType::Callback { parameters, .. }
+            | ItemType::ArgsCallback { parameters, .. }
+            | ItemType::Handler { parameters, .. }
+            | ItemType::Dispatch { parameters, .. } => parameters,
+            ItemType::Menu(_) => &[],
+        }
+    }
+
+    /// Is this item a sub-menu?
+    pub const fn is_menu(&self) -> bool {
+        matches!(self.item_type, ItemType::Menu(_))
+    }
+
+    /// The sub-menu this item enters, if it's a sub-menu item.
+    pub const fn submenu(&self) -> Option<&'a Menu<'a, I, T>> {
+        match self.item_type {
+            ItemType::Menu(menu) => Some(menu),
+            _ => None,
+        }
+    }
+
+    /// Get the data attached with [`Item::data`], if there is some and it
+    /// is a `D`.
+    pub fn data_as<D>(&self) -> Option<&'a D>
+    where
+        D: Any,
+    {
+        self.data.and_then(|data| (data as &dyn Any).downcast_ref())
+    }
 }
 
 /// A `Menu` is made of one or more `Item`s.
+///
+/// Prefer building a `Menu` with [`Menu::new`] over filling in the fields
+/// yourself.
+///
+/// A `Menu` is `Copy`, so one `static` menu can be given to several
+/// `Runner`s, such as one for each console. See `examples/dual_console.rs`.
 pub struct Menu<'a, I, T>
 where
     T: 'a,
@@ -97,29 +918,173 @@ where
     pub entry: Option<MenuCallbackFn<I, T>>,
     /// A function to call when this menu is exited. Never called for the root menu.
     pub exit: Option<MenuCallbackFn<I, T>>,
+    /// A function to call when this menu is entered, after `entry`, which is
+    /// told where the menu was entered from.
+    pub entry_with_context: Option<MenuEntryFn<I, T>>,
+}
+
+impl<'a, I, T> Menu<'a, I, T> {
+    /// Make a menu with the given label and items.
+    pub const fn new(label: &'a str, items: &'a [&'a Item<'a, I, T>]) -> Self {
+        Menu {
+            label,
+            items,
+            entry: None,
+            exit: None,
+            entry_with_context: None,
+        }
+    }
+
+    /// Set the function to call when this menu is entered.
+    pub const fn entry(mut self, entry: MenuCallbackFn<I, T>) -> Self {
+        self.entry = Some(entry);
+        self
+    }
+
+    /// Set the function to call when this menu is entered, which is told
+    /// where the menu was entered from.
+    pub const fn entry_with_context(mut self, entry: MenuEntryFn<I, T>) -> Self {
+        self.entry_with_context = Some(entry);
+        self
+    }
+
+    /// Set the function to call when this menu is exited.
+    ///
+    /// The root menu is only exited when the session ends, with Ctrl-D on
+    /// an empty line.
+    pub const fn exit(mut self, exit: MenuCallbackFn<I, T>) -> Self {
+        self.exit = Some(exit);
+        self
+    }
+
+    /// The item `command` runs, and where it is in the menu, found the way
+    /// a command line's first word is.
+    pub(crate) fn find(&self, command: &str) -> Option<(usize, &'a Item<'a, I, T>)> {
+        let items = self.items;
+        items
+            .iter()
+            .position(|item| item.command == command)
+            .map(|i| (i, items[i]))
+    }
 }
 
 /// This structure handles the menu. You feed it bytes as they are read from
 /// the console and it executes menu actions when commands are typed in
 /// (followed by Enter).
+///
+/// # After a callback
+///
+/// Whatever a callback did, once it returns:
+///
+/// * The next prompt starts on a new line, if the callback's output didn't
+///   end with one, unless [`PromptSpacing::Never`] is set.
+/// * At most one thing is waiting for input: the last of raw mode,
+///   passthrough, a question or a line the callback asked for. A callback
+///   given the answer to a question or a line can ask for another, and the
+///   prompt comes back once nothing is waiting.
+/// * The line being typed is empty, or holds the template the callback
+///   gave [`CommandContext::prefill`] or [`CommandContext::edit_line`].
+///   The `Runner` doesn't keep input of its own, so each byte given to
+///   [`Runner::input_byte`] afterwards goes to whatever is waiting for it,
+///   or to the next command line. Keys a
+///   callback read with [`CommandContext::poll_key`] are gone.
 pub struct Runner<'a, I, T>
 where
     I: core::fmt::Write,
 {
     buffer: &'a mut [u8],
     used: usize,
+    /// Where in the line new characters go, in bytes
+    cursor: usize,
+    /// How far through an escape sequence typed on the line we are
+    #[cfg(feature = "ansi")]
+    escape: input::Escape,
+    started: bool,
     menu_mgr: menu_manager::MenuManager<'a, I, T>,
+    output: OutputState,
+    raw_mode: Option<RawModeFn<I, T>>,
+    passthrough: Option<(PassthroughFn<T>, EscapeMatcher)>,
+    confirm: Option<ConfirmFn<I, T>>,
+    line_input: Option<command::LineRequest<I, T>>,
+    /// Showing `--More--`, with the next page of a callback's output
+    #[cfg(feature = "paging")]
+    more: Option<command::Page>,
+    /// Lines of paged output shown before `--More--`
+    #[cfg(feature = "paging")]
+    page_height: Option<usize>,
+    /// Which lines the next callback shows, if it pages its output
+    #[cfg(feature = "paging")]
+    page: Option<command::Page>,
+    /// Where in the buffer a callback left a line to start the next one
+    /// with
+    template: Option<Range<usize>>,
+    busy_hook: Option<BusyHookFn<T>>,
+    interrupt_hook: Option<MenuCallbackFn<I, T>>,
+    keys: command::Keys<I>,
+    /// Don't print the prompt after the command being run
+    suppress_prompt: bool,
+    /// The line was thrown away with Ctrl-U or ESC, so an empty one isn't
+    /// run
+    #[cfg(feature = "ansi")]
+    discarded: bool,
+    /// What's typed replaces the character at the cursor, after Insert
+    #[cfg(feature = "line-editing")]
+    overwrite: bool,
+    #[cfg(feature = "history")]
+    history: Option<history::History<'a>>,
+    /// Searching the history, after Ctrl-R
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    search: Option<history::Search>,
+    /// Where we are with bracketed paste, if it's on
+    #[cfg(feature = "ansi")]
+    paste: Option<paste::Paste>,
+    /// Where we are in a telnet command, and how to refuse options, if
+    /// telnet's commands are being taken out of the input
+    #[cfg(feature = "telnet")]
+    telnet: Option<(telnet::Telnet, Option<TelnetReplyFn<T>>)>,
+    #[cfg(feature = "builtins-extra")]
+    macros: Option<macros::Macros<'a>>,
+    capture: Option<capture::CaptureBuffer<'a>>,
+    #[cfg(feature = "history")]
+    history_list_len: usize,
+    prompt_spacing: PromptSpacing,
+    prompt_rendering: PromptRendering<T>,
+    prompt_prefix: &'static str,
+    terminal_title: Option<&'static str>,
+    colour: bool,
+    help_links: Option<&'static str>,
+    #[cfg(feature = "builtins-extra")]
+    about: Option<About>,
+    machine_mode: bool,
+    input_encoding: InputEncoding,
+    echo: Echo,
+    line_ending: LineEnding,
+    key_bindings: &'a [KeyBinding<'a>],
+    /// How much of a binding's sequence has been typed
+    key_matcher: keymap::KeyMatcher,
+    bell: bool,
+    /// The line was too long for the buffer, so the rest of it is thrown
+    /// away, and it isn't run
+    overflowed: bool,
+    /// The last byte given to [`Runner::input_byte`] was a carriage return
+    after_cr: bool,
+    allow_option_prefixes: bool,
+    #[cfg(feature = "completion")]
+    value_completer: Option<ValueCompleterFn<I, T>>,
+    throttle: Option<(ThrottleFn<T>, ThrottleFailureFn<T>)>,
+    dispatcher: Option<&'a dyn Handler<I, T>>,
+    #[cfg(feature = "builtins-extra")]
+    show: Option<value::ShowFn<I, T>>,
+    session_timeout: Option<(SessionTimeout, SessionTimeoutFn<T>)>,
+    /// Calls to `tick` since the last input
+    idle_ticks: u32,
     pub interface: I,
 }
 
-/// Describes the ways in which the API can fail
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Error {
-    /// Tried to find arguments on an item that was a `Callback` item
-    NotACallbackItem,
-    /// The argument you asked for was not found
-    NotFound,
-}
+/// The old name of [`MenuError`], from before it held more than the errors
+/// of [`argument_finder`] and friends.
+#[deprecated(note = "Use `MenuError`")]
+pub type Error = MenuError<'static>;
 
 /// Looks for the named parameter in the parameter list of the item, then
 /// finds the correct argument.
@@ -135,118 +1100,532 @@ pub fn argument_finder<'a, I, T>(
     item: &'a Item<'a, I, T>,
     argument_list: &'a [&'a str],
     name_to_find: &'a str,
-) -> Result<Option<&'a str>, Error> {
-    let ItemType::Callback { parameters, .. } = item.item_type else {
-        return Err(Error::NotACallbackItem);
-    };
-    // Step 1 - Find `name_to_find` in the parameter list.
-    let mut found_param = None;
-    let mut mandatory_count = 0;
-    let mut optional_count = 0;
-    for param in parameters.iter() {
-        match param {
-            Parameter::Mandatory { parameter_name, .. } => {
-                mandatory_count += 1;
-                if *parameter_name == name_to_find {
-                    found_param = Some((param, mandatory_count));
-                }
-            }
-            Parameter::Optional { parameter_name, .. } => {
-                optional_count += 1;
-                if *parameter_name == name_to_find {
-                    found_param = Some((param, optional_count));
-                }
-            }
-            Parameter::Named { parameter_name, .. } => {
-                if *parameter_name == name_to_find {
-                    found_param = Some((param, 0));
-                }
-            }
-            Parameter::NamedValue { parameter_name, .. } => {
-                if *parameter_name == name_to_find {
-                    found_param = Some((param, 0));
-                }
-            }
-        }
+) -> Result<Option<&'a str>, MenuError<'static>> {
+    if item.is_menu() {
+        return Err(MenuError::NotACallbackItem);
     }
-    // Step 2 - What sort of parameter is it?
-    match found_param {
-        // Step 2a - Mandatory Positional
-        Some((Parameter::Mandatory { .. }, mandatory_idx)) => {
-            // We want positional parameter number `mandatory_idx`.
-            let mut positional_args_seen = 0;
-            for arg in argument_list.iter().filter(|x| !x.starts_with("--")) {
-                // Positional
-                positional_args_seen += 1;
-                if positional_args_seen == mandatory_idx {
-                    return Ok(Some(arg));
-                }
-            }
-            // Valid thing to ask for but we don't have it
-            Ok(None)
+    parser::find_argument(item.parameters(), argument_list, name_to_find)
+}
+
+/// Finds the argument for the named parameter of the item, like
+/// [`argument_finder`], and parses it as a `V`.
+///
+/// * Returns `Ok(None)` if the argument wasn't given, and the parameter has
+///   no default.
+/// * Returns `Err(ArgError::NotFound)` if `parameter_name` isn't one of the
+///   item's parameters.
+/// * Returns `Err(ArgError::BadValue)`, with what was typed, if it
+///   couldn't be parsed, such as `300` for a `u8`.
+///
+/// ```rust
+/// # use menu::*;
+/// # fn noop(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+/// const DIM: Item<(), ()> = Item::new("dim", noop, &[Parameter::mandatory("level")]);
+/// assert_eq!(argument_parse::<u8, _, _>(&DIM, &["75"], "level"), Ok(Some(75)));
+/// ```
+pub fn argument_parse<'a, V, I, T>(
+    item: &'a Item<'a, I, T>,
+    argument_list: &'a [&'a str],
+    name_to_find: &'a str,
+) -> Result<Option<V>, parser::ArgError<'a>>
+where
+    V: parser::FromArgument<'a>,
+{
+    parser::parse_found(
+        argument_finder(item, argument_list, name_to_find),
+        name_to_find,
+    )
+}
+
+/// Counts the arguments given for the named parameter of the item: how
+/// many times an option was given, such as 3 for `-vvv`, or 0 or 1 for a
+/// positional parameter.
+///
+/// Gives the same errors as [`argument_finder`].
+pub fn argument_count<'a, I, T>(
+    item: &'a Item<'a, I, T>,
+    argument_list: &'a [&'a str],
+    name_to_find: &'a str,
+) -> Result<usize, MenuError<'static>> {
+    Ok(arguments_iter(item, argument_list, name_to_find)?.count())
+}
+
+/// Finds every argument given for the named parameter of the item, in the
+/// order they were given, such as each path of `--include=a --include=b`.
+///
+/// Gives the same errors as [`argument_finder`].
+pub fn arguments_iter<'a, I, T>(
+    item: &'a Item<'a, I, T>,
+    argument_list: &'a [&'a str],
+    name_to_find: &'a str,
+) -> Result<parser::Values<'a, 'a, 'a>, MenuError<'static>> {
+    if item.is_menu() {
+        return Err(MenuError::NotACallbackItem);
+    }
+    parser::find_arguments(item.parameters(), argument_list, name_to_find)
+}
+
+/// Works out the length of the longest command line a user could plausibly
+/// type into the given menu, or any of its sub-menus.
+///
+/// Each positional parameter counts as its parameter name, each named
+/// parameter as `--name` and each named value as `--name=ARG`, with a single
+/// space between each word. The built-in `help <command>` is included too.
+/// Values typed by the user may of course be longer than the names used here,
+/// so add some headroom:
+///
+/// ```rust,ignore
+/// const MENU_MAX_LINE: usize = menu::max_line_len(&ROOT_MENU);
+/// let mut buffer = [0u8; MENU_MAX_LINE + 32];
+/// ```
+pub const fn max_line_len<I, T>(menu: &Menu<I, T>) -> usize {
+    max_line_len_at_depth(menu, 0)
+}
+
+const fn max_line_len_at_depth<I, T>(menu: &Menu<I, T>, depth: usize) -> usize {
+    const HELP: &str = "help";
+    const EXIT: &str = "exit";
+    let mut longest_command = 0;
+    let mut longest_line = if depth != 0 { EXIT.len() } else { 0 };
+    let mut i = 0;
+    while i < menu.items.len() {
+        let item = menu.items[i];
+        let mut line = item.command.len();
+        if line > longest_command {
+            longest_command = line;
         }
-        // Step 2b - Optional Positional
-        Some((Parameter::Optional { .. }, optional_idx)) => {
-            // We want positional parameter number `mandatory_count + optional_idx`.
-            let mut positional_args_seen = 0;
-            for arg in argument_list.iter().filter(|x| !x.starts_with("--")) {
-                // Positional
-                positional_args_seen += 1;
-                if positional_args_seen == (mandatory_count + optional_idx) {
-                    return Ok(Some(arg));
+        match item.submenu() {
+            None => {
+                let parameters = item.parameters();
+                let mut p = 0;
+                while p < parameters.len() {
+                    // One for the space before each argument
+                    line += 1 + match parameters[p] {
+                        Parameter::Mandatory { parameter_name, .. }
+                        | Parameter::Optional { parameter_name, .. } => parameter_name.len(),
+                        Parameter::Named { parameter_name, .. } => 2 + parameter_name.len(),
+                        Parameter::NamedValue {
+                            parameter_name,
+                            argument_name,
+                            ..
+                        } => 2 + parameter_name.len() + 1 + argument_name.len(),
+                    };
+                    p += 1;
                 }
             }
-            // Valid thing to ask for but we don't have it
-            Ok(None)
-        }
-        // Step 2c - Named (e.g. `--verbose`)
-        Some((Parameter::Named { parameter_name, .. }, _)) => {
-            for arg in argument_list {
-                if arg.starts_with("--") && (&arg[2..] == *parameter_name) {
-                    return Ok(Some(""));
+            Some(sub_menu) => {
+                if depth < menu_manager::MAX_DEPTH {
+                    let sub_line = max_line_len_at_depth(sub_menu, depth + 1);
+                    if sub_line > line {
+                        line = sub_line;
+                    }
                 }
             }
-            // Valid thing to ask for but we don't have it
-            Ok(None)
         }
-        // Step 2d - NamedValue (e.g. `--level=123`)
-        Some((Parameter::NamedValue { parameter_name, .. }, _)) => {
-            let name_start = 2;
-            let equals_start = name_start + parameter_name.len();
-            let value_start = equals_start + 1;
-            for arg in argument_list {
-                if arg.starts_with("--")
-                    && (arg.len() >= value_start)
-                    && (arg.get(equals_start..=equals_start) == Some("="))
-                    && (arg.get(name_start..equals_start) == Some(*parameter_name))
-                {
-                    return Ok(Some(&arg[value_start..]));
-                }
-            }
-            // Valid thing to ask for but we don't have it
-            Ok(None)
+        if line > longest_line {
+            longest_line = line;
         }
-        // Step 2e - not found
-        _ => Err(Error::NotFound),
+        i += 1;
+    }
+    let help_line = HELP.len() + 1 + longest_command;
+    if help_line > longest_line {
+        help_line
+    } else {
+        longest_line
     }
 }
 
-enum Outcome {
-    CommandProcessed,
-    NeedMore,
+/// Checks that no two items in the list share a command, and that the same
+/// is true inside every sub-menu.
+///
+/// This is a `const fn`, so you can check your menu at compile time:
+///
+/// ```rust
+/// # use menu::*;
+/// # fn f(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+/// const ITEMS: &[&Item<(), ()>] = &[&Item::new("foo", f, &[]), &Item::new("bar", f, &[])];
+/// const _: () = assert!(menu::assert_unique_commands(ITEMS));
+/// ```
+///
+/// A duplicate stops the build:
+///
+/// ```rust,compile_fail
+/// # use menu::*;
+/// # fn f(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+/// const ITEMS: &[&Item<(), ()>] = &[&Item::new("foo", f, &[]), &Item::new("foo", f, &[])];
+/// const _: () = assert!(menu::assert_unique_commands(ITEMS));
+/// ```
+///
+/// ```rust,compile_fail
+/// # use menu::*;
+/// # fn f(_: &Menu<(), ()>, _: &Item<(), ()>, _: &[&str], _: &mut CommandContext<(), ()>, _: &mut ()) {}
+/// const SUB: Menu<(), ()> = Menu::new("sub", &[&Item::new("a", f, &[]), &Item::new("a", f, &[])]);
+/// const ITEMS: &[&Item<(), ()>] = &[&Item::new_menu("sub", &SUB)];
+/// const _: () = assert!(menu::assert_unique_commands(ITEMS));
+/// ```
+pub const fn assert_unique_commands<I, T>(items: &[&Item<I, T>]) -> bool {
+    unique_commands_at_depth(items, 0)
 }
 
-impl<'a, I, T> core::clone::Clone for Menu<'a, I, T> {
-    fn clone(&self) -> Menu<'a, I, T> {
-        Menu {
-            label: self.label,
-            items: self.items,
-            entry: self.entry,
-            exit: self.exit,
+const fn unique_commands_at_depth<I, T>(items: &[&Item<I, T>], depth: usize) -> bool {
+    let mut i = 0;
+    while i < items.len() {
+        let mut j = i + 1;
+        while j < items.len() {
+            if const_str_eq(items[i].command, items[j].command) {
+                return false;
+            }
+            j += 1;
         }
-    }
+        if let Some(sub_menu) = items[i].submenu() {
+            if depth < menu_manager::MAX_DEPTH
+                && !unique_commands_at_depth(sub_menu.items, depth + 1)
+            {
+                return false;
+            }
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Checks that no two parameters share a name, which would leave
+/// [`argument_finder`] only ever finding the first, or a
+/// [`Parameter::short`] form. Two can share a [`Parameter::display`] name.
+///
+/// ```rust
+/// # use menu::*;
+/// const PARAMETERS: &[Parameter] = &[
+///     Parameter::mandatory("from").display("address"),
+///     Parameter::mandatory("to").display("address"),
+/// ];
+/// const _: () = assert!(menu::assert_unique_parameters(PARAMETERS));
+/// ```
+///
+/// ```rust,compile_fail
+/// # use menu::*;
+/// const PARAMETERS: &[Parameter] = &[Parameter::mandatory("to"), Parameter::named("to")];
+/// const _: () = assert!(menu::assert_unique_parameters(PARAMETERS));
+/// ```
+pub const fn assert_unique_parameters(parameters: &[Parameter]) -> bool {
+    let mut i = 0;
+    while i < parameters.len() {
+        let mut j = i + 1;
+        while j < parameters.len() {
+            if const_str_eq(parameters[i].name(), parameters[j].name()) {
+                return false;
+            }
+            if let (Some(a), Some(b)) = (parameters[i].short_name(), parameters[j].short_name()) {
+                if a == b {
+                    return false;
+                }
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Is `byte` the second or later byte of a UTF-8 character?
+const fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// `str::eq` isn't `const`, so compare the bytes by hand.
+const fn const_str_eq(a: &str, b: &str) -> bool {
+    let a = a.as_bytes();
+    let b = b.as_bytes();
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// What the `Runner` prints before each prompt. See
+/// [`Runner::set_prompt_spacing`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PromptSpacing {
+    /// Start a new line if the output didn't end with one, then leave a blank
+    /// line
+    #[default]
+    Always,
+    /// Start a new line if the output didn't end with one
+    OnlyIfNeeded,
+    /// Nothing, so the prompt follows straight on from any output
+    Never,
+}
+
+/// Who draws the prompt. See [`Runner::set_prompt_rendering`].
+pub enum PromptRendering<T> {
+    /// The `Runner` prints it, with the sub-menus it's in, like `/sub> `
+    Internal,
+    /// The application draws it, whenever the `Runner` would have printed
+    /// one, with the function given. The `Runner` prints nothing for it,
+    /// though it still spaces it out from the output before it and echoes
+    /// the line typed after it.
+    External(PromptFn<T>),
+    /// The `Runner` prints what the function given writes, in place of the
+    /// prefix and sub-menus, whenever it would have printed a prompt for a
+    /// command, including when it draws the line again. Questions from
+    /// [`CommandContext::read_line`] are printed as usual.
+    Custom(PromptWriteFn<T>),
+}
+
+/// What goes in a prompt drawn by a [`PromptFn`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptInfo<'p> {
+    labels: [&'p str; menu_manager::MAX_DEPTH],
+    /// How many sub-menus deep the `Runner` is, with 0 for the root menu
+    pub depth: usize,
+    /// What a callback gave to [`CommandContext::set_prompt_prefix`]
+    pub prefix: &'p str,
+    /// What a callback gave to [`CommandContext::read_line`], if the line
+    /// being typed is for it, rather than a command
+    pub question: Option<&'p str>,
+    /// The prompt was drawn already, and is being drawn again over itself
+    /// after a `\r`, with the line being typed to follow
+    pub redraw: bool,
+}
+
+impl<'p> PromptInfo<'p> {
+    /// The labels of the sub-menus the `Runner` is in, outermost first.
+    /// The root menu isn't included.
+    pub fn labels(&self) -> &[&'p str] {
+        &self.labels[..self.depth]
+    }
+}
+
+/// A built-in command which identifies the application, such as its name
+/// and firmware version. See [`Runner::set_about`].
+///
+/// ```rust
+/// # use menu::*;
+/// const ABOUT: About = About::new(&[("Product", "Widget"), ("Firmware", "1.2.3")])
+///     .with_crate_version();
+/// ```
+///
+/// Entering `about` then prints:
+///
+/// ```text
+/// Product:  Widget
+/// Firmware: 1.2.3
+/// menu:     0.5.0
+/// ```
+#[cfg(feature = "builtins-extra")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct About {
+    command: &'static str,
+    lines: &'static [(&'static str, &'static str)],
+    crate_version: bool,
+    hidden: bool,
+}
+
+#[cfg(feature = "builtins-extra")]
+impl About {
+    /// Print `lines`, each a name and a value, when `about` is entered.
+    pub const fn new(lines: &'static [(&'static str, &'static str)]) -> Self {
+        About {
+            command: "about",
+            lines,
+            crate_version: false,
+            hidden: false,
+        }
+    }
+
+    /// Use `command` rather than `about`, such as `version`.
+    pub const fn command(mut self, command: &'static str) -> Self {
+        self.command = command;
+        self
+    }
+
+    /// Add a line with the version of this crate.
+    pub const fn with_crate_version(mut self) -> Self {
+        self.crate_version = true;
+        self
+    }
+
+    /// Leave the command out of the list printed by `help`.
+    pub const fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+}
+
+/// When to log out an idle session. See [`Runner::set_session_timeout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionTimeout {
+    ticks: u32,
+    #[cfg(feature = "history")]
+    clear_history: bool,
+}
+
+impl SessionTimeout {
+    /// Time out after `ticks` calls to [`Runner::tick`] with no input.
+    pub const fn new(ticks: u32) -> Self {
+        SessionTimeout {
+            ticks,
+            #[cfg(feature = "history")]
+            clear_history: true,
+        }
+    }
+
+    /// Keep the history when timing out, rather than clearing it.
+    #[cfg(feature = "history")]
+    pub const fn keep_history(mut self) -> Self {
+        self.clear_history = false;
+        self
+    }
+}
+
+/// How running a command line went. Returned by [`Runner::run_command`].
+///
+/// Its `Display` gives the reason for a failure, such as `Insufficient
+/// arguments given`, and is empty for [`CommandOutcome::Success`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandOutcome<'l> {
+    /// The command ran and didn't report a failure
+    Success,
+    /// The line was empty
+    Empty,
+    /// There's no such command in the current menu
+    NotFound(&'l str),
+    /// `help` was asked about a command which doesn't exist
+    NoHelp(&'l str),
+    /// The arguments didn't match the command's parameters
+    BadArguments(parser::ArgError<'l>),
+    /// The command ran, or couldn't be run, and reported a failure. See
+    /// [`CommandContext::fail`].
+    Failed(&'static str),
+    /// The command wasn't run, because its throttle group said no, with
+    /// this message unless it was quiet. See [`Runner::set_throttle`].
+    Denied(Option<&'static str>),
+}
+
+impl CommandOutcome<'_> {
+    /// Did the command run without failing?
+    pub fn is_success(&self) -> bool {
+        *self == CommandOutcome::Success
+    }
+}
+
+impl core::fmt::Display for CommandOutcome<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CommandOutcome::Success => Ok(()),
+            CommandOutcome::Empty => write!(f, "Input was empty"),
+            CommandOutcome::NotFound(cmd) => write!(f, "Command {:?} not found", cmd),
+            CommandOutcome::NoHelp(cmd) => write!(f, "No help for {:?}", cmd),
+            CommandOutcome::BadArguments(e) => write!(f, "{}", e),
+            CommandOutcome::Failed(reason) => write!(f, "{}", reason),
+            CommandOutcome::Denied(message) => write!(f, "{}", message.unwrap_or("Denied")),
+        }
+    }
+}
+
+/// How the bytes given to [`Runner::input_byte`] encode text. See
+/// [`Runner::set_input_encoding`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// UTF-8, which is kept as it is
+    #[default]
+    Utf8,
+    /// Latin-1 (ISO 8859-1), where each byte from `0x80` up is turned into
+    /// the two bytes of its UTF-8 encoding
+    Latin1,
+    /// ASCII, where any byte from `0x80` up is thrown away as it arrives
+    ///
+    /// This suits links which can deliver stray 8-bit bytes, as they can
+    /// never leave a line which isn't valid UTF-8, and the line doesn't need
+    /// checking for half-typed characters after each byte. The cost is that
+    /// nothing outside ASCII can be typed, though completions can still add
+    /// it.
+    Ascii,
+}
+
+impl InputEncoding {
+    /// How many bytes `input` takes up in the buffer.
+    fn encoded_len(self, input: u8) -> usize {
+        match self {
+            InputEncoding::Latin1 => char::from(input).len_utf8(),
+            InputEncoding::Utf8 | InputEncoding::Ascii => 1,
+        }
+    }
+}
+
+/// How what's typed is shown. See [`Runner::set_echo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Echo {
+    /// Show what's typed, as it's typed and edited
+    On,
+    /// Show nothing that's typed, such as a passphrase
+    Off,
+    /// Show this character in place of each one typed
+    Mask(char),
+    /// Leave showing what's typed to the terminal, which echoes it
+    /// locally. Backspace at the end of the line is rubbed out, and the
+    /// line is written again over what the terminal showed when Enter is
+    /// pressed.
+    Local,
+}
+
+impl Default for Echo {
+    /// [`Echo::On`] with the `echo` feature, and [`Echo::Local`] without.
+    fn default() -> Self {
+        if cfg!(feature = "echo") {
+            Echo::On
+        } else {
+            Echo::Local
+        }
+    }
+}
+
+/// Which bytes given to [`Runner::input_byte`] end a line. See
+/// [`Runner::set_line_ending`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Carriage return, which terminals send for Enter. Line feeds are
+    /// ignored.
+    #[default]
+    Cr,
+    /// Line feed, as in a file with Unix line endings. Carriage returns are
+    /// ignored.
+    Lf,
+    /// Either, except that a line feed straight after a carriage return is
+    /// ignored, so CR LF only ends one line
+    CrOrLf,
+}
+
+impl LineEnding {
+    /// A carriage return if `input`, typed after a carriage return or not,
+    /// ends a line, or a line feed if it's a line ending to ignore.
+    /// Anything else is kept as it is.
+    const fn apply(self, input: u8, after_cr: bool) -> u8 {
+        match (self, input) {
+            (LineEnding::Lf, b'\r') => b'\n',
+            (LineEnding::Lf, b'\n') => b'\r',
+            (LineEnding::CrOrLf, b'\n') if !after_cr => b'\r',
+            _ => input,
+        }
+    }
+}
+
+impl<I, T> core::clone::Clone for Menu<'_, I, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+// Not derived, as that would need `I` and `T` to be `Copy` too
+impl<I, T> Copy for Menu<'_, I, T> {}
+
 impl<'a, I, T> Runner<'a, I, T>
 where
     I: core::fmt::Write,
@@ -255,622 +1634,6377 @@ where
     /// buffer that the `Runner` can use. Feel free to pass anything as the
     /// `context` type - the only requirement is that the `Runner` can
     /// `write!` to the context, which it will do for all text output.
-    pub fn new(
+    ///
+    /// This calls the root menu's entry callback and prints the first
+    /// prompt. See [`Runner::new_deferred`] if you can't do I/O yet.
+    pub fn new(menu: Menu<'a, I, T>, buffer: &'a mut [u8], interface: I, context: &mut T) -> Self {
+        let mut r = Self::new_deferred(menu, buffer, interface);
+        r.start(context);
+        r
+    }
+
+    /// Create a new `Runner` which remembers the command lines typed in, in
+    /// `history`, as if [`Runner::set_history_buffer`] had been called before
+    /// it started.
+    #[cfg(feature = "history")]
+    pub fn new_with_history(
         menu: Menu<'a, I, T>,
         buffer: &'a mut [u8],
-        mut interface: I,
+        history: &'a mut [u8],
+        interface: I,
         context: &mut T,
     ) -> Self {
-        if let Some(cb_fn) = menu.entry {
-            cb_fn(&menu, &mut interface, context);
-        }
-        let mut r = Runner {
+        let mut r = Self::new_deferred(menu, buffer, interface);
+        r.set_history_buffer(history);
+        r.start(context);
+        r
+    }
+
+    /// Create a new `Runner` without performing any I/O or calling any
+    /// callbacks.
+    ///
+    /// Call [`Runner::start`] once the interface is ready to run the root
+    /// menu's entry callback and print the first prompt. If you feed the
+    /// `Runner` input before calling `start`, it will start itself first.
+    pub fn new_deferred(menu: Menu<'a, I, T>, buffer: &'a mut [u8], interface: I) -> Self {
+        Runner {
             menu_mgr: menu_manager::MenuManager::new(menu),
             buffer,
             used: 0,
+            cursor: 0,
+            #[cfg(feature = "ansi")]
+            escape: input::Escape::None,
+            started: false,
+            output: OutputState::default(),
+            raw_mode: None,
+            passthrough: None,
+            confirm: None,
+            line_input: None,
+            #[cfg(feature = "paging")]
+            more: None,
+            #[cfg(feature = "paging")]
+            page_height: None,
+            #[cfg(feature = "paging")]
+            page: None,
+            template: None,
+            busy_hook: None,
+            interrupt_hook: None,
+            keys: command::Keys::NONE,
+            suppress_prompt: false,
+            #[cfg(feature = "ansi")]
+            discarded: false,
+            #[cfg(feature = "line-editing")]
+            overwrite: false,
+            #[cfg(feature = "history")]
+            history: None,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            search: None,
+            #[cfg(feature = "ansi")]
+            paste: None,
+            #[cfg(feature = "telnet")]
+            telnet: None,
+            #[cfg(feature = "builtins-extra")]
+            macros: None,
+            capture: None,
+            #[cfg(feature = "history")]
+            history_list_len: usize::MAX,
+            prompt_spacing: PromptSpacing::Always,
+            prompt_rendering: PromptRendering::Internal,
+            prompt_prefix: "",
+            terminal_title: None,
+            colour: false,
+            help_links: None,
+            #[cfg(feature = "builtins-extra")]
+            about: None,
+            machine_mode: false,
+            input_encoding: InputEncoding::Utf8,
+            echo: Echo::default(),
+            line_ending: LineEnding::Cr,
+            key_bindings: &[],
+            key_matcher: keymap::KeyMatcher::default(),
+            bell: false,
+            overflowed: false,
+            after_cr: false,
+            allow_option_prefixes: false,
+            #[cfg(feature = "completion")]
+            value_completer: None,
+            throttle: None,
+            dispatcher: None,
+            #[cfg(feature = "builtins-extra")]
+            show: None,
+            session_timeout: None,
+            idle_ticks: 0,
             interface,
+        }
+    }
+
+    /// Call the root menu's entry callback and print the first prompt.
+    ///
+    /// Does nothing if the `Runner` has already been started.
+    pub fn start(&mut self, context: &mut T) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        #[cfg(feature = "ansi")]
+        if self.paste.is_some() {
+            self.output().write_control(paste::ENABLE);
+        }
+        let menu = self.menu_mgr.current();
+        if let Some(cb_fn) = menu.entry {
+            cb_fn(menu, &mut self.interface, context);
+        }
+        if let Some(cb_fn) = menu.entry_with_context {
+            let entry = EntryContext {
+                entered: menu,
+                parent: None,
+                previous: None,
+            };
+            cb_fn(&entry, &mut self.interface, context);
+        }
+        self.show_prompt(true, context);
+    }
+
+    /// Get the counters for this `Runner`.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> &Stats {
+        &self.output.stats
+    }
+
+    /// Set all the counters for this `Runner` back to zero.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) {
+        self.output.stats = Stats::default();
+    }
+
+    /// Has [`Runner::start`] been called yet?
+    ///
+    /// This is `false` again once the session has been ended with Ctrl-D on
+    /// an empty line, so the host can close the connection. If it doesn't,
+    /// the next byte of input starts a new session.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Everything we print goes through here.
+    fn output(&mut self) -> Output<'_, I> {
+        Output::new(&mut self.interface, &mut self.output)
+    }
+
+    /// Call `hook` every so often while busy, so it can feed a watchdog or
+    /// the like.
+    ///
+    /// The `Runner` calls it after each line of help it prints, and callbacks
+    /// can call it with [`CommandContext::keep_alive`]. It's only given the
+    /// context, so it can't run commands or print anything.
+    pub fn set_busy_hook(&mut self, hook: BusyHookFn<T>) {
+        self.busy_hook = Some(hook);
+    }
+
+    /// Call `hook` when Ctrl-C is pressed, after the line being typed has
+    /// been thrown away. It's given the current menu.
+    pub fn set_interrupt_hook(&mut self, hook: MenuCallbackFn<I, T>) {
+        self.interrupt_hook = Some(hook);
+    }
+
+    /// Use `poll` to check for a key waiting on the interface, so callbacks
+    /// can stop when a key is pressed. See [`CommandContext::poll_key`].
+    pub fn set_key_poll(&mut self, poll: KeyPollFn<I>) {
+        self.keys.poll = Some(poll);
+    }
+
+    /// Show `tap` every byte of input before it's handled, whether it was
+    /// given to [`Runner::input_byte`] or read by a callback with
+    /// [`CommandContext::poll_key`].
+    ///
+    /// With the `test-util` feature, `test_util::Recorder` uses this to record
+    /// a session, so it can be replayed.
+    pub fn set_input_tap(&mut self, tap: InputTapFn<I>) {
+        self.keys.tap = Some(tap);
+    }
+
+    /// Remember the command lines typed in, in `buffer`.
+    ///
+    /// Once the buffer is full, the oldest lines are forgotten. This also adds
+    /// a `history` command, which lists the lines with their numbers, and
+    /// `history clear`, which forgets them all. Typing `!<n>` runs line `n`
+    /// again, and `!!` runs the newest line again.
+    ///
+    /// The up and down arrow keys step through the lines, replacing the one
+    /// being typed. Going down past the newest line gives back what was
+    /// being typed before.
+    ///
+    /// Ctrl-R searches back through the lines for what's typed next,
+    /// showing the newest line containing it. Ctrl-R again finds an older
+    /// one, Enter runs the line found, and Ctrl-C or ESC goes back to the
+    /// line being typed.
+    #[cfg(feature = "history")]
+    pub fn set_history_buffer(&mut self, buffer: &'a mut [u8]) {
+        self.history = Some(history::History::new(buffer));
+    }
+
+    /// Remember the command lines typed in, as
+    /// [`set_history_buffer`](Runner::set_history_buffer) does, but in a
+    /// buffer on the heap which grows as needed, so no line is forgotten.
+    #[cfg(all(feature = "history", feature = "alloc"))]
+    pub fn set_unbounded_history(&mut self) {
+        self.history = Some(history::History::new(storage::Buffer::Growing(
+            alloc::vec::Vec::new(),
+        )));
+    }
+
+    /// Let command lines be recorded into `slots`, and played back later.
+    ///
+    /// This adds three commands. `record <slot>` starts recording into
+    /// `slot`, counting from 1, and `stop` finishes. Each command line which
+    /// works is recorded, apart from `record` and `stop`. `play <slot>` runs
+    /// the lines in `slot` again, showing each one as it goes. An item with
+    /// the same command as one of these, in the current menu, is run instead.
+    ///
+    /// Recording won't start unless the slot has room for the longest line
+    /// the `Runner`'s buffer can hold, plus two bytes. If the slot fills up,
+    /// recording stops, keeping the lines which fitted. A macro can't play a
+    /// macro.
+    #[cfg(feature = "builtins-extra")]
+    pub fn set_macro_buffers(&mut self, slots: &'a mut [&'a mut [u8]]) {
+        self.macros = Some(macros::Macros::new(slots, self.buffer.len()));
+    }
+
+    /// Let commands keep a copy of what they write in `buffer`, for the next
+    /// command to use. See [`CommandContext::capture`].
+    ///
+    /// Half of `buffer` holds the last capture, while the command being run
+    /// captures into the other half, so each capture is limited to half of
+    /// `buffer`. A capture which doesn't fit is cut short at the end of a
+    /// character, and [`CommandContext::last_capture_truncated`] says so.
+    ///
+    /// A command which doesn't capture anything leaves the last capture as
+    /// it was.
+    pub fn set_capture_buffer(&mut self, buffer: &'a mut [u8]) {
+        self.capture = Some(capture::CaptureBuffer::new(buffer));
+    }
+
+    /// Only list the most recent `len` lines when `history` is entered.
+    #[cfg(feature = "history")]
+    pub fn set_history_list_len(&mut self, len: usize) {
+        self.history_list_len = len;
+    }
+
+    /// Show `height` lines of a callback's output at a time, if it writes
+    /// through [`CommandContext::pager`], or all of it for `None`, which is
+    /// the default. A height of 0 counts as `None`.
+    #[cfg(feature = "paging")]
+    pub fn set_page_height(&mut self, height: Option<usize>) {
+        self.page_height = height.filter(|&height| height > 0);
+    }
+
+    /// Choose what to print before each prompt. The default is
+    /// [`PromptSpacing::Always`], which leaves a blank line.
+    pub fn set_prompt_spacing(&mut self, spacing: PromptSpacing) {
+        self.prompt_spacing = spacing;
+    }
+
+    /// Choose who draws the prompt. By default, it's
+    /// [`PromptRendering::Internal`], and the `Runner` prints it.
+    ///
+    /// The application should show the prompt itself with
+    /// [`Runner::show_prompt`], rather than [`Runner::prompt`], so the
+    /// function is given the context.
+    pub fn set_prompt_rendering(&mut self, rendering: PromptRendering<T>) {
+        self.prompt_rendering = rendering;
+    }
+
+    /// Print `prefix` at the start of every prompt.
+    pub fn set_prompt_prefix(&mut self, prefix: &'static str) {
+        self.prompt_prefix = prefix;
+    }
+
+    /// Set the terminal's title to `name` and the current menu's path, like
+    /// `device:/config/network`, with every prompt. Off to begin with, as
+    /// only a terminal emulator understands the escape sequence.
+    pub fn set_terminal_title(&mut self, name: Option<&'static str>) {
+        self.terminal_title = name;
+    }
+
+    /// Colour the prompt, show what went wrong in red and the headings in
+    /// the help in bold. Off to begin with, as only a terminal emulator
+    /// understands the escape sequences. The colour is always turned off
+    /// again before the end of the line, so a callback's output is left as
+    /// it is.
+    pub fn set_colour(&mut self, on: bool) {
+        self.colour = on;
+    }
+
+    /// Have the terminal mark whatever is pasted, so it can be typed in
+    /// as a whole once it has all arrived, without being echoed a
+    /// character at a time. Each line pasted is run in turn, and anything
+    /// after the last line break is left on the line being typed. Off to
+    /// begin with, as only a terminal emulator understands the escape
+    /// sequences, and a terminal which doesn't mark pastes works as before.
+    ///
+    /// What's pasted is kept in the buffer, so a paste which doesn't fit
+    /// after the line being typed is thrown away.
+    #[cfg(feature = "ansi")]
+    pub fn set_bracketed_paste(&mut self, on: bool) {
+        if on == self.paste.is_some() {
+            return;
+        }
+        self.paste = on.then(paste::Paste::new);
+        if self.started {
+            let mode = if on { paste::ENABLE } else { paste::DISABLE };
+            self.output().write_control(mode);
+        }
+    }
+
+    /// Take telnet's commands out of the input, for a console served over
+    /// a TCP socket to telnet clients. A byte of 0xFF, which telnet sends
+    /// twice, is only given once, and a NUL sent after a carriage return
+    /// is dropped. Off to begin with.
+    ///
+    /// Options the client asks for are refused by giving `reply` the bytes
+    /// to send back, if it's given. Otherwise they're ignored.
+    #[cfg(feature = "telnet")]
+    pub fn enable_telnet(&mut self, reply: Option<TelnetReplyFn<T>>) {
+        self.telnet = Some((telnet::Telnet::Data, reply));
+    }
+
+    /// Make each command listed by `help` a hyperlink, to `template` with
+    /// each `{}` replaced by the command, like
+    /// `https://example.com/docs/{}`. Off to begin with, as only a terminal
+    /// emulator understands the escape sequence.
+    pub fn set_help_links(&mut self, template: Option<&'static str>) {
+        self.help_links = template;
+    }
+
+    /// Add a built-in command, `about` unless renamed, which prints what
+    /// this is. It's listed by `help` in every menu, unless hidden.
+    #[cfg(feature = "builtins-extra")]
+    pub fn set_about(&mut self, about: About) {
+        self.about = Some(about);
+    }
+
+    /// Turn machine mode on or off. It's off to begin with.
+    ///
+    /// In machine mode, each command's output is bracketed by lines a host
+    /// script can look for, rather than scraping the output to work out
+    /// whether the command worked:
+    ///
+    /// ```text
+    /// <<<BEGIN cmd="set-ip">>>
+    /// ...anything the command prints...
+    /// <<<RESULT cmd="set-ip" status=ok>>>
+    /// ```
+    ///
+    /// A failure, including a bad command line, ends with `status=err` and a
+    /// quoted reason, as in `<<<RESULT cmd="set-ip" status=err
+    /// reason="Missing argument <addr>">>>`, and the `Runner` doesn't
+    /// print its usual error message. Echo is best turned off in this mode.
+    ///
+    /// A callback can turn machine mode on or off with
+    /// [`CommandContext::set_machine_mode`], so it can be switched by a
+    /// command.
+    pub fn set_machine_mode(&mut self, machine_mode: bool) {
+        self.machine_mode = machine_mode;
+    }
+
+    /// Say how the bytes given to [`Runner::input_byte`] encode text, for
+    /// terminals which don't send UTF-8. The default is
+    /// [`InputEncoding::Utf8`].
+    ///
+    /// Only what's typed into the command line is translated. Raw mode
+    /// handlers still get every byte as it is, and output is unaffected.
+    pub fn set_input_encoding(&mut self, encoding: InputEncoding) {
+        self.input_encoding = encoding;
+    }
+
+    /// Say how what's typed is shown, such as not at all while a
+    /// passphrase is typed. Callbacks can change it with
+    /// [`CommandContext::set_echo`]. The default is [`Echo::On`] with the
+    /// `echo` feature, and [`Echo::Local`] without.
+    ///
+    /// Only what's typed is affected, and not the prompt or any output. A
+    /// line read with [`CommandContext::read_secret`] shows a `*` for each
+    /// character, unless this says otherwise.
+    pub fn set_echo(&mut self, echo: Echo) {
+        self.echo = echo;
+    }
+
+    /// Say which bytes end a line, for input piped in from a file or sent
+    /// by a terminal program which doesn't send a carriage return for
+    /// Enter. The default is [`LineEnding::Cr`].
+    ///
+    /// Raw mode handlers, and input being passed through, still get every
+    /// byte as it is.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Say what keys typed on the command line do, such as the custom
+    /// bytes sent by a front panel's buttons. A key which isn't bound does
+    /// what it usually does, and one bound to [`KeyAction::Ignore`] does
+    /// nothing.
+    ///
+    /// A key can be a sequence of bytes, such as the escape sequence a
+    /// terminal sends for F1. The bytes of a sequence are held back until
+    /// they finish a binding, or stop matching any, when they do what they
+    /// usually do. If one binding starts with another, the first to be
+    /// finished wins. The bindings aren't used while a callback's raw mode
+    /// handler, question or pager is taking the input.
+    ///
+    /// ```rust
+    /// # use menu::*;
+    /// const PANEL: &[KeyBinding] = &[
+    ///     KeyBinding::new(&[0x80], KeyAction::HistoryPrev),
+    ///     KeyBinding::new(&[0x81], KeyAction::HistoryNext),
+    ///     KeyBinding::new(&[0x82], KeyAction::Submit),
+    ///     KeyBinding::new(b"\x1b[11~", KeyAction::Redraw),
+    /// ];
+    /// # let mut buffer = [0u8; 32];
+    /// # let menu: Menu<String, ()> = Menu::new("root", &[]);
+    /// let mut r = Runner::new(menu, &mut buffer, String::new(), &mut ());
+    /// r.set_key_bindings(PANEL);
+    /// ```
+    pub fn set_key_bindings(&mut self, bindings: &'a [KeyBinding<'a>]) {
+        self.key_bindings = bindings;
+        self.key_matcher = keymap::KeyMatcher::default();
+    }
+
+    /// Ring the terminal's bell when a key can't do anything: typing past
+    /// the end of the buffer, Tab with nothing to complete, or Backspace
+    /// at the start of the line. It's off by default.
+    ///
+    /// A line too long for the buffer rings it once, for the first byte
+    /// which didn't fit.
+    pub fn set_bell(&mut self, on: bool) {
+        self.bell = on;
+    }
+
+    /// Accept the start of an option's name, such as `--verb` for
+    /// `--verbose`, as long as no other option of the command starts the same
+    /// way. Off by default.
+    pub fn set_allow_option_prefixes(&mut self, allow: bool) {
+        self.allow_option_prefixes = allow;
+    }
+
+    /// Use `completer` to finish off a parameter's value when Tab is pressed,
+    /// such as a file name or a network name which only the application
+    /// knows.
+    ///
+    /// If every candidate starts the same way, as much as they have in
+    /// common is added to the line, followed by a space if there was only
+    /// one. Otherwise the candidates are listed, up to 16 of them, and the
+    /// line is shown again. Tab does nothing while typing a command or an
+    /// option's name. An option with
+    /// [allowed values](Parameter::allowed_values) is completed from those
+    /// instead, with or without a completer.
+    #[cfg(feature = "completion")]
+    pub fn set_value_completer(&mut self, completer: ValueCompleterFn<I, T>) {
+        self.value_completer = Some(completer);
+    }
+
+    /// Check with `check` before running any item with a
+    /// [throttle group](Item::throttle_group), and tell `on_failure` about
+    /// each failure a callback reports with
+    /// [`CommandContext::report_failure`].
+    ///
+    /// Both are given the group and the context, so the context can keep
+    /// count of failures and, with a clock, lock a group out for a while.
+    /// When `check` denies a command, the `Runner` prints the message it
+    /// gives, if any, instead of running it.
+    pub fn set_throttle(&mut self, check: ThrottleFn<T>, on_failure: ThrottleFailureFn<T>) {
+        self.throttle = Some((check, on_failure));
+    }
+
+    /// Log the session out after a while with no input, as counted by
+    /// [`Runner::tick`].
+    ///
+    /// Timing out calls `on_timeout`, which might lower the user's access
+    /// level, then goes back to the root menu, throws away anything half
+    /// typed or half answered, clears the history unless told to keep it,
+    /// and prints `Session timed out`.
+    pub fn set_session_timeout(
+        &mut self,
+        timeout: SessionTimeout,
+        on_timeout: SessionTimeoutFn<T>,
+    ) {
+        self.session_timeout = Some((timeout, on_timeout));
+        self.idle_ticks = 0;
+    }
+
+    /// Count time passing, for [`Runner::set_session_timeout`]. Call this
+    /// regularly, such as once a second. Every byte of input, and every
+    /// command given to [`Runner::run_command`], starts the count again.
+    pub fn tick(&mut self, context: &mut T) {
+        let Some((timeout, on_timeout)) = self.session_timeout else {
+            return;
         };
-        r.prompt(true);
-        r
+        self.idle_ticks = self.idle_ticks.saturating_add(1);
+        if self.idle_ticks != timeout.ticks {
+            return;
+        }
+        on_timeout(context);
+        self.change_menu([None; menu_manager::MAX_DEPTH], context);
+        self.abandon_input();
+        #[cfg(feature = "history")]
+        if timeout.clear_history {
+            if let Some(history) = &mut self.history {
+                history.clear();
+            }
+        }
+        let mut output = self.output();
+        output.ensure_newline().ok();
+        writeln!(output, "Session timed out").ok();
+        self.show_prompt(true, context);
+    }
+
+    /// Clear the screen, as Ctrl-L does, and show the prompt at the top,
+    /// with the line being typed after it if it's echoed.
+    pub fn redraw_screen(&mut self, context: &mut T) {
+        write!(self.output(), "\x1b[2J\x1b[H").ok();
+        self.show_prompt(false, context);
+        if self.echoes() {
+            self.write_line_buffer();
+            self.cursor_back(self.tail_len());
+        }
+    }
+
+    /// Throw away anything half typed or half answered, and stop recording
+    /// a macro.
+    fn abandon_input(&mut self) {
+        self.used = 0;
+        self.cursor = 0;
+        #[cfg(feature = "ansi")]
+        {
+            self.escape = input::Escape::None;
+        }
+        self.raw_mode = None;
+        self.passthrough = None;
+        self.confirm = None;
+        self.line_input = None;
+        #[cfg(feature = "paging")]
+        {
+            self.more = None;
+        }
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        {
+            self.search = None;
+        }
+        #[cfg(feature = "ansi")]
+        if self.paste.is_some() {
+            self.paste = Some(paste::Paste::new());
+        }
+        #[cfg(feature = "builtins-extra")]
+        if let Some(macros) = &mut self.macros {
+            macros.stop();
+        }
     }
 
     /// Print out a new command prompt, including sub-menu names if
     /// applicable.
+    ///
+    /// If `newline` is true, the prompt is spaced out from any output before
+    /// it, as set by [`Runner::set_prompt_spacing`]. Otherwise, it's being
+    /// drawn again, over the old one, with the line being typed to follow.
+    ///
+    /// This always prints the `Runner`'s own prompt, as the functions given
+    /// to [`Runner::set_prompt_rendering`] need the context. Use
+    /// [`Runner::show_prompt`] to have them draw it.
     pub fn prompt(&mut self, newline: bool) {
+        self.draw_prompt(newline, None);
+    }
+
+    /// Show the prompt, as [`Runner::prompt`] does, but drawn as set by
+    /// [`Runner::set_prompt_rendering`], such as when a client reconnects.
+    pub fn show_prompt(&mut self, newline: bool, context: &mut T) {
+        self.draw_prompt(newline, Some(context));
+    }
+
+    /// Print or draw the prompt, with the renderer set, if it's given the
+    /// context, or as [`PromptRendering::Internal`] if not.
+    fn draw_prompt(&mut self, newline: bool, mut context: Option<&mut T>) {
         if newline {
-            writeln!(self.interface).unwrap();
+            self.overflowed = false;
+            // A callback's question goes straight after whatever it printed
+            let spacing = match self.line_input {
+                Some(_) => PromptSpacing::OnlyIfNeeded,
+                None => self.prompt_spacing,
+            };
+            let mut output = self.output();
+            if spacing != PromptSpacing::Never {
+                output.ensure_newline().ok();
+            }
+            if spacing == PromptSpacing::Always {
+                writeln!(output).ok();
+            }
+        }
+        if let (PromptRendering::External(render), Some(context)) =
+            (&self.prompt_rendering, context.as_deref_mut())
+        {
+            render(&self.prompt_info(newline), context);
+            return;
         }
-        for i in 0..self.menu_mgr.depth() {
-            if i > 1 {
-                write!(self.interface, "/").unwrap();
+        if let Some(request) = &self.line_input {
+            let prompt = request.prompt;
+            write!(self.output(), "{}", prompt).ok();
+            return;
+        }
+        if let Some(name) = self.terminal_title {
+            write!(self.output(), "\x1b]0;{}:", name).ok();
+            if self.menu_mgr.depth() == 0 {
+                write!(self.output(), "/").ok();
             }
+            for i in 1..=self.menu_mgr.depth() {
+                let label = self.menu_mgr.at_depth(i).map_or("", |menu| menu.label);
+                write!(self.output(), "/{}", label).ok();
+            }
+            write!(self.output(), "\x07").ok();
+        }
+        if let (PromptRendering::Custom(write_prompt), Some(context)) =
+            (&self.prompt_rendering, context)
+        {
+            let (write_prompt, info) = (*write_prompt, self.prompt_info(newline));
+            write_prompt(&info, &mut self.output(), context);
+            return;
+        }
+        if self.colour {
+            write!(self.output(), "{}", output::PROMPT_COLOUR).ok();
+        }
+        let prefix = self.prompt_prefix;
+        write!(self.output(), "{}", prefix).ok();
+        // The root menu isn't shown
+        for i in 1..=self.menu_mgr.depth() {
+            let label = self.menu_mgr.at_depth(i).map_or("", |menu| menu.label);
+            write!(self.output(), "/{}", label).ok();
+        }
+        write!(self.output(), "> ").ok();
+        if self.colour {
+            write!(self.output(), "{}", output::RESET).ok();
+        }
+    }
 
-            let menu = self.menu_mgr.get_menu(Some(i));
-            write!(self.interface, "/{}", menu.label).unwrap();
+    /// What a [`PromptFn`] or [`PromptWriteFn`] is given, for a prompt on
+    /// a new line if `newline`, or drawn again over itself if not.
+    fn prompt_info(&self, newline: bool) -> PromptInfo<'a> {
+        let mut info = PromptInfo {
+            labels: [""; menu_manager::MAX_DEPTH],
+            depth: self.menu_mgr.depth(),
+            prefix: self.prompt_prefix,
+            question: self.line_input.as_ref().map(|request| request.prompt),
+            redraw: !newline,
+        };
+        for (i, label) in info.labels.iter_mut().enumerate().take(info.depth) {
+            *label = self.menu_mgr.at_depth(i + 1).map_or("", |menu| menu.label);
         }
-        write!(self.interface, "> ").unwrap();
+        info
     }
 
     /// Add a byte to the menu runner's buffer. If this byte is a
     /// carriage-return, the buffer is scanned and the appropriate action
     /// performed.
-    /// By default, an echo feature is enabled to display commands on the terminal.
+    /// What's typed is shown as [`Runner::set_echo`] says.
     pub fn input_byte(&mut self, input: u8, context: &mut T) {
-        // Strip carriage returns
-        if input == 0x0A {
+        if let Some(tap) = self.keys.tap {
+            tap(&mut self.interface, input, InputSource::Typed);
+        }
+        #[cfg(feature = "telnet")]
+        let Some(input) = self.filter_telnet(input, context) else {
             return;
+        };
+        self.start(context);
+        self.idle_ticks = 0;
+        #[cfg(feature = "stats")]
+        {
+            self.output.stats.bytes_read += 1;
         }
-        let outcome = if input == 0x0D {
-            #[cfg(not(feature = "echo"))]
-            {
-                // Echo the command
-                write!(self.interface, "\r").unwrap();
-                if let Ok(s) = core::str::from_utf8(&self.buffer[0..self.used]) {
-                    write!(self.interface, "{}", s).unwrap();
-                }
-            }
-            // Handle the command
-            self.process_command(context);
-            Outcome::CommandProcessed
-        } else if (input == 0x08) || (input == 0x7F) {
-            // Handling backspace or delete
-            if self.used > 0 {
-                write!(self.interface, "\u{0008} \u{0008}").unwrap();
-                self.used -= 1;
-            }
-            Outcome::NeedMore
-        } else if self.used < self.buffer.len() {
-            self.buffer[self.used] = input;
-            self.used += 1;
-
-            #[cfg(feature = "echo")]
-            {
-                // We have to do this song and dance because `self.prompt()` needs
-                // a mutable reference to self, and we can't have that while
-                // holding a reference to the buffer at the same time.
-                // This line grabs the buffer, checks it's OK, then releases it again
-                let valid = core::str::from_utf8(&self.buffer[0..self.used]).is_ok();
-                // Now we've released the buffer, we can draw the prompt
-                if valid {
-                    write!(self.interface, "\r").unwrap();
-                    self.prompt(false);
-                }
-                // Grab the buffer again to render it to the screen
-                if let Ok(s) = core::str::from_utf8(&self.buffer[0..self.used]) {
-                    write!(self.interface, "{}", s).unwrap();
-                }
-            }
-            Outcome::NeedMore
-        } else {
-            writeln!(self.interface, "Buffer overflow!").unwrap();
-            Outcome::NeedMore
+        let input = self.end_lines(input);
+        self.handle_byte(input, context);
+    }
+
+    /// Turn a byte which ends a line, as set by
+    /// [`Runner::set_line_ending`], into a carriage return, and one which
+    /// doesn't into a line feed, which is ignored. Raw mode handlers, and
+    /// what's passed through or pasted, get the byte as it is.
+    fn end_lines(&mut self, input: u8) -> u8 {
+        let after_cr = core::mem::replace(&mut self.after_cr, input == b'\r');
+        match self.input_state() {
+            InputState::RawPassthrough | InputState::Passthrough => input,
+            #[cfg(feature = "ansi")]
+            InputState::Paste => input,
+            _ => self.line_ending.apply(input, after_cr),
+        }
+    }
+
+    /// Take telnet's commands out of the input, if asked to, refusing any
+    /// options the client asks for. Returns the byte which was typed, if
+    /// one was.
+    #[cfg(feature = "telnet")]
+    fn filter_telnet(&mut self, input: u8, context: &mut T) -> Option<u8> {
+        let Some((telnet, reply)) = &mut self.telnet else {
+            return Some(input);
         };
-        match outcome {
-            Outcome::CommandProcessed => {
-                self.used = 0;
-                self.prompt(true);
+        match telnet.feed(input) {
+            telnet::Filtered::Typed(byte) => Some(byte),
+            telnet::Filtered::Dropped => None,
+            telnet::Filtered::Refuse(bytes) => {
+                if let Some(reply) = reply {
+                    reply(&bytes, context);
+                }
+                None
             }
-            Outcome::NeedMore => {}
         }
     }
 
-    /// Scan the buffer and do the right thing based on its contents.
-    fn process_command(&mut self, context: &mut T) {
-        // Go to the next line, below the prompt
-        writeln!(self.interface).unwrap();
-        if let Ok(command_line) = core::str::from_utf8(&self.buffer[0..self.used]) {
-            // We have a valid string
-            let mut parts = command_line.split_whitespace();
-            if let Some(cmd) = parts.next() {
-                let menu = self.menu_mgr.get_menu(None);
-                if cmd == "help" {
-                    match parts.next() {
-                        Some(arg) => match menu.items.iter().find(|i| i.command == arg) {
-                            Some(item) => {
-                                self.print_long_help(item);
-                            }
-                            None => {
-                                writeln!(self.interface, "I can't help with {:?}", arg).unwrap();
-                            }
-                        },
-                        _ => {
-                            writeln!(self.interface, "AVAILABLE ITEMS:").unwrap();
-                            for item in menu.items {
-                                self.print_short_help(item);
-                            }
-                            if self.menu_mgr.depth() != 0 {
-                                self.print_short_help(&Item {
-                                    command: "exit",
-                                    help: Some("Leave this menu."),
-                                    item_type: ItemType::_Dummy,
-                                });
-                            }
-                            self.print_short_help(&Item {
-                                command: "help [ <command> ]",
-                                help: Some("Show this help, or get help on a specific command."),
-                                item_type: ItemType::_Dummy,
-                            });
-                        }
-                    }
-                } else if cmd == "exit" && self.menu_mgr.depth() != 0 {
-                    if let Some(cb_fn) = menu.exit {
-                        cb_fn(menu, &mut self.interface, context);
-                    }
-                    self.menu_mgr.pop_menu();
-                } else {
-                    let mut found = false;
-                    for (i, item) in menu.items.iter().enumerate() {
-                        if cmd == item.command {
-                            match item.item_type {
-                                ItemType::Callback {
-                                    function,
-                                    parameters,
-                                } => Self::call_function(
-                                    &mut self.interface,
-                                    context,
-                                    function,
-                                    parameters,
-                                    menu,
-                                    item,
-                                    command_line,
-                                ),
-                                ItemType::Menu(_) => {
-                                    if let Some(cb_fn) = self.menu_mgr.get_menu(None).entry {
-                                        cb_fn(menu, &mut self.interface, context);
-                                    }
-                                    self.menu_mgr.push_menu(i);
-                                }
-                                ItemType::_Dummy => {
-                                    unreachable!();
-                                }
-                            }
-                            found = true;
-                            break;
+    /// Do what `input` asks for, in whichever state the input is in.
+    fn handle_byte(&mut self, input: u8, context: &mut T) {
+        let state = self.input_state();
+        let action = match state {
+            InputState::Normal | InputState::Masked => {
+                match self.key_matcher.feed(self.key_bindings, input) {
+                    keymap::Fed::Bound(action) => action.action(),
+                    keymap::Fed::Partial => return,
+                    keymap::Fed::Unbound(held) if !held.is_empty() => {
+                        for &byte in held {
+                            let state = self.input_state();
+                            self.act(state, input::step(state, byte), byte, context);
                         }
+                        // It might start a sequence of its own
+                        return self.handle_byte(input, context);
                     }
-                    if !found {
-                        writeln!(self.interface, "Command {:?} not found. Try 'help'.", cmd)
-                            .unwrap();
-                    }
+                    keymap::Fed::Unbound(_) => input::step(state, input),
                 }
-            } else {
-                writeln!(self.interface, "Input was empty?").unwrap();
             }
-        } else {
-            // Hmm ..  we did not have a valid string
-            writeln!(self.interface, "Input was not valid UTF-8").unwrap();
-        }
+            _ => input::step(state, input),
+        };
+        self.act(state, action, input, context);
     }
 
-    fn print_short_help(&mut self, item: &Item<I, T>) {
-        let mut has_options = false;
-        match item.item_type {
-            ItemType::Callback { parameters, .. } => {
-                write!(self.interface, "  {}", item.command).unwrap();
-                if !parameters.is_empty() {
-                    for param in parameters.iter() {
-                        match param {
-                            Parameter::Mandatory { parameter_name, .. } => {
-                                write!(self.interface, " <{}>", parameter_name).unwrap();
-                            }
-                            Parameter::Optional { parameter_name, .. } => {
-                                write!(self.interface, " [ <{}> ]", parameter_name).unwrap();
-                            }
-                            Parameter::Named { .. } => {
-                                has_options = true;
-                            }
-                            Parameter::NamedValue { .. } => {
-                                has_options = true;
-                            }
-                        }
-                    }
+    /// Do `action`, which is what `input` asks for in `state`.
+    fn act(&mut self, state: InputState, action: Action, input: u8, context: &mut T) {
+        // Once the line is too long, the rest of it is thrown away
+        let action = match (state, action) {
+            (_, Action::Submit | Action::Cancel) => action,
+            (InputState::Normal | InputState::Masked, _) if self.overflowed => Action::Ignore,
+            _ => action,
+        };
+        #[cfg(feature = "ansi")]
+        {
+            self.escape = input::Escape::after(action);
+        }
+        match action {
+            Action::Raw => {
+                let Some(handler) = self.raw_mode else {
+                    return;
+                };
+                let mut writer = ConsoleWriter::new(&mut self.interface, &mut self.output);
+                if handler(input, &mut writer, context) == RawModeAction::Finish {
+                    self.raw_mode = None;
+                    self.show_prompt(true, context);
                 }
             }
-            ItemType::Menu(_menu) => {
-                write!(self.interface, "  {}", item.command).unwrap();
+            Action::Forward => {
+                let Some((forward, matcher)) = &mut self.passthrough else {
+                    return;
+                };
+                let forward = *forward;
+                if matcher.feed(input, |bytes| forward(bytes, context)) {
+                    self.passthrough = None;
+                    self.show_prompt(true, context);
+                }
             }
-            ItemType::_Dummy => {
-                write!(self.interface, "  {}", item.command).unwrap();
+            Action::Answer(confirmed) => {
+                if let Some(then) = self.confirm {
+                    self.answer(confirmed, then, context);
+                }
             }
+            #[cfg(feature = "paging")]
+            Action::More(key) => self.more(key, context),
+            #[cfg(feature = "ansi")]
+            Action::Paste => self.paste(input, context),
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            Action::StartSearch => self.start_search(),
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            Action::Search(key) => self.search(key, input, context),
+            Action::Ignore => {}
+            Action::Submit => self.submit(context),
+            #[cfg(feature = "completion")]
+            Action::Complete => self.complete(context),
+            Action::Erase => self.erase(),
+            Action::Cancel => self.cancel(context),
+            Action::EndOfInput if self.used == 0 => self.end_session(context),
+            #[cfg(feature = "line-editing")]
+            Action::EndOfInput => self.erase_forward(),
+            #[cfg(not(feature = "line-editing"))]
+            Action::EndOfInput => {}
+            Action::Redraw => self.redraw_screen(context),
+            #[cfg(feature = "line-editing")]
+            Action::EraseWord => self.erase_word(),
+            #[cfg(feature = "line-editing")]
+            Action::EraseLine => self.erase_line(context),
+            Action::Insert => self.insert(input, context),
+            Action::InsertChar(c) => self.insert_char(c, context),
+            #[cfg(feature = "line-editing")]
+            Action::CursorLeft => self.move_cursor(true),
+            #[cfg(feature = "line-editing")]
+            Action::CursorRight => self.move_cursor(false),
+            #[cfg(feature = "line-editing")]
+            Action::WordLeft => self.move_word(true),
+            #[cfg(feature = "line-editing")]
+            Action::WordRight => self.move_word(false),
+            #[cfg(feature = "line-editing")]
+            Action::Delete => self.erase_forward(),
+            #[cfg(feature = "line-editing")]
+            Action::ToggleOverwrite => self.overwrite = !self.overwrite,
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            Action::HistoryOlder => self.recall(true, context),
+            #[cfg(all(feature = "ansi", feature = "history"))]
+            Action::HistoryNewer => self.recall(false, context),
+            #[cfg(feature = "ansi")]
+            Action::LoneEscape => {
+                self.erase_line(context);
+                // The byte after it is handled on its own, and looked at
+                // for the start of a paste then
+                return self.handle_byte(input, context);
+            }
+            #[cfg(feature = "ansi")]
+            Action::StartEscape
+            | Action::StartCsi
+            | Action::StartSs3
+            | Action::ContinueCsi(_)
+            | Action::EndCsi => {}
         }
-        if has_options {
-            write!(self.interface, " [OPTIONS...]").unwrap();
+        // What's given to a raw mode handler, or passed through, is left
+        // alone
+        #[cfg(feature = "ansi")]
+        if let (Some(paste), false) = (
+            &mut self.paste,
+            matches!(state, InputState::RawPassthrough | InputState::Passthrough),
+        ) {
+            paste.watch(input);
         }
-        writeln!(self.interface).unwrap();
     }
 
-    fn print_long_help(&mut self, item: &Item<I, T>) {
-        writeln!(self.interface, "SUMMARY:").unwrap();
-        match item.item_type {
-            ItemType::Callback { parameters, .. } => {
-                write!(self.interface, "  {}", item.command).unwrap();
-                if !parameters.is_empty() {
-                    for param in parameters.iter() {
-                        match param {
-                            Parameter::Mandatory { parameter_name, .. } => {
-                                write!(self.interface, " <{}>", parameter_name).unwrap();
-                            }
-                            Parameter::Optional { parameter_name, .. } => {
-                                write!(self.interface, " [ <{}> ]", parameter_name).unwrap();
-                            }
-                            Parameter::Named { parameter_name, .. } => {
-                                write!(self.interface, " [ --{} ]", parameter_name).unwrap();
-                            }
-                            Parameter::NamedValue {
-                                parameter_name,
-                                argument_name,
-                                ..
-                            } => {
-                                write!(
-                                    self.interface,
-                                    " [ --{}={} ]",
-                                    parameter_name, argument_name
-                                )
-                                .unwrap();
-                            }
-                        }
-                    }
-                    writeln!(self.interface, "\n\nPARAMETERS:").unwrap();
-                    let default_help = "Undocumented option";
-                    for param in parameters.iter() {
-                        match param {
-                            Parameter::Mandatory {
-                                parameter_name,
-                                help,
-                            } => {
-                                writeln!(
-                                    self.interface,
-                                    "  <{0}>\n    {1}\n",
-                                    parameter_name,
-                                    help.unwrap_or(default_help),
-                                )
-                                .unwrap();
-                            }
-                            Parameter::Optional {
-                                parameter_name,
-                                help,
-                            } => {
-                                writeln!(
-                                    self.interface,
-                                    "  <{0}>\n    {1}\n",
-                                    parameter_name,
-                                    help.unwrap_or(default_help),
-                                )
-                                .unwrap();
-                            }
-                            Parameter::Named {
-                                parameter_name,
-                                help,
-                            } => {
-                                writeln!(
-                                    self.interface,
-                                    "  --{0}\n    {1}\n",
-                                    parameter_name,
-                                    help.unwrap_or(default_help),
-                                )
-                                .unwrap();
-                            }
-                            Parameter::NamedValue {
-                                parameter_name,
-                                argument_name,
-                                help,
-                            } => {
-                                writeln!(
-                                    self.interface,
-                                    "  --{0}={1}\n    {2}\n",
-                                    parameter_name,
-                                    argument_name,
-                                    help.unwrap_or(default_help),
-                                )
-                                .unwrap();
-                            }
-                        }
-                    }
+    /// Is input being passed through, after
+    /// [`CommandContext::request_passthrough`]? If so, whatever comes back
+    /// should be written to the interface.
+    pub fn in_passthrough(&self) -> bool {
+        self.passthrough.is_some()
+    }
+
+    /// Is something other than a new command line waiting for input, which
+    /// prints its own prompt when it's done?
+    fn waiting(&self) -> bool {
+        #[cfg(feature = "paging")]
+        if self.more.is_some() {
+            return true;
+        }
+        self.raw_mode.is_some() || self.passthrough.is_some() || self.confirm.is_some()
+    }
+
+    /// Which state the input is in, for [`input::step`].
+    fn input_state(&self) -> InputState {
+        #[cfg(feature = "ansi")]
+        if let Some(paste::Paste::Pasting { .. }) = self.paste {
+            return InputState::Paste;
+        }
+        if self.raw_mode.is_some() {
+            return InputState::RawPassthrough;
+        }
+        if self.passthrough.is_some() {
+            return InputState::Passthrough;
+        }
+        if self.confirm.is_some() {
+            return InputState::Confirm;
+        }
+        #[cfg(feature = "paging")]
+        if self.more.is_some() {
+            return InputState::More;
+        }
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        if self.search.is_some() {
+            return InputState::Search;
+        }
+        #[cfg(feature = "ansi")]
+        match self.escape {
+            input::Escape::None => {}
+            input::Escape::Started => return InputState::Escape,
+            #[cfg(feature = "history")]
+            input::Escape::LeftSearch => return InputState::LeftSearch,
+            input::Escape::Csi(param) => return InputState::Csi(param),
+            input::Escape::Ss3 => return InputState::Ss3,
+        }
+        if self.line_input.as_ref().is_some_and(|r| r.masked) {
+            InputState::Masked
+        } else {
+            InputState::Normal
+        }
+    }
+
+    /// Run the line, or give it to the callback waiting for it, then start
+    /// a new one.
+    fn submit(&mut self, context: &mut T) {
+        self.suppress_prompt = false;
+        // A line pasted is shown once it's all there
+        if self.echo == Echo::Local {
+            // Echo the command
+            write!(self.output(), "\r").ok();
+            self.write_line_buffer();
+        } else if self.typing_paste() && self.echoes() {
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+        }
+        #[cfg(feature = "ansi")]
+        if core::mem::take(&mut self.discarded) && self.used == 0 && self.line_input.is_none() {
+            // Nothing to run, so just start again
+            self.cursor = 0;
+            writeln!(self.output()).ok();
+            self.show_prompt(true, context);
+            return;
+        }
+        if self.overflowed {
+            // Only the start of the line is in the buffer, which mustn't be
+            // run. A callback waiting for a line is asked again.
+            writeln!(self.output()).ok();
+            let size = self.buffer.len();
+            self.write_error(format_args!("Line too long: only {} bytes fit", size));
+        } else {
+            match self.line_input.take() {
+                Some(request) => self.process_line(request, context),
+                None => self.process_command(context),
+            }
+        }
+        self.used = 0;
+        self.cursor = 0;
+        #[cfg(feature = "history")]
+        if let Some(history) = &mut self.history {
+            history.stop_recalling();
+        }
+        // A raw mode handler, or a question, gets the prompt back when it's
+        // finished
+        if self.suppress_prompt {
+            self.suppress_prompt = false;
+        } else if !self.waiting() {
+            self.show_prompt(true, context);
+            self.fill_template();
+        }
+    }
+
+    /// Abandon the line, or the callback waiting for it, after Ctrl-C, and
+    /// start a new one.
+    fn cancel(&mut self, context: &mut T) {
+        writeln!(self.output(), "^C").ok();
+        self.used = 0;
+        self.cursor = 0;
+        self.line_input = None;
+        #[cfg(feature = "ansi")]
+        {
+            self.discarded = false;
+        }
+        #[cfg(feature = "history")]
+        if let Some(history) = &mut self.history {
+            history.stop_recalling();
+        }
+        if let Some(hook) = self.interrupt_hook {
+            hook(self.menu_mgr.current(), &mut self.interface, context);
+        }
+        self.show_prompt(true, context);
+    }
+
+    /// End the session, after Ctrl-D on an empty line. Every menu is exited,
+    /// including the root menu, and the `Runner` waits to be started again.
+    fn end_session(&mut self, context: &mut T) {
+        writeln!(self.output()).ok();
+        self.change_menu([None; menu_manager::MAX_DEPTH], context);
+        self.abandon_input();
+        #[cfg(feature = "ansi")]
+        {
+            self.discarded = false;
+        }
+        #[cfg(feature = "history")]
+        if let Some(history) = &mut self.history {
+            history.stop_recalling();
+        }
+        let menu = self.menu_mgr.current();
+        if let Some(cb_fn) = menu.exit {
+            cb_fn(menu, &mut self.interface, context);
+        }
+        self.started = false;
+    }
+
+    /// Add a byte to the line at the cursor, and echo it.
+    fn insert(&mut self, input: u8, context: &mut T) {
+        if self.input_encoding == InputEncoding::Ascii && !input.is_ascii() {
+            return;
+        }
+        let mut encoded = [input; 4];
+        if self.input_encoding == InputEncoding::Latin1 {
+            char::from(input).encode_utf8(&mut encoded);
+        }
+        let len = self.input_encoding.encoded_len(input);
+        self.insert_bytes(&encoded[..len], context);
+    }
+
+    /// Type in a character a key is bound to.
+    fn insert_char(&mut self, c: char, context: &mut T) {
+        let mut encoded = [0; 4];
+        let len = c.encode_utf8(&mut encoded).len();
+        self.insert_bytes(&encoded[..len], context);
+    }
+
+    /// Add `bytes` to the line at the cursor, and echo them. They're all or
+    /// part of one character.
+    fn insert_bytes(&mut self, bytes: &[u8], context: &mut T) {
+        let len = bytes.len();
+        let replaced = self.replaced_len(bytes[0]);
+        if self.used - replaced + len > self.room() {
+            self.refuse();
+            self.overflowed = true;
+            return;
+        }
+        // Take out the character being written over, which means the line
+        // is redrawn, as it isn't typed on the end
+        self.buffer
+            .copy_within(self.cursor + replaced..self.used, self.cursor);
+        self.used -= replaced;
+        // Make room for it
+        self.buffer
+            .copy_within(self.cursor..self.used, self.cursor + len);
+        self.buffer[self.cursor..self.cursor + len].copy_from_slice(bytes);
+        self.used += len;
+        self.cursor += len;
+
+        if !self.typing_paste() && self.echoes() {
+            // A whole character on the end of the line is all that changed
+            if self.appended_whole(self.cursor - len) {
+                self.write_line_from(self.cursor - len);
+                return;
+            }
+            // We have to do this song and dance because `self.prompt()` needs
+            // a mutable reference to self, and we can't have that while
+            // holding a reference to the buffer at the same time.
+            // This line grabs the buffer, checks it's OK, then releases it again.
+            // Only ASCII is typed in ASCII mode, so there's never half a
+            // character to wait for.
+            let valid = self.input_encoding == InputEncoding::Ascii
+                || core::str::from_utf8(&self.buffer[0..self.used]).is_ok();
+            // Now we've released the buffer, we can draw the prompt
+            if valid {
+                #[cfg(feature = "stats")]
+                {
+                    self.output.stats.redraws += 1;
                 }
+                write!(self.output(), "\r").ok();
+                self.show_prompt(false, context);
             }
-            ItemType::Menu(_menu) => {
-                write!(self.interface, "  {}", item.command).unwrap();
+            // Grab the buffer again to render it to the screen
+            self.write_line_buffer();
+            if valid {
+                self.cursor_back(self.tail_len());
             }
-            ItemType::_Dummy => {
-                write!(self.interface, "  {}", item.command).unwrap();
+        }
+    }
+
+    /// How many bytes of the line are written over by what's typed,
+    /// starting with `first`, in overwrite mode. Only the first byte of a
+    /// character does, and not at the end of the line, where it's added as
+    /// usual.
+    fn replaced_len(&self, first: u8) -> usize {
+        #[cfg(feature = "line-editing")]
+        if self.overwrite && self.cursor < self.used && !is_continuation(first) {
+            let mut end = self.cursor + 1;
+            while end < self.used && is_continuation(self.buffer[end]) {
+                end += 1;
             }
+            return end - self.cursor;
+        }
+        #[cfg(not(feature = "line-editing"))]
+        let _ = first;
+        0
+    }
+
+    /// Was a whole character typed at `start`, on the end of the line,
+    /// after a whole character? Then it can be written on its own.
+    ///
+    /// The first character of a line always redraws it, as the prompt
+    /// may not have been written yet.
+    fn appended_whole(&self, start: usize) -> bool {
+        if start == 0 || self.cursor != self.used || is_continuation(self.buffer[start]) {
+            return false;
         }
-        if let Some(help) = item.help {
-            writeln!(self.interface, "\n\nDESCRIPTION:\n{}", help).unwrap();
+        // Look back no further than the start of the character before
+        let mut before = start - 1;
+        while before > 0 && start - before < 4 && is_continuation(self.buffer[before]) {
+            before -= 1;
         }
+        core::str::from_utf8(&self.buffer[before..self.used]).is_ok()
     }
 
-    fn call_function(
-        interface: &mut I,
-        context: &mut T,
-        callback_function: ItemCallbackFn<I, T>,
-        parameters: &[Parameter],
-        parent_menu: &Menu<I, T>,
-        item: &Item<I, T>,
-        command: &str,
-    ) {
-        let mandatory_parameter_count = parameters
-            .iter()
-            .filter(|p| matches!(p, Parameter::Mandatory { .. }))
-            .count();
-        let positional_parameter_count = parameters
+    /// Say what went wrong, in red if [`Runner::set_colour`] is on.
+    fn write_error(&mut self, message: core::fmt::Arguments) {
+        let colour = self.colour;
+        output::write_error(&mut self.output(), colour, message).ok();
+    }
+
+    /// Say a key couldn't do anything, by ringing the bell if it's on.
+    fn refuse(&mut self) {
+        if self.bell {
+            write!(self.output(), "\x07").ok();
+        }
+    }
+
+    /// Remove the character before the cursor, and echo that.
+    fn erase(&mut self) {
+        if self.cursor == 0 {
+            self.refuse();
+            return;
+        }
+        // Remove the whole of a multi-byte character
+        let mut start = self.cursor - 1;
+        while start > 0 && is_continuation(self.buffer[start]) {
+            start -= 1;
+        }
+        self.erase_back_to(start);
+    }
+
+    /// Remove the word before the cursor, and any separators after it, like
+    /// readline's Ctrl-W.
+    #[cfg(feature = "line-editing")]
+    fn erase_word(&mut self) {
+        let Ok(before) = core::str::from_utf8(&self.buffer[..self.cursor]) else {
+            self.erase();
+            return;
+        };
+        let start = before
+            .trim_end_matches(parser::is_separator)
+            .trim_end_matches(|c| !parser::is_separator(c))
+            .len();
+        self.erase_back_to(start);
+    }
+
+    /// Throw away the whole line, like Ctrl-U or ESC, and show an empty
+    /// prompt.
+    #[cfg(feature = "ansi")]
+    fn erase_line(&mut self, context: &mut T) {
+        if self.echoes() {
+            // Rub out the line with spaces, rather than needing escape
+            // sequences
+            let columns = self.buffer[..self.used]
+                .iter()
+                .filter(|b| !is_continuation(**b))
+                .count();
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+            for _ in 0..columns {
+                write!(self.output(), " ").ok();
+            }
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+        }
+        self.used = 0;
+        self.cursor = 0;
+        self.discarded = true;
+    }
+
+    /// Remove the character at the cursor, and echo that.
+    #[cfg(feature = "line-editing")]
+    fn erase_forward(&mut self) {
+        if self.cursor == self.used {
+            return;
+        }
+        let mut end = self.cursor + 1;
+        while end < self.used && is_continuation(self.buffer[end]) {
+            end += 1;
+        }
+        self.buffer.copy_within(end..self.used, self.cursor);
+        self.used -= end - self.cursor;
+        if self.echoes() {
+            // Move what was after it along, and rub out the column left over
+            self.write_line_from(self.cursor);
+            write!(self.output(), " ").ok();
+            self.cursor_back(self.tail_len() + 1);
+        }
+    }
+
+    /// Remove everything from byte `start` up to the cursor, and echo that.
+    fn erase_back_to(&mut self, start: usize) {
+        let end = self.cursor;
+        let columns = self.buffer[start..end]
             .iter()
-            .filter(|p| matches!(p, Parameter::Mandatory { .. } | Parameter::Optional { .. }))
+            .filter(|b| !is_continuation(**b))
             .count();
-        if command.len() >= item.command.len() {
-            // Maybe arguments
-            let mut argument_buffer: [&str; 16] = [""; 16];
-            let mut argument_count = 0;
-            let mut positional_arguments = 0;
-            for (slot, arg) in argument_buffer
-                .iter_mut()
-                .zip(command[item.command.len()..].split_whitespace())
-            {
-                *slot = arg;
-                argument_count += 1;
-                if let Some(tail) = arg.strip_prefix("--") {
-                    // Validate named argument
-                    let mut found = false;
-                    for param in parameters.iter() {
-                        match param {
-                            Parameter::Named { parameter_name, .. } => {
-                                if tail == *parameter_name {
-                                    found = true;
-                                    break;
-                                }
-                            }
-                            Parameter::NamedValue { parameter_name, .. } => {
-                                if arg.contains('=') {
-                                    if let Some(given_name) = tail.split('=').next() {
-                                        if given_name == *parameter_name {
-                                            found = true;
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                            _ => {
-                                // Ignore
-                            }
-                        }
-                    }
-                    if !found {
-                        writeln!(interface, "Error: Did not understand {:?}", arg).unwrap();
-                        return;
-                    }
-                } else {
-                    positional_arguments += 1;
-                }
+        self.buffer.copy_within(end..self.used, start);
+        self.used -= end - start;
+        self.cursor = start;
+        if self.echo == Echo::Off {
+            return;
+        }
+        if self.cursor == self.used {
+            for _ in 0..columns {
+                write!(self.output(), "\u{0008} \u{0008}").ok();
             }
-            if positional_arguments < mandatory_parameter_count {
-                writeln!(interface, "Error: Insufficient arguments given").unwrap();
-            } else if positional_arguments > positional_parameter_count {
-                writeln!(interface, "Error: Too many arguments given").unwrap();
-            } else {
-                callback_function(
-                    parent_menu,
-                    item,
-                    &argument_buffer[0..argument_count],
-                    interface,
-                    context,
-                );
+        } else if self.echoes() {
+            // Move what was after it along, and rub out the columns left
+            // over at the end
+            for _ in 0..columns {
+                write!(self.output(), "\u{0008}").ok();
             }
-        } else {
-            // Definitely no arguments
-            if mandatory_parameter_count == 0 {
-                callback_function(parent_menu, item, &[], interface, context);
-            } else {
-                writeln!(interface, "Error: Insufficient arguments given").unwrap();
+            self.write_line_from(self.cursor);
+            for _ in 0..columns {
+                write!(self.output(), " ").ok();
             }
+            self.cursor_back(self.tail_len() + columns);
         }
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    fn dummy(
-        _menu: &Menu<(), u32>,
-        _item: &Item<(), u32>,
-        _args: &[&str],
-        _interface: &mut (),
-        _context: &mut u32,
-    ) {
+    /// Move the cursor one character to the left, or to the right.
+    #[cfg(feature = "line-editing")]
+    fn move_cursor(&mut self, left: bool) {
+        if left && self.cursor > 0 {
+            self.cursor -= 1;
+            while self.cursor > 0 && is_continuation(self.buffer[self.cursor]) {
+                self.cursor -= 1;
+            }
+            if self.echoes() {
+                self.cursor_back(1);
+            }
+        } else if !left && self.cursor < self.used {
+            self.cursor += 1;
+            while self.cursor < self.used && is_continuation(self.buffer[self.cursor]) {
+                self.cursor += 1;
+            }
+            if self.echoes() {
+                self.cursor_forward(1);
+            }
+        }
     }
 
-    #[test]
-    fn find_arg_mandatory() {
-        let item = Item {
-            command: "dummy",
-            help: None,
-            item_type: ItemType::Callback {
-                function: dummy,
-                parameters: &[
-                    Parameter::Mandatory {
-                        parameter_name: "foo",
-                        help: Some("Some help for foo"),
-                    },
-                    Parameter::Mandatory {
-                        parameter_name: "bar",
-                        help: Some("Some help for bar"),
-                    },
-                    Parameter::Mandatory {
-                        parameter_name: "baz",
-                        help: Some("Some help for baz"),
-                    },
-                ],
-            },
+    /// Move the cursor to the start of the word before it, or the end of
+    /// the word after it, like readline's Alt-B and Alt-F.
+    #[cfg(feature = "line-editing")]
+    fn move_word(&mut self, left: bool) {
+        let Ok(line) = core::str::from_utf8(&self.buffer[..self.used]) else {
+            return;
         };
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "foo"),
-            Ok(Some("a"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "bar"),
-            Ok(Some("b"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "baz"),
-            Ok(Some("c"))
-        );
-        // Not an argument
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "quux"),
-            Err(Error::NotFound)
-        );
+        // The cursor can be inside a character put together from bytes
+        // typed on either side of it
+        let (Some(before), Some(after)) = (line.get(..self.cursor), line.get(self.cursor..)) else {
+            return;
+        };
+        let (cursor, columns) = if left {
+            let start = before
+                .trim_end_matches(parser::is_separator)
+                .trim_end_matches(|c| !parser::is_separator(c))
+                .len();
+            (start, before[start..].chars().count())
+        } else {
+            let rest = after
+                .trim_start_matches(parser::is_separator)
+                .trim_start_matches(|c| !parser::is_separator(c));
+            let word = &after[..after.len() - rest.len()];
+            (self.cursor + word.len(), word.chars().count())
+        };
+        self.cursor = cursor;
+        if !self.echoes() {
+            return;
+        }
+        if left {
+            self.cursor_back(columns);
+        } else {
+            self.cursor_forward(columns);
+        }
     }
 
-    #[test]
-    fn find_arg_optional() {
-        let item = Item {
-            command: "dummy",
-            help: None,
-            item_type: ItemType::Callback {
-                function: dummy,
-                parameters: &[
-                    Parameter::Mandatory {
-                        parameter_name: "foo",
-                        help: Some("Some help for foo"),
-                    },
-                    Parameter::Mandatory {
-                        parameter_name: "bar",
-                        help: Some("Some help for bar"),
-                    },
-                    Parameter::Optional {
-                        parameter_name: "baz",
-                        help: Some("Some help for baz"),
-                    },
-                ],
-            },
+    /// Replace the line with an older or newer one from the history, and
+    /// show it.
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    fn recall(&mut self, older: bool, context: &mut T) {
+        // Lines read for a callback aren't commands
+        if self.line_input.is_some() {
+            return;
+        }
+        let Some(history) = &mut self.history else {
+            return;
         };
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "foo"),
-            Ok(Some("a"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "bar"),
-            Ok(Some("b"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "baz"),
-            Ok(Some("c"))
-        );
-        // Not an argument
-        assert_eq!(
-            argument_finder(&item, &["a", "b", "c"], "quux"),
-            Err(Error::NotFound)
-        );
-        // Missing optional
-        assert_eq!(argument_finder(&item, &["a", "b"], "baz"), Ok(None));
+        let line = if older {
+            let typed = core::str::from_utf8(&self.buffer[..self.used]).unwrap_or("");
+            history.older(typed)
+        } else {
+            history.newer()
+        };
+        let Some(line) = line.filter(|line| line.len() <= self.buffer.len()) else {
+            return;
+        };
+        self.buffer[..line.len()].copy_from_slice(line.as_bytes());
+        self.used = line.len();
+        self.cursor = self.used;
+        if self.echoes() {
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+            write!(self.output(), "\x1b[K").ok();
+        }
     }
 
-    #[test]
-    fn find_arg_named() {
-        let item = Item {
-            command: "dummy",
-            help: None,
-            item_type: ItemType::Callback {
-                function: dummy,
-                parameters: &[
-                    Parameter::Mandatory {
-                        parameter_name: "foo",
-                        help: Some("Some help for foo"),
-                    },
-                    Parameter::Named {
-                        parameter_name: "bar",
-                        help: Some("Some help for bar"),
-                    },
-                    Parameter::Named {
-                        parameter_name: "baz",
-                        help: Some("Some help for baz"),
-                    },
-                ],
-            },
+    /// Keep a byte which was pasted. Once the paste has ended, what was
+    /// pasted is typed in.
+    #[cfg(feature = "ansi")]
+    fn paste(&mut self, input: u8, context: &mut T) {
+        use paste::Paste;
+        let Some(paste) = &mut self.paste else {
+            return;
         };
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "foo"),
-            Ok(Some("a"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "bar"),
+        if !paste.keep(input, &mut self.buffer[self.used..]) {
+            return;
+        }
+        let Paste::Pasting {
+            len, overflowed, ..
+        } = *paste
+        else {
+            return;
+        };
+        *paste = Paste::new();
+        if overflowed {
+            let mut output = self.output();
+            output.ensure_newline().ok();
+            writeln!(output, "Paste too long to fit").ok();
+            self.show_prompt(true, context);
+            if self.echoes() {
+                self.write_line_buffer();
+                let tail = self.tail_len();
+                self.cursor_back(tail);
+            }
+            return;
+        }
+        // Move it out of the way of the line, which grows as it's typed in
+        let next = self.buffer.len() - len;
+        self.buffer.copy_within(self.used..self.used + len, next);
+        self.paste = Some(Paste::Typing { next });
+        while let Some(Paste::Typing { next }) = self.paste {
+            let Some(&byte) = self.buffer.get(next) else {
+                break;
+            };
+            self.paste = Some(Paste::Typing { next: next + 1 });
+            self.handle_byte(byte, context);
+        }
+        if self.paste.is_some() {
+            self.paste = Some(Paste::new());
+        }
+        // Show what's left on the line, which wasn't echoed as it was typed
+        if self.used > 0 && !self.waiting() && self.echoes() {
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+            let tail = self.tail_len();
+            self.cursor_back(tail);
+        }
+    }
+
+    /// Is what was pasted being typed in?
+    fn typing_paste(&self) -> bool {
+        #[cfg(feature = "ansi")]
+        return matches!(self.paste, Some(paste::Paste::Typing { .. }));
+        #[cfg(not(feature = "ansi"))]
+        false
+    }
+
+    /// How much of the buffer the line, and callbacks, can use. The rest
+    /// holds what was pasted and is still to be typed in.
+    fn room(&self) -> usize {
+        #[cfg(feature = "ansi")]
+        if let Some(paste::Paste::Typing { next }) = self.paste {
+            return next;
+        }
+        self.buffer.len()
+    }
+
+    /// Start searching the history, after Ctrl-R. Does nothing without a
+    /// history buffer.
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    fn start_search(&mut self) {
+        // Lines read for a callback aren't commands
+        if self.line_input.is_some() || self.history.is_none() {
+            return;
+        }
+        self.search = Some(history::Search {
+            len: 0,
+            found: None,
+            failed: false,
+        });
+        self.draw_search();
+    }
+
+    /// Change what's being looked for in the history, or finish looking.
+    ///
+    /// What's typed is kept in the buffer after the line, which is left
+    /// alone until a line found is accepted.
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    fn search(&mut self, key: input::SearchKey, input: u8, context: &mut T) {
+        use input::SearchKey;
+        let (Some(mut search), Some(history)) = (self.search, &self.history) else {
+            return;
+        };
+        let typed = &mut self.buffer[self.used..];
+        let from = match key {
+            SearchKey::Type => {
+                if search.len == typed.len() {
+                    return;
+                }
+                typed[search.len] = input;
+                search.len += 1;
+                // What's found still matches if it has more than was typed
+                search.found
+            }
+            SearchKey::Erase => {
+                while search.len > 0 {
+                    search.len -= 1;
+                    if !is_continuation(typed[search.len]) {
+                        break;
+                    }
+                }
+                None
+            }
+            SearchKey::Older => search.found.map(|n| n - 1),
+            SearchKey::Accept => {
+                let line = search.found.and_then(|n| history.get(n));
+                if let Some(line) = line.filter(|line| line.len() <= self.buffer.len()) {
+                    self.buffer[..line.len()].copy_from_slice(line.as_bytes());
+                    self.used = line.len();
+                    self.cursor = self.used;
+                }
+                self.end_search(context);
+                self.submit(context);
+                return;
+            }
+            SearchKey::Abort | SearchKey::Leave => {
+                self.end_search(context);
+                return;
+            }
+        };
+        // Wait for the rest of a character before looking for it
+        if let Ok(text) = core::str::from_utf8(&typed[..search.len]) {
+            if text.is_empty() {
+                search.found = None;
+                search.failed = false;
+            } else if let Some(n) = history.find(text, from) {
+                search.found = Some(n);
+                search.failed = false;
+            } else {
+                search.failed = true;
+            }
+        }
+        self.search = Some(search);
+        self.draw_search();
+    }
+
+    /// Show what's being looked for in the history, and the line found, in
+    /// place of the prompt and the line being typed.
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    fn draw_search(&mut self) {
+        let (Some(search), Some(history)) = (self.search, &self.history) else {
+            return;
+        };
+        if !self.echoes() {
+            return;
+        }
+        let typed = &self.buffer[self.used..self.used + search.len];
+        let typed = core::str::from_utf8(typed)
+            .or_else(|e| core::str::from_utf8(&typed[..e.valid_up_to()]))
+            .unwrap_or("");
+        let found = search.found.and_then(|n| history.get(n)).unwrap_or("");
+        let failed = if search.failed { "failed " } else { "" };
+        let mut output = Output::new(&mut self.interface, &mut self.output);
+        write!(
+            output,
+            "\r({}reverse-i-search)'{}': {}\x1b[K",
+            failed, typed, found
+        )
+        .ok();
+    }
+
+    /// Stop searching the history, and show the prompt and the line again.
+    #[cfg(all(feature = "ansi", feature = "history"))]
+    fn end_search(&mut self, context: &mut T) {
+        self.search = None;
+        if self.echoes() {
+            write!(self.output(), "\r").ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+            write!(self.output(), "\x1b[K").ok();
+            let tail = self.tail_len();
+            self.cursor_back(tail);
+        }
+    }
+
+    /// Do we show what's typed as it's typed?
+    fn echoes(&self) -> bool {
+        matches!(self.echo, Echo::On | Echo::Mask(_))
+    }
+
+    /// How many characters there are after the cursor.
+    fn tail_len(&self) -> usize {
+        self.buffer[self.cursor..self.used]
+            .iter()
+            .filter(|b| !is_continuation(**b))
+            .count()
+    }
+
+    /// Move the terminal's cursor `columns` to the left.
+    fn cursor_back(&mut self, columns: usize) {
+        match columns {
+            0 => {}
+            1 => {
+                write!(self.output(), "\x1b[D").ok();
+            }
+            n => {
+                write!(self.output(), "\x1b[{}D", n).ok();
+            }
+        }
+    }
+
+    /// Move the terminal's cursor `columns` to the right.
+    #[cfg(feature = "line-editing")]
+    fn cursor_forward(&mut self, columns: usize) {
+        match columns {
+            0 => {}
+            1 => {
+                write!(self.output(), "\x1b[C").ok();
+            }
+            n => {
+                write!(self.output(), "\x1b[{}C", n).ok();
+            }
+        }
+    }
+
+    /// Write out the line being edited, or a `*` for each character if it's
+    /// a secret.
+    fn write_line_buffer(&mut self) {
+        self.write_line_from(0);
+    }
+
+    /// Write out the line being edited from byte `start` on, or a `*` for
+    /// each character if it's a secret.
+    fn write_line_from(&mut self, start: usize) {
+        let masked = self.line_input.as_ref().filter(|r| r.masked).map(|_| '*');
+        let mask = match self.echo {
+            Echo::On | Echo::Local => masked,
+            Echo::Off => return,
+            Echo::Mask(c) => Some(c),
+        };
+        if let Ok(s) = core::str::from_utf8(&self.buffer[start..self.used]) {
+            let mut output = Output::new(&mut self.interface, &mut self.output);
+            match mask {
+                Some(mask) => {
+                    for _ in s.chars() {
+                        write!(output, "{}", mask).ok();
+                    }
+                }
+                None => {
+                    write!(output, "{}", s).ok();
+                }
+            }
+        }
+    }
+
+    /// Complete the option name or parameter value at the end of the line,
+    /// after Tab, or type the Tab if nothing could be completed.
+    #[cfg(feature = "completion")]
+    fn complete(&mut self, context: &mut T) {
+        if !self.complete_option(context) && !self.complete_value(context) {
+            self.insert(b'\t', context);
+        }
+    }
+
+    /// The item the command line being typed runs, and where its arguments
+    /// start, if the end of the line is being typed.
+    #[cfg(feature = "completion")]
+    fn item_being_typed(&self) -> Option<(&'a Item<'a, I, T>, usize)> {
+        // Only the end of the line is completed
+        if self.line_input.is_some() || self.cursor != self.used {
+            return None;
+        }
+        let line = core::str::from_utf8(&self.buffer[..self.used]).ok()?;
+        let (cmd, args) = parser::split_command(line);
+        let (_, item) = self.menu_mgr.current().find(cmd)?;
+        Some((item, line.len() - args.len()))
+    }
+
+    /// Add the `added` bytes just after the line in the buffer to it, and
+    /// echo them.
+    #[cfg(feature = "completion")]
+    fn accept_completion(&mut self, added: usize) {
+        let start = self.used;
+        self.used += added;
+        self.cursor = self.used;
+        self.write_line_from(start);
+    }
+
+    /// Add to, or list the candidates for, the name of the option at the end
+    /// of the line, after `--`. Returns `false` if an option's name isn't
+    /// being typed.
+    #[cfg(feature = "completion")]
+    fn complete_option(&mut self, context: &mut T) -> bool {
+        let Some((item, args_start)) = self.item_being_typed() else {
+            return false;
+        };
+        let parameters = item.parameters();
+        let (line, room) = self.buffer.split_at_mut(self.used);
+        let Some(partial) = core::str::from_utf8(&line[args_start..])
+            .ok()
+            .and_then(complete::option_position)
+        else {
+            return false;
+        };
+        let mut candidates = complete::Candidates::new(partial, room);
+        for (name, _) in complete::options(parameters) {
+            candidates.add(name);
+        }
+        let (count, mut added) = candidates.result();
+        if candidates.is_whole() && added < room.len() {
+            // A value goes straight after the `=`
+            let after = complete::options(parameters)
+                .find(|(name, _)| {
+                    name.strip_prefix(partial)
+                        .is_some_and(|tail| tail.as_bytes() == &room[..added])
+                })
+                .map_or(' ', |(_, after)| after);
+            room[added] = after as u8;
+            added += 1;
+        }
+        if added > 0 {
+            self.accept_completion(added);
+        } else if count > 1 {
+            let mut output = Output::new(&mut self.interface, &mut self.output);
+            writeln!(output).ok();
+            let mut lister = complete::Lister::default();
+            for (name, after) in complete::options(parameters) {
+                if name.starts_with(partial) {
+                    let after = if after == '=' { "=" } else { "" };
+                    lister.add(&mut output, format_args!("--{}{}", name, after));
+                }
+            }
+            writeln!(output).ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+        } else if count == 0 {
+            self.refuse();
+        }
+        true
+    }
+
+    /// Add to, or list the candidates for, the parameter value at the end of
+    /// the line, from the parameter's allowed values if it has them, or the
+    /// value completer. Returns `false` if there's neither.
+    #[cfg(feature = "completion")]
+    fn complete_value(&mut self, context: &mut T) -> bool {
+        let completer = self.value_completer;
+        let Some((item, args_start)) = self.item_being_typed() else {
+            return completer.is_some();
+        };
+        let (line, room) = self.buffer.split_at_mut(self.used);
+        let Ok(args) = core::str::from_utf8(&line[args_start..]) else {
+            return completer.is_some();
+        };
+        let Some((parameter, partial)) =
+            complete::value_position(item.parameters(), args, self.allow_option_prefixes)
+        else {
+            return completer.is_some();
+        };
+        if parameter.allowed().is_none() && completer.is_none() {
+            return false;
+        }
+        let each_candidate =
+            |context: &mut T, emit: &mut dyn FnMut(&str)| match (parameter.allowed(), completer) {
+                (Some(allowed), _) => allowed.iter().for_each(|c| emit(c)),
+                (None, Some(completer)) => completer(item, parameter, partial, context, emit),
+                (None, None) => {}
+            };
+        let mut candidates = complete::Candidates::new(partial, room);
+        each_candidate(context, &mut |c| candidates.add(c));
+        let (count, mut added) = candidates.result();
+        if candidates.is_whole() && added < room.len() {
+            room[added] = b' ';
+            added += 1;
+        }
+        if added > 0 {
+            self.accept_completion(added);
+        } else if count > 1 {
+            let mut output = Output::new(&mut self.interface, &mut self.output);
+            writeln!(output).ok();
+            let mut lister = complete::Lister::default();
+            each_candidate(context, &mut |c| {
+                if c.starts_with(partial) {
+                    lister.add(&mut output, format_args!("{}", c));
+                }
+            });
+            writeln!(output).ok();
+            self.show_prompt(false, context);
+            self.write_line_buffer();
+        } else if count == 0 {
+            self.refuse();
+        }
+        true
+    }
+
+    /// Give a line typed in answer to [`CommandContext::read_line`] to the
+    /// callback which asked for it.
+    fn process_line(&mut self, request: command::LineRequest<I, T>, context: &mut T) {
+        writeln!(self.output()).ok();
+        let room = self.room();
+        let (line, spare) = self.buffer[..room].split_at_mut(self.used);
+        let Ok(line) = core::str::from_utf8(line) else {
+            self.write_error(format_args!("Input was not valid UTF-8"));
+            // Ask again
+            self.line_input = Some(request);
+            return;
+        };
+        let spare = command::Spare {
+            start: line.len(),
+            bytes: spare,
+        };
+        let writer = ConsoleWriter::new(&mut self.interface, &mut self.output);
+        let mut command_context =
+            CommandContext::new(writer, &self.menu_mgr, self.busy_hook, self.keys)
+                .with_spare(spare);
+        (request.then)(line, &mut command_context, context);
+        let requests = command_context.into_requests();
+        self.apply_requests(requests, context);
+    }
+
+    /// Scan the buffer and do the right thing based on its contents.
+    fn process_command(&mut self, context: &mut T) {
+        // Go to the next line, below the prompt
+        writeln!(self.output()).ok();
+        // Take the buffer out of `self` while we work on the command, so we
+        // can still print things.
+        let room = self.room();
+        let buffer = core::mem::take(&mut self.buffer);
+        #[cfg(feature = "history")]
+        let Some(used) = self.expand_history(&mut buffer[..room]) else {
+            self.buffer = buffer;
+            return;
+        };
+        #[cfg(not(feature = "history"))]
+        let used = self.used;
+        let (command_line, spare) = buffer[..room].split_at_mut(used);
+        if let Ok(command_line) = core::str::from_utf8(command_line) {
+            // We have a valid string
+            #[cfg(feature = "history")]
+            if let Some(history) = &mut self.history {
+                history.push(command_line);
+            }
+            #[cfg(feature = "paging")]
+            {
+                self.page = self
+                    .page_height
+                    .map(|height| command::Page::new(used, 0, height));
+            }
+            let spare = command::Spare {
+                start: used,
+                bytes: spare,
+            };
+            let outcome = self.run_line(command_line, spare, context);
+            #[cfg(feature = "paging")]
+            {
+                self.page = None;
+            }
+            #[cfg(feature = "builtins-extra")]
+            self.record_line(command_line, outcome.is_success());
+            #[cfg(not(feature = "builtins-extra"))]
+            let _ = outcome;
+        } else {
+            // Hmm ..  we did not have a valid string
+            self.write_error(format_args!("Input was not valid UTF-8"));
+        }
+        self.buffer = buffer;
+    }
+
+    /// Add a line which was run to the macro being recorded, if one is.
+    #[cfg(feature = "builtins-extra")]
+    fn record_line(&mut self, command_line: &str, succeeded: bool) {
+        let (cmd, _) = parser::split_command(command_line);
+        let is_record_or_stop = matches!(cmd, "record" | "stop") && self.is_macro_command(cmd);
+        let Some(macros) = &mut self.macros else {
+            return;
+        };
+        if succeeded && !is_record_or_stop && !macros.push(command_line) {
+            self.write_error(format_args!("Macro full, so recording has stopped"));
+        }
+    }
+
+    /// If the line in `buffer` is `!<n>`, replace it with line `n` from the
+    /// history and print it. `!!` is the newest line.
+    ///
+    /// Returns the length of the line, or `None` if there's no such line.
+    #[cfg(feature = "history")]
+    fn expand_history(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        let Some(history) = &self.history else {
+            return Some(self.used);
+        };
+        let Some(reference) = buffer[0..self.used]
+            .strip_prefix(b"!")
+            .and_then(|n| core::str::from_utf8(n).ok())
+        else {
+            return Some(self.used);
+        };
+        let number = match reference {
+            "!" => match history.entries().last() {
+                Some((number, _)) => number,
+                None => {
+                    self.write_error(format_args!("No previous command"));
+                    return None;
+                }
+            },
+            n => match n.parse::<usize>() {
+                Ok(number) => number,
+                Err(_) => return Some(self.used),
+            },
+        };
+        match history.get(number) {
+            Some(line) if line.len() <= buffer.len() => {
+                buffer[0..line.len()].copy_from_slice(line.as_bytes());
+                let used = line.len();
+                let mut output = Output::new(&mut self.interface, &mut self.output);
+                writeln!(output, "{}", line).ok();
+                Some(used)
+            }
+            _ => {
+                self.write_error(format_args!("No history entry {}", number));
+                None
+            }
+        }
+    }
+
+    /// Run a command line, exactly as if it had been typed in and followed
+    /// by Enter.
+    ///
+    /// This doesn't touch anything the user might be part way through
+    /// typing, and doesn't print a prompt afterwards - call
+    /// [`Runner::prompt`] if you want one. It will never panic, whatever
+    /// `command_line` contains, which makes it a good target for fuzzing.
+    pub fn run_command<'l>(&mut self, command_line: &'l str, context: &mut T) -> CommandOutcome<'l>
+    where
+        'a: 'l,
+    {
+        self.idle_ticks = 0;
+        self.run_line(command_line, command::Spare::none(), context)
+    }
+
+    /// Run a command line, with `spare` room in the buffer after it.
+    fn run_line<'l>(
+        &mut self,
+        command_line: &'l str,
+        spare: command::Spare,
+        context: &mut T,
+    ) -> CommandOutcome<'l>
+    where
+        'a: 'l,
+    {
+        self.start(context);
+        // Split off the first word. The rest of the line holds the arguments.
+        let (cmd, args) = parser::split_command(command_line);
+        let machine_mode = self.machine_mode;
+        if machine_mode {
+            writeln!(self.output(), "<<<BEGIN cmd={:?}>>>", cmd).ok();
+        }
+        let outcome = self.execute(cmd, args, spare, context);
+        let colour = self.colour;
+        Self::report(&mut self.output(), machine_mode, colour, cmd, &outcome).ok();
+        outcome
+    }
+
+    /// Say how a command went, if it didn't go well or we're in machine
+    /// mode, in colour if `colour`.
+    fn report(
+        output: &mut Output<I>,
+        machine_mode: bool,
+        colour: bool,
+        cmd: &str,
+        outcome: &CommandOutcome,
+    ) -> core::fmt::Result {
+        if machine_mode {
+            output.ensure_newline()?;
+            return match outcome {
+                CommandOutcome::Success => {
+                    writeln!(output, "<<<RESULT cmd={:?} status=ok>>>", cmd)
+                }
+                _ => writeln!(
+                    output,
+                    "<<<RESULT cmd={:?} status=err reason={}>>>",
+                    cmd,
+                    output::Quoted(outcome)
+                ),
+            };
+        }
+        let mut error = |message| output::write_error(output, colour, message);
+        match outcome {
+            CommandOutcome::Success => Ok(()),
+            CommandOutcome::Empty => error(format_args!("Input was empty")),
+            CommandOutcome::NotFound(cmd) => {
+                error(format_args!("Command {:?} not found. Try 'help'.", cmd))
+            }
+            CommandOutcome::NoHelp(cmd) => error(format_args!("I can't help with {:?}", cmd)),
+            CommandOutcome::BadArguments(_) | CommandOutcome::Failed(_) => {
+                error(format_args!("Error: {}", outcome))
+            }
+            CommandOutcome::Denied(Some(message)) => error(format_args!("{}", message)),
+            CommandOutcome::Denied(None) => Ok(()),
+        }
+    }
+
+    /// Run a command, without reporting how it went.
+    fn execute<'l>(
+        &mut self,
+        cmd: &'l str,
+        args: &'l str,
+        spare: command::Spare,
+        context: &mut T,
+    ) -> CommandOutcome<'l>
+    where
+        'a: 'l,
+    {
+        if cmd.is_empty() {
+            return CommandOutcome::Empty;
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.output.stats.commands_executed += 1;
+        }
+        #[cfg(feature = "builtins-extra")]
+        if let Some(outcome) = self.extra_builtin(cmd, args, context) {
+            return outcome;
+        }
+        #[cfg(feature = "history")]
+        if cmd == "history" && self.history.is_some() {
+            return self.print_history(args);
+        }
+        let menu = self.menu_mgr.current();
+        if cmd == "help" {
+            match parser::tokenize(args).next() {
+                Some(arg) => match menu.find(arg) {
+                    Some((_, item)) => {
+                        self.print_long_help(item, context);
+                    }
+                    None => return CommandOutcome::NoHelp(arg),
+                },
+                _ => {
+                    let items = menu.items;
+                    let in_sub_menu = self.menu_mgr.depth() != 0;
+                    #[allow(unused_mut)]
+                    let mut builtins = help::Builtins::default();
+                    #[cfg(feature = "builtins-extra")]
+                    {
+                        builtins.about = self.about.filter(|a| !a.hidden).map(|a| a.command);
+                        builtins.show = self.show.is_some() && menu.find("show").is_none();
+                        let macros = self.macros.is_some();
+                        builtins.record = macros && menu.find("record").is_none();
+                        builtins.stop = macros && menu.find("stop").is_none();
+                        builtins.play = macros && menu.find("play").is_none();
+                    }
+                    #[cfg(feature = "history")]
+                    {
+                        builtins.history = self.history.is_some();
+                    }
+                    let hook = self.busy_hook;
+                    let links = self.help_links;
+                    let colour = self.colour;
+                    help::write_item_list(
+                        &mut KeepAlive::new(self.output(), hook, context),
+                        items.iter().filter(|i| !i.hidden),
+                        in_sub_menu,
+                        &builtins,
+                        links,
+                        colour,
+                    )
+                    .ok();
+                }
+            }
+        } else if cmd == "exit" && self.menu_mgr.depth() != 0 {
+            let mut target = self.menu_mgr.clone();
+            target.pop();
+            let target = target.path();
+            self.change_menu(target, context);
+        } else {
+            let Some((i, item)) = menu.find(cmd) else {
+                return CommandOutcome::NotFound(cmd);
+            };
+            if let (Some(group), Some((check, _))) = (item.throttle_group, self.throttle) {
+                match check(group, context) {
+                    ThrottleDecision::Allow => {}
+                    ThrottleDecision::Deny(message) => {
+                        return CommandOutcome::Denied(Some(message))
+                    }
+                    ThrottleDecision::DenyQuiet => return CommandOutcome::Denied(None),
+                }
+            }
+            return self.invoke(i, item, args, spare, context);
+        }
+        CommandOutcome::Success
+    }
+
+    /// Run the item at `i` in the current menu, once we know it's allowed
+    /// to run.
+    fn invoke<'l>(
+        &mut self,
+        i: usize,
+        item: &'a Item<'a, I, T>,
+        args: &'l str,
+        spare: command::Spare,
+        context: &mut T,
+    ) -> CommandOutcome<'l>
+    where
+        'a: 'l,
+    {
+        let menu = self.menu_mgr.current();
+        let (handler, parameters): (&dyn Handler<I, T>, _) = match &item.item_type {
+            ItemType::Callback {
+                function,
+                parameters,
+            } => (function, *parameters),
+            ItemType::ArgsCallback {
+                function,
+                parameters,
+            } => (function, *parameters),
+            ItemType::Handler {
+                handler,
+                parameters,
+            } => (*handler, *parameters),
+            ItemType::Dispatch { parameters, .. } => match self.dispatcher {
+                Some(dispatcher) => (dispatcher, *parameters),
+                None => return CommandOutcome::Failed("No command handler"),
+            },
+            ItemType::Menu(_) => {
+                let mut target = self.menu_mgr.clone();
+                if target.push(i).is_err() {
+                    return CommandOutcome::Failed("Too many menus deep");
+                }
+                let target = target.path();
+                self.change_menu(target, context);
+                return CommandOutcome::Success;
+            }
+        };
+        let requests = Self::call_function(
+            &mut Output::new(&mut self.interface, &mut self.output),
+            &self.menu_mgr,
+            self.busy_hook,
+            self.keys,
+            self.capture.as_mut(),
+            #[cfg(feature = "paging")]
+            self.page.take(),
+            spare,
+            context,
+            handler,
+            parser::ArgumentParser::new(parameters).allow_prefixes(self.allow_option_prefixes),
+            menu,
+            item,
+            args,
+        );
+        match requests {
+            Ok(requests) => {
+                self.suppress_prompt = item.suppress_prompt_after;
+                let failure = requests.failure;
+                if let (true, Some(group), Some((_, on_failure))) = (
+                    requests.throttle_failure,
+                    item.throttle_group,
+                    self.throttle,
+                ) {
+                    on_failure(group, context);
+                }
+                if let (Some(capture), Some((used, truncated))) =
+                    (&mut self.capture, requests.captured)
+                {
+                    capture.finish(used, truncated);
+                }
+                self.apply_requests(requests, context);
+                if let Some(reason) = failure {
+                    return CommandOutcome::Failed(reason);
+                }
+            }
+            Err(e) => return CommandOutcome::BadArguments(e),
+        }
+        CommandOutcome::Success
+    }
+
+    /// Do whatever a callback asked for.
+    fn apply_requests(&mut self, requests: command::Requests<I, T>, context: &mut T) {
+        if let Some(target) = requests.menu {
+            self.change_menu(target, context);
+        }
+        if let Some(prefix) = requests.prompt_prefix {
+            self.prompt_prefix = prefix;
+        }
+        if let Some(machine_mode) = requests.machine_mode {
+            self.machine_mode = machine_mode;
+        }
+        if let Some(echo) = requests.echo {
+            self.echo = echo;
+        }
+        if let Some(input) = requests.input {
+            // Whatever was waiting for input before has been replaced
+            self.raw_mode = None;
+            self.passthrough = None;
+            self.confirm = None;
+            self.line_input = None;
+            #[cfg(feature = "paging")]
+            {
+                self.more = None;
+            }
+            self.template = None;
+            match input {
+                InputRequest::Raw(handler) => self.raw_mode = Some(handler),
+                InputRequest::Passthrough(forward, escape) => {
+                    self.passthrough = Some((forward, EscapeMatcher::new(escape)));
+                }
+                InputRequest::Confirm(question, then) => {
+                    write!(self.output(), "{} [y/N] ", question).ok();
+                    self.confirm = Some(then);
+                }
+                InputRequest::Line(request) => {
+                    self.template = Some(request.template.clone());
+                    self.line_input = Some(request);
+                }
+                InputRequest::Prefill(template) => self.template = Some(template),
+                #[cfg(feature = "paging")]
+                InputRequest::More(page) => {
+                    write!(self.output(), "--More--").ok();
+                    self.more = Some(page);
+                }
+            }
+        }
+    }
+
+    /// Handle a byte typed in answer to [`CommandContext::confirm`].
+    fn answer(&mut self, confirmed: bool, then: ConfirmFn<I, T>, context: &mut T) {
+        self.confirm = None;
+        writeln!(self.output(), "{}", if confirmed { "y" } else { "n" }).ok();
+        // Nothing is typed while a question is waiting
+        let room = self.room();
+        let spare = command::Spare {
+            start: 0,
+            bytes: &mut self.buffer[..room],
+        };
+        let writer = ConsoleWriter::new(&mut self.interface, &mut self.output);
+        let mut command_context =
+            CommandContext::new(writer, &self.menu_mgr, self.busy_hook, self.keys)
+                .with_spare(spare);
+        then(confirmed, &mut command_context, context);
+        let requests = command_context.into_requests();
+        self.apply_requests(requests, context);
+        if !self.waiting() {
+            self.show_prompt(true, context);
+            self.fill_template();
+        }
+    }
+
+    /// Put the line a callback asked to start the next one with on the
+    /// line, as if it had been typed, once the prompt is showing.
+    fn fill_template(&mut self) {
+        let Some(template) = self.template.take() else {
+            return;
+        };
+        let len = template.len();
+        self.buffer.copy_within(template, 0);
+        self.used = len;
+        self.cursor = len;
+        self.write_line_buffer();
+    }
+
+    /// Handle a key pressed at `--More--`, by calling the item again for the
+    /// next page of its output, or going back to the prompt.
+    ///
+    /// The item is called directly, as the command line was run in full for
+    /// the first page: it isn't checked with the throttle function, counted,
+    /// or framed in machine mode again.
+    #[cfg(feature = "paging")]
+    fn more(&mut self, key: MoreKey, context: &mut T) {
+        let Some(page) = self.more.take() else {
+            return;
+        };
+        // Rub out `--More--`, which leaves us at the start of a line
+        write!(self.output(), "\r        \r").ok();
+        self.output.mid_line = false;
+        let height = match key {
+            MoreKey::Page => self.page_height.unwrap_or(page.height),
+            MoreKey::Line => 1,
+            MoreKey::Quit => {
+                self.show_prompt(true, context);
+                return;
+            }
+        };
+        self.page = Some(command::Page::new(page.line_len, page.skip, height));
+        // The command line is still in the buffer, as nothing could be typed
+        let buffer = core::mem::take(&mut self.buffer);
+        if let Ok(command_line) = core::str::from_utf8(&buffer[0..page.line_len]) {
+            let (cmd, args) = parser::split_command(command_line);
+            if let Some((i, item)) = self.menu_mgr.current().find(cmd) {
+                let outcome = self.invoke(i, item, args, command::Spare::none(), context);
+                let colour = self.colour;
+                Self::report(&mut self.output(), false, colour, cmd, &outcome).ok();
+            }
+        }
+        self.buffer = buffer;
+        self.page = None;
+        if self.suppress_prompt {
+            self.suppress_prompt = false;
+        } else if !self.waiting() {
+            self.show_prompt(true, context);
+        }
+    }
+
+    /// Move to another menu in the tree, calling the exit callback of every
+    /// menu we leave and the entry callback of every menu we enter.
+    fn change_menu(&mut self, target: [Option<usize>; menu_manager::MAX_DEPTH], context: &mut T) {
+        self.menu_mgr
+            .change_menu(target, &mut self.interface, context, |_, _, _| {});
+    }
+
+    /// The `history` command.
+    #[cfg(feature = "history")]
+    fn print_history<'l>(&mut self, args: &'l str) -> CommandOutcome<'l> {
+        let Some(history) = &mut self.history else {
+            return CommandOutcome::Success;
+        };
+        let mut output = Output::new(&mut self.interface, &mut self.output);
+        match parser::tokenize(args).next() {
+            None => {
+                let skip = history.len().saturating_sub(self.history_list_len);
+                for (number, line) in history.entries().skip(skip) {
+                    writeln!(output, "{:>5}  {}", number, line).ok();
+                }
+            }
+            Some("clear") => history.clear(),
+            Some(arg) => {
+                return CommandOutcome::BadArguments(parser::ArgError::UnknownOption(arg, &[]));
+            }
+        }
+        CommandOutcome::Success
+    }
+
+    /// Run `cmd` if it's one of the commands `builtins-extra` adds, and
+    /// has been set up.
+    #[cfg(feature = "builtins-extra")]
+    fn extra_builtin<'l>(
+        &mut self,
+        cmd: &'l str,
+        args: &'l str,
+        context: &mut T,
+    ) -> Option<CommandOutcome<'l>> {
+        if let Some(about) = self.about.filter(|a| a.command == cmd) {
+            self.print_about(&about);
+        } else if let Some(show) = self
+            .show
+            .filter(|_| cmd == "show" && self.menu_mgr.current().find(cmd).is_none())
+        {
+            let items = self.menu_mgr.current().items;
+            show(&mut self.output(), items, context).ok();
+        } else if self.is_macro_command(cmd) {
+            return Some(self.macro_command(cmd, args, context));
+        } else {
+            return None;
+        }
+        Some(CommandOutcome::Success)
+    }
+
+    /// Is `cmd` `record`, `stop` or `play`, with macro buffers set up and no
+    /// item in the current menu with the same command?
+    #[cfg(feature = "builtins-extra")]
+    fn is_macro_command(&self, cmd: &str) -> bool {
+        self.macros.is_some()
+            && matches!(cmd, "record" | "stop" | "play")
+            && self.menu_mgr.current().find(cmd).is_none()
+    }
+
+    /// Run `record`, `stop` or `play`.
+    #[cfg(feature = "builtins-extra")]
+    fn macro_command<'l>(
+        &mut self,
+        cmd: &'l str,
+        args: &'l str,
+        context: &mut T,
+    ) -> CommandOutcome<'l> {
+        let Some(macros) = &mut self.macros else {
+            return CommandOutcome::Success;
+        };
+        if cmd == "stop" {
+            let Some(number) = macros.stop() else {
+                return CommandOutcome::Failed("Not recording");
+            };
+            writeln!(self.output(), "Recorded macro {}", number).ok();
+            return CommandOutcome::Success;
+        }
+        let number = match parser::tokenize(args).next() {
+            None => {
+                return CommandOutcome::BadArguments(parser::ArgError::InsufficientArguments(
+                    "slot",
+                ))
+            }
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(number) => number,
+                Err(_) => {
+                    return CommandOutcome::BadArguments(parser::ArgError::BadValue("slot", arg))
+                }
+            },
+        };
+        if macros.playing {
+            return CommandOutcome::Failed("A macro can't play a macro");
+        }
+        if cmd == "record" {
+            if let Err(reason) = macros.record(number) {
+                return CommandOutcome::Failed(reason);
+            }
+            writeln!(
+                self.output(),
+                "Recording macro {}. Type 'stop' to finish.",
+                number
+            )
+            .ok();
+            return CommandOutcome::Success;
+        }
+        let Some(slot) = macros.take(number) else {
+            return CommandOutcome::Failed("No such macro");
+        };
+        macros.playing = true;
+        for line in macros::lines(slot) {
+            self.show_prompt(true, context);
+            writeln!(self.output(), "{}", line).ok();
+            self.run_command(line, context);
+        }
+        if let Some(macros) = &mut self.macros {
+            macros.playing = false;
+            macros.put_back(number, slot);
+        }
+        CommandOutcome::Success
+    }
+
+    #[cfg(feature = "builtins-extra")]
+    fn print_about(&mut self, about: &About) {
+        let crate_line = ("menu", env!("CARGO_PKG_VERSION"));
+        let crate_line = about.crate_version.then_some(&crate_line);
+        let lines = || about.lines.iter().chain(crate_line);
+        let width = lines().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, value) in lines() {
+            let name_width = name.len();
+            writeln!(
+                self.output(),
+                "{}:{:pad$} {}",
+                name,
+                "",
+                value,
+                pad = width - name_width
+            )
+            .ok();
+        }
+    }
+
+    fn print_long_help(&mut self, item: &Item<I, T>, context: &mut T) {
+        let (hook, colour) = (self.busy_hook, self.colour);
+        let mut out = KeepAlive::new(self.output(), hook, context);
+        help::write_long_help(&mut out, item, None, colour).ok();
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn call_function<'l>(
+        output: &mut Output<I>,
+        menu_mgr: &menu_manager::MenuManager<I, T>,
+        busy_hook: Option<BusyHookFn<T>>,
+        keys: command::Keys<I>,
+        capture: Option<&mut capture::CaptureBuffer>,
+        #[cfg(feature = "paging")] page: Option<command::Page>,
+        spare: command::Spare,
+        context: &mut T,
+        handler: &dyn Handler<I, T>,
+        parser: parser::ArgumentParser<'l>,
+        parent_menu: &Menu<I, T>,
+        item: &Item<I, T>,
+        args: &'l str,
+    ) -> Result<command::Requests<I, T>, parser::ArgError<'l>> {
+        let mut slots = storage::argument_slots(args);
+        match parser.parse(args, slots.as_mut()) {
+            Ok(parsed) => {
+                let writer = ConsoleWriter::new(output.interface, output.state);
+                let mut command_context =
+                    CommandContext::new(writer, menu_mgr, busy_hook, keys).with_spare(spare);
+                #[cfg(feature = "paging")]
+                {
+                    command_context = command_context.with_page(page);
+                }
+                if let Some(capture) = capture {
+                    command_context = command_context.with_capture(capture.start());
+                }
+                handler.handle_parsed(parent_menu, item, &parsed, &mut command_context, context);
+                Ok(command_context.into_requests())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<'a, I, T> Runner<'a, I, T>
+where
+    I: core::fmt::Write,
+    T: CommandHandler<I>,
+{
+    /// Route `Dispatch` items to the context's [`CommandHandler`].
+    ///
+    /// Until this is called, entering the command of a `Dispatch` item just
+    /// prints an error.
+    pub fn enable_dispatch(&mut self) {
+        self.dispatcher = Some(&dispatch::Dispatcher);
+    }
+}
+
+#[cfg(feature = "builtins-extra")]
+impl<'a, I, T> Runner<'a, I, T>
+where
+    I: core::fmt::Write,
+    T: 'static,
+{
+    /// Add a `show` command, which lists every item in the current menu
+    /// built with [`value::value`], with its current value. An item called
+    /// `show` in the current menu is run instead.
+    pub fn enable_show(&mut self) {
+        self.show = Some(value::show);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::string::{String, ToString};
+
+    fn dummy(
+        _menu: &Menu<(), u32>,
+        _item: &Item<(), u32>,
+        _args: &[&str],
+        _interface: &mut CommandContext<(), u32>,
+        _context: &mut u32,
+    ) {
+    }
+
+    #[test]
+    fn find_arg_mandatory() {
+        let item = Item {
+            command: "dummy",
+            help: None,
+            item_type: ItemType::Callback {
+                function: dummy,
+                parameters: &[
+                    Parameter::Mandatory {
+                        parameter_name: "foo",
+                        help: Some("Some help for foo"),
+                        display: None,
+                    },
+                    Parameter::Mandatory {
+                        parameter_name: "bar",
+                        help: Some("Some help for bar"),
+                        display: None,
+                    },
+                    Parameter::Mandatory {
+                        parameter_name: "baz",
+                        help: Some("Some help for baz"),
+                        display: None,
+                    },
+                ],
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        };
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "foo"),
+            Ok(Some("a"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "bar"),
+            Ok(Some("b"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "baz"),
+            Ok(Some("c"))
+        );
+        // Not an argument
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "quux"),
+            Err(MenuError::NotFound)
+        );
+    }
+
+    #[test]
+    fn find_arg_optional() {
+        let item = Item {
+            command: "dummy",
+            help: None,
+            item_type: ItemType::Callback {
+                function: dummy,
+                parameters: &[
+                    Parameter::Mandatory {
+                        parameter_name: "foo",
+                        help: Some("Some help for foo"),
+                        display: None,
+                    },
+                    Parameter::Mandatory {
+                        parameter_name: "bar",
+                        help: Some("Some help for bar"),
+                        display: None,
+                    },
+                    Parameter::Optional {
+                        parameter_name: "baz",
+                        help: Some("Some help for baz"),
+                        display: None,
+                        default: None,
+                    },
+                ],
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        };
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "foo"),
+            Ok(Some("a"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "bar"),
+            Ok(Some("b"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "baz"),
+            Ok(Some("c"))
+        );
+        // Not an argument
+        assert_eq!(
+            argument_finder(&item, &["a", "b", "c"], "quux"),
+            Err(MenuError::NotFound)
+        );
+        // Missing optional
+        assert_eq!(argument_finder(&item, &["a", "b"], "baz"), Ok(None));
+    }
+
+    #[test]
+    fn find_arg_named() {
+        let item = Item {
+            command: "dummy",
+            help: None,
+            item_type: ItemType::Callback {
+                function: dummy,
+                parameters: &[
+                    Parameter::Mandatory {
+                        parameter_name: "foo",
+                        help: Some("Some help for foo"),
+                        display: None,
+                    },
+                    Parameter::Named {
+                        parameter_name: "bar",
+                        help: Some("Some help for bar"),
+                        short: None,
+                    },
+                    Parameter::Named {
+                        parameter_name: "baz",
+                        help: Some("Some help for baz"),
+                        short: None,
+                    },
+                ],
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        };
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "foo"),
+            Ok(Some("a"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "bar"),
+            Ok(Some(""))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "baz"),
             Ok(Some(""))
         );
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "baz"),
-            Ok(Some(""))
+        // Not an argument
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "quux"),
+            Err(MenuError::NotFound)
+        );
+        // Missing named
+        assert_eq!(argument_finder(&item, &["a"], "baz"), Ok(None));
+    }
+
+    #[test]
+    fn find_arg_end_of_options() {
+        let parameters = [Parameter::mandatory("foo"), Parameter::named("bar")];
+        let item = Item::new("dummy", dummy, &parameters);
+        // Not after `--`
+        assert_eq!(
+            argument_finder(&item, &["--", "--bar"], "foo"),
+            Ok(Some("--bar"))
+        );
+        assert_eq!(argument_finder(&item, &["--", "--bar"], "bar"), Ok(None));
+    }
+
+    #[test]
+    fn find_arg_repeated() {
+        let parameters = [
+            Parameter::optional("foo"),
+            Parameter::named("verbose").short('v'),
+            Parameter::named_value("include", "PATH").short('I'),
+        ];
+        let item = Item::new("dummy", dummy, &parameters);
+        let args = [
+            "--include=a",
+            "-v",
+            "x",
+            "--include",
+            "b",
+            "-vvI",
+            "c",
+            "--verbose",
+        ];
+        assert_eq!(argument_count(&item, &args, "verbose"), Ok(4));
+        assert_eq!(argument_count(&item, &args, "include"), Ok(3));
+        assert_eq!(argument_count(&item, &args, "foo"), Ok(1));
+        assert_eq!(argument_count(&item, &[], "foo"), Ok(0));
+        assert_eq!(
+            argument_count(&item, &args, "quux"),
+            Err(MenuError::NotFound)
+        );
+        let includes: std::vec::Vec<&str> =
+            arguments_iter(&item, &args, "include").unwrap().collect();
+        assert_eq!(includes, ["a", "b", "c"]);
+        assert_eq!(
+            arguments_iter(&item, &args, "foo")
+                .unwrap()
+                .collect::<std::vec::Vec<_>>(),
+            ["x"]
+        );
+        // The first is still what's found
+        assert_eq!(argument_finder(&item, &args, "include"), Ok(Some("a")));
+    }
+
+    #[test]
+    fn find_arg_parse() {
+        use parser::ArgError;
+
+        let parameters = [
+            Parameter::mandatory("level"),
+            Parameter::optional("offset"),
+            Parameter::named("verbose"),
+            Parameter::named_value("enable", "BOOL"),
+            Parameter::named_value("gain", "DB").default("1.5"),
+        ];
+        let item = Item::new("dummy", dummy, &parameters);
+        let args = ["200", "-40", "--enable=on"];
+        assert_eq!(
+            argument_parse::<u8, _, _>(&item, &args, "level"),
+            Ok(Some(200))
+        );
+        assert_eq!(
+            argument_parse::<i8, _, _>(&item, &args, "offset"),
+            Ok(Some(-40))
+        );
+        assert_eq!(
+            argument_parse::<i16, _, _>(&item, &args, "level"),
+            Ok(Some(200))
+        );
+        assert_eq!(
+            argument_parse::<bool, _, _>(&item, &args, "enable"),
+            Ok(Some(true))
+        );
+        assert_eq!(
+            argument_parse::<&str, _, _>(&item, &args, "verbose"),
+            Ok(None)
+        );
+        assert_eq!(
+            argument_parse::<f32, _, _>(&item, &args, "gain"),
+            Ok(Some(1.5))
+        );
+        assert_eq!(
+            argument_parse::<i8, _, _>(&item, &args, "level"),
+            Err(ArgError::BadValue("level", "200"))
+        );
+        assert_eq!(
+            argument_parse::<u32, _, _>(&item, &args, "offset"),
+            Err(ArgError::BadValue("offset", "-40"))
+        );
+        assert_eq!(
+            argument_parse::<u8, _, _>(&item, &["300"], "level"),
+            Err(ArgError::BadValue("level", "300"))
+        );
+        assert_eq!(
+            argument_parse::<bool, _, _>(&item, &["1", "--enable=yes"], "enable"),
+            Err(ArgError::BadValue("enable", "yes"))
+        );
+        assert_eq!(
+            argument_parse::<u8, _, _>(&item, &args, "quux"),
+            Err(ArgError::NotFound("quux"))
+        );
+        assert_eq!(
+            ArgError::BadValue("level", "300").to_string(),
+            "Invalid value \"300\" for level"
+        );
+    }
+
+    #[test]
+    fn find_arg_namedvalue() {
+        let item = Item {
+            command: "dummy",
+            help: None,
+            item_type: ItemType::Callback {
+                function: dummy,
+                parameters: &[
+                    Parameter::Mandatory {
+                        parameter_name: "foo",
+                        help: Some("Some help for foo"),
+                        display: None,
+                    },
+                    Parameter::Named {
+                        parameter_name: "bar",
+                        help: Some("Some help for bar"),
+                        short: None,
+                    },
+                    Parameter::NamedValue {
+                        parameter_name: "baz",
+                        argument_name: "TEST",
+                        help: Some("Some help for baz"),
+                        short: None,
+                        default: None,
+                        allowed_values: None,
+                    },
+                ],
+            },
+            hidden: false,
+            data: None,
+            suppress_prompt_after: false,
+            throttle_group: None,
+        };
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "foo"),
+            Ok(Some("a"))
+        );
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "bar"),
+            Ok(Some(""))
+        );
+        // No argument so mark as not found
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "baz"),
+            Ok(None)
+        );
+        // Empty argument
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz="], "baz"),
+            Ok(Some(""))
+        );
+        // Short argument
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz=1"], "baz"),
+            Ok(Some("1"))
+        );
+        // Long argument
+        assert_eq!(
+            argument_finder(
+                &item,
+                &["a", "--bar", "--baz=abcdefghijklmnopqrstuvwxyz"],
+                "baz"
+            ),
+            Ok(Some("abcdefghijklmnopqrstuvwxyz"))
+        );
+        // Not an argument
+        assert_eq!(
+            argument_finder(&item, &["a", "--bar", "--baz"], "quux"),
+            Err(MenuError::NotFound)
+        );
+        // Missing named
+        assert_eq!(argument_finder(&item, &["a"], "baz"), Ok(None));
+    }
+
+    fn count_entry(_menu: &Menu<String, u32>, interface: &mut String, context: &mut u32) {
+        *context += 1;
+        interface.push_str("entered\n");
+    }
+
+    const ENTRY_MENU: Menu<String, u32> = Menu::new("root", &[]).entry(count_entry);
+
+    #[test]
+    fn new_starts_immediately() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let r = Runner::new(ENTRY_MENU, &mut buffer, String::new(), &mut context);
+        assert!(r.is_started());
+        assert_eq!(context, 1);
+        assert_eq!(r.interface, "entered\n\n> ");
+    }
+
+    #[test]
+    fn new_deferred_does_no_io() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new_deferred(ENTRY_MENU, &mut buffer, String::new());
+        assert!(!r.is_started());
+        assert_eq!(context, 0);
+        assert_eq!(r.interface, "");
+        r.start(&mut context);
+        assert!(r.is_started());
+        assert_eq!(context, 1);
+        assert_eq!(r.interface, "entered\n\n> ");
+        // Starting again does nothing
+        r.start(&mut context);
+        assert_eq!(context, 1);
+        assert_eq!(r.interface, "entered\n\n> ");
+    }
+
+    #[test]
+    fn input_before_start_starts_implicitly() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new_deferred(ENTRY_MENU, &mut buffer, String::new());
+        r.input_byte(b'x', &mut context);
+        assert!(r.is_started());
+        assert_eq!(context, 1);
+        assert!(r.interface.starts_with("entered\n\n> "));
+    }
+
+    const SUB_ENTRY_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[&Item::new_menu(
+            "sub",
+            &Menu::new("sub", &[]).entry(count_entry),
+        )],
+    );
+
+    #[test]
+    fn entering_sub_menu_calls_its_entry() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(SUB_ENTRY_MENU, &mut buffer, String::new(), &mut context);
+        assert_eq!(context, 0);
+        for &b in b"sub\r" {
+            r.input_byte(b, &mut context);
+        }
+        assert_eq!(context, 1);
+        assert!(r.interface.ends_with("entered\n\n/sub> "));
+    }
+
+    const LONG_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new(
+                "foo",
+                dummy_string,
+                &[
+                    Parameter::mandatory("a"),
+                    Parameter::optional("bb"),
+                    Parameter::named("verbose"),
+                    Parameter::named_value("level", "INT"),
+                ],
+            ),
+            &Item::new_menu(
+                "sub",
+                &Menu::new(
+                    "sub",
+                    &[&Item::new(
+                        "deeper",
+                        dummy_string,
+                        &[Parameter::named_value("filename", "PATH")],
+                    )],
+                ),
+            ),
+        ],
+    );
+
+    fn dummy_string(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        _interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+    }
+
+    #[test]
+    fn max_line_len_empty() {
+        // "help" plus a space
+        assert_eq!(max_line_len(&ENTRY_MENU), 5);
+    }
+
+    #[test]
+    fn max_line_len_params() {
+        // "foo a bb --verbose --level=INT"
+        const LEN: usize = max_line_len(&LONG_MENU);
+        assert_eq!(LEN, "foo a bb --verbose --level=INT".len());
+    }
+
+    #[test]
+    fn max_line_len_submenu() {
+        let items = [LONG_MENU.items[1]];
+        let menu = Menu::new("root", &items);
+        // "deeper --filename=PATH", from inside the sub-menu
+        assert_eq!(max_line_len(&menu), "deeper --filename=PATH".len());
+    }
+
+    fn count_args(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &[&str],
+        _interface: &mut CommandContext<String, u32>,
+        context: &mut u32,
+    ) {
+        *context = args.len() as u32;
+    }
+
+    const OPTIONAL: Parameter = Parameter::optional("x");
+
+    const MANY_ARGS_MENU: Menu<String, u32> =
+        Menu::new("root", &[&Item::new("many", count_args, &[OPTIONAL; 20])]);
+
+    fn run_line(line: &str) -> u32 {
+        let mut buffer = [0u8; 128];
+        let mut context = 0;
+        let mut r = Runner::new(MANY_ARGS_MENU, &mut buffer, String::new(), &mut context);
+        for b in line.bytes() {
+            r.input_byte(b, &mut context);
+        }
+        r.input_byte(b'\r', &mut context);
+        context
+    }
+
+    #[test]
+    fn sixteen_arguments() {
+        assert_eq!(run_line("many a b c d e f g h i j k l m n o p"), 16);
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    #[test]
+    fn more_than_sixteen_arguments() {
+        for count in [17, 20, 40] {
+            let mut buffer = [0u8; 128];
+            let mut context = 99;
+            let mut r = Runner::new(MANY_ARGS_MENU, &mut buffer, String::new(), &mut context);
+            r.run_command(&std::format!("many{}", " x".repeat(count)), &mut context);
+            // The callback wasn't called
+            assert_eq!(context, 99, "{count}");
+            assert!(r
+                .interface
+                .ends_with("Error: Too many arguments (limit 16)\n"));
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn more_than_sixteen_arguments() {
+        assert_eq!(run_line(&std::format!("many{}", " x".repeat(17))), 17);
+        assert_eq!(run_line(&std::format!("many{}", " x".repeat(20))), 20);
+        // Only too many for the parameters
+        let mut buffer = [0u8; 128];
+        let mut context = 99;
+        let mut r = Runner::new(MANY_ARGS_MENU, &mut buffer, String::new(), &mut context);
+        r.run_command(&std::format!("many{}", " x".repeat(40)), &mut context);
+        assert_eq!(context, 99);
+        assert!(r.interface.ends_with(
+            "Error: Unexpected argument \"x\" (command takes at most 20 positional arguments)\n"
+        ));
+    }
+
+    #[test]
+    fn run_command_direct() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(MANY_ARGS_MENU, &mut buffer, String::new(), &mut context);
+        r.input_byte(b'x', &mut context);
+        r.run_command("many a b", &mut context);
+        assert_eq!(context, 2);
+        // What the user was typing is left alone
+        r.run_command("many", &mut context);
+        assert_eq!(context, 0);
+        r.input_byte(b'\r', &mut context);
+        assert!(r.interface.contains("Command \"x\" not found"));
+    }
+
+    #[test]
+    fn leading_whitespace() {
+        // These used to slice the line at the wrong place, giving the wrong
+        // arguments, or panicking if that wasn't a character boundary.
+        assert_eq!(run_line("   many a b"), 2);
+        assert_eq!(run_line("\u{2000}many a"), 1);
+        assert_eq!(run_line("\u{3000}\u{3000}many"), 0);
+    }
+
+    fn write_args(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        writeln!(interface, "{:?}", args).unwrap();
+    }
+
+    const SPACING_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[&Item::new(
+            "set-ip",
+            write_args,
+            &[
+                Parameter::mandatory("ip"),
+                Parameter::optional("gateway"),
+                Parameter::named_value("mask", "BITS"),
+            ],
+        )],
+    );
+
+    #[test]
+    fn spacing() {
+        let lines = [
+            "set-ip 10.0.0.1 --mask=8",
+            "set-ip  10.0.0.1 --mask=8",
+            "set-ip\t\t10.0.0.1\t--mask=8",
+            "  set-ip 10.0.0.1 --mask=8",
+            "set-ip 10.0.0.1 --mask=8  ",
+            "set-ip 10.0.0.1 --mask=8\t",
+            "\tset-ip\t10.0.0.1 \t --mask=8 \t",
+            "set-ip\u{3000}10.0.0.1\u{a0}--mask=8",
+            "set-ip \r10.0.0.1 --mask=8",
+            "set-ip 10.0.0.1\u{2000}\u{2000}--mask=8",
+            "\u{2028}set-ip 10.0.0.1 --mask=8\u{2029}",
+            "set-ip   10.0.0.1   --mask=8   ",
+        ];
+        let mut buffer = [0u8; 64];
+        let mut r = Runner::new(SPACING_MENU, &mut buffer, String::new(), &mut 0);
+        for line in lines {
+            r.interface.clear();
+            assert_eq!(
+                r.run_command(line, &mut 0),
+                CommandOutcome::Success,
+                "{:?}",
+                line
+            );
+            assert_eq!(r.interface, "[\"10.0.0.1\", \"--mask=8\"]\n", "{:?}", line);
+        }
+        for line in ["", " ", "\t\t", "\u{3000}", " \t \r "] {
+            r.interface.clear();
+            assert_eq!(
+                r.run_command(line, &mut 0),
+                CommandOutcome::Empty,
+                "{:?}",
+                line
+            );
+            assert_eq!(r.interface, "Input was empty\n", "{:?}", line);
+        }
+    }
+
+    fn set_ip(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        write!(interface, "IP is {}", args[0]).unwrap();
+    }
+
+    fn reboot(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        interface.fail("Busy, \"try later\"");
+    }
+
+    fn set_mode(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        interface.set_machine_mode(args[0] == "machine");
+    }
+
+    const MACHINE_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("set-ip", set_ip, &[Parameter::mandatory("addr")]),
+            &Item::new("reboot", reboot, &[]),
+            &Item::new("mode", set_mode, &[Parameter::mandatory("mode")]),
+        ],
+    );
+
+    #[test]
+    fn machine_mode() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(MACHINE_MENU, &mut buffer, String::new(), &mut context);
+        let mut run = |line: &str| {
+            r.interface.clear();
+            let outcome = r.run_command(line, &mut context);
+            (outcome.to_string(), core::mem::take(&mut r.interface))
+        };
+        // Human mode is as it always was
+        assert_eq!(
+            run("reboot"),
+            (
+                "Busy, \"try later\"".into(),
+                "Error: Busy, \"try later\"\n".into()
+            )
+        );
+        assert_eq!(run("mode machine").1, "");
+        assert_eq!(
+            run("set-ip 10.0.0.1"),
+            (
+                "".into(),
+                "<<<BEGIN cmd=\"set-ip\">>>\nIP is 10.0.0.1\n<<<RESULT cmd=\"set-ip\" status=ok>>>\n"
+                    .into()
+            )
+        );
+        assert_eq!(
+            run("set-ip").1,
+            "<<<BEGIN cmd=\"set-ip\">>>\n\
+             <<<RESULT cmd=\"set-ip\" status=err reason=\"Missing argument <addr>\">>>\n"
+        );
+        assert_eq!(
+            run("nope").1,
+            "<<<BEGIN cmd=\"nope\">>>\n\
+             <<<RESULT cmd=\"nope\" status=err reason=\"Command \\\"nope\\\" not found\">>>\n"
+        );
+        assert_eq!(
+            run("reboot").1,
+            "<<<BEGIN cmd=\"reboot\">>>\n\
+             <<<RESULT cmd=\"reboot\" status=err reason=\"Busy, \\\"try later\\\"\">>>\n"
+        );
+        assert_eq!(
+            run("mode human").1,
+            "<<<BEGIN cmd=\"mode\">>>\n<<<RESULT cmd=\"mode\" status=ok>>>\n"
+        );
+        assert_eq!(run("nope").1, "Command \"nope\" not found. Try 'help'.\n");
+    }
+
+    fn measure(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        write!(interface, "Measuring... ").unwrap();
+        writeln!(interface.capture(), "{}", args[0]).unwrap();
+    }
+
+    fn send_report(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        _context: &mut u32,
+    ) {
+        let report = (interface.last_capture(), interface.last_capture_truncated());
+        writeln!(interface, "Sending {:?}", report).unwrap();
+    }
+
+    const CAPTURE_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("measure", measure, &[Parameter::mandatory("value")]),
+            &Item::new("send-report", send_report, &[]),
+        ],
+    );
+
+    #[test]
+    fn capture() {
+        let mut buffer = [0u8; 32];
+        let mut capture = [0u8; 13];
+        let mut context = 0;
+        let mut r = Runner::new(CAPTURE_MENU, &mut buffer, String::new(), &mut context);
+        let mut run = |r: &mut Runner<String, u32>, line: &str| {
+            r.interface.clear();
+            r.run_command(line, &mut context);
+            core::mem::take(&mut r.interface)
+        };
+        // Without a capture buffer, there's nothing to find
+        run(&mut r, "measure 1");
+        assert_eq!(run(&mut r, "send-report"), "Sending (\"\", false)\n");
+        r.set_capture_buffer(&mut capture);
+        assert_eq!(run(&mut r, "measure 23.5"), "Measuring... 23.5\n");
+        assert_eq!(run(&mut r, "send-report"), "Sending (\"23.5\\n\", false)\n");
+        // Commands which don't capture leave the last capture alone
+        assert_eq!(run(&mut r, "send-report"), "Sending (\"23.5\\n\", false)\n");
+        // Each capture gets half the buffer, cut short at a character boundary
+        assert_eq!(run(&mut r, "measure 12345é"), "Measuring... 12345é\n");
+        assert_eq!(run(&mut r, "send-report"), "Sending (\"12345\", true)\n");
+        run(&mut r, "measure 7");
+        assert_eq!(run(&mut r, "send-report"), "Sending (\"7\\n\", false)\n");
+    }
+
+    /// An interface which can't be written to.
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write_str(&mut self, _s: &str) -> core::fmt::Result {
+            Err(core::fmt::Error)
+        }
+    }
+
+    fn count_and_write(
+        _menu: &Menu<FailingWriter, u32>,
+        _item: &Item<FailingWriter, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<FailingWriter, u32>,
+        context: &mut u32,
+    ) {
+        *context += 1;
+        assert!(writeln!(interface, "Hello").is_err());
+    }
+
+    const FAILING_MENU: Menu<FailingWriter, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("count", count_and_write, &[Parameter::mandatory("a")]),
+            &Item::new_menu(
+                "sub",
+                &Menu::new("sub", &[&Item::new("count", count_and_write, &[])]),
+            ),
+        ],
+    );
+
+    #[test]
+    fn failing_writer() {
+        let mut buffer = [0u8; 8];
+        #[cfg(feature = "history")]
+        let mut history = [0u8; 32];
+        let mut context = 0;
+        let mut r = Runner::new(FAILING_MENU, &mut buffer, FailingWriter, &mut context);
+        #[cfg(feature = "history")]
+        r.set_history_buffer(&mut history);
+        r.set_machine_mode(true);
+        for line in ["count 1", "sub", "count", "exit", "help", "help count"] {
+            r.run_command(line, &mut context);
+        }
+        r.set_machine_mode(false);
+        let script = "count 1\rcount\rhelp x\rdummy\rnope\r!1\r!9\rhistory\r\rtoo long!\r\x08\r";
+        for byte in script.bytes() {
+            r.input_byte(byte, &mut context);
+        }
+        r.prompt(true);
+        // Commands still ran, even though nothing could be printed, and `!1`
+        // ran `count 1` again
+        assert_eq!(context, if cfg!(feature = "history") { 4 } else { 3 });
+    }
+
+    fn noop_menu_fn(_menu: &Menu<String, u32>, _interface: &mut String, _context: &mut u32) {}
+
+    static BUILT_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new(
+                "foo",
+                dummy_string,
+                &[
+                    Parameter::mandatory("a").help("The a"),
+                    Parameter::named_value("level", "INT"),
+                ],
+            )
+            .help("Do a foo"),
+            &Item::new("secret", dummy_string, &[]).hidden(),
+            &Item::new_menu(
+                "sub",
+                &Menu::new("sub", &[&Item::new("bar", dummy_string, &[])])
+                    .entry(noop_menu_fn)
+                    .exit(noop_menu_fn),
+            )
+            .help("A sub-menu"),
+        ],
+    )
+    .entry(noop_menu_fn);
+
+    #[test]
+    fn builders() {
+        assert_eq!(BUILT_MENU.label, "root");
+        assert!(BUILT_MENU.entry.is_some());
+        assert!(BUILT_MENU.exit.is_none());
+        let foo = BUILT_MENU.items[0];
+        assert_eq!(foo.command, "foo");
+        assert_eq!(foo.help, Some("Do a foo"));
+        assert!(!foo.hidden);
+        let ItemType::Callback { parameters, .. } = foo.item_type else {
+            panic!("foo is not a callback");
+        };
+        assert!(matches!(
+            parameters,
+            [
+                Parameter::Mandatory {
+                    parameter_name: "a",
+                    help: Some("The a"),
+                    display: None,
+                },
+                Parameter::NamedValue {
+                    parameter_name: "level",
+                    argument_name: "INT",
+                    help: None,
+                    short: None,
+                    default: None,
+                    allowed_values: None,
+                }
+            ]
+        ));
+        assert!(BUILT_MENU.items[1].hidden);
+        let ItemType::Menu(sub) = BUILT_MENU.items[2].item_type else {
+            panic!("sub is not a menu");
+        };
+        assert_eq!(sub.items[0].command, "bar");
+        assert!(sub.entry.is_some());
+        assert!(sub.exit.is_some());
+    }
+
+    #[test]
+    fn accessors() {
+        const PARAMETERS: &[Parameter] = &[Parameter::mandatory("a")];
+        const ITEMS: &[&Item<String, u32>] = &[
+            &Item::new("callback", dummy_string, PARAMETERS),
+            &Item::new_handler("handler", &COUNTING_HANDLER, PARAMETERS),
+            &Item::new_dispatch("dispatch", 3, PARAMETERS),
+            &Item::new_menu("menu", &BUILT_MENU),
+        ];
+        for item in &ITEMS[..3] {
+            assert_eq!(item.parameters(), PARAMETERS, "{}", item.command);
+            assert!(!item.is_menu());
+            assert!(item.submenu().is_none());
+        }
+        let menu = ITEMS[3];
+        assert_eq!(menu.parameters(), []);
+        assert!(menu.is_menu());
+        assert_eq!(menu.submenu().map(|m| m.label), Some("root"));
+    }
+
+    struct Register {
+        address: u32,
+        width: u8,
+    }
+
+    fn read_register(
+        _menu: &Menu<String, u32>,
+        item: &Item<String, u32>,
+        _args: &[&str],
+        _interface: &mut CommandContext<String, u32>,
+        context: &mut u32,
+    ) {
+        let register = item.data_as::<Register>().unwrap();
+        *context = register.address + u32::from(register.width);
+    }
+
+    static REGISTER_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new("ctrl", read_register, &[]).data(&Register {
+                address: 0x4000,
+                width: 8,
+            }),
+            &Item::new("status", read_register, &[]).data(&Register {
+                address: 0x4004,
+                width: 16,
+            }),
+            &Item::new("plain", dummy_string, &[]),
+        ],
+    );
+
+    #[test]
+    fn item_data() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(REGISTER_MENU, &mut buffer, String::new(), &mut context);
+        r.run_command("ctrl", &mut context);
+        assert_eq!(context, 0x4008);
+        r.run_command("status", &mut context);
+        assert_eq!(context, 0x4014);
+        assert!(REGISTER_MENU.items[0].data_as::<u32>().is_none());
+        assert!(REGISTER_MENU.items[2].data_as::<Register>().is_none());
+    }
+
+    struct CountingHandler(core::sync::atomic::AtomicU32);
+
+    impl Handler<String, u32> for CountingHandler {
+        fn handle(
+            &self,
+            _menu: &Menu<String, u32>,
+            _item: &Item<String, u32>,
+            _args: &[&str],
+            _interface: &mut CommandContext<String, u32>,
+            context: &mut u32,
+        ) {
+            let calls = self.0.fetch_add(1, core::sync::atomic::Ordering::Relaxed) + 1;
+            *context = calls;
+        }
+    }
+
+    static COUNTING_HANDLER: CountingHandler =
+        CountingHandler(core::sync::atomic::AtomicU32::new(0));
+
+    static HANDLER_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[
+            &Item::new_handler("count", &COUNTING_HANDLER, &[]),
+            &Item::new_handler(
+                "sum",
+                &|_menu: &Menu<String, u32>,
+                  item: &Item<String, u32>,
+                  args: &[&str],
+                  interface: &mut CommandContext<String, u32>,
+                  context: &mut u32| {
+                    let a = argument_finder(item, args, "a").unwrap().unwrap();
+                    let b = argument_finder(item, args, "b").unwrap().unwrap();
+                    *context = a.parse::<u32>().unwrap() + b.parse::<u32>().unwrap();
+                    write!(interface, "{}", context).unwrap();
+                },
+                &[Parameter::mandatory("a"), Parameter::mandatory("b")],
+            ),
+        ],
+    );
+
+    #[test]
+    fn handlers() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(HANDLER_MENU, &mut buffer, String::new(), &mut context);
+        r.run_command("count", &mut context);
+        assert_eq!(context, 1);
+        r.run_command("count", &mut context);
+        assert_eq!(context, 2);
+        r.run_command("sum 3 4", &mut context);
+        assert_eq!(context, 7);
+        assert!(r.interface.ends_with("7"));
+        r.run_command("sum 3", &mut context);
+        assert!(r.interface.ends_with("Error: Missing argument <b>\n"));
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(u32)]
+    enum Cmd {
+        Get,
+        Set,
+        Fail,
+    }
+
+    impl Cmd {
+        fn from_id(id: CommandId) -> Option<Cmd> {
+            [Cmd::Get, Cmd::Set, Cmd::Fail]
+                .into_iter()
+                .find(|cmd| id == *cmd as CommandId)
+        }
+    }
+
+    #[derive(Default)]
+    struct Device {
+        value: u32,
+        last_path: String,
+    }
+
+    impl CommandHandler<String> for Device {
+        fn dispatch(
+            &mut self,
+            path: &[&str],
+            cmd: CommandId,
+            args: &parser::ParsedArgs,
+            interface: &mut CommandContext<String, Self>,
+        ) -> Result<(), &'static str> {
+            self.last_path = path.join("/");
+            match Cmd::from_id(cmd).ok_or("unknown command")? {
+                Cmd::Get => write!(interface, "{}", self.value).unwrap(),
+                Cmd::Set => {
+                    let value = args.get("value").unwrap().unwrap();
+                    self.value = value.parse().map_err(|_| "bad value")?;
+                }
+                Cmd::Fail => return Err("failed"),
+            }
+            Ok(())
+        }
+    }
+
+    static DISPATCH_MENU: Menu<String, Device> = Menu::new(
+        "root",
+        &[
+            &Item::new_dispatch("get", Cmd::Get as CommandId, &[]),
+            &Item::new_menu(
+                "regs",
+                &Menu::new(
+                    "regs",
+                    &[
+                        &Item::new_dispatch(
+                            "set",
+                            Cmd::Set as CommandId,
+                            &[Parameter::mandatory("value")],
+                        ),
+                        &Item::new_dispatch("fail", Cmd::Fail as CommandId, &[]),
+                        &Item::new_dispatch("bogus", 99, &[]),
+                    ],
+                ),
+            ),
+        ],
+    );
+
+    #[test]
+    fn dispatch() {
+        let mut buffer = [0u8; 16];
+        let mut device = Device::default();
+        let mut r = Runner::new(DISPATCH_MENU, &mut buffer, String::new(), &mut device);
+        r.run_command("get", &mut device);
+        assert!(r.interface.ends_with("Error: No command handler\n"));
+
+        r.enable_dispatch();
+        r.run_command("get", &mut device);
+        assert!(r.interface.ends_with("0"));
+        assert_eq!(device.last_path, "");
+        r.run_command("regs", &mut device);
+        r.run_command("set 42", &mut device);
+        assert_eq!(device.value, 42);
+        assert_eq!(device.last_path, "regs");
+        r.run_command("set", &mut device);
+        assert!(r.interface.ends_with("Error: Missing argument <value>\n"));
+        r.run_command("set x", &mut device);
+        assert!(r.interface.ends_with("Error: bad value\n"));
+        r.run_command("fail", &mut device);
+        assert!(r.interface.ends_with("Error: failed\n"));
+        r.run_command("bogus", &mut device);
+        assert!(r.interface.ends_with("Error: unknown command\n"));
+        assert_eq!(device.value, 42);
+        r.run_command("help", &mut device);
+        assert!(r.interface.contains("  set <value>\n"));
+    }
+
+    fn copy(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        args: &Args,
+        interface: &mut CommandContext<String, u32>,
+        context: &mut u32,
+    ) {
+        let count = match args.parse::<u32>("count") {
+            Ok(count) => count.unwrap(),
+            Err(e) => return writeln!(interface, "Error: {}", e).unwrap(),
+        };
+        *context += count;
+        write!(
+            interface,
+            "{} to {:?}, {} times, verbose {}, first {:?}",
+            args.get("from").unwrap().unwrap(),
+            args.get("to").unwrap(),
+            count,
+            args.contains("verbose"),
+            args.positional(0),
+        )
+        .unwrap();
+    }
+
+    static ARGS_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[&Item::new_args(
+            "copy",
+            copy,
+            &[
+                Parameter::mandatory("from"),
+                Parameter::optional("to"),
+                Parameter::named("verbose").short('v'),
+                Parameter::named_value("count", "N").default("1"),
+            ],
+        )],
+    );
+
+    #[test]
+    fn args_callback() {
+        let mut buffer = [0u8; 32];
+        let mut total = 0;
+        let mut r = Runner::new(ARGS_MENU, &mut buffer, String::new(), &mut total);
+        r.run_command("copy a", &mut total);
+        assert!(r
+            .interface
+            .ends_with("a to None, 1 times, verbose false, first Some(\"a\")"));
+        r.run_command("copy --count 0x10 -v a b", &mut total);
+        assert!(r
+            .interface
+            .ends_with("a to Some(\"b\"), 16 times, verbose true, first Some(\"a\")"));
+        assert_eq!(total, 17);
+        r.set_allow_option_prefixes(true);
+        r.run_command("copy -- --a --co", &mut total);
+        assert!(r
+            .interface
+            .ends_with("--a to Some(\"--co\"), 1 times, verbose false, first Some(\"--a\")"));
+        r.run_command("copy --co=2 a", &mut total);
+        assert!(r
+            .interface
+            .ends_with("2 times, verbose false, first Some(\"a\")"));
+        r.run_command("copy --count=x a", &mut total);
+        assert!(r
+            .interface
+            .ends_with("Error: Invalid value \"x\" for count\n"));
+        r.run_command("copy", &mut total);
+        assert!(r.interface.ends_with("Error: Missing argument <from>\n"));
+        assert_eq!(total, 20);
+    }
+
+    #[test]
+    fn args_callback_unchecked() {
+        // Called without the `Runner`, the arguments are checked here
+        let menu_mgr = menu_manager::MenuManager::new(ARGS_MENU);
+        let (mut output, mut state) = (String::new(), output::OutputState::default());
+        let writer = ConsoleWriter::new(&mut output, &mut state);
+        let mut interface = CommandContext::new(writer, &menu_mgr, None, command::Keys::NONE);
+        let handler: &dyn Handler<String, u32> = &(copy as ArgsCallbackFn<String, u32>);
+        let mut total = 0;
+        handler.handle(
+            &ARGS_MENU,
+            ARGS_MENU.items[0],
+            &["a", "--bogus"],
+            &mut interface,
+            &mut total,
+        );
+        assert_eq!(interface.into_requests().failure, Some("Bad arguments"));
+        assert_eq!(total, 0);
+        assert!(
+            output.starts_with("Did not understand \"--bogus\""),
+            "{output:?}"
+        );
+    }
+
+    fn selftest(
+        _menu: &Menu<String, u32>,
+        _item: &Item<String, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<String, u32>,
+        context: &mut u32,
+    ) {
+        for _ in 0..10 {
+            interface.keep_alive(context);
+        }
+    }
+
+    static BUSY_MENU: Menu<String, u32> = Menu::new(
+        "root",
+        &[&Item::new(
+            "selftest",
+            selftest,
+            &[Parameter::optional("x").help("One\nTwo")],
+        )
+        .help("Test\neverything")],
+    );
+
+    #[test]
+    fn busy_hook() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(BUSY_MENU, &mut buffer, String::new(), &mut context);
+        // No hook, so nothing happens
+        r.run_command("selftest", &mut context);
+        assert_eq!(context, 0);
+
+        r.set_busy_hook(|count| *count += 1);
+        r.run_command("selftest", &mut context);
+        assert_eq!(context, 10);
+
+        // Once for every line of help
+        context = 0;
+        r.interface.clear();
+        r.run_command("help", &mut context);
+        assert_eq!(context, r.interface.lines().count() as u32);
+        context = 0;
+        r.interface.clear();
+        r.run_command("help selftest", &mut context);
+        assert_eq!(context, 13);
+        assert_eq!(context, r.interface.lines().count() as u32);
+    }
+
+    #[test]
+    fn hidden_items_not_listed() {
+        let mut buffer = [0u8; 16];
+        let mut context = 0;
+        let mut r = Runner::new(BUILT_MENU, &mut buffer, String::new(), &mut context);
+        for b in b"help\r" {
+            r.input_byte(*b, &mut context);
+        }
+        assert!(r.interface.contains("  foo <a> [OPTIONS...]\n"));
+        assert!(r.interface.contains("  sub\n"));
+        assert!(!r.interface.contains("secret"));
+    }
+
+    #[test]
+    fn unique_commands() {
+        const _: () = assert!(assert_unique_commands(BUILT_MENU.items));
+        assert!(assert_unique_commands(LONG_MENU.items));
+        let items = [LONG_MENU.items[0], LONG_MENU.items[1], LONG_MENU.items[0]];
+        assert!(!assert_unique_commands(&items));
+        assert!(const_str_eq("", ""));
+        assert!(!const_str_eq("abc", "abd"));
+        assert!(!const_str_eq("abc", "ab"));
+    }
+
+    #[cfg(feature = "echo")]
+    mod transcripts {
+        use super::*;
+        use crate::test_util::{
+            assert_transcript, run_script, script_bytes, MockConsole, Transcript,
+        };
+
+        fn say_args(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            writeln!(interface, "Args = {:?}", args).unwrap();
+        }
+
+        fn say_partial(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            write!(interface, "abc").unwrap();
+            interface.writer().ensure_newline().unwrap();
+            interface.writer().ensure_newline().unwrap();
+            write!(interface, "def").unwrap();
+        }
+
+        const MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new(
+                    "foo",
+                    say_args,
+                    &[
+                        Parameter::mandatory("a").help("The a"),
+                        Parameter::optional("b"),
+                        Parameter::named("verbose"),
+                        Parameter::named_value("level", "INT").help("The level"),
+                    ],
+                )
+                .help("Makes a foo appear."),
+                &Item::new("bar", say_args, &[]).help("fandoggles a bar"),
+                &Item::new("partial", say_partial, &[]).hidden(),
+                &Item::new_menu(
+                    "sub",
+                    &Menu::new("sub", &[&Item::new("baz", say_args, &[])]),
+                )
+                .help("enter sub-menu"),
+            ],
+        );
+
+        #[test]
+        fn help_list() {
+            assert_transcript(
+                &run_script(MENU, "help\n", &mut 0),
+                "
+> help
+AVAILABLE ITEMS:
+  foo <a> [ <b> ] [OPTIONS...]
+  bar
+  sub
+  help [ <command> ]
+
+>",
+            );
+        }
+
+        #[test]
+        fn help_item() {
+            assert_transcript(
+                &run_script(MENU, "help foo\nhelp nope\n", &mut 0),
+                "
+> help foo
+SUMMARY:
+  foo <a> [ <b> ] [ --verbose ] [ --level=INT ]
+
+PARAMETERS:
+  <a>
+    The a
+
+  <b>
+    Undocumented option
+
+  --verbose
+    Undocumented option
+
+  --level=INT
+    The level
+
+
+
+DESCRIPTION:
+Makes a foo appear.
+
+> help nope
+I can't help with \"nope\"
+
+>",
+            );
+        }
+
+        #[test]
+        fn errors() {
+            assert_transcript(
+                &run_script(
+                    MENU,
+                    "foo\nfoo 1 2 3\nfoo 1 --bad\nfoo 1 --level\nnope\n \n",
+                    &mut 0,
+                ),
+                "
+> foo
+Error: Missing argument <a>
+
+> foo 1 2 3
+Error: Unexpected argument \"3\" (command takes at most 2 positional arguments)
+
+> foo 1 --bad
+Error: Did not understand \"--bad\". Options are: --verbose, --level=INT
+
+> foo 1 --level
+Error: Option --level requires a value (--level=INT)
+
+> nope
+Command \"nope\" not found. Try 'help'.
+
+>
+Input was empty
+
+>",
+            );
+        }
+
+        #[test]
+        fn option_prefixes() {
+            let mut buffer = [0u8; 64];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.type_script("foo 1 --verb\n", &mut context);
+            t.runner().set_allow_option_prefixes(true);
+            t.type_script("foo 1 --verb --l=2\nfoo 1 --v=2\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo 1 --verb
+Error: Did not understand \"--verb\". Options are: --verbose, --level=INT
+
+> foo 1 --verb --l=2
+Args = [\"1\", \"--verb\", \"--l=2\"]
+
+> foo 1 --v=2
+Error: Did not understand \"--v=2\". Options are: --verbose, --level=INT
+
+>",
+            );
+        }
+
+        #[test]
+        fn arguments() {
+            assert_transcript(
+                &run_script(MENU, "foo 1 2 --verbose --level=3\n", &mut 0),
+                "
+> foo 1 2 --verbose --level=3
+Args = [\"1\", \"2\", \"--verbose\", \"--level=3\"]
+
+>",
+            );
+        }
+
+        #[test]
+        fn prompt_in_sub_menu() {
+            assert_transcript(
+                &run_script(MENU, "sub\nhelp\nexit\n", &mut 0),
+                "
+> sub
+
+/sub> help
+AVAILABLE ITEMS:
+  baz
+  exit
+  help [ <command> ]
+
+/sub> exit
+
+>",
+            );
+        }
+
+        #[test]
+        fn render_help_matches_interactive() {
+            let mut rendered = String::new();
+            render_help(&MENU, &mut rendered, &HelpRenderOptions::new()).unwrap();
+            let script = "help\nhelp foo\nhelp bar\nhelp sub\nsub\nhelp\nhelp baz\n";
+            let screen = run_script(MENU, script, &mut 0);
+            // The transcript has a blank line as the sub-menu is entered,
+            // which `render_help` doesn't.
+            let screen = screen.replace("> sub\n\n", "");
+            assert_transcript(&rendered, screen.trim_start().trim_end_matches("\n/sub>"));
+        }
+
+        #[test]
+        fn render_help_snapshot() {
+            const HIDDEN_MENU: Menu<MockConsole, u32> = Menu::new(
+                "root",
+                &[
+                    &Item::new("foo", say_args, &[Parameter::mandatory("a").help("The a")])
+                        .help("Makes a foo appear, with a long description which needs wrapping."),
+                    &Item::new("secret", say_args, &[]).hidden(),
+                    &Item::new_menu(
+                        "sub",
+                        &Menu::new("sub", &[&Item::new("baz", say_args, &[])]),
+                    ),
+                ],
+            );
+            let mut rendered = String::new();
+            let opts = HelpRenderOptions::new().include_hidden(true).wrap_width(30);
+            render_help(&HIDDEN_MENU, &mut rendered, &opts).unwrap();
+            assert_transcript(
+                &rendered,
+                "> help
+AVAILABLE ITEMS:
+  foo <a>
+  secret
+  sub
+  help [ <command> ]
+
+> help foo
+SUMMARY:
+  foo <a>
+
+PARAMETERS:
+  <a>
+    The a
+
+
+
+DESCRIPTION:
+Makes a foo appear, with a
+long description which needs
+wrapping.
+
+> help secret
+SUMMARY:
+  secret
+
+> help sub
+SUMMARY:
+  sub
+
+/sub> help
+AVAILABLE ITEMS:
+  baz
+  exit
+  help [ <command> ]
+
+/sub> help baz
+SUMMARY:
+  baz
+",
+            );
+        }
+
+        fn login(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            if args == ["secret"] {
+                interface.enter_menu("admin").unwrap();
+                interface.set_prompt_prefix("#");
+            } else {
+                writeln!(interface, "Wrong password").unwrap();
+            }
+        }
+
+        fn logout(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            interface.exit_menu();
+            interface.set_prompt_prefix("");
+        }
+
+        fn go(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            if let Err(e) = interface.enter_menu(args[0]) {
+                writeln!(interface, "Error: {:?}", e).unwrap();
+            }
+        }
+
+        fn upload(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            context: &mut u32,
+        ) {
+            writeln!(interface, "Send data, then Ctrl-D").unwrap();
+            *context = 0;
+            interface.request_raw_mode(upload_byte);
+        }
+
+        fn upload_byte(
+            input: u8,
+            interface: &mut ConsoleWriter<MockConsole>,
+            context: &mut u32,
+        ) -> RawModeAction {
+            if input == 0x04 {
+                write!(interface, "Got {} bytes", context).unwrap();
+                RawModeAction::Finish
+            } else {
+                *context += 1;
+                RawModeAction::Continue
+            }
+        }
+
+        fn enter_tools(
+            _menu: &Menu<MockConsole, u32>,
+            interface: &mut MockConsole,
+            _context: &mut u32,
+        ) {
+            writeln!(interface, "Entering tools").unwrap();
+        }
+
+        fn exit_tools(
+            _menu: &Menu<MockConsole, u32>,
+            interface: &mut MockConsole,
+            _context: &mut u32,
+        ) {
+            writeln!(interface, "Leaving tools").unwrap();
+        }
+
+        const NAV_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("login", login, &[Parameter::mandatory("password")]),
+                &Item::new("go", go, &[Parameter::mandatory("path")]),
+                &Item::new_menu(
+                    "admin",
+                    &Menu::new(
+                        "admin",
+                        &[
+                            &Item::new("upload", upload, &[]),
+                            &Item::new("logout", logout, &[]),
+                            &Item::new("go", go, &[Parameter::mandatory("path")]),
+                            &Item::new_menu(
+                                "tools",
+                                &Menu::new(
+                                    "tools",
+                                    &[&Item::new("go", go, &[Parameter::mandatory("path")])],
+                                )
+                                .entry(enter_tools)
+                                .exit(exit_tools),
+                            ),
+                        ],
+                    ),
+                )
+                .hidden(),
+            ],
+        );
+
+        const SESSION_END_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[&Item::new_menu(
+                "tools",
+                &Menu::new("tools", &[]).exit(exit_tools),
+            )],
+        )
+        .exit(|_, interface, context| {
+            writeln!(interface, "Bye").unwrap();
+            *context += 1;
+        });
+
+        #[test]
+        fn end_of_input() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(SESSION_END_MENU, &mut buffer, &mut context);
+            t.type_script("tools\n<C-d>", &mut context);
+            assert!(!t.runner().is_started());
+            assert_eq!(context, 1);
+            // The next byte starts again
+            t.type_script("tools\nabc", &mut context);
+            if cfg!(feature = "line-editing") {
+                // Ctrl-D on a line removes the character at the cursor
+                t.type_script("<LEFT><LEFT><C-d><RIGHT><C-d>\n", &mut context);
+            } else {
+                t.type_script("<C-d><BS><BS>c\n", &mut context);
+            }
+            assert!(t.runner().is_started());
+            assert_transcript(
+                &t.screen(),
+                "
+> tools
+
+/tools>
+Leaving tools
+Bye
+
+> tools
+
+/tools> ac
+Command \"ac\" not found. Try 'help'.
+
+/tools>",
+            );
+        }
+
+        fn log_entry(
+            entry: &EntryContext<MockConsole, u32>,
+            interface: &mut MockConsole,
+            _context: &mut u32,
+        ) {
+            writeln!(
+                interface,
+                "Entered {} from {:?} in {:?}",
+                entry.entered.label,
+                entry.previous.map(|m| m.label),
+                entry.parent.map(|m| m.label)
+            )
+            .unwrap();
+        }
+
+        const ENTRY_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("go", go, &[Parameter::mandatory("path")]),
+                &Item::new_menu(
+                    "a",
+                    &Menu::new(
+                        "a",
+                        &[
+                            &Item::new("go", go, &[Parameter::mandatory("path")]),
+                            &Item::new_menu(
+                                "c",
+                                &Menu::new("c", &[]).entry_with_context(log_entry),
+                            ),
+                        ],
+                    )
+                    .entry_with_context(log_entry),
+                ),
+                &Item::new_menu("b", &Menu::new("b", &[]).entry_with_context(log_entry)),
+            ],
+        )
+        .entry_with_context(log_entry);
+
+        #[test]
+        fn entry_context() {
+            assert_transcript(
+                &run_script(ENTRY_MENU, "a\ngo ../b\nexit\ngo a/c\n", &mut 0),
+                "Entered root from None in None
+
+> a
+Entered a from Some(\"root\") in Some(\"root\")
+
+/a> go ../b
+Entered b from Some(\"a\") in Some(\"root\")
+
+/b> exit
+
+> go a/c
+Entered a from Some(\"root\") in Some(\"root\")
+Entered c from Some(\"root\") in Some(\"a\")
+
+/a/c>",
+            );
+        }
+
+        fn monitor(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            for tick in 1..=3 {
+                writeln!(interface, "tick {}", tick).unwrap();
+                if let Some(key) = interface.poll_key() {
+                    writeln!(interface, "Stopped by {:?}", key as char).unwrap();
+                    return;
+                }
+            }
+        }
+
+        const MONITOR_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("monitor", monitor, &[]).suppress_prompt_after(),
+                &Item::new("bar", say_args, &[]),
+            ],
         );
-        // Not an argument
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "quux"),
-            Err(Error::NotFound)
+
+        #[test]
+        fn streaming() {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MONITOR_MENU, &mut buffer, &mut context);
+            // Without a key poll, there are never any keys
+            t.type_script("monitor\n", &mut context);
+            t.runner().set_key_poll(MockConsole::poll_key);
+            t.type_script("bar\nmonitor\n", &mut context);
+            t.runner().interface.press_keys("q");
+            t.type_script("monitor\nbar\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> monitor
+tick 1
+tick 2
+tick 3
+> bar
+Args = []
+
+> monitor
+tick 1
+tick 2
+tick 3
+> monitor
+tick 1
+Stopped by 'q'
+> bar
+Args = []
+
+>",
+            );
+        }
+
+        fn encoded_transcript(encoding: InputEncoding, buffer_len: usize, script: &str) -> String {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer[..buffer_len], &mut context);
+            t.runner().set_input_encoding(encoding);
+            t.type_script(script, &mut context);
+            t.screen()
+        }
+
+        #[test]
+        fn input_encoding() {
+            assert_transcript(
+                &encoded_transcript(InputEncoding::Latin1, 32, "foo caf<xE9>\nfoo x<xE9><BS>y\n"),
+                "
+> foo café
+Args = [\"café\"]
+
+> foo xy
+Args = [\"xy\"]
+
+>",
+            );
+            assert_transcript(
+                &encoded_transcript(InputEncoding::Ascii, 32, "foo caf<xE9>\n"),
+                "
+> foo caf
+Args = [\"caf\"]
+
+>",
+            );
+            // Without translation, the line isn't valid UTF-8
+            assert!(
+                encoded_transcript(InputEncoding::Utf8, 32, "foo caf<xE9>\n")
+                    .contains("Input was not valid UTF-8")
+            );
+            // Nothing changes for a session in ASCII
+            let script = "foo ab<BS>c\nbar\nnope\n";
+            assert_eq!(
+                encoded_transcript(InputEncoding::Ascii, 32, script),
+                encoded_transcript(InputEncoding::Utf8, 32, script)
+            );
+            // There's no room for both bytes of the é
+            assert!(encoded_transcript(InputEncoding::Latin1, 5, "foo <xE9>\n")
+                .contains("Line too long: only 5 bytes fit"));
+        }
+
+        #[test]
+        fn line_ending() {
+            let run = |line_ending, script| {
+                let mut buffer = [0u8; 32];
+                let mut context = 0;
+                let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+                t.runner().set_line_ending(line_ending);
+                t.type_script(script, &mut context);
+                t.screen()
+            };
+            let expected = "
+> foo a
+Args = [\"a\"]
+
+> bar
+Args = []
+
+>";
+            assert_transcript(&run(LineEnding::Cr, "foo a\n<LF>bar<LF>\n"), expected);
+            assert_transcript(&run(LineEnding::Lf, "foo a<LF>bar\n<LF>"), expected);
+            // CR LF is one line ending, and either on its own is another
+            assert_transcript(&run(LineEnding::CrOrLf, "foo a\n<LF>bar<LF>"), expected);
+            assert_transcript(&run(LineEnding::CrOrLf, "foo a\nbar\n"), expected);
+        }
+
+        /// What's on the SD card, for completing file names.
+        #[cfg(feature = "completion")]
+        struct Card {
+            files: &'static [&'static str],
+        }
+
+        #[cfg(feature = "completion")]
+        fn say_file(
+            _menu: &Menu<MockConsole, Card>,
+            _item: &Item<MockConsole, Card>,
+            args: &[&str],
+            interface: &mut CommandContext<MockConsole, Card>,
+            _context: &mut Card,
+        ) {
+            writeln!(interface, "Args = {:?}", args).unwrap();
+        }
+
+        #[cfg(feature = "completion")]
+        fn complete_file(
+            _item: &Item<MockConsole, Card>,
+            parameter: &Parameter,
+            _partial: &str,
+            context: &mut Card,
+            emit: &mut dyn FnMut(&str),
+        ) {
+            if matches!(
+                parameter,
+                Parameter::Mandatory {
+                    parameter_name: "file",
+                    ..
+                }
+            ) {
+                for file in context.files {
+                    emit(file);
+                }
+            }
+        }
+
+        #[cfg(feature = "completion")]
+        const CARD_MENU: Menu<MockConsole, Card> = Menu::new(
+            "root",
+            &[
+                &Item::new(
+                    "cat",
+                    say_file,
+                    &[Parameter::mandatory("file"), Parameter::optional("lines")],
+                ),
+                &Item::new(
+                    "grep",
+                    say_file,
+                    &[
+                        Parameter::mandatory("pattern"),
+                        Parameter::named("count"),
+                        Parameter::named("case"),
+                        Parameter::named_value("context", "N"),
+                    ],
+                ),
+            ],
+        );
+
+        #[cfg(feature = "completion")]
+        fn card_transcript(files: &'static [&'static str], script: &str) -> String {
+            let mut buffer = [0u8; 32];
+            let mut context = Card { files };
+            let mut t = Transcript::new(CARD_MENU, &mut buffer, &mut context);
+            t.runner().set_value_completer(complete_file);
+            t.type_script(script, &mut context);
+            t.screen()
+        }
+
+        #[test]
+        #[cfg(feature = "completion")]
+        fn value_completion() {
+            const FILES: &[&str] = &["boot.bin", "log.txt", "logo.png", "log2.txt"];
+            // One candidate is finished off, with a space after it
+            assert_transcript(
+                &card_transcript(FILES, "cat b<TAB>10\n"),
+                "
+> cat boot.bin 10
+Args = [\"boot.bin\", \"10\"]
+
+>",
+            );
+            // What several candidates have in common is added, then another
+            // Tab lists them
+            assert_transcript(
+                &card_transcript(FILES, "cat l<TAB><TAB>.<TAB>\n"),
+                "
+> cat log
+log.txt  logo.png  log2.txt
+> cat log.txt
+Args = [\"log.txt\"]
+
+>",
+            );
+            // No candidates, or no parameter to complete, does nothing
+            assert_transcript(
+                &card_transcript(FILES, "cat x<TAB>\nca<TAB>\ncat a 1 <TAB>\n"),
+                "
+> cat x
+Args = [\"x\"]
+
+> ca
+Command \"ca\" not found. Try 'help'.
+
+> cat a 1
+Args = [\"a\", \"1\"]
+
+>",
+            );
+            assert_transcript(
+                &card_transcript(&[], "cat <TAB>\n"),
+                "
+> cat
+Error: Missing argument <file>
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "completion")]
+        fn option_completion() {
+            // Several candidates are listed, then one is finished off, with
+            // an `=` if it takes a value
+            assert_transcript(
+                &card_transcript(&[], "grep --co<TAB>n<TAB>2 x<TAB>\n"),
+                "
+> grep --co
+--count  --context=
+> grep --context=2 x
+Args = [\"--context=2\", \"x\"]
+
+>",
+            );
+            // With no value completer, and no candidates
+            let mut context = Card { files: &[] };
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(CARD_MENU, &mut buffer, &mut context);
+            t.type_script("grep x --ca<TAB>--v<TAB>\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> grep x --case --v
+Error: Did not understand \"--v\". Options are: --count, --case, --context=N
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "completion")]
+        fn allowed_value_completion() {
+            const MODES: Menu<MockConsole, Card> = Menu::new(
+                "root",
+                &[&Item::new(
+                    "fan",
+                    say_file,
+                    &[Parameter::named_value("mode", "MODE")
+                        .short('m')
+                        .allowed_values(&["fast", "slow", "auto"])],
+                )],
+            );
+            let mut context = Card { files: &[] };
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MODES, &mut buffer, &mut context);
+            t.type_script(
+                "fan --mode=<TAB>s<TAB>\nfan -m a<TAB>\nfan --mode=Fast\n",
+                &mut context,
+            );
+            assert_transcript(
+                &t.screen(),
+                "
+> fan --mode=
+fast  slow  auto
+> fan --mode=slow
+Args = [\"--mode=slow\"]
+
+> fan -m auto
+Args = [\"-m\", \"auto\"]
+
+> fan --mode=Fast
+Error: Invalid value \"Fast\" for --mode. Choices are: fast, slow, auto
+
+>",
+            );
+            let mut out = [""; 4];
+            let count = complete::complete(&MODES, "fan --mode=a", &mut out);
+            assert_eq!(out[..count], ["auto"]);
+        }
+
+        #[test]
+        fn overflow() {
+            const COUNTED: Menu<MockConsole, u32> = Menu::new(
+                "root",
+                &[&Item::new(
+                    "count",
+                    |_, _, _, _, context| *context += 1,
+                    &[Parameter::optional("n")],
+                )],
+            );
+            let mut context = 0;
+            let mut buffer = [0u8; 64];
+            let mut t = Transcript::new(COUNTED, &mut buffer, &mut context);
+            // 200 bytes, of which only the first 64 fit
+            let line = std::format!("count {}", "9".repeat(194));
+            t.type_script(&std::format!("{line}\n"), &mut context);
+            let output = t.runner().interface.take_output();
+            assert_eq!(output.matches("Line too long").count(), 1, "{output:?}");
+            assert!(output.ends_with("\nLine too long: only 64 bytes fit\n\n> "));
+            assert_eq!(context, 0);
+            // The next line is run as usual
+            t.type_script("count 9\n", &mut context);
+            assert_eq!(context, 1);
+        }
+
+        #[test]
+        fn bell() {
+            let mut context = 0;
+            let mut buffer = [0u8; 16];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_bell(true);
+            // Once for Backspace, once for a Tab with nothing to complete,
+            // and once for the line being too long, however many more bytes
+            // are typed
+            t.type_script("<BS>foo --x<TAB> 0123456789abcdef\n", &mut context);
+            let rings = if cfg!(feature = "completion") { 3 } else { 2 };
+            let output = t.runner().interface.take_output();
+            assert_eq!(output.matches('\x07').count(), rings, "{output:?}");
+            // And once more for the next line
+            t.type_script("foo 0123456789abcdef", &mut context);
+            assert_eq!(t.runner().interface.output(), "\r> foo 0123456789ab\x07");
+        }
+
+        #[test]
+        fn colour() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_colour(true);
+            t.runner().interface.take_output();
+            t.type_script("help bar\nnope\n", &mut context);
+            let output = t.runner().interface.take_output();
+            assert!(
+                output.starts_with("\r\x1b[1;32m> \x1b[0mhelp bar\n"),
+                "{output:?}"
+            );
+            assert!(output.contains("\x1b[1mSUMMARY:\x1b[0m\n  bar\n\n"));
+            assert!(output.contains("\x1b[1mDESCRIPTION:\x1b[0m\nfandoggles a bar\n"));
+            // Turned off before the end of the line
+            assert!(output.contains("\x1b[31mCommand \"nope\" not found. Try 'help'.\x1b[0m\n"));
+            assert!(output.ends_with("\n\x1b[1;32m> \x1b[0m"));
+        }
+
+        #[cfg(feature = "builtins-extra")]
+        fn macro_transcript(script: &str) -> String {
+            let mut buffer = [0u8; 16];
+            let mut one = [0u8; 40];
+            let mut two = [0u8; 18];
+            let mut three = [0u8; 17];
+            let mut slots = [&mut one[..], &mut two[..], &mut three[..]];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_macro_buffers(&mut slots);
+            t.type_script(script, &mut context);
+            t.screen()
+        }
+
+        #[test]
+        #[cfg(feature = "builtins-extra")]
+        fn macros() {
+            // Lines which fail aren't recorded
+            assert_transcript(
+                &macro_transcript("record 1\nfoo 1\nnope\nbar\nsub\nstop\nexit\nplay 1\n"),
+                "
+> record 1
+Recording macro 1. Type 'stop' to finish.
+
+> foo 1
+Args = [\"1\"]
+
+> nope
+Command \"nope\" not found. Try 'help'.
+
+> bar
+Args = []
+
+> sub
+
+/sub> stop
+Recorded macro 1
+
+/sub> exit
+
+> play 1
+
+> foo 1
+Args = [\"1\"]
+
+> bar
+Args = []
+
+> sub
+
+/sub>",
+            );
+            // Slot 3 is too small for a 16 byte line, and slot 2 only has
+            // room for one
+            assert_transcript(
+                &macro_transcript("record 3\nrecord 2\nfoo 123456789012\nbar\nstop\nplay 2\n"),
+                "
+> record 3
+Error: Macro buffer too small
+
+> record 2
+Recording macro 2. Type 'stop' to finish.
+
+> foo 123456789012
+Args = [\"123456789012\"]
+
+> bar
+Args = []
+Macro full, so recording has stopped
+
+> stop
+Error: Not recording
+
+> play 2
+
+> foo 123456789012
+Args = [\"123456789012\"]
+
+>",
+            );
+            // Playing a macro works while recording, but not from a macro
+            assert_transcript(
+                &macro_transcript("record 1\nbar\nstop\nrecord 2\nplay 1\nstop\nplay 2\n"),
+                "
+> record 1
+Recording macro 1. Type 'stop' to finish.
+
+> bar
+Args = []
+
+> stop
+Recorded macro 1
+
+> record 2
+Recording macro 2. Type 'stop' to finish.
+
+> play 1
+
+> bar
+Args = []
+
+> stop
+Recorded macro 2
+
+> play 2
+
+> play 1
+Error: A macro can't play a macro
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "builtins-extra")]
+        fn macro_commands() {
+            let screen = macro_transcript("help\nrecord\nplay x\n");
+            assert!(screen.contains("  record <slot>\n  stop\n  play <slot>\n  help"));
+            assert!(screen.contains("> record\nError: Missing argument <slot>\n"));
+            assert!(screen.contains("> play x\nError: Invalid value \"x\" for slot\n"));
+        }
+
+        #[test]
+        #[cfg(feature = "builtins-extra")]
+        fn macro_commands_yield_to_items() {
+            const STOP_MENU: Menu<MockConsole, u32> =
+                Menu::new("root", &[&Item::new("stop", say_args, &[])]);
+            let mut buffer = [0u8; 16];
+            let mut one = [0u8; 40];
+            let mut slots = [&mut one[..]];
+            let mut context = 0;
+            let mut t = Transcript::new(STOP_MENU, &mut buffer, &mut context);
+            t.runner().set_macro_buffers(&mut slots);
+            t.type_script("help\nrecord 1\nstop\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> help
+AVAILABLE ITEMS:
+  stop
+  record <slot>
+  play <slot>
+  help [ <command> ]
+
+> record 1
+Recording macro 1. Type 'stop' to finish.
+
+> stop
+Args = []
+
+>",
+            );
+        }
+
+        /// Keeps count of wrong passwords, with a pretend clock in seconds.
+        struct Lock {
+            now: u32,
+            failures: u8,
+            locked_until: u32,
+        }
+
+        fn check_password(
+            _menu: &Menu<MockConsole, Lock>,
+            _item: &Item<MockConsole, Lock>,
+            args: &[&str],
+            interface: &mut CommandContext<MockConsole, Lock>,
+            _context: &mut Lock,
+        ) {
+            if args == ["secret"] {
+                writeln!(interface, "Welcome").unwrap();
+            } else {
+                interface.report_failure();
+                interface.fail("Wrong password");
+            }
+        }
+
+        fn check_lock(group: u8, context: &mut Lock) -> ThrottleDecision {
+            match group {
+                1 if context.now < context.locked_until => {
+                    ThrottleDecision::Deny("Locked out. Try again later.")
+                }
+                2 => ThrottleDecision::DenyQuiet,
+                _ => ThrottleDecision::Allow,
+            }
+        }
+
+        fn count_failure(_group: u8, context: &mut Lock) {
+            context.failures += 1;
+            if context.failures == 3 {
+                context.failures = 0;
+                context.locked_until = context.now + 30;
+            }
+        }
+
+        const LOCK_MENU: Menu<MockConsole, Lock> = Menu::new(
+            "root",
+            &[
+                &Item::new("login", check_password, &[Parameter::mandatory("password")])
+                    .throttle_group(1),
+                &Item::new("sneak", check_password, &[Parameter::mandatory("password")])
+                    .throttle_group(2),
+            ],
+        );
+
+        #[test]
+        fn throttle() {
+            let mut buffer = [0u8; 32];
+            let mut context = Lock {
+                now: 0,
+                failures: 0,
+                locked_until: 0,
+            };
+            let mut t = Transcript::new(LOCK_MENU, &mut buffer, &mut context);
+            t.runner().set_throttle(check_lock, count_failure);
+            t.type_script("login a\nlogin b\nsneak secret\nlogin c\n", &mut context);
+            context.now = 29;
+            t.type_script("login secret\n", &mut context);
+            context.now = 30;
+            t.type_script("login secret\n", &mut context);
+            assert_eq!(context.failures, 0);
+            assert_transcript(
+                &t.screen(),
+                "
+> login a
+Error: Wrong password
+
+> login b
+Error: Wrong password
+
+> sneak secret
+
+> login c
+Error: Wrong password
+
+> login secret
+Locked out. Try again later.
+
+> login secret
+Welcome
+
+>",
+            );
+            assert_eq!(
+                t.runner().run_command("sneak secret", &mut context),
+                CommandOutcome::Denied(None)
+            );
+        }
+
+        #[cfg(feature = "builtins-extra")]
+        fn about_transcript(about: Option<About>) -> String {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            if let Some(about) = about {
+                t.runner().set_about(about);
+            }
+            t.type_script("help\nabout\nsub\nversion\n", &mut context);
+            t.screen()
+        }
+
+        #[test]
+        fn terminal_title_and_help_links() {
+            let run = |title, links| {
+                let mut buffer = [0u8; 64];
+                let mut t = Transcript::new(MENU, &mut buffer, &mut 0);
+                t.runner().set_terminal_title(title);
+                t.runner().set_help_links(links);
+                t.type_script("help\nsub\n", &mut 0);
+                (t.runner().interface.output().to_string(), t.screen())
+            };
+            let (plain, plain_screen) = run(None, None);
+            assert!(!plain.contains("\x1b]"));
+            let (fancy, fancy_screen) = run(Some("dev"), Some("https://example.com/{}.html"));
+            assert_eq!(fancy_screen, plain_screen);
+            assert!(fancy.contains("\x1b]0;dev:/\x07> sub"));
+            assert!(fancy.contains("\x1b]0;dev:/sub\x07/sub> "));
+            assert!(fancy.contains(
+                "  \x1b]8;;https://example.com/foo.html\x1b\\foo\x1b]8;;\x1b\\ <a> [ <b> ]"
+            ));
+            // Only the menu's own items are linked
+            assert!(fancy.contains("\n  help [ <command> ]"));
+        }
+
+        #[test]
+        #[cfg(feature = "builtins-extra")]
+        fn about() {
+            const LINES: &[(&str, &str)] = &[("Product", "Widget"), ("Firmware", "1.2.3")];
+            assert_transcript(
+                &about_transcript(Some(About::new(LINES))),
+                "
+> help
+AVAILABLE ITEMS:
+  foo <a> [ <b> ] [OPTIONS...]
+  bar
+  sub
+  about
+  help [ <command> ]
+
+> about
+Product:  Widget
+Firmware: 1.2.3
+
+> sub
+
+/sub> version
+Command \"version\" not found. Try 'help'.
+
+/sub>",
+            );
+            let renamed = about_transcript(Some(
+                About::new(LINES)
+                    .command("version")
+                    .with_crate_version()
+                    .hidden(),
+            ));
+            assert!(renamed.contains(concat!(
+                "/sub> version\nProduct:  Widget\nFirmware: 1.2.3\nmenu:     ",
+                env!("CARGO_PKG_VERSION"),
+                "\n\n/sub>"
+            )));
+            assert!(renamed.contains("Command \"about\" not found."));
+            assert!(!about_transcript(Some(About::new(LINES).hidden())).contains("  about\n"));
+        }
+
+        #[test]
+        #[cfg(feature = "builtins-extra")]
+        fn about_disabled() {
+            let screen = about_transcript(None);
+            assert!(!screen.contains("  about\n"));
+            assert!(screen.contains("Command \"about\" not found."));
+        }
+
+        #[test]
+        fn callback_changes_menu() {
+            assert_transcript(
+                &run_script(NAV_MENU, "login nope\nlogin secret\nlogout\n", &mut 0),
+                "
+> login nope
+Wrong password
+
+> login secret
+
+#/admin> logout
+
+>",
+            );
+        }
+
+        #[test]
+        fn callback_menu_paths() {
+            assert_transcript(
+                &run_script(
+                    NAV_MENU,
+                    "go admin/tools\ngo ../nope\ngo /\ngo /admin/tools/..\n",
+                    &mut 0,
+                ),
+                "
+> go admin/tools
+Entering tools
+
+/admin/tools> go ../nope
+Error: NotFound
+
+/admin/tools> go /
+Leaving tools
+
+> go /admin/tools/..
+
+/admin>",
+            );
+        }
+
+        #[test]
+        fn callback_raw_mode() {
+            let mut context = 0;
+            assert_transcript(
+                &run_script(
+                    NAV_MENU,
+                    "login secret\nupload\nab\ncd<C-d>logout\n",
+                    &mut context,
+                ),
+                "
+> login secret
+
+#/admin> upload
+Send data, then Ctrl-D
+Got 5 bytes
+
+#/admin> logout
+
+>",
+            );
+            assert_eq!(context, 5);
+        }
+
+        /// A pretend module with its own console, which answers `OK` to
+        /// each line.
+        #[derive(Default)]
+        struct Modem {
+            received: std::vec::Vec<u8>,
+            lines: usize,
+        }
+
+        const MODEM_MENU: Menu<MockConsole, Modem> = Menu::new(
+            "root",
+            &[&Item::new(
+                "modem",
+                |_, _, _, interface, _| {
+                    writeln!(interface, "Connected. Type +++ to return.").unwrap();
+                    interface.request_passthrough(
+                        |bytes, modem| modem.received.extend_from_slice(bytes),
+                        b"+++",
+                    );
+                },
+                &[],
+            )],
+        );
+
+        #[test]
+        fn passthrough() {
+            let mut modem = Modem::default();
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MODEM_MENU, &mut buffer, &mut modem);
+            let exchange = |t: &mut Transcript<Modem>, typed: &str, modem: &mut Modem| {
+                t.type_script(typed, modem);
+                // The application copies the module's replies to the console
+                let replies = modem.received.iter().filter(|b| **b == b'\r').count();
+                while t.runner().in_passthrough() && modem.lines < replies {
+                    modem.lines += 1;
+                    write!(t.runner().interface, "OK\r\n").unwrap();
+                }
+            };
+            exchange(&mut t, "modem\n", &mut modem);
+            assert!(t.runner().in_passthrough());
+            exchange(&mut t, "AT+C++\n", &mut modem);
+            exchange(&mut t, "ATI\n+", &mut modem);
+            // The escape sequence split across reads
+            exchange(&mut t, "+", &mut modem);
+            assert!(t.runner().in_passthrough());
+            exchange(&mut t, "+", &mut modem);
+            assert!(!t.runner().in_passthrough());
+            exchange(&mut t, "help\n", &mut modem);
+            assert_eq!(modem.received, b"AT+C++\rATI\r");
+            assert_eq!(modem.lines, 2);
+            assert_transcript(
+                &t.screen(),
+                "
+> modem
+Connected. Type +++ to return.
+OK
+OK
+
+> help
+AVAILABLE ITEMS:
+  modem
+  help [ <command> ]
+
+>",
+            );
+        }
+
+        const UNRULY_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("quiet", |_, _, _, _, _| {}, &[]),
+                &Item::new(
+                    "flood",
+                    |_, _, _, interface, _| {
+                        for _ in 0..1000 {
+                            write!(interface, "0123456789").unwrap();
+                        }
+                        // Red, with no newline
+                        write!(interface, "\x1b[31m").unwrap();
+                    },
+                    &[],
+                ),
+                &Item::new(
+                    "both",
+                    |_, _, _, interface, _| {
+                        interface.confirm("Sure?", |_, interface, _| {
+                            writeln!(interface, "Answered").unwrap();
+                        });
+                        interface.read_line("Name: ", |line, interface, _| {
+                            writeln!(interface, "Hello {}", line).unwrap();
+                        });
+                    },
+                    &[],
+                ),
+                &Item::new(
+                    "raw",
+                    |_, _, _, interface, _| {
+                        interface.read_line("Name: ", |_, _, _| {});
+                        interface.request_raw_mode(|input, _, context| {
+                            if input == b'q' {
+                                return RawModeAction::Finish;
+                            }
+                            *context += 1;
+                            RawModeAction::Continue
+                        });
+                    },
+                    &[],
+                ),
+                &Item::new(
+                    "again",
+                    |_, _, _, interface, _| {
+                        interface.confirm("First?", |_, interface, _| {
+                            interface.confirm("Second?", |confirmed, interface, _| {
+                                writeln!(interface, "Second was {}", confirmed).unwrap();
+                            });
+                        });
+                    },
+                    &[],
+                ),
+            ],
+        );
+
+        #[test]
+        fn unruly_callbacks() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(UNRULY_MENU, &mut buffer, &mut context);
+            // Whatever the output ended with, the prompt starts a new line
+            t.type_script("flood\n", &mut context);
+            assert!(t.runner().interface.output().ends_with("9\x1b[31m\n\n> "));
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(UNRULY_MENU, &mut buffer, &mut context);
+            // Only the last thing asked for takes the input, and only its
+            // question is shown
+            t.type_script("both\ny\nraw\nabcq", &mut context);
+            assert_eq!(context, 3);
+            // A question asked in answer to another is unwound in turn
+            t.type_script("again\nyn", &mut context);
+            t.type_script("quiet\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> both
+Name: y
+Hello y
+
+> raw
+
+> again
+First? [y/N] y
+Second? [y/N] n
+Second was false
+
+> quiet
+
+>",
+            );
+        }
+
+        fn reset(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            interface.confirm("Are you sure?", |confirmed, interface, context| {
+                if confirmed {
+                    *context = 0;
+                    writeln!(interface, "Reset").unwrap();
+                } else {
+                    writeln!(interface, "Cancelled").unwrap();
+                }
+            });
+        }
+
+        fn add_one(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            _interface: &mut CommandContext<MockConsole, u32>,
+            context: &mut u32,
+        ) {
+            *context += 1;
+        }
+
+        const CONFIRM_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("reset", reset, &[]),
+                &Item::new("add", add_one, &[]),
+            ],
+        );
+
+        #[test]
+        fn confirm() {
+            let mut context = 0;
+            assert_transcript(
+                &run_script(
+                    CONFIRM_MENU,
+                    "add\nreset\nxn<LF>add\nreset\n\nreset\n<LF>Y",
+                    &mut context,
+                ),
+                "
+> add
+
+> reset
+Are you sure? [y/N] n
+Cancelled
+
+> add
+
+> reset
+Are you sure? [y/N] n
+Cancelled
+
+> reset
+Are you sure? [y/N] y
+Reset
+
+>",
+            );
+            assert_eq!(context, 0);
+        }
+
+        #[test]
+        fn confirm_waits() {
+            let mut buffer = [0u8; 16];
+            let mut context = 5;
+            let mut r = Runner::new(CONFIRM_MENU, &mut buffer, MockConsole::new(), &mut context);
+            r.run_command("reset", &mut context);
+            assert!(r.interface.output().ends_with("Are you sure? [y/N] "));
+            // Nothing happens until an answer arrives, and what's typed isn't
+            // a command
+            for b in *b"add" {
+                r.input_byte(b, &mut context);
+            }
+            assert_eq!(context, 5);
+            r.input_byte(b'y', &mut context);
+            assert_eq!(context, 0);
+            assert!(r.interface.output().ends_with("[y/N] y\nReset\n\n> "));
+        }
+
+        fn wifi(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            interface.read_line("SSID: ", |ssid, interface, context| {
+                writeln!(interface, "Joining {:?}", ssid).unwrap();
+                *context = ssid.len() as u32;
+                interface.read_secret("Password: ", |password, interface, context| {
+                    writeln!(interface, "Password is {} long", password.len()).unwrap();
+                    *context += password.len() as u32;
+                });
+            });
+        }
+
+        fn unlock(
+            _menu: &Menu<MockConsole, u32>,
+            _item: &Item<MockConsole, u32>,
+            _args: &[&str],
+            interface: &mut CommandContext<MockConsole, u32>,
+            _context: &mut u32,
+        ) {
+            interface.set_echo(Echo::Off);
+            interface.read_line("Passphrase: ", |passphrase, interface, _| {
+                interface.set_echo(Echo::On);
+                writeln!(interface, "Passphrase is {} long", passphrase.len()).unwrap();
+            });
+        }
+
+        const LINE_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new("wifi", wifi, &[]),
+                &Item::new("add", add_one, &[]),
+                &Item::new("unlock", unlock, &[]),
+            ],
         );
-        // Missing named
-        assert_eq!(argument_finder(&item, &["a"], "baz"), Ok(None));
-    }
 
-    #[test]
-    fn find_arg_namedvalue() {
-        let item = Item {
-            command: "dummy",
-            help: None,
-            item_type: ItemType::Callback {
-                function: dummy,
-                parameters: &[
-                    Parameter::Mandatory {
-                        parameter_name: "foo",
-                        help: Some("Some help for foo"),
+        #[test]
+        fn read_lines() {
+            let mut context = 0;
+            assert_transcript(
+                &run_script(
+                    LINE_MENU,
+                    "wifi\nmy netx<BS>\nhelp<BS><BS>ll\nadd\n",
+                    &mut context,
+                ),
+                "
+> wifi
+SSID: my net
+Joining \"my net\"
+Password: ****
+Password is 4 long
+
+> add
+
+>",
+            );
+            assert_eq!(context, 11);
+        }
+
+        #[test]
+        fn echo() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(LINE_MENU, &mut buffer, &mut context);
+            t.type_script("unlock\nopen sesamx<BS>e\nadd\n", &mut context);
+            t.runner().set_echo(Echo::Mask('#'));
+            t.type_script("adx<BS>d\n", &mut context);
+            t.runner().set_echo(Echo::Off);
+            t.type_script("wifi\nhome\npass\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> unlock
+Passphrase:
+Passphrase is 11 long
+
+> add
+
+> ###
+
+>
+SSID:
+Joining \"home\"
+Password:
+Password is 4 long
+
+>",
+            );
+            // A mask replaces the `*`s of a secret
+            t.runner().set_echo(Echo::Mask('#'));
+            t.type_script("wifi\nhome\nkey\n", &mut context);
+            assert!(t
+                .screen()
+                .ends_with("SSID: ####\nJoining \"home\"\nPassword: ###\nPassword is 3 long\n\n>"));
+            // Left to the terminal, only a backspace and the line at Enter
+            // are written
+            t.runner().set_echo(Echo::Local);
+            t.type_script("addx<BS>\n", &mut context);
+            assert!(t.screen().ends_with("\nadd\n\n>"));
+        }
+
+        /// Keeps a number in the context, which `edit` and `rename` start
+        /// the next line with
+        const TEMPLATE_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[
+                &Item::new(
+                    "edit",
+                    |_, _, _, interface, context| {
+                        if let Err(e) = interface.prefill(&std::format!("set {}", context)) {
+                            writeln!(interface, "{}", e).unwrap();
+                        }
                     },
-                    Parameter::Named {
-                        parameter_name: "bar",
-                        help: Some("Some help for bar"),
+                    &[],
+                ),
+                &Item::new(
+                    "set",
+                    |_, _, args, interface, context| match args[0].parse() {
+                        Ok(value) => *context = value,
+                        Err(_) => interface.fail("Not a number"),
                     },
-                    Parameter::NamedValue {
-                        parameter_name: "baz",
-                        argument_name: "TEST",
-                        help: Some("Some help for baz"),
+                    &[Parameter::mandatory("value")],
+                ),
+                &Item::new(
+                    "rename",
+                    |_, _, _, interface, context| {
+                        interface
+                            .edit_line(
+                                "Value: ",
+                                &std::format!("{}", context),
+                                |line, _, context| {
+                                    *context = line.parse().unwrap_or(0);
+                                },
+                            )
+                            .unwrap();
                     },
-                ],
-            },
-        };
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "foo"),
-            Ok(Some("a"))
-        );
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "bar"),
-            Ok(Some(""))
-        );
-        // No argument so mark as not found
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "baz"),
-            Ok(None)
-        );
-        // Empty argument
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz="], "baz"),
-            Ok(Some(""))
+                    &[],
+                ),
+                &Item::new(
+                    "long",
+                    |_, _, _, interface, _| {
+                        let e = interface.prefill(&"x".repeat(30)).unwrap_err();
+                        writeln!(interface, "{}", e).unwrap();
+                    },
+                    &[],
+                ),
+            ],
         );
-        // Short argument
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz=1"], "baz"),
-            Ok(Some("1"))
+
+        #[test]
+        fn templates() {
+            let mut context = 42;
+            let mut buffer = [0u8; 32];
+            #[cfg(feature = "builtins-extra")]
+            let mut slot = [0u8; 64];
+            #[cfg(feature = "builtins-extra")]
+            let mut slots = [&mut slot[..]];
+            let mut t = Transcript::new(TEMPLATE_MENU, &mut buffer, &mut context);
+            // Edited, then as it is, then thrown away
+            t.type_script("edit\n<BS>7\nedit\n\nedit\n<C-c>", &mut context);
+            assert_eq!(context, 47);
+            t.type_script("rename\n<BS><BS>5\nlong\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> edit
+
+> set 47
+
+> edit
+
+> set 47
+
+> edit
+
+> set 47^C
+
+> rename
+Value: 5
+
+> long
+Too long to fit
+
+>",
+            );
+            assert_eq!(context, 5);
+            // There's no room in the buffer for a command which wasn't typed
+            t.runner().interface.take_output();
+            t.runner().run_command("edit", &mut context);
+            assert_eq!(t.runner().interface.output(), "Too long to fit\n");
+            // Nor for one played back from a macro
+            #[cfg(feature = "builtins-extra")]
+            {
+                t.runner().set_macro_buffers(&mut slots);
+                t.type_script("record 1\nedit\n<C-c>stop\n", &mut context);
+                t.runner().interface.take_output();
+                t.type_script("play 1\n", &mut context);
+                let output = t.runner().interface.take_output();
+                assert!(output.contains("edit\nToo long to fit\n"), "{output:?}");
+                assert!(output.ends_with("\n> "), "{output:?}");
+            }
+        }
+
+        /// Every prompt drawn by [`draw_prompt`].
+        #[derive(Default)]
+        struct Drawn {
+            prompts: std::vec::Vec<String>,
+        }
+
+        fn draw_prompt(info: &PromptInfo, drawn: &mut Drawn) {
+            let mut prompt = std::format!("{}/{}", info.prefix, info.labels().join("/"));
+            if let Some(question) = info.question {
+                prompt = std::format!("{:?}", question);
+            }
+            if info.redraw {
+                prompt.push_str(" again");
+            }
+            drawn.prompts.push(prompt);
+        }
+
+        const DRAWN_MENU: Menu<MockConsole, Drawn> = Menu::new(
+            "root",
+            &[&Item::new_menu(
+                "sub",
+                &Menu::new(
+                    "sub",
+                    &[&Item::new(
+                        "ask",
+                        |_, _, _, interface, _| {
+                            interface.read_line("Name: ", |line, interface, _| {
+                                writeln!(interface, "Hello {}", line).unwrap();
+                            });
+                        },
+                        &[],
+                    )],
+                ),
+            )],
         );
-        // Long argument
-        assert_eq!(
-            argument_finder(
-                &item,
-                &["a", "--bar", "--baz=abcdefghijklmnopqrstuvwxyz"],
-                "baz"
-            ),
-            Ok(Some("abcdefghijklmnopqrstuvwxyz"))
+
+        #[test]
+        fn external_prompt() {
+            let mut drawn = Drawn::default();
+            let mut buffer = [0u8; 32];
+            let mut r = Runner::new_deferred(DRAWN_MENU, &mut buffer, MockConsole::new());
+            r.set_prompt_rendering(PromptRendering::External(draw_prompt));
+            r.start(&mut drawn);
+            for byte in script_bytes("sub\nask\nJo\n<C-c>") {
+                r.input_byte(byte, &mut drawn);
+            }
+            // As when reconnecting
+            r.show_prompt(true, &mut drawn);
+            // Only the first character typed on a line redraws it
+            let expected = [
+                "/",
+                "/ again",
+                "/sub",
+                "/sub again",
+                "\"Name: \"",
+                "\"Name: \" again",
+                "/sub",
+                "/sub",
+                "/sub",
+            ];
+            assert_eq!(drawn.prompts, expected);
+            // The line typed is still echoed, but the prompts aren't printed
+            assert_eq!(
+                r.interface.output(),
+                "\n\rsub\n\n\rask\n\rJo\nHello Jo\n\n^C\n\n\n"
+            );
+            // Without the context, the `Runner` prints its own
+            r.interface.take_output();
+            r.prompt(true);
+            assert_eq!(r.interface.output(), "\n/sub> ");
+            assert_eq!(drawn.prompts.len(), expected.len());
+        }
+
+        fn write_prompt(info: &PromptInfo, out: &mut dyn Write, drawn: &mut Drawn) {
+            drawn.prompts.push(info.labels().join("/"));
+            write!(out, "[{}] $ ", info.depth).unwrap();
+        }
+
+        #[test]
+        fn custom_prompt() {
+            let mut drawn = Drawn::default();
+            let mut buffer = [0u8; 32];
+            let mut r = Runner::new_deferred(DRAWN_MENU, &mut buffer, MockConsole::new());
+            r.set_prompt_rendering(PromptRendering::Custom(write_prompt));
+            r.start(&mut drawn);
+            for byte in script_bytes("sub\nask\nJo\n<C-l>") {
+                r.input_byte(byte, &mut drawn);
+            }
+            assert_eq!(drawn.prompts, ["", "", "sub", "sub", "sub", "sub"]);
+            // Questions are asked as usual
+            assert_eq!(
+                r.interface.output(),
+                "\n[0] $ \r[0] $ sub\n\n[1] $ \r[1] $ ask\nName: \rName: Jo\nHello Jo\n\n[1] $ \x1b[2J\x1b[H[1] $ "
+            );
+        }
+
+        #[test]
+        fn redraw_screen() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.type_script("bar\nfoo ab<C-l>", &mut context);
+            assert!(t
+                .runner()
+                .interface
+                .output()
+                .ends_with("\x1b[2J\x1b[H> foo ab"));
+            t.type_script("c\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "> foo abc
+Args = [\"abc\"]
+
+>",
+            );
+        }
+
+        /// Writes 100 lines through the pager, counting how often it's run
+        #[cfg(feature = "paging")]
+        const PAGED_MENU: Menu<MockConsole, u32> = Menu::new(
+            "root",
+            &[&Item::new(
+                "log",
+                |_, _, _, interface, runs| {
+                    *runs += 1;
+                    let mut pager = interface.pager();
+                    for i in 0..100 {
+                        if pager.cancelled() {
+                            break;
+                        }
+                        writeln!(pager, "line {}", i).unwrap();
+                    }
+                },
+                &[],
+            )],
         );
-        // Not an argument
-        assert_eq!(
-            argument_finder(&item, &["a", "--bar", "--baz"], "quux"),
-            Err(Error::NotFound)
+
+        /// The lines the `log` command shows, from `start` up to `end`.
+        #[cfg(feature = "paging")]
+        fn log_lines(lines: core::ops::Range<usize>) -> std::string::String {
+            lines.map(|i| std::format!("line {}\n", i)).collect()
+        }
+
+        #[test]
+        #[cfg(feature = "paging")]
+        fn pager() {
+            let mut runs = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(PAGED_MENU, &mut buffer, &mut runs);
+            // Without a page height, everything is shown at once
+            t.type_script("log\n", &mut runs);
+            assert_transcript(
+                &t.screen(),
+                &std::format!("\n> log\n{}\n>", log_lines(0..100)),
+            );
+            assert_eq!(runs, 1);
+            t.runner().interface.take_output();
+
+            t.runner().set_page_height(Some(10));
+            t.type_script("log\n", &mut runs);
+            assert!(t.runner().interface.output().ends_with("line 9\n--More--"));
+            // Typing doesn't go into the command line
+            t.type_script("x", &mut runs);
+            assert!(t.runner().interface.output().ends_with("--More--"));
+            // A page, a line, then quit
+            t.type_script(" \rq", &mut runs);
+            assert_transcript(&t.screen(), &std::format!("> log\n{}\n>", log_lines(0..21)));
+            assert_eq!(runs, 4);
+            t.runner().interface.take_output();
+
+            // To the end, where there's no --More--
+            t.type_script("log\n         ", &mut runs);
+            assert_transcript(
+                &t.screen(),
+                &std::format!("> log\n{}\n>", log_lines(0..100)),
+            );
+            assert_eq!(runs, 14);
+            t.type_script("log\n<C-c>", &mut runs);
+            assert!(t
+                .runner()
+                .interface
+                .output()
+                .ends_with("line 9\n--More--\r        \r\n> "));
+        }
+
+        #[test]
+        #[cfg(feature = "paging")]
+        fn pager_runs_command_once() {
+            let mut runs = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(PAGED_MENU, &mut buffer, &mut runs);
+            t.runner().set_page_height(Some(60));
+            t.runner().set_machine_mode(true);
+            t.type_script("log\n ", &mut runs);
+            // Called for each page, but framed and counted as one command
+            assert_eq!(runs, 2);
+            let output = t.runner().interface.take_output();
+            assert_eq!(output.matches("<<<BEGIN").count(), 1, "{output:?}");
+            assert_eq!(output.matches("<<<RESULT").count(), 1, "{output:?}");
+            #[cfg(feature = "stats")]
+            assert_eq!(t.runner().stats().commands_executed(), 1);
+        }
+
+        #[test]
+        fn interrupt() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(LINE_MENU, &mut buffer, &mut context);
+            t.runner().set_interrupt_hook(|menu, interface, context| {
+                writeln!(interface, "Interrupted in {}", menu.label).unwrap();
+                *context += 100;
+            });
+            // A line being read for a callback is abandoned too
+            t.type_script("wifi nonsense<C-c>wifi\nmy<C-c>add\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> wifi nonsense^C
+Interrupted in root
+
+> wifi
+SSID: my^C
+Interrupted in root
+
+> add
+
+>",
+            );
+            assert_eq!(context, 201);
+        }
+
+        #[test]
+        #[cfg(feature = "ansi")]
+        fn unknown_escape_sequences() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            // F1, F5 and Ctrl-F1
+            t.type_script("foo a<F1>b<x1b>[15~c<x1b>[1;5Pd\n", &mut context);
+            // ESC on its own throws the line away, once the byte after it
+            // shows it didn't start an escape sequence
+            t.type_script("nope<ESC>\nnope<ESC><ESC>[Dbar\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo abcd
+Args = [\"abcd\"]
+
+>
+
+> bar
+Args = []
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn cursor_movement() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            // Off the start and the end of the line
+            t.type_script("ar<LEFT><LEFT><LEFT>b<RIGHT><RIGHT><RIGHT>\n", &mut context);
+            // Backspace before the cursor, and a character of two bytes
+            t.type_script("foo éxy<LEFT><BS>z<LEFT><LEFT>a\n", &mut context);
+            // Keys we don't do anything with are ignored
+            t.type_script("bar<UP><END>\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> bar
+Args = []
+
+> foo aézy
+Args = [\"aézy\"]
+
+> bar
+Args = []
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn overwrite_and_delete() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.type_script("foo abcd<LEFT><LEFT><LEFT><FDEL>\n", &mut context);
+            // Overwriting at the end of the line adds to it
+            t.type_script("foo aébc<LEFT><LEFT><LEFT><INS>xyz!\n", &mut context);
+            // Until Insert is pressed again
+            t.type_script("foo abd<LEFT><LEFT>é<INS>c\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo acd
+Args = [\"acd\"]
+
+> foo axyz!
+Args = [\"axyz!\"]
+
+> foo aécd
+Args = [\"aécd\"]
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn word_movement() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            // With Alt, then with Ctrl and the arrow keys
+            t.type_script("foo two  thrée<ESC>b<ESC>bX<ESC>fY<ESC>f!\n", &mut context);
+            t.type_script(
+                "fo a<ESC>[1;5D<ESC>[1;5D<ESC>[1;5D<ESC>[1;3Co\n",
+                &mut context,
+            );
+            assert_transcript(
+                &t.screen(),
+                "
+> foo XtwoY  thrée!
+Args = [\"XtwoY\", \"thrée!\"]
+
+> foo a
+Args = [\"a\"]
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn word_movement_mid_character() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            // A stray continuation byte, then a lead byte typed before it,
+            // leaves the cursor inside the character they make
+            for byte in [0xA9, 0x1B, b'[', b'D', 0xC3, 0x1B, b'f'] {
+                t.runner().input_byte(byte, &mut context);
+            }
+            t.type_script("<C-u>foo x\n", &mut context);
+            let screen = t.screen();
+            assert!(
+                screen.ends_with("> foo x\nArgs = [\"x\"]\n\n>"),
+                "{screen:?}"
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn erase_word() {
+            let mut context = 0;
+            assert_transcript(
+                &run_script(
+                    MENU,
+                    "<C-w>foo one  twö  <C-w>\nfoo aa bb<LEFT><LEFT><C-w>x\nfoo<C-w><C-w>bar\n",
+                    &mut context,
+                ),
+                "
+> foo one
+Args = [\"one\"]
+
+> foo xbb
+Args = [\"xbb\"]
+
+> bar
+Args = []
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn erase_line() {
+            let mut context = 0;
+            assert_transcript(
+                &run_script(
+                    MENU,
+                    "foo one twö<C-u>\nfoo aa<LEFT><C-u>bar\n<C-u>\n",
+                    &mut context,
+                ),
+                "
+>
+
+> bar
+Args = []
+
+>
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(feature = "line-editing")]
+        fn cursor_redraw() {
+            let mut context = 0;
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.type_script("bxr<LEFT><LEFT>", &mut context);
+            let start = t.runner().interface.output().len();
+            t.type_script("<RIGHT><BS>a", &mut context);
+            let output = &t.runner().interface.output()[start..];
+            // Only what's after the cursor is redrawn when deleting
+            assert!(output.starts_with("\x1b[C\x08r \x1b[2D"), "{:?}", output);
+            assert!(output.ends_with("\r> bar\x1b[D"), "{:?}", output);
+        }
+
+        /// How much the user may do, and how many times they've been
+        /// logged out.
+        #[derive(Default)]
+        struct Session {
+            level: u8,
+            timeouts: u32,
+        }
+
+        const SESSION_MENU: Menu<MockConsole, Session> = Menu::new(
+            "root",
+            &[
+                &Item::new("unlock", |_, _, _, _, session| session.level = 2, &[]),
+                &Item::new_menu(
+                    "admin",
+                    &Menu::new(
+                        "admin",
+                        &[&Item::new(
+                            "level",
+                            |_, _, _, interface, session| {
+                                writeln!(interface, "Level {}", session.level).unwrap();
+                            },
+                            &[],
+                        )],
+                    ),
+                ),
+            ],
         );
-        // Missing named
-        assert_eq!(argument_finder(&item, &["a"], "baz"), Ok(None));
+
+        #[test]
+        fn session_timeout() {
+            let mut buffer = [0u8; 32];
+            let mut session = Session::default();
+            let mut t = Transcript::new(SESSION_MENU, &mut buffer, &mut session);
+            t.runner()
+                .set_session_timeout(SessionTimeout::new(3), |session| {
+                    session.level = 0;
+                    session.timeouts += 1;
+                });
+            t.type_script("unlock\nadmin\n", &mut session);
+            for _ in 0..2 {
+                t.runner().tick(&mut session);
+            }
+            // Typing starts the count again
+            t.type_script("lev", &mut session);
+            for _ in 0..2 {
+                t.runner().tick(&mut session);
+            }
+            assert_eq!(session.timeouts, 0);
+            t.runner().tick(&mut session);
+            assert_eq!((session.level, session.timeouts), (0, 1));
+            // Only once, however long it stays idle
+            for _ in 0..10 {
+                t.runner().tick(&mut session);
+            }
+            assert_eq!(session.timeouts, 1);
+            t.type_script("el\n", &mut session);
+            assert_transcript(
+                &t.screen(),
+                "
+> unlock
+
+> admin
+
+/admin> lev
+Session timed out
+
+> el
+Command \"el\" not found. Try 'help'.
+
+>",
+            );
+            // So does running a command
+            for _ in 0..2 {
+                t.runner().tick(&mut session);
+            }
+            t.runner().run_command("unlock", &mut session);
+            for _ in 0..2 {
+                t.runner().tick(&mut session);
+            }
+            assert_eq!(session.timeouts, 1);
+            t.runner().tick(&mut session);
+            assert_eq!(session.timeouts, 2);
+        }
+
+        #[test]
+        #[cfg(feature = "history")]
+        fn session_timeout_history() {
+            let mut session = Session::default();
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut t = Transcript::new(SESSION_MENU, &mut buffer, &mut session);
+            t.runner().set_history_buffer(&mut history);
+            t.runner()
+                .set_session_timeout(SessionTimeout::new(1), |_| {});
+            t.type_script("unlock\n", &mut session);
+            t.runner().tick(&mut session);
+            t.type_script("history\n", &mut session);
+            assert!(t
+                .screen()
+                .ends_with("Session timed out\n\n> history\n    1  history\n\n>"));
+
+            // The history can be kept
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut t = Transcript::new(SESSION_MENU, &mut buffer, &mut session);
+            t.runner().set_history_buffer(&mut history);
+            t.runner()
+                .set_session_timeout(SessionTimeout::new(1).keep_history(), |_| {});
+            t.type_script("unlock\n", &mut session);
+            t.runner().tick(&mut session);
+            t.type_script("history\n", &mut session);
+            assert!(t
+                .screen()
+                .ends_with("Session timed out\n\n> history\n    1  unlock\n    2  history\n\n>"));
+        }
+
+        #[test]
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        fn key_bindings() {
+            // A front panel's buttons, which send bytes a terminal wouldn't
+            const PANEL: &[KeyBinding] = &[
+                KeyBinding::new(&[0x80], KeyAction::HistoryPrev),
+                KeyBinding::new(&[0x81], KeyAction::HistoryNext),
+                KeyBinding::new(&[0x82], KeyAction::Submit),
+                KeyBinding::new(&[0x83], KeyAction::Insert('é')),
+                KeyBinding::new(b"\r", KeyAction::Ignore),
+                // A terminal's F1 and F2
+                KeyBinding::new(b"\x1bOP", KeyAction::Submit),
+                KeyBinding::new(b"\x1bOQ", KeyAction::Insert('!')),
+            ];
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_history_buffer(&mut history);
+            t.runner().set_key_bindings(PANEL);
+            t.type_script("foo a<x82>bar<x82>", &mut context);
+            t.type_script("<x80><x80><x81><x80><x83><x82>", &mut context);
+            // Enter does nothing now
+            t.type_script("bar\n<x82>", &mut context);
+            // The arrow keys start like F1 and F2, but still work
+            t.type_script("foo b<ESC>OQ<ESC>OP<UP><ESC>OP", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo a
+Args = [\"a\"]
+
+> bar
+Args = []
+
+> foo aé
+Args = [\"aé\"]
+
+> bar
+Args = []
+
+> foo b!
+Args = [\"b!\"]
+
+> foo b!
+Args = [\"b!\"]
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        fn history_recall() {
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_history_buffer(&mut history);
+            t.type_script("foo a\nbar\n", &mut context);
+            // Past the oldest line, then back down to what was being typed
+            t.type_script(
+                "ba<UP><UP><UP><DOWN><DOWN><DOWN>r\n<UP><UP><UP><BS>b\n",
+                &mut context,
+            );
+            assert_transcript(
+                &t.screen(),
+                "
+> foo a
+Args = [\"a\"]
+
+> bar
+Args = []
+
+> bar
+Args = []
+
+> foo b
+Args = [\"b\"]
+
+>",
+            );
+
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut r = Runner::new_with_history(
+                MENU,
+                &mut buffer,
+                &mut history,
+                MockConsole::new(),
+                &mut context,
+            );
+            for byte in "bar\r\x1b[A".bytes() {
+                r.input_byte(byte, &mut context);
+            }
+            assert!(r.interface.output().ends_with("\r> bar\x1b[K"));
+        }
+
+        #[test]
+        #[cfg(feature = "telnet")]
+        fn telnet() {
+            let mut buffer = [0u8; 32];
+            let mut refused = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut refused);
+            t.runner()
+                .enable_telnet(Some(|bytes, refused| *refused += bytes.len() as u32));
+            // DO ECHO, then a terminal type subnegotiation, with Enter as
+            // CR NUL
+            t.type_script(
+                "<xff><xfd><x01>fo<xff><xfa><x18><x00>xterm<xff><xf0>o a\n<x00>",
+                &mut refused,
+            );
+            assert_transcript(
+                &t.screen(),
+                "
+> foo a
+Args = [\"a\"]
+
+>",
+            );
+            assert_eq!(refused, 3);
+        }
+
+        #[test]
+        #[cfg(feature = "telnet")]
+        fn telnet_tapped() {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().enable_telnet(None);
+            t.runner()
+                .set_input_tap(|console, byte, _| write!(console, "[{:02x}]", byte).unwrap());
+            t.runner().interface.take_output();
+            t.type_script("<xff><xfd><x01>f", &mut context);
+            // The tap is shown telnet's commands too, as they arrived
+            assert!(t
+                .runner()
+                .interface
+                .output()
+                .starts_with("[ff][fd][01][66]"));
+        }
+
+        #[test]
+        #[cfg(feature = "ansi")]
+        fn bracketed_paste() {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_bracketed_paste(true);
+            assert!(t.runner().interface.output().ends_with("\x1b[?2004h"));
+            #[cfg(feature = "stats")]
+            {
+                let written = t.runner().interface.output().len();
+                assert_eq!(t.runner().stats().bytes_written, written);
+            }
+            // Nothing is echoed until the paste has ended
+            t.type_script("fo<ESC>[200~o a<TAB>\nbar\nfoo", &mut context);
+            assert!(t.runner().interface.output().ends_with("\r> fo"));
+            t.type_script("<ESC>[201~ b\n", &mut context);
+            // A paste which doesn't fit is thrown away
+            t.type_script(
+                "<ESC>[200~bar 0123456789012345678901234567\n<ESC>[201~",
+                &mut context,
+            );
+            t.type_script("bar\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo a
+Args = [\"a\"]
+
+> bar
+Args = []
+
+> foo b
+Args = [\"b\"]
+
+>
+Paste too long to fit
+
+> bar
+Args = []
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(all(feature = "ansi", feature = "history"))]
+        fn history_search() {
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_history_buffer(&mut history);
+            t.type_script("foo a\nbar\nfoo b\n", &mut context);
+            // Ctrl-R again finds an older match
+            t.type_script("<C-r>fo<C-r>\n", &mut context);
+            // ESC goes back to the line being typed, without throwing it
+            // away as ESC on its own usually does
+            t.type_script("foo<C-r>b<ESC> c\n", &mut context);
+            // Nothing matches, and what was being typed comes back
+            t.type_script("ba<C-r>x<C-c>r\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> foo a
+Args = [\"a\"]
+
+> bar
+Args = []
+
+> foo b
+Args = [\"b\"]
+
+> foo a
+Args = [\"a\"]
+
+> foo c
+Args = [\"c\"]
+
+> bar
+Args = []
+
+>",
+            );
+
+            // What's shown while searching
+            let mut r = Runner::new_with_history(
+                MENU,
+                &mut buffer,
+                &mut history,
+                MockConsole::new(),
+                &mut context,
+            );
+            for byte in "bar\rfoo\r".bytes() {
+                r.input_byte(byte, &mut context);
+            }
+            r.interface.take_output();
+            for byte in "\x12a".bytes() {
+                r.input_byte(byte, &mut context);
+            }
+            assert_eq!(
+                r.interface.take_output(),
+                "\r(reverse-i-search)'': \x1b[K\r(reverse-i-search)'a': bar\x1b[K"
+            );
+            for byte in "z\x08".bytes() {
+                r.input_byte(byte, &mut context);
+            }
+            assert_eq!(
+                r.interface.take_output(),
+                "\r(failed reverse-i-search)'az': bar\x1b[K\r(reverse-i-search)'a': bar\x1b[K"
+            );
+            // ESC goes back to the line, ready for an arrow key
+            for byte in "\x1b[A".bytes() {
+                r.input_byte(byte, &mut context);
+            }
+            assert!(r.interface.output().starts_with("\r> \x1b[K\r> foo\x1b[K"));
+
+            // Without a history buffer, Ctrl-R does nothing
+            let mut buffer = [0u8; 32];
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.type_script("ba<C-r>r\n", &mut context);
+            assert!(t.screen().ends_with("> bar\nArgs = []\n\n>"));
+        }
+
+        #[test]
+        #[cfg(feature = "history")]
+        fn history() {
+            let mut buffer = [0u8; 32];
+            let mut history = [0u8; 24];
+            let mut context = 0;
+            let mut t = Transcript::new(CONFIRM_MENU, &mut buffer, &mut context);
+            t.runner().set_history_buffer(&mut history);
+            t.runner().set_history_list_len(3);
+            t.type_script(
+                "!!\nadd\nadd  \nhistory\n!3\n!1\n!2\n!9\n!!\nhistory\n",
+                &mut context,
+            );
+            assert_eq!(context, 4);
+            t.type_script("history clear\nhistory\nhistory x\nhelp\n", &mut context);
+            assert_transcript(
+                &t.screen(),
+                "
+> !!
+No previous command
+
+> add
+
+> add
+
+> history
+    1  add
+    2  add
+    3  history
+
+> !3
+history
+    2  add
+    3  history
+    4  history
+
+> !1
+No history entry 1
+
+> !2
+add
+
+> !9
+No history entry 9
+
+> !!
+add
+
+> history
+    5  add
+    6  add
+    7  history
+
+> history clear
+
+> history
+    1  history
+
+> history x
+Error: Did not understand \"x\"
+
+> help
+AVAILABLE ITEMS:
+  reset
+  add
+  history [ clear ]
+  help [ <command> ]
+
+>",
+            );
+        }
+
+        #[test]
+        #[cfg(all(feature = "history", feature = "alloc"))]
+        fn unbounded_history() {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(CONFIRM_MENU, &mut buffer, &mut context);
+            t.runner().set_unbounded_history();
+            t.type_script(&"add\n".repeat(500), &mut context);
+            t.type_script("!1\n", &mut context);
+            assert_eq!(context, 501);
+            assert!(t.screen().ends_with("> !1\nadd\n\n>"), "{:?}", t.screen());
+        }
+
+        #[test]
+        fn prompt_placement() {
+            assert_transcript(
+                &run_script(MENU, "bar\npartial\n", &mut 0),
+                "
+> bar
+Args = []
+
+> partial
+abc
+def
+
+>",
+            );
+        }
+
+        fn spaced_transcript(spacing: PromptSpacing) -> String {
+            let mut buffer = [0u8; 32];
+            let mut context = 0;
+            let mut t = Transcript::new(MENU, &mut buffer, &mut context);
+            t.runner().set_prompt_spacing(spacing);
+            t.runner().prompt(true);
+            t.type_script("bar\npartial\n", &mut context);
+            t.screen()
+        }
+
+        #[test]
+        fn prompt_spacing() {
+            assert_transcript(
+                &spaced_transcript(PromptSpacing::Always),
+                "
+>
+
+> bar
+Args = []
+
+> partial
+abc
+def
+
+>",
+            );
+            assert_transcript(
+                &spaced_transcript(PromptSpacing::OnlyIfNeeded),
+                "
+>
+> bar
+Args = []
+> partial
+abc
+def
+>",
+            );
+            assert_transcript(
+                &spaced_transcript(PromptSpacing::Never),
+                "
+> bar
+Args = []
+> partial
+abc
+def>",
+            );
+        }
+
+        #[test]
+        fn editing() {
+            assert_transcript(
+                &run_script(MENU, "barx<BS>\n", &mut 0),
+                "
+> bar
+Args = []
+
+>",
+            );
+        }
     }
 }
@@ -0,0 +1,128 @@
+//! Records command lines, to be played back later.
+#![deny(missing_docs)]
+
+/// Slots which each hold one recorded macro.
+///
+/// A macro is its command lines one after another, each ending with a NUL,
+/// and then another NUL to mark the end. Slots are numbered from 1.
+pub(crate) struct Macros<'a> {
+    slots: &'a mut [&'a mut [u8]],
+    /// The longest line which can be typed
+    line_len: usize,
+    /// The slot being recorded into, and how much of it is used
+    recording: Option<(usize, usize)>,
+    /// A macro is being played
+    pub(crate) playing: bool,
+}
+
+impl<'a> Macros<'a> {
+    /// Use the given slots, which all start off empty, for lines of up to
+    /// `line_len` bytes.
+    pub(crate) fn new(slots: &'a mut [&'a mut [u8]], line_len: usize) -> Self {
+        for slot in slots.iter_mut() {
+            if let Some(first) = slot.first_mut() {
+                *first = 0;
+            }
+        }
+        Macros {
+            slots,
+            line_len,
+            recording: None,
+            playing: false,
+        }
+    }
+
+    /// Start recording into slot `number`, forgetting what was there.
+    ///
+    /// The slot must have room for at least one line of the longest length.
+    pub(crate) fn record(&mut self, number: usize) -> Result<(), &'static str> {
+        let line_len = self.line_len;
+        let slot = self.slot(number).ok_or("No such macro")?;
+        if slot.len() < line_len + 2 {
+            return Err("Macro buffer too small");
+        }
+        slot[0] = 0;
+        self.recording = Some((number, 0));
+        Ok(())
+    }
+
+    /// Stop recording. Returns the slot which was being recorded into.
+    pub(crate) fn stop(&mut self) -> Option<usize> {
+        self.recording.take().map(|(number, _)| number)
+    }
+
+    /// Add a line to the macro being recorded, if there is one.
+    ///
+    /// Returns `false`, and stops recording, if there isn't room for it.
+    pub(crate) fn push(&mut self, line: &str) -> bool {
+        let Some((number, used)) = self.recording else {
+            return true;
+        };
+        let Some(slot) = self.slot(number) else {
+            return false;
+        };
+        let end = used + line.len();
+        if line.contains('\0') || end + 2 > slot.len() {
+            self.recording = None;
+            return false;
+        }
+        slot[used..end].copy_from_slice(line.as_bytes());
+        slot[end] = 0;
+        slot[end + 1] = 0;
+        self.recording = Some((number, end + 1));
+        true
+    }
+
+    /// Take slot `number` out, so its lines can be run. Give it back with
+    /// [`Macros::put_back`].
+    pub(crate) fn take(&mut self, number: usize) -> Option<&'a mut [u8]> {
+        let index = number.checked_sub(1)?;
+        self.slots.get_mut(index).map(core::mem::take)
+    }
+
+    /// Put back a slot taken with [`Macros::take`].
+    pub(crate) fn put_back(&mut self, number: usize, slot: &'a mut [u8]) {
+        if let Some(place) = number.checked_sub(1).and_then(|i| self.slots.get_mut(i)) {
+            *place = slot;
+        }
+    }
+
+    fn slot(&mut self, number: usize) -> Option<&mut [u8]> {
+        let index = number.checked_sub(1)?;
+        self.slots.get_mut(index).map(|slot| &mut **slot)
+    }
+}
+
+/// The lines of a macro taken with [`Macros::take`].
+pub(crate) fn lines(slot: &[u8]) -> impl Iterator<Item = &str> {
+    slot.split(|b| *b == 0)
+        .take_while(|line| !line.is_empty())
+        .map(|line| core::str::from_utf8(line).unwrap_or(""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn record() {
+        let mut one = [0xFFu8; 12];
+        let mut two = [0xFFu8; 4];
+        let mut slots = [&mut one[..], &mut two[..]];
+        let mut macros = Macros::new(&mut slots, 4);
+        assert_eq!(macros.record(2), Err("Macro buffer too small"));
+        assert_eq!(macros.record(3), Err("No such macro"));
+        assert_eq!(macros.record(1), Ok(()));
+        assert!(macros.push("ab"));
+        assert!(macros.push("cde"));
+        assert!(!macros.push("fghi"));
+        assert_eq!(macros.stop(), None);
+
+        let slot = macros.take(1).unwrap();
+        assert_eq!(lines(slot).collect::<Vec<_>>(), ["ab", "cde"]);
+        macros.put_back(1, slot);
+        let slot = macros.take(2).unwrap();
+        assert_eq!(lines(slot).count(), 0);
+    }
+}
@@ -1,14 +1,39 @@
 //! The Menu Manager looks after the menu and where we currently are within it.
 #![deny(missing_docs)]
 
-use super::{ItemType, Menu};
+use super::{EntryContext, Menu, MenuError};
+
+/// How many sub-menus deep we can go below the root menu.
+pub const MAX_DEPTH: usize = 4;
 
 /// Holds a nested tree of Menus and remembers which menu within the tree we're
 /// currently looking at.
+///
+/// The root menu is at depth 0, and each sub-menu we go into adds one, down
+/// to [`MAX_DEPTH`]. Nothing here panics: asking for something which isn't
+/// there gives `None` or an error, and leaves us where we were.
 pub struct MenuManager<'a, I, T> {
     menu: Menu<'a, I, T>,
-    /// Maximum four levels deep
-    menu_index: [Option<usize>; 4],
+    /// Maximum `MAX_DEPTH` levels deep
+    menu_index: [Option<usize>; MAX_DEPTH],
+}
+
+impl<'a, I, T> Clone for MenuManager<'a, I, T> {
+    fn clone(&self) -> Self {
+        Self {
+            menu: self.menu,
+            menu_index: self.menu_index,
+        }
+    }
+}
+
+impl<I, T> core::fmt::Debug for MenuManager<'_, I, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MenuManager")
+            .field("current", &self.current().label)
+            .field("path", &self.menu_index)
+            .finish()
+    }
 }
 
 impl<'a, I, T> MenuManager<'a, I, T> {
@@ -18,60 +43,278 @@ impl<'a, I, T> MenuManager<'a, I, T> {
     pub fn new(menu: Menu<'a, I, T>) -> Self {
         Self {
             menu,
-            menu_index: [None, None, None, None],
+            menu_index: [None; MAX_DEPTH],
         }
     }
 
-    /// How deep into the tree are we?
+    /// How many sub-menus deep are we? The root menu is at depth 0, and we
+    /// can go down to [`MAX_DEPTH`].
     pub fn depth(&self) -> usize {
         self.menu_index.iter().take_while(|x| x.is_some()).count()
     }
 
-    /// Go back up to a higher-level menu
-    pub fn pop_menu(&mut self) {
-        if let Some(pos) = self.menu_index.iter_mut().rev().find(|x| x.is_some()) {
-            pos.take();
-        }
+    /// Where we are: the index of each sub-menu item we went through.
+    pub(crate) fn path(&self) -> [Option<usize>; MAX_DEPTH] {
+        self.menu_index
     }
 
-    /// Drop into a sub-menu.
+    /// Go back up to the menu above this one.
     ///
-    /// The index must be the index of a valid sub-menu, not any other kind of
-    /// item. Do not push too many items.
-    pub fn push_menu(&mut self, index: usize) {
-        let menu = self.get_menu(None);
-        let item = menu.items[index];
-        if !matches!(item.item_type, ItemType::Menu(_)) {
-            panic!("Specified index is not a menu");
+    /// Returns `false`, and stays put, if we're already in the root menu.
+    pub fn pop(&mut self) -> bool {
+        match self.menu_index.iter_mut().rev().find(|x| x.is_some()) {
+            Some(pos) => {
+                pos.take();
+                true
+            }
+            None => false,
         }
+    }
 
-        let pos = self.menu_index.iter_mut().find(|x| x.is_none()).unwrap();
+    /// Drop into the sub-menu of the item at `index` in the current menu.
+    ///
+    /// Gives [`MenuError::NotFound`], and stays put, if `index` isn't the
+    /// index of a sub-menu item, or [`MenuError::TooDeep`] if we're already
+    /// [`MAX_DEPTH`] menus deep. Never panics.
+    pub fn push(&mut self, index: usize) -> Result<(), MenuError<'static>> {
+        let menu = self.current();
+        let is_menu = menu.items.get(index).is_some_and(|item| item.is_menu());
+        if !is_menu {
+            return Err(MenuError::NotFound);
+        }
+        let pos = self
+            .menu_index
+            .iter_mut()
+            .find(|x| x.is_none())
+            .ok_or(MenuError::TooDeep)?;
         pos.replace(index);
+        Ok(())
     }
 
-    /// Get a menu.
-    ///
-    /// Menus are nested. If `depth` is `None`, get the current menu. Otherwise
-    /// if it is `Some(i)` get the menu at depth `i`.
-    pub fn get_menu(&self, depth: Option<usize>) -> &Menu<'a, I, T> {
-        let mut menu = &self.menu;
+    /// The menu we're in.
+    pub fn current(&self) -> &Menu<'a, I, T> {
+        self.descend(self.depth())
+    }
 
-        let depth = depth.unwrap_or_else(|| self.depth());
+    /// The menu at `depth` on the way to the current one: the root menu at
+    /// depth 0, and the current menu at [`MenuManager::depth`]. `None` if
+    /// we're not that deep.
+    pub fn at_depth(&self, depth: usize) -> Option<&Menu<'a, I, T>> {
+        (depth <= self.depth()).then(|| self.descend(depth))
+    }
 
-        for position in self
+    /// The menu `depth` levels down the path, which mustn't be deeper than
+    /// we are.
+    fn descend(&self, depth: usize) -> &Menu<'a, I, T> {
+        let mut menu = &self.menu;
+        // `push` only lets us into sub-menus, so this always goes as deep as
+        // asked
+        for position in self.menu_index.iter().map_while(|x| *x).take(depth) {
+            match menu.items.get(position).and_then(|item| item.submenu()) {
+                Some(m) => menu = m,
+                None => break,
+            }
+        }
+        menu
+    }
+
+    /// Go to the menu at `target`, which came from [`MenuManager::path`],
+    /// calling the exit callback of every menu we leave and the entry
+    /// callbacks of every menu we enter. Stops short if `target` goes
+    /// somewhere we can't.
+    ///
+    /// As we go into each sub-menu, and before its entry callbacks, `on_push`
+    /// is given the depth we came from, the index of the sub-menu item and
+    /// the sub-menu.
+    pub(crate) fn change_menu(
+        &mut self,
+        target: [Option<usize>; MAX_DEPTH],
+        interface: &mut I,
+        context: &mut T,
+        mut on_push: impl FnMut(usize, usize, &Menu<'a, I, T>),
+    ) {
+        let previous = *self.current();
+        // Stay in any menus the two paths share
+        let common = self
             .menu_index
             .iter()
-            .take_while(|x| x.is_some())
-            .map(|x| x.unwrap())
-            .take(depth)
-        {
-            if let ItemType::Menu(m) = menu.items[position].item_type {
-                menu = m
-            } else {
-                panic!("Selected item is not a menu");
+            .zip(target.iter())
+            .take_while(|(a, b)| a.is_some() && a == b)
+            .count();
+        while self.depth() > common {
+            let menu = self.current();
+            if let Some(cb_fn) = menu.exit {
+                cb_fn(menu, interface, context);
             }
+            self.pop();
         }
+        for index in target.iter().skip(common).map_while(|x| *x) {
+            let parent = *self.current();
+            let depth = self.depth();
+            if self.push(index).is_err() {
+                break;
+            }
+            let menu = self.current();
+            on_push(depth, index, menu);
+            if let Some(cb_fn) = menu.entry {
+                cb_fn(menu, interface, context);
+            }
+            if let Some(cb_fn) = menu.entry_with_context {
+                let entry = EntryContext {
+                    entered: menu,
+                    parent: Some(&parent),
+                    previous: Some(&previous),
+                };
+                cb_fn(&entry, interface, context);
+            }
+        }
+    }
 
-        menu
+    /// Go back up to a higher-level menu
+    #[deprecated(note = "Use `MenuManager::pop`")]
+    pub fn pop_menu(&mut self) {
+        self.pop();
+    }
+
+    /// Drop into a sub-menu.
+    #[deprecated(note = "Use `MenuManager::push`")]
+    pub fn push_menu(&mut self, index: usize) -> Result<(), MenuError<'static>> {
+        self.push(index)
+    }
+
+    /// Get a menu.
+    ///
+    /// If `depth` is `None`, get the current menu. Otherwise if it is
+    /// `Some(i)` get the menu at depth `i`, or the current menu if we're not
+    /// that deep.
+    #[deprecated(note = "Use `MenuManager::current` or `MenuManager::at_depth`")]
+    pub fn get_menu(&self, depth: Option<usize>) -> &Menu<'a, I, T> {
+        self.descend(depth.map_or(self.depth(), |depth| depth.min(self.depth())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommandContext, Item};
+    use std::format;
+
+    fn noop(
+        _menu: &Menu<(), ()>,
+        _item: &Item<(), ()>,
+        _args: &[&str],
+        _interface: &mut CommandContext<(), ()>,
+        _context: &mut (),
+    ) {
+    }
+
+    /// Each level has a command, then a sub-menu, five levels deep.
+    const LEVEL_5: Menu<(), ()> = Menu::new("5", &[&Item::new("cmd", noop, &[])]);
+    const LEVEL_4: Menu<(), ()> = Menu::new(
+        "4",
+        &[
+            &Item::new("cmd", noop, &[]),
+            &Item::new_menu("down", &LEVEL_5),
+        ],
+    );
+    const LEVEL_3: Menu<(), ()> = Menu::new(
+        "3",
+        &[
+            &Item::new("cmd", noop, &[]),
+            &Item::new_menu("down", &LEVEL_4),
+        ],
+    );
+    const LEVEL_2: Menu<(), ()> = Menu::new(
+        "2",
+        &[
+            &Item::new("cmd", noop, &[]),
+            &Item::new_menu("down", &LEVEL_3),
+        ],
+    );
+    const LEVEL_1: Menu<(), ()> = Menu::new(
+        "1",
+        &[
+            &Item::new("cmd", noop, &[]),
+            &Item::new_menu("down", &LEVEL_2),
+        ],
+    );
+    const ROOT: Menu<(), ()> = Menu::new(
+        "root",
+        &[
+            &Item::new("cmd", noop, &[]),
+            &Item::new_menu("down", &LEVEL_1),
+        ],
+    );
+
+    #[test]
+    fn push_and_pop() {
+        let mut mgr = MenuManager::new(ROOT);
+        assert_eq!(mgr.depth(), 0);
+        assert_eq!(mgr.current().label, "root");
+        assert!(!mgr.pop());
+        assert_eq!(mgr.push(1), Ok(()));
+        assert_eq!(mgr.push(1), Ok(()));
+        assert_eq!(mgr.depth(), 2);
+        assert_eq!(mgr.current().label, "2");
+        assert!(mgr.pop());
+        assert_eq!(mgr.current().label, "1");
+        assert_eq!(mgr.push(1), Ok(()));
+        assert_eq!(mgr.current().label, "2");
+        assert!(mgr.pop());
+        assert!(mgr.pop());
+        assert!(!mgr.pop());
+        assert_eq!(mgr.current().label, "root");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut mgr = MenuManager::new(ROOT);
+        for _ in 0..MAX_DEPTH {
+            assert_eq!(mgr.push(1), Ok(()));
+        }
+        assert_eq!(mgr.push(1), Err(MenuError::TooDeep));
+        assert_eq!(mgr.depth(), MAX_DEPTH);
+        assert_eq!(mgr.current().label, "4");
+        let labels: std::vec::Vec<_> = (0..=MAX_DEPTH + 1)
+            .map(|depth| mgr.at_depth(depth).map(|menu| menu.label))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                Some("root"),
+                Some("1"),
+                Some("2"),
+                Some("3"),
+                Some("4"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_index() {
+        let mut mgr = MenuManager::new(ROOT);
+        // A command, and off the end
+        assert_eq!(mgr.push(0), Err(MenuError::NotFound));
+        assert_eq!(mgr.push(2), Err(MenuError::NotFound));
+        assert_eq!(mgr.push(usize::MAX), Err(MenuError::NotFound));
+        assert_eq!(mgr.depth(), 0);
+        assert_eq!(mgr.at_depth(1).map(|menu| menu.label), None);
+        assert_eq!(
+            format!("{:?}", mgr),
+            "MenuManager { current: \"root\", path: [None, None, None, None] }"
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn old_names() {
+        let mut mgr = MenuManager::new(ROOT);
+        mgr.push_menu(1).unwrap();
+        assert_eq!(mgr.get_menu(None).label, "1");
+        assert_eq!(mgr.get_menu(Some(0)).label, "root");
+        assert_eq!(mgr.get_menu(Some(3)).label, "1");
+        mgr.pop_menu();
+        assert_eq!(mgr.depth(), 0);
     }
 }
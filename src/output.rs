@@ -0,0 +1,221 @@
+//! Everything the `Runner` writes goes through here.
+
+use core::fmt::Write;
+
+#[cfg(feature = "stats")]
+use super::stats::Stats;
+use super::BusyHookFn;
+
+/// The state kept about our output between writes.
+#[derive(Default)]
+pub(crate) struct OutputState {
+    /// The last thing written didn't end with a newline
+    pub(crate) mid_line: bool,
+    #[cfg(feature = "stats")]
+    pub(crate) stats: Stats,
+}
+
+impl OutputState {
+    fn wrote(&mut self, s: &str) {
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_written += s.len();
+        }
+        if !s.is_empty() {
+            self.mid_line = !s.ends_with('\n');
+        }
+    }
+}
+
+/// Wraps the interface, so all of the `Runner`'s own output takes the same
+/// path.
+///
+/// Writing never fails. If the interface gives an error, there's nowhere to
+/// report it, so it's ignored and the `Runner` carries on.
+pub(crate) struct Output<'w, I> {
+    pub(crate) interface: &'w mut I,
+    pub(crate) state: &'w mut OutputState,
+}
+
+impl<'w, I> Output<'w, I> {
+    /// Wrap an interface.
+    pub(crate) fn new(interface: &'w mut I, state: &'w mut OutputState) -> Self {
+        Output { interface, state }
+    }
+}
+
+impl<I> Output<'_, I>
+where
+    I: Write,
+{
+    /// Start a new line, unless we're already at the start of one.
+    pub(crate) fn ensure_newline(&mut self) -> core::fmt::Result {
+        if self.state.mid_line {
+            self.write_str("\n")?;
+        }
+        Ok(())
+    }
+
+    /// Write an escape sequence which doesn't move the cursor, leaving us
+    /// wherever we were on the line.
+    #[cfg(feature = "ansi")]
+    pub(crate) fn write_control(&mut self, s: &str) {
+        let mid_line = self.state.mid_line;
+        self.write_str(s).ok();
+        self.state.mid_line = mid_line;
+    }
+}
+
+impl<I> Write for Output<'_, I>
+where
+    I: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.state.wrote(s);
+        self.interface.write_str(s).ok();
+        Ok(())
+    }
+}
+
+/// Displays something in double quotes, escaped like a Rust string, without
+/// needing to format it into a buffer first.
+pub(crate) struct Quoted<D>(pub(crate) D);
+
+impl<D> core::fmt::Display for Quoted<D>
+where
+    D: core::fmt::Display,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        struct Escaper<'f, 'g>(&'f mut core::fmt::Formatter<'g>);
+
+        impl Write for Escaper<'_, '_> {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                for c in s.chars() {
+                    write!(self.0, "{}", c.escape_debug())?;
+                }
+                Ok(())
+            }
+        }
+
+        f.write_str("\"")?;
+        write!(Escaper(f), "{}", self.0)?;
+        f.write_str("\"")
+    }
+}
+
+/// Turns on the colour of the prompt, with [`Runner::set_colour`](crate::Runner::set_colour).
+pub(crate) const PROMPT_COLOUR: &str = "\x1b[1;32m";
+/// Turns on the colour of an error message.
+const ERROR_COLOUR: &str = "\x1b[31m";
+/// Turns on bold, for a heading in the help.
+const BOLD: &str = "\x1b[1m";
+/// Turns off every colour and style.
+pub(crate) const RESET: &str = "\x1b[0m";
+
+/// Writes a line saying what went wrong, in red if `colour`. The colour is
+/// turned off again before the newline.
+pub(crate) fn write_error(
+    out: &mut impl Write,
+    colour: bool,
+    message: core::fmt::Arguments,
+) -> core::fmt::Result {
+    if colour {
+        writeln!(out, "{}{}{}", ERROR_COLOUR, message, RESET)
+    } else {
+        writeln!(out, "{}", message)
+    }
+}
+
+/// Writes a heading in the help, like `SUMMARY:`, on a line of its own, in
+/// bold if `colour`.
+pub(crate) fn write_heading(
+    out: &mut impl Write,
+    colour: bool,
+    heading: &str,
+) -> core::fmt::Result {
+    if colour {
+        writeln!(out, "{}{}:{}", BOLD, heading, RESET)
+    } else {
+        writeln!(out, "{}:", heading)
+    }
+}
+
+/// Calls the busy hook after every line written, so it keeps being called
+/// while the `Runner` prints something long.
+pub(crate) struct KeepAlive<'k, W, T> {
+    out: W,
+    hook: Option<BusyHookFn<T>>,
+    context: &'k mut T,
+}
+
+impl<'k, W, T> KeepAlive<'k, W, T> {
+    pub(crate) fn new(out: W, hook: Option<BusyHookFn<T>>, context: &'k mut T) -> Self {
+        KeepAlive { out, hook, context }
+    }
+}
+
+impl<W, T> Write for KeepAlive<'_, W, T>
+where
+    W: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.out.write_str(s)?;
+        if let Some(hook) = self.hook {
+            for _ in s.matches('\n') {
+                hook(self.context);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lets callbacks write to the interface, while keeping track of where the
+/// cursor is.
+///
+/// The `Runner` uses this to make sure there's exactly one blank line before
+/// each prompt, whether or not your output ended with a newline.
+pub struct ConsoleWriter<'w, I> {
+    interface: &'w mut I,
+    state: &'w mut OutputState,
+}
+
+impl<'w, I> ConsoleWriter<'w, I> {
+    pub(crate) fn new(interface: &'w mut I, state: &'w mut OutputState) -> Self {
+        ConsoleWriter { interface, state }
+    }
+
+    /// Get at the interface, for anything other than writing text.
+    ///
+    /// Anything you write directly to the interface isn't tracked.
+    pub fn interface(&mut self) -> &mut I {
+        self.interface
+    }
+
+    /// Did the last thing written end with a newline?
+    pub fn at_line_start(&self) -> bool {
+        !self.state.mid_line
+    }
+}
+
+impl<I> ConsoleWriter<'_, I>
+where
+    I: Write,
+{
+    /// Start a new line, unless we're already at the start of one.
+    pub fn ensure_newline(&mut self) -> core::fmt::Result {
+        if self.state.mid_line {
+            self.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl<I> Write for ConsoleWriter<'_, I>
+where
+    I: Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.state.wrote(s);
+        self.interface.write_str(s)
+    }
+}
@@ -0,0 +1,1633 @@
+//! Turns command lines into arguments, and arguments into parameters.
+//!
+//! Nothing in here does any I/O, so you can use it on its own if you want to
+//! parse command lines the same way the `Runner` does.
+//!
+//! Words are separated by any amount of whitespace, as decided by
+//! [`is_separator`]. Whitespace at the start and end of a line is ignored,
+//! so `"  status  "` is the same as `"status"`, `"set-ip\t\t10.0.0.1"` is
+//! the same as `"set-ip 10.0.0.1"`, and a line of nothing but whitespace is
+//! empty. No word is ever empty.
+#![deny(missing_docs)]
+
+use super::{MenuError, Parameter};
+
+/// Does this character separate words? Any Unicode whitespace does.
+pub fn is_separator(c: char) -> bool {
+    c.is_whitespace()
+}
+
+/// Split a command line into the command and everything after it.
+///
+/// Leading whitespace is skipped. The rest of the line starts with the
+/// whitespace that followed the command, if there was any. The command is
+/// empty if the line is blank.
+pub fn split_command(line: &str) -> (&str, &str) {
+    let line = line.trim_start_matches(is_separator);
+    let cmd_end = line.find(is_separator).unwrap_or(line.len());
+    line.split_at(cmd_end)
+}
+
+/// Split some arguments into words, at any run of whitespace. Whitespace at
+/// either end gives no words.
+pub fn tokenize(args: &str) -> impl Iterator<Item = &str> {
+    args.split(is_separator).filter(|word| !word.is_empty())
+}
+
+/// Split off the last word of a line, which may be empty if the line ends
+/// with whitespace, from everything before it.
+pub fn split_last_word(line: &str) -> (&str, &str) {
+    let start = line.rfind(is_separator).map_or(0, |i| {
+        i + line[i..].chars().next().map_or(0, char::len_utf8)
+    });
+    line.split_at(start)
+}
+
+/// What sort of argument a token is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Token<'a> {
+    /// Anything which isn't an option, such as `value`, `-`, or a number
+    /// after one or two dashes, like `-42` or `--5.5`
+    Positional(&'a str),
+    /// `--name`
+    Named(&'a str),
+    /// `--name=value`
+    NamedValue(&'a str, &'a str),
+    /// `-n`, where `n` is any one character but a digit, or several short
+    /// options typed together, like `-vq`
+    Short(&'a str),
+    /// `-n=value`, or `-vn=value` for several
+    ShortValue(&'a str, &'a str),
+    /// `--` on its own, after which every argument is positional
+    EndOfOptions,
+}
+
+/// Work out what sort of argument a token is.
+pub fn classify(token: &str) -> Token<'_> {
+    if token == "--" {
+        return Token::EndOfOptions;
+    }
+    if let Some(tail) = token.strip_prefix("--") {
+        return match tail.split_once('=') {
+            _ if is_number(tail) => Token::Positional(token),
+            Some((name, value)) => Token::NamedValue(name, value),
+            None => Token::Named(tail),
+        };
+    }
+    let Some(tail) = token.strip_prefix('-') else {
+        return Token::Positional(token);
+    };
+    if tail.is_empty() || tail.starts_with(|c: char| c.is_ascii_digit()) || is_number(tail) {
+        return Token::Positional(token);
+    }
+    match tail.split_once('=') {
+        Some(("", _)) => Token::Positional(token),
+        Some((shorts, value)) => Token::ShortValue(shorts, value),
+        None => Token::Short(tail),
+    }
+}
+
+/// Is `text` some digits, with a decimal point among them if any, like `5`,
+/// `5.5` or `.5`?
+fn is_number(text: &str) -> bool {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    !(whole.is_empty() && fraction.is_empty()) && digits(whole) && digits(fraction)
+}
+
+/// Why some arguments don't suit a list of parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgError<'a> {
+    /// Fewer positional arguments than mandatory parameters. Holds what
+    /// `help` calls the first one which wasn't given.
+    InsufficientArguments(&'a str),
+    /// More positional arguments than positional parameters. Holds the
+    /// first one too many, and how many there can be.
+    TooManyArguments(&'a str, usize),
+    /// An option which isn't a `Named` or `NamedValue` parameter. Also holds
+    /// the parameters, so the options can be listed.
+    UnknownOption(&'a str, &'a [Parameter<'a>]),
+    /// A short option, typed with others like `-vq`, which isn't the short
+    /// form of a `Named` parameter, or of a `NamedValue` parameter at the
+    /// end. Also holds what was typed, and the parameters.
+    UnknownShortOption(char, &'a str, &'a [Parameter<'a>]),
+    /// An option which is the start of more than one option's name. Also
+    /// holds the parameters, so the candidates can be listed.
+    AmbiguousOption(&'a str, &'a [Parameter<'a>]),
+    /// More arguments than there was room to keep. Holds how many there was
+    /// room for.
+    OutOfSlots(usize),
+    /// A value for a `NamedValue` parameter which isn't one of its allowed
+    /// values. Holds the option's name, the value and the allowed values.
+    NotAllowed(&'a str, &'a str, &'a [&'a str]),
+    /// A `NamedValue` parameter given without a value, like `--level` on
+    /// its own. Holds the parameter.
+    MissingValue(&'a Parameter<'a>),
+    /// No parameter has the name which was asked for. Holds the name.
+    NotFound(&'a str),
+    /// An argument was given, but couldn't be parsed as the type asked for.
+    /// Holds the parameter's name and the argument.
+    BadValue(&'a str, &'a str),
+}
+
+impl core::fmt::Display for ArgError<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ArgError::InsufficientArguments(missing) => {
+                write!(f, "Missing argument <{}>", missing)
+            }
+            ArgError::TooManyArguments(arg, 0) => write!(
+                f,
+                "Unexpected argument {:?} (command takes no positional arguments)",
+                arg
+            ),
+            ArgError::TooManyArguments(arg, 1) => write!(
+                f,
+                "Unexpected argument {:?} (command takes at most 1 positional argument)",
+                arg
+            ),
+            ArgError::TooManyArguments(arg, most) => write!(
+                f,
+                "Unexpected argument {:?} (command takes at most {} positional arguments)",
+                arg, most
+            ),
+            ArgError::UnknownOption(arg, parameters) => {
+                write!(f, "Did not understand {:?}", arg)?;
+                write_options(f, parameters)
+            }
+            ArgError::UnknownShortOption(short, arg, parameters) => {
+                write!(f, "Did not understand -{} in {:?}", short, arg)?;
+                write_options(f, parameters)
+            }
+            ArgError::AmbiguousOption(arg, parameters) => {
+                write!(f, "{:?} could be", arg)?;
+                let (given, takes_value) = match classify(arg) {
+                    Token::NamedValue(name, _) => (name, true),
+                    Token::Named(name) | Token::Positional(name) => (name, false),
+                    // Never ambiguous
+                    Token::Short(_) | Token::ShortValue(..) | Token::EndOfOptions => ("", false),
+                };
+                let candidates = parameters.iter().filter(|p| {
+                    matches_option(p, takes_value).is_some_and(|n| n.starts_with(given))
+                });
+                for (i, param) in candidates.enumerate() {
+                    let separator = if i == 0 { " " } else { " or " };
+                    write!(f, "{}{}", separator, OptionForm(param))?;
+                }
+                Ok(())
+            }
+            ArgError::OutOfSlots(limit) => write!(f, "Too many arguments (limit {})", limit),
+            ArgError::NotAllowed(name, value, allowed) => {
+                write!(f, "Invalid value {:?} for --{}. Choices are: ", value, name)?;
+                for (i, choice) in allowed.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, choice)?;
+                }
+                Ok(())
+            }
+            ArgError::MissingValue(param) => write!(
+                f,
+                "Option --{} requires a value ({})",
+                param.name(),
+                OptionForm(param)
+            ),
+            ArgError::NotFound(name) => write!(f, "No parameter called {:?}", name),
+            ArgError::BadValue(name, value) => {
+                write!(f, "Invalid value {:?} for {}", value, name)
+            }
+        }
+    }
+}
+
+/// Lists the options in `parameters`, after what was wrong, if there are
+/// any.
+fn write_options(f: &mut core::fmt::Formatter<'_>, parameters: &[Parameter]) -> core::fmt::Result {
+    let mut options = parameters.iter().filter(|p| option_name(p).is_some());
+    if let Some(first) = options.next() {
+        write!(f, ". Options are: {}", OptionForm(first))?;
+        for param in options {
+            write!(f, ", {}", OptionForm(param))?;
+        }
+    }
+    Ok(())
+}
+
+/// Displays an option parameter as it's typed, like `--verbose` or
+/// `--level=INT`.
+struct OptionForm<'p>(&'p Parameter<'p>);
+
+impl core::fmt::Display for OptionForm<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            Parameter::Named { parameter_name, .. } => write!(f, "--{}", parameter_name),
+            Parameter::NamedValue {
+                parameter_name,
+                argument_name,
+                ..
+            } => write!(f, "--{}={}", parameter_name, argument_name),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The name of an option parameter, or `None` for a positional parameter.
+fn option_name<'p>(param: &Parameter<'p>) -> Option<&'p str> {
+    match param {
+        Parameter::Named { parameter_name, .. } | Parameter::NamedValue { parameter_name, .. } => {
+            Some(parameter_name)
+        }
+        _ => None,
+    }
+}
+
+/// The name of `param`, if it's a `NamedValue` parameter and `takes_value`,
+/// or a `Named` parameter and not.
+fn matches_option<'p>(param: &Parameter<'p>, takes_value: bool) -> Option<&'p str> {
+    match (param, takes_value) {
+        (Parameter::Named { parameter_name, .. }, false)
+        | (Parameter::NamedValue { parameter_name, .. }, true) => Some(parameter_name),
+        _ => None,
+    }
+}
+
+/// Which parameter an option typed on the command line means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionMatch<'p> {
+    /// The option's name was typed in full
+    Exact(&'p str),
+    /// The start of the option's name was typed, and no other option starts
+    /// that way
+    Prefix(&'p str),
+    /// The start of more than one option's name was typed
+    Ambiguous,
+    /// No such option
+    Unknown,
+}
+
+/// Work out which parameter an option means, given its name without the
+/// `--`.
+///
+/// `takes_value` says whether the option was given a value, as in
+/// `--name=value`, and so must be a `NamedValue` parameter rather than a
+/// `Named` one.
+pub fn resolve_option<'p>(
+    parameters: &'p [Parameter<'p>],
+    given_name: &str,
+    takes_value: bool,
+) -> OptionMatch<'p> {
+    let names = parameters
+        .iter()
+        .filter_map(|p| matches_option(p, takes_value));
+    if let Some(name) = names.clone().find(|name| *name == given_name) {
+        return OptionMatch::Exact(name);
+    }
+    let mut prefixed = names.filter(|name| !given_name.is_empty() && name.starts_with(given_name));
+    match (prefixed.next(), prefixed.next()) {
+        (Some(name), None) => OptionMatch::Prefix(name),
+        (Some(_), Some(_)) => OptionMatch::Ambiguous,
+        (None, _) => OptionMatch::Unknown,
+    }
+}
+
+/// Work out which parameter an option means, given the character after its
+/// `-`. Only an [`OptionMatch::Exact`] or [`OptionMatch::Unknown`] is given.
+///
+/// `takes_value` says whether it must be a `NamedValue` parameter, rather
+/// than a `Named` one, as for [`resolve_option`].
+pub fn resolve_short<'p>(
+    parameters: &'p [Parameter<'p>],
+    given: char,
+    takes_value: bool,
+) -> OptionMatch<'p> {
+    parameters
+        .iter()
+        .filter(|p| p.short_name() == Some(given))
+        .find_map(|p| matches_option(p, takes_value))
+        .map_or(OptionMatch::Unknown, OptionMatch::Exact)
+}
+
+/// An argument, read along with the parameters it's for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Argument<'p, 'a> {
+    /// Anything which isn't an option or an option's value
+    Positional(&'a str),
+    /// An option, as it was typed, with the parameter it means and its value
+    Option {
+        arg: &'a str,
+        resolved: OptionMatch<'p>,
+        value: Option<&'a str>,
+        /// Which short option it is, if it's one of several typed together
+        /// in `arg`, like `-vq`
+        bundled: Option<char>,
+    },
+}
+
+/// Read `arguments` along with `parameters`.
+///
+/// An option which takes a value can be given it after `=`, as in
+/// `--level=3`, or as the next word, as in `--level 3`, as long as that
+/// word isn't an option and the option isn't also a `Named` parameter. The
+/// word is then its value, rather than a positional argument. The same goes
+/// for the short form of an option, as in `-l=3` and `-l 3`.
+///
+/// Short options can be typed together, so `-vq` is `-v -q`. Only the last
+/// can take a value, as in `-vl=3` or `-vl 3`.
+///
+/// Every argument after a `--` on its own is positional, even if it starts
+/// with a `-`. The `--` itself isn't an argument.
+pub(crate) fn read_arguments<'p, 'a, A>(
+    parameters: &'p [Parameter<'p>],
+    arguments: A,
+    allow_prefixes: bool,
+) -> ReadArguments<'p, 'a, A>
+where
+    A: Iterator<Item = &'a str>,
+{
+    ReadArguments {
+        parameters,
+        arguments: arguments.peekable(),
+        allow_prefixes,
+        shorts_left: None,
+        options_ended: false,
+    }
+}
+
+/// The arguments read by [`read_arguments`].
+#[derive(Debug, Clone)]
+pub(crate) struct ReadArguments<'p, 'a, A>
+where
+    A: Iterator<Item = &'a str>,
+{
+    parameters: &'p [Parameter<'p>],
+    arguments: core::iter::Peekable<A>,
+    allow_prefixes: bool,
+    /// The short options typed together which are still to be read: where
+    /// they were typed, the ones left, their value, and whether there was
+    /// more than one to begin with
+    shorts_left: Option<(&'a str, &'a str, Option<&'a str>, bool)>,
+    options_ended: bool,
+}
+
+impl<'p, 'a, A> ReadArguments<'p, 'a, A>
+where
+    A: Iterator<Item = &'a str>,
+{
+    /// Does `resolved` name an option?
+    fn found(&self, resolved: OptionMatch) -> bool {
+        match resolved {
+            OptionMatch::Exact(_) => true,
+            OptionMatch::Prefix(_) => self.allow_prefixes,
+            _ => false,
+        }
+    }
+}
+
+impl<'p, 'a, A> Iterator for ReadArguments<'p, 'a, A>
+where
+    A: Iterator<Item = &'a str>,
+{
+    type Item = Argument<'p, 'a>;
+
+    fn next(&mut self) -> Option<Argument<'p, 'a>> {
+        let parameters = self.parameters;
+        let (arg, flag, valued, value, bundled) = loop {
+            if let Some((arg, shorts, value, many)) = self.shorts_left.take() {
+                let mut chars = shorts.chars();
+                let short = chars.next()?;
+                let bundled = if many { Some(short) } else { None };
+                if !chars.as_str().is_empty() {
+                    // Only the last can take a value
+                    self.shorts_left = Some((arg, chars.as_str(), value, many));
+                    return Some(Argument::Option {
+                        arg,
+                        resolved: resolve_short(parameters, short, false),
+                        value: None,
+                        bundled,
+                    });
+                }
+                let flag = match value {
+                    Some(_) => OptionMatch::Unknown,
+                    None => resolve_short(parameters, short, false),
+                };
+                let valued = resolve_short(parameters, short, true);
+                break (arg, flag, valued, value, bundled);
+            }
+            let arg = self.arguments.next()?;
+            if self.options_ended {
+                return Some(Argument::Positional(arg));
+            }
+            let many = |shorts: &str| shorts.chars().nth(1).is_some();
+            match classify(arg) {
+                Token::Positional(_) => return Some(Argument::Positional(arg)),
+                Token::EndOfOptions => self.options_ended = true,
+                Token::Named(name) => {
+                    let flag = resolve_option(parameters, name, false);
+                    let valued = resolve_option(parameters, name, true);
+                    break (arg, flag, valued, None, None);
+                }
+                Token::NamedValue(name, value) => {
+                    let valued = resolve_option(parameters, name, true);
+                    break (arg, OptionMatch::Unknown, valued, Some(value), None);
+                }
+                Token::Short(shorts) => self.shorts_left = Some((arg, shorts, None, many(shorts))),
+                Token::ShortValue(shorts, value) => {
+                    self.shorts_left = Some((arg, shorts, Some(value), many(shorts)));
+                }
+            }
+        };
+        let value_next = self
+            .arguments
+            .peek()
+            .is_some_and(|next| matches!(classify(next), Token::Positional(_)));
+        let (resolved, value) = match value {
+            Some(_) => (valued, value),
+            None if value_next && !self.found(flag) && self.found(valued) => {
+                (valued, self.arguments.next())
+            }
+            None => (flag, None),
+        };
+        Some(Argument::Option {
+            arg,
+            resolved,
+            value,
+            bundled,
+        })
+    }
+}
+
+/// The name of the option an argument means, and its value, if it's an
+/// option which is given in full or as an unambiguous prefix.
+fn named<'p, 'a>(argument: Argument<'p, 'a>) -> Option<(&'p str, Option<&'a str>)> {
+    match argument {
+        Argument::Option {
+            resolved: OptionMatch::Exact(name) | OptionMatch::Prefix(name),
+            value,
+            ..
+        } => Some((name, value)),
+        _ => None,
+    }
+}
+
+/// The argument, if it's a positional one.
+fn positional<'a>(argument: Argument<'_, 'a>) -> Option<&'a str> {
+    match argument {
+        Argument::Positional(arg) => Some(arg),
+        Argument::Option { .. } => None,
+    }
+}
+
+/// Some arguments which have been checked against a list of parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct ParsedArgs<'p, 'a> {
+    parameters: &'p [Parameter<'p>],
+    arguments: &'a [&'a str],
+}
+
+impl<'p, 'a> ParsedArgs<'p, 'a> {
+    /// The arguments, in the order they were given.
+    pub fn arguments(&self) -> &'a [&'a str] {
+        self.arguments
+    }
+
+    /// Find the argument given for a parameter. See [`find_argument`].
+    pub fn get(&self, parameter_name: &str) -> Result<Option<&'a str>, MenuError<'static>>
+    where
+        'p: 'a,
+    {
+        find_argument(self.parameters, self.arguments, parameter_name)
+    }
+
+    /// Find every argument given for a parameter. See [`find_arguments`].
+    pub fn get_all(&self, parameter_name: &str) -> Result<Values<'p, 'a, 'a>, MenuError<'static>> {
+        find_arguments(self.parameters, self.arguments, parameter_name)
+    }
+
+    /// How many times an argument was given for a parameter: for an option,
+    /// however many times it was given, and for a positional parameter, 0
+    /// or 1.
+    pub fn count(&self, parameter_name: &str) -> Result<usize, MenuError<'static>> {
+        Ok(self.get_all(parameter_name)?.count())
+    }
+
+    /// Was an argument given for a parameter, such as a `Named` option?
+    /// `false` if `parameter_name` isn't one of the parameters.
+    pub fn contains(&self, parameter_name: &str) -> bool {
+        self.count(parameter_name).is_ok_and(|count| count > 0)
+    }
+
+    /// Find the argument given for a parameter, and parse it as a `V`. See
+    /// [`argument_parse`](crate::argument_parse).
+    pub fn parse<V>(&self, parameter_name: &'a str) -> Result<Option<V>, ArgError<'a>>
+    where
+        V: FromArgument<'a>,
+        'p: 'a,
+    {
+        parse_found(self.get(parameter_name), parameter_name)
+    }
+
+    /// Find the argument given for a parameter, and parse it.
+    ///
+    /// Gives `None` if the argument wasn't given, or `parameter_name` isn't
+    /// one of the parameters.
+    pub fn get_as<V>(&self, parameter_name: &str) -> Option<Result<V, V::Err>>
+    where
+        V: core::str::FromStr,
+    {
+        self.get(parameter_name).ok().flatten().map(str::parse)
+    }
+
+    /// The positional argument at `index`, counting from zero and skipping
+    /// any options and their values.
+    pub fn positional(&self, index: usize) -> Option<&'a str> {
+        read_arguments(self.parameters, self.arguments.iter().copied(), true)
+            .filter_map(positional)
+            .nth(index)
+    }
+}
+
+/// Parses arguments against a list of parameters, exactly as the `Runner`
+/// does, but without needing a `Runner`, a menu or any I/O.
+///
+/// ```rust
+/// # use menu::{parser::ArgumentParser, Parameter};
+/// const PARAMETERS: &[Parameter] = &[
+///     Parameter::mandatory("channel"),
+///     Parameter::named_value("level", "INT"),
+/// ];
+/// let mut slots = [""; 4];
+/// let args = ArgumentParser::new(PARAMETERS)
+///     .parse("3 --level=10", &mut slots)
+///     .unwrap();
+/// assert_eq!(args.get("channel"), Ok(Some("3")));
+/// assert_eq!(args.get_as::<u32>("level"), Some(Ok(10)));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ArgumentParser<'p> {
+    parameters: &'p [Parameter<'p>],
+    allow_prefixes: bool,
+}
+
+impl<'p> ArgumentParser<'p> {
+    /// Make a parser for the given parameters.
+    pub const fn new(parameters: &'p [Parameter<'p>]) -> Self {
+        ArgumentParser {
+            parameters,
+            allow_prefixes: false,
+        }
+    }
+
+    /// Accept the start of an option's name, such as `--verb` for
+    /// `--verbose`, as long as no other option starts the same way.
+    pub const fn allow_prefixes(mut self, allow_prefixes: bool) -> Self {
+        self.allow_prefixes = allow_prefixes;
+        self
+    }
+
+    /// Parse whatever followed the command on a command line.
+    ///
+    /// The arguments are kept in `slots`. If they don't all fit, that's an
+    /// [`ArgError::OutOfSlots`].
+    pub fn parse<'s, 'a: 's>(
+        &self,
+        line_after_command: &'a str,
+        slots: &'s mut [&'a str],
+    ) -> Result<ParsedArgs<'p, 's>, ArgError<'a>>
+    where
+        'p: 'a,
+    {
+        let mut used = 0;
+        for arg in tokenize(line_after_command) {
+            let Some(slot) = slots.get_mut(used) else {
+                return Err(ArgError::OutOfSlots(slots.len()));
+            };
+            *slot = arg;
+            used += 1;
+        }
+        self.parse_arguments(&slots[..used])
+    }
+
+    /// Check some arguments, already split into words, against the
+    /// parameters.
+    ///
+    /// Every option must be one of the `Named` or `NamedValue` parameters,
+    /// with a `NamedValue` parameter's value after `=` or as the next word,
+    /// and there must be enough positional arguments for the `Mandatory`
+    /// parameters but no more than there are `Mandatory` and `Optional`
+    /// parameters.
+    pub fn parse_arguments<'s, 'a: 's>(
+        &self,
+        arguments: &'s [&'a str],
+    ) -> Result<ParsedArgs<'p, 's>, ArgError<'a>>
+    where
+        'p: 'a,
+    {
+        let parameters = self.parameters;
+        let positional_parameter_count = parameters
+            .iter()
+            .filter(|p| matches!(p, Parameter::Mandatory { .. } | Parameter::Optional { .. }))
+            .count();
+        let mut positional_arguments = 0;
+        let mut unexpected = None;
+        let arguments_read =
+            read_arguments(parameters, arguments.iter().copied(), self.allow_prefixes);
+        for argument in arguments_read {
+            let (arg, resolved, value, bundled) = match argument {
+                Argument::Positional(arg) => {
+                    positional_arguments += 1;
+                    if positional_arguments > positional_parameter_count {
+                        unexpected = unexpected.or(Some(arg));
+                    }
+                    continue;
+                }
+                Argument::Option {
+                    arg,
+                    resolved,
+                    value,
+                    bundled,
+                } => (arg, resolved, value, bundled),
+            };
+            match (resolved, bundled) {
+                (OptionMatch::Exact(_), _) => {}
+                (OptionMatch::Prefix(_), _) if self.allow_prefixes => {}
+                (OptionMatch::Ambiguous, _) if self.allow_prefixes => {
+                    return Err(ArgError::AmbiguousOption(arg, parameters));
+                }
+                (_, bundled) => {
+                    return Err(match (self.needs_value(arg, bundled), bundled) {
+                        (Some(param), _) => ArgError::MissingValue(param),
+                        (None, Some(short)) => ArgError::UnknownShortOption(short, arg, parameters),
+                        (None, None) => ArgError::UnknownOption(arg, parameters),
+                    })
+                }
+            }
+            if let (OptionMatch::Exact(name) | OptionMatch::Prefix(name), Some(value)) =
+                (resolved, value)
+            {
+                let allowed = parameters
+                    .iter()
+                    .find(|p| matches!(p, Parameter::NamedValue { .. }) && p.name() == name)
+                    .and_then(Parameter::allowed);
+                if let Some(allowed) = allowed.filter(|allowed| !allowed.contains(&value)) {
+                    return Err(ArgError::NotAllowed(name, value, allowed));
+                }
+            }
+        }
+        let missing = parameters
+            .iter()
+            .filter(|p| matches!(p, Parameter::Mandatory { .. }))
+            .nth(positional_arguments);
+        if let Some(missing) = missing {
+            Err(ArgError::InsufficientArguments(missing.display_name()))
+        } else if let Some(arg) = unexpected {
+            Err(ArgError::TooManyArguments(arg, positional_parameter_count))
+        } else {
+            Ok(ParsedArgs {
+                parameters,
+                arguments,
+            })
+        }
+    }
+
+    /// The `NamedValue` parameter `arg` gives without a value, if it's one
+    /// on its own, like `--level`, or at the end of some short options,
+    /// like `-vl`.
+    fn needs_value(&self, arg: &str, bundled: Option<char>) -> Option<&'p Parameter<'p>> {
+        let resolved = match classify(arg) {
+            Token::Named(name) => resolve_option(self.parameters, name, true),
+            Token::Short(shorts) => {
+                let last = shorts.chars().next_back()?;
+                if bundled.is_some_and(|short| short != last) {
+                    return None;
+                }
+                resolve_short(self.parameters, last, true)
+            }
+            _ => return None,
+        };
+        let name = match resolved {
+            OptionMatch::Exact(name) => name,
+            OptionMatch::Prefix(name) if self.allow_prefixes => name,
+            _ => return None,
+        };
+        self.parameters
+            .iter()
+            .find(|p| matches!(p, Parameter::NamedValue { .. }) && p.name() == name)
+    }
+}
+
+/// Check some arguments against a list of parameters, with an
+/// [`ArgumentParser`] which doesn't allow prefixes.
+pub fn match_parameters<'p: 'a, 's, 'a: 's>(
+    parameters: &'p [Parameter<'p>],
+    arguments: &'s [&'a str],
+) -> Result<ParsedArgs<'p, 's>, ArgError<'a>> {
+    ArgumentParser::new(parameters).parse_arguments(arguments)
+}
+
+/// A type an argument can be parsed as, with
+/// [`argument_parse`](crate::argument_parse).
+///
+/// It's implemented for the integer types, which take anything
+/// [`parse_int`] does, `f32`, `&str`, and `bool`, which is given as `true`,
+/// `false`, `1`, `0`, `on` or `off`.
+pub trait FromArgument<'a>: Sized {
+    /// Parse `arg`, or give `None` if it isn't one of these.
+    fn from_argument(arg: &'a str) -> Option<Self>;
+}
+
+impl<'a> FromArgument<'a> for &'a str {
+    fn from_argument(arg: &'a str) -> Option<Self> {
+        Some(arg)
+    }
+}
+
+impl FromArgument<'_> for bool {
+    fn from_argument(arg: &str) -> Option<Self> {
+        match arg {
+            "true" | "1" | "on" => Some(true),
+            "false" | "0" | "off" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FromArgument<'_> for f32 {
+    fn from_argument(arg: &str) -> Option<Self> {
+        arg.parse().ok()
+    }
+}
+
+/// Parses what [`find_argument`] found for the parameter called `name`.
+pub(crate) fn parse_found<'a, V>(
+    found: Result<Option<&'a str>, MenuError<'static>>,
+    name: &'a str,
+) -> Result<Option<V>, ArgError<'a>>
+where
+    V: FromArgument<'a>,
+{
+    let Ok(arg) = found else {
+        return Err(ArgError::NotFound(name));
+    };
+    arg.map(|arg| V::from_argument(arg).ok_or(ArgError::BadValue(name, arg)))
+        .transpose()
+}
+
+/// Implements [`FromArgument`] with [`parse_int`].
+macro_rules! int_argument {
+    ($($t:ty),*) => {
+        $(
+            impl FromArgument<'_> for $t {
+                fn from_argument(arg: &str) -> Option<Self> {
+                    parse_int(arg).ok()
+                }
+            }
+        )*
+    };
+}
+
+int_argument!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+/// Why [`parse_int`] couldn't parse a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseIntError {
+    /// There were no digits
+    Empty,
+    /// Something which isn't a digit, or a suffix, was found
+    InvalidDigit,
+    /// The number doesn't fit in the type, or is negative and the type is
+    /// unsigned
+    OutOfRange,
+}
+
+impl core::fmt::Display for ParseIntError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseIntError::Empty => write!(f, "No number given"),
+            ParseIntError::InvalidDigit => write!(f, "Not a number"),
+            ParseIntError::OutOfRange => write!(f, "Number out of range"),
+        }
+    }
+}
+
+/// Parses an integer as it might be typed for an address or a size.
+///
+/// * `0x`, `0o` or `0b` before it means it's in hex, octal or binary.
+/// * Underscores between the digits are ignored, as in `0x2000_0000`.
+/// * `k`, `M` or `G` after it multiply it by 2<sup>10</sup>, 2<sup>20</sup>
+///   or 2<sup>30</sup>, as do `ki`, `Mi` and `Gi`.
+/// * A `-` before it makes it negative, for a signed type. A `+` before it
+///   is allowed too, as `str::parse` allows it.
+///
+/// ```rust
+/// # use menu::parser::{parse_int, ParseIntError};
+/// assert_eq!(parse_int::<u32>("0x2000_0000"), Ok(0x2000_0000));
+/// assert_eq!(parse_int::<u8>("0b1010"), Ok(10));
+/// assert_eq!(parse_int::<u32>("64k"), Ok(65536));
+/// assert_eq!(parse_int::<i32>("-1Mi"), Ok(-1048576));
+/// assert_eq!(parse_int::<u8>("300"), Err(ParseIntError::OutOfRange));
+/// ```
+pub fn parse_int<V>(s: &str) -> Result<V, ParseIntError>
+where
+    V: TryFrom<u128> + TryFrom<i128>,
+{
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (radix, s) = match s.as_bytes() {
+        [b'0', b'x' | b'X', ..] => (16, &s[2..]),
+        [b'0', b'o' | b'O', ..] => (8, &s[2..]),
+        [b'0', b'b' | b'B', ..] => (2, &s[2..]),
+        _ => (10, s),
+    };
+    let (s, shift) = match s
+        .char_indices()
+        .find(|(_, c)| c.to_digit(radix).is_none() && *c != '_')
+    {
+        Some((at, _)) => match &s[at..] {
+            "k" | "ki" => (&s[..at], 10),
+            "M" | "Mi" => (&s[..at], 20),
+            "G" | "Gi" => (&s[..at], 30),
+            _ => return Err(ParseIntError::InvalidDigit),
+        },
+        None => (s, 0),
+    };
+    let mut magnitude: u128 = 0;
+    let mut digits = 0;
+    for digit in s.chars().filter_map(|c| c.to_digit(radix)) {
+        magnitude = magnitude
+            .checked_mul(u128::from(radix))
+            .and_then(|m| m.checked_add(u128::from(digit)))
+            .ok_or(ParseIntError::OutOfRange)?;
+        digits += 1;
+    }
+    if digits == 0 {
+        return Err(ParseIntError::Empty);
+    }
+    let magnitude = magnitude
+        .checked_mul(1 << shift)
+        .ok_or(ParseIntError::OutOfRange)?;
+    let value = match negative {
+        true => 0i128
+            .checked_sub_unsigned(magnitude)
+            .and_then(|value| V::try_from(value).ok()),
+        false => V::try_from(magnitude).ok(),
+    };
+    value.ok_or(ParseIntError::OutOfRange)
+}
+
+/// Looks for the named parameter in the parameter list, then finds the
+/// corresponding argument.
+///
+/// * Returns `Ok(None)` if `parameter_name` gives a parameter and that
+///   argument was not given, unless the parameter has a default (see
+///   [`Parameter::default`]), when that's returned instead.
+/// * Returns `Ok(arg)` if the argument was found. `arg` is the empty string
+///   if the parameter was `Parameter::Named` (and hence doesn't take a value).
+/// * Returns `Err(MenuError::NotFound)` if `parameter_name` was not in
+///   `parameters`.
+///
+/// An option given as an unambiguous prefix of its name, such as `--verb`
+/// for `--verbose`, counts as that option.
+///
+/// If an option was given more than once, this is the first. See
+/// [`find_arguments`] for them all.
+pub fn find_argument<'a>(
+    parameters: &[Parameter<'a>],
+    arguments: &[&'a str],
+    name_to_find: &str,
+) -> Result<Option<&'a str>, MenuError<'static>> {
+    Ok(find_arguments(parameters, arguments, name_to_find)?
+        .next()
+        .or_else(|| {
+            parameters
+                .iter()
+                .rev()
+                .find(|parameter| parameter.name() == name_to_find)
+                .and_then(Parameter::default_value)
+        }))
+}
+
+/// Looks for the named parameter in the parameter list, then finds every
+/// argument given for it, in the order they were given.
+///
+/// An option can be given any number of times, and is found each time,
+/// including in a bundle of short options, so `-vvv` gives `-v` three
+/// times. A positional parameter is given at most once. Gives
+/// `Err(MenuError::NotFound)` if `parameter_name` was not in `parameters`.
+///
+/// ```rust
+/// # use menu::{parser::find_arguments, Parameter};
+/// let parameters = [Parameter::named_value("include", "PATH")];
+/// let arguments = ["--include=a", "--include", "b"];
+/// let mut paths = find_arguments(&parameters, &arguments, "include").unwrap();
+/// assert_eq!(paths.next(), Some("a"));
+/// assert_eq!(paths.next(), Some("b"));
+/// assert_eq!(paths.next(), None);
+/// ```
+pub fn find_arguments<'p, 's, 'a>(
+    parameters: &'p [Parameter<'p>],
+    arguments: &'s [&'a str],
+    name_to_find: &str,
+) -> Result<Values<'p, 's, 'a>, MenuError<'static>> {
+    // Find `name_to_find` in the parameter list, and for a positional
+    // parameter, which of the positional arguments is its
+    let mut found = None;
+    let mut mandatory_count = 0;
+    let mut optional_count = 0;
+    for param in parameters.iter() {
+        let index = match param {
+            Parameter::Mandatory { .. } => {
+                mandatory_count += 1;
+                mandatory_count
+            }
+            Parameter::Optional { .. } => {
+                optional_count += 1;
+                optional_count
+            }
+            Parameter::Named { .. } | Parameter::NamedValue { .. } => 0,
+        };
+        if param.name() == name_to_find {
+            found = Some((param, index));
+        }
+    }
+    let (parameter, position) = match found {
+        Some((param @ Parameter::Optional { .. }, optional_idx)) => {
+            (param, mandatory_count + optional_idx)
+        }
+        Some(found) => found,
+        None => return Err(MenuError::NotFound),
+    };
+    Ok(Values {
+        read: read_arguments(parameters, arguments.iter().copied(), true),
+        parameter,
+        position,
+        positionals: 0,
+    })
+}
+
+/// Every argument given for one parameter, in the order they were given.
+/// See [`find_arguments`].
+#[derive(Debug, Clone)]
+pub struct Values<'p, 's, 'a> {
+    read: ReadArguments<'p, 'a, core::iter::Copied<core::slice::Iter<'s, &'a str>>>,
+    parameter: &'p Parameter<'p>,
+    /// Which positional argument a positional parameter takes, counting
+    /// from 1
+    position: usize,
+    /// How many positional arguments have been read
+    positionals: usize,
+}
+
+impl<'a> Iterator for Values<'_, '_, 'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        for argument in self.read.by_ref() {
+            let value = match (self.parameter, argument) {
+                (
+                    Parameter::Mandatory { .. } | Parameter::Optional { .. },
+                    Argument::Positional(arg),
+                ) => {
+                    self.positionals += 1;
+                    (self.positionals == self.position).then_some(arg)
+                }
+                (Parameter::Named { parameter_name, .. }, argument) => named(argument)
+                    .filter(|(name, value)| name == parameter_name && value.is_none())
+                    .map(|_| ""),
+                (Parameter::NamedValue { parameter_name, .. }, argument) => named(argument)
+                    .and_then(|(name, value)| value.filter(|_| name == *parameter_name)),
+                _ => None,
+            };
+            if value.is_some() {
+                return value;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{format, string::String, vec::Vec};
+
+    /// A small, repeatable source of randomness, so we don't need any
+    /// dependencies.
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0 as usize
+        }
+
+        fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+            choices[self.next() % choices.len()]
+        }
+    }
+
+    const PIECES: &[&str] = &[
+        "a", "b", "-", "--", "=", "é", " ", "  ", "\t", "\u{2000}", "--x", "--y=", "=1",
+    ];
+
+    const PARAMETERS: &[Parameter] = &[
+        Parameter::mandatory("a"),
+        Parameter::optional("b"),
+        Parameter::optional("c"),
+        Parameter::named("x"),
+        Parameter::named("y"),
+        Parameter::named_value("y", "Y"),
+        Parameter::named_value("é", "X"),
+    ];
+
+    fn random_line(rng: &mut Rng) -> String {
+        let len = rng.next() % 10;
+        (0..len).map(|_| rng.pick(PIECES)).collect()
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(split_command("  foo a b"), ("foo", " a b"));
+        assert_eq!(split_command("\u{2000}foo"), ("foo", ""));
+        assert_eq!(split_command(" "), ("", ""));
+        assert_eq!(split_command("\t\tfoo\t"), ("foo", "\t"));
+        assert_eq!(split_last_word("a b"), ("a ", "b"));
+        assert_eq!(split_last_word("a\u{3000}"), ("a\u{3000}", ""));
+        assert_eq!(split_last_word("ab"), ("", "ab"));
+    }
+
+    #[test]
+    fn classify_examples() {
+        assert_eq!(classify("a"), Token::Positional("a"));
+        assert_eq!(classify("-a"), Token::Short("a"));
+        assert_eq!(classify("-é=1"), Token::ShortValue("é", "1"));
+        assert_eq!(classify("-4"), Token::Positional("-4"));
+        assert_eq!(classify("-4x"), Token::Positional("-4x"));
+        assert_eq!(classify("-.5"), Token::Positional("-.5"));
+        assert_eq!(classify("--5"), Token::Positional("--5"));
+        assert_eq!(classify("--5.5"), Token::Positional("--5.5"));
+        assert_eq!(classify("--5x"), Token::Named("5x"));
+        assert_eq!(classify("--."), Token::Named("."));
+        assert_eq!(classify("--5.5.5"), Token::Named("5.5.5"));
+        assert_eq!(classify("-"), Token::Positional("-"));
+        assert_eq!(classify("-=1"), Token::Positional("-=1"));
+        assert_eq!(classify("-ab"), Token::Short("ab"));
+        assert_eq!(classify("-ab=c=d"), Token::ShortValue("ab", "c=d"));
+        assert_eq!(classify("--a"), Token::Named("a"));
+        assert_eq!(classify("--"), Token::EndOfOptions);
+        assert_eq!(classify("--="), Token::NamedValue("", ""));
+        assert_eq!(classify("--a=b=c"), Token::NamedValue("a", "b=c"));
+    }
+
+    #[test]
+    fn parse_ints() {
+        assert_eq!(parse_int::<u32>("0x2000_0000"), Ok(0x2000_0000));
+        assert_eq!(parse_int::<u32>("0XfF"), Ok(255));
+        assert_eq!(parse_int::<u8>("0o17"), Ok(15));
+        assert_eq!(parse_int::<u8>("0b1010"), Ok(10));
+        assert_eq!(parse_int::<u32>("1_000"), Ok(1000));
+        assert_eq!(parse_int::<u32>("64k"), Ok(64 * 1024));
+        assert_eq!(parse_int::<u32>("64ki"), Ok(64 * 1024));
+        assert_eq!(parse_int::<u32>("1M"), Ok(1 << 20));
+        assert_eq!(parse_int::<u32>("2Mi"), Ok(2 << 20));
+        assert_eq!(parse_int::<u64>("4G"), Ok(4 << 30));
+        assert_eq!(parse_int::<u32>("0x10k"), Ok(16 * 1024));
+        assert_eq!(parse_int::<i32>("-0x80"), Ok(-128));
+        assert_eq!(parse_int::<i8>("-128"), Ok(i8::MIN));
+        assert_eq!(parse_int::<u8>("+5"), Ok(5));
+        assert_eq!(parse_int::<i32>("+0x10"), Ok(16));
+        assert_eq!(
+            parse_int::<i128>(&std::format!("{}", i128::MIN)),
+            Ok(i128::MIN)
+        );
+        assert_eq!(
+            parse_int::<u128>(&std::format!("{}", u128::MAX)),
+            Ok(u128::MAX)
+        );
+        assert_eq!(parse_int::<u8>("-0"), Ok(0));
+        // Out of range
+        assert_eq!(parse_int::<u8>("256"), Err(ParseIntError::OutOfRange));
+        assert_eq!(parse_int::<i8>("-129"), Err(ParseIntError::OutOfRange));
+        assert_eq!(parse_int::<u32>("-1"), Err(ParseIntError::OutOfRange));
+        assert_eq!(parse_int::<u32>("4G"), Err(ParseIntError::OutOfRange));
+        assert_eq!(
+            parse_int::<u128>("0x1_0000_0000_0000_0000_0000_0000_0000_0000"),
+            Err(ParseIntError::OutOfRange)
+        );
+        // Not numbers
+        assert_eq!(parse_int::<u32>(""), Err(ParseIntError::Empty));
+        assert_eq!(parse_int::<u32>("0x"), Err(ParseIntError::Empty));
+        assert_eq!(parse_int::<u32>("-k"), Err(ParseIntError::Empty));
+        assert_eq!(parse_int::<u32>("12x"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<u32>("0b102"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<u32>("1kk"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<u32>("1K"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<i32>("--1"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<i32>("+-1"), Err(ParseIntError::InvalidDigit));
+        assert_eq!(parse_int::<i32>("-+1"), Err(ParseIntError::InvalidDigit));
+        // And as an argument
+        assert_eq!(u16::from_argument("0x1_0"), Some(16));
+        assert_eq!(i8::from_argument("-0b1"), Some(-1));
+    }
+
+    #[test]
+    fn match_examples() {
+        let args = ["1", "--x", "--y=2", "3"];
+        let parsed = match_parameters(PARAMETERS, &args).unwrap();
+        assert_eq!(parsed.get("a"), Ok(Some("1")));
+        assert_eq!(parsed.get("b"), Ok(Some("3")));
+        assert_eq!(parsed.get("c"), Ok(None));
+        assert_eq!(parsed.get("x"), Ok(Some("")));
+        assert_eq!(parsed.get("y"), Ok(Some("2")));
+        assert_eq!(parsed.get("é"), Ok(None));
+        assert_eq!(parsed.get("z"), Err(MenuError::NotFound));
+        assert_eq!(
+            match_parameters(PARAMETERS, &[]).unwrap_err(),
+            ArgError::InsufficientArguments("a")
+        );
+        assert_eq!(
+            match_parameters(PARAMETERS, &["1", "2", "3", "4"]).unwrap_err(),
+            ArgError::TooManyArguments("4", 3)
+        );
+        assert_eq!(
+            match_parameters(PARAMETERS, &["1", "--c"]).unwrap_err(),
+            ArgError::UnknownOption("--c", PARAMETERS)
+        );
+        assert_eq!(
+            format!("{}", ArgError::UnknownOption("--c", PARAMETERS)),
+            "Did not understand \"--c\". Options are: --x, --y, --y=Y, --é=X"
+        );
+        assert_eq!(
+            format!("{}", ArgError::UnknownOption("--c", &[])),
+            "Did not understand \"--c\""
+        );
+    }
+
+    /// Parse a line with [`ArgumentParser`], and check what we find for one
+    /// parameter.
+    #[track_caller]
+    fn assert_parse(
+        parameters: &[Parameter],
+        line: &str,
+        name: &str,
+        expected: Result<Result<Option<&str>, MenuError<'static>>, ArgError>,
+    ) {
+        let mut slots = [""; 8];
+        let found = ArgumentParser::new(parameters)
+            .parse(line, &mut slots)
+            .map(|parsed| parsed.get(name));
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn standalone_mandatory_and_optional() {
+        let parameters = [
+            Parameter::mandatory("foo"),
+            Parameter::mandatory("bar"),
+            Parameter::optional("baz"),
+        ];
+        assert_parse(&parameters, " a b c", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "a\tb c", "bar", Ok(Ok(Some("b"))));
+        assert_parse(&parameters, "a b c ", "baz", Ok(Ok(Some("c"))));
+        assert_parse(&parameters, "a b c", "quux", Ok(Err(MenuError::NotFound)));
+        assert_parse(&parameters, "a b", "baz", Ok(Ok(None)));
+        assert_parse(
+            &parameters,
+            "a",
+            "foo",
+            Err(ArgError::InsufficientArguments("bar")),
+        );
+        assert_parse(
+            &parameters,
+            "a b c d e",
+            "foo",
+            Err(ArgError::TooManyArguments("d", 3)),
+        );
+        assert_eq!(
+            format!("{}", ArgError::InsufficientArguments("bar")),
+            "Missing argument <bar>"
+        );
+        assert_eq!(
+            format!("{}", ArgError::TooManyArguments("fastt", 2)),
+            "Unexpected argument \"fastt\" (command takes at most 2 positional arguments)"
+        );
+        assert_eq!(
+            format!("{}", ArgError::TooManyArguments("fastt", 1)),
+            "Unexpected argument \"fastt\" (command takes at most 1 positional argument)"
+        );
+        assert_eq!(
+            format!("{}", ArgError::TooManyArguments("fastt", 0)),
+            "Unexpected argument \"fastt\" (command takes no positional arguments)"
+        );
+        // What `help` calls it
+        let parameters = [Parameter::mandatory("ip").display("ip-address")];
+        assert_parse(
+            &parameters,
+            "",
+            "ip",
+            Err(ArgError::InsufficientArguments("ip-address")),
+        );
+    }
+
+    #[test]
+    fn standalone_named() {
+        let parameters = [
+            Parameter::mandatory("foo"),
+            Parameter::named("bar"),
+            Parameter::named_value("baz", "TEST"),
+        ];
+        assert_parse(&parameters, "--bar a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "a --bar", "bar", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "a", "bar", Ok(Ok(None)));
+        assert_parse(&parameters, "a --baz=", "baz", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "a --baz=1", "baz", Ok(Ok(Some("1"))));
+        assert_parse(&parameters, "a --baz=1=2", "baz", Ok(Ok(Some("1=2"))));
+        assert_parse(
+            &parameters,
+            "a --baz",
+            "baz",
+            Err(ArgError::MissingValue(&parameters[2])),
+        );
+        assert_eq!(
+            format!("{}", ArgError::MissingValue(&parameters[2])),
+            "Option --baz requires a value (--baz=TEST)"
+        );
+        assert_parse(
+            &parameters,
+            "a --bar=1",
+            "bar",
+            Err(ArgError::UnknownOption("--bar=1", &parameters)),
+        );
+    }
+
+    #[test]
+    fn standalone_spaced_values() {
+        let parameters = [
+            Parameter::mandatory("foo"),
+            Parameter::named("bar"),
+            Parameter::named_value("baz", "TEST"),
+        ];
+        assert_parse(&parameters, "--baz 1 a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "--baz 1 a", "baz", Ok(Ok(Some("1"))));
+        assert_parse(&parameters, "a --baz -1", "baz", Ok(Ok(Some("-1"))));
+        assert_parse(&parameters, "--bar a", "bar", Ok(Ok(Some(""))));
+        assert_parse(
+            &parameters,
+            "--baz 1 a b",
+            "foo",
+            Err(ArgError::TooManyArguments("b", 1)),
+        );
+        // Another option isn't a value
+        assert_parse(
+            &parameters,
+            "a --baz --bar",
+            "baz",
+            Err(ArgError::MissingValue(&parameters[2])),
+        );
+        // Nor is anything after an option which can be given on its own
+        let args = ["--y", "1", "2"];
+        let parsed = match_parameters(PARAMETERS, &args).unwrap();
+        assert_eq!(parsed.positional(0), Some("1"));
+        assert_eq!(parsed.positional(1), Some("2"));
+        let args = ["--é", "1", "2"];
+        let parsed = match_parameters(PARAMETERS, &args).unwrap();
+        assert_eq!(parsed.get("é"), Ok(Some("1")));
+        assert_eq!(parsed.get("a"), Ok(Some("2")));
+        assert_eq!(parsed.positional(0), Some("2"));
+    }
+
+    #[test]
+    fn standalone_quotes_and_dashes() {
+        let parameters = [Parameter::mandatory("foo"), Parameter::optional("bar")];
+        // Quotes aren't special, so a quoted argument with a space in it is
+        // two arguments
+        assert_parse(&parameters, "\"a b\"", "foo", Ok(Ok(Some("\"a"))));
+        assert_parse(&parameters, "\"a b\"", "bar", Ok(Ok(Some("b\""))));
+        // A bare `--` ends the options, and isn't an argument itself
+        assert_parse(&parameters, "-- --a -b", "foo", Ok(Ok(Some("--a"))));
+        assert_parse(&parameters, "-- --a -b", "bar", Ok(Ok(Some("-b"))));
+        assert_parse(&parameters, "a -- --", "bar", Ok(Ok(Some("--"))));
+        assert_parse(&parameters, "a --", "bar", Ok(Ok(None)));
+        assert_parse(
+            &parameters,
+            "a -- b c",
+            "foo",
+            Err(ArgError::TooManyArguments("c", 2)),
+        );
+        // A single dash is just an argument, as is a negative number
+        assert_parse(&parameters, "- -42", "bar", Ok(Ok(Some("-42"))));
+        assert_parse(&parameters, "-5 --5.5", "foo", Ok(Ok(Some("-5"))));
+        assert_parse(&parameters, "-5 --5.5", "bar", Ok(Ok(Some("--5.5"))));
+        assert_parse(
+            &parameters,
+            "--5x",
+            "foo",
+            Err(ArgError::UnknownOption("--5x", &parameters)),
+        );
+        assert_parse(
+            &parameters,
+            "- -b",
+            "bar",
+            Err(ArgError::UnknownOption("-b", &parameters)),
+        );
+    }
+
+    #[test]
+    fn standalone_short() {
+        let parameters = [
+            Parameter::optional("foo"),
+            Parameter::named("verbose").short('v'),
+            Parameter::named_value("level", "INT").short('l'),
+        ];
+        assert_parse(&parameters, "-v a", "verbose", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "-v a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "-l=3", "level", Ok(Ok(Some("3"))));
+        assert_parse(&parameters, "-l 3 a", "level", Ok(Ok(Some("3"))));
+        assert_parse(&parameters, "-l 3 a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "-l -3", "level", Ok(Ok(Some("-3"))));
+        assert_parse(
+            &parameters,
+            "-v=1",
+            "verbose",
+            Err(ArgError::UnknownOption("-v=1", &parameters)),
+        );
+        assert_parse(
+            &parameters,
+            "-q",
+            "foo",
+            Err(ArgError::UnknownOption("-q", &parameters)),
+        );
+        assert_eq!(
+            resolve_short(&parameters, 'l', true),
+            OptionMatch::Exact("level")
+        );
+        assert_eq!(resolve_short(&parameters, 'l', false), OptionMatch::Unknown);
+    }
+
+    #[test]
+    fn standalone_bundled() {
+        let parameters = [
+            Parameter::optional("foo"),
+            Parameter::named("verbose").short('v'),
+            Parameter::named("quiet").short('q'),
+            Parameter::named_value("level", "INT").short('l'),
+        ];
+        assert_parse(&parameters, "-vq a", "verbose", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "-vq a", "quiet", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "-vq a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(&parameters, "-vl=3", "level", Ok(Ok(Some("3"))));
+        assert_parse(&parameters, "-ql 3 a", "level", Ok(Ok(Some("3"))));
+        assert_parse(&parameters, "-ql 3 a", "quiet", Ok(Ok(Some(""))));
+        assert_parse(&parameters, "-ql 3 a", "verbose", Ok(Ok(None)));
+        assert_parse(&parameters, "-ql 3 a", "foo", Ok(Ok(Some("a"))));
+        assert_parse(
+            &parameters,
+            "-vxq",
+            "foo",
+            Err(ArgError::UnknownShortOption('x', "-vxq", &parameters)),
+        );
+        // A value can only be given to the last
+        assert_parse(
+            &parameters,
+            "-lv 3",
+            "foo",
+            Err(ArgError::UnknownShortOption('l', "-lv", &parameters)),
+        );
+        // And the last must be given one
+        assert_parse(
+            &parameters,
+            "-vl",
+            "foo",
+            Err(ArgError::MissingValue(&parameters[3])),
+        );
+        assert_parse(
+            &parameters,
+            "-l -q",
+            "foo",
+            Err(ArgError::MissingValue(&parameters[3])),
+        );
+        assert_parse(
+            &parameters,
+            "-vq=3",
+            "foo",
+            Err(ArgError::UnknownShortOption('q', "-vq=3", &parameters)),
+        );
+        let mut slots = [""; 8];
+        let parsed = ArgumentParser::new(&parameters)
+            .parse("-vvq --verbose -l 1 -l=2", &mut slots)
+            .unwrap();
+        assert_eq!(parsed.count("verbose"), Ok(3));
+        assert_eq!(parsed.count("foo"), Ok(0));
+        let levels: Vec<&str> = parsed.get_all("level").unwrap().collect();
+        assert_eq!(levels, ["1", "2"]);
+        assert!(parsed.contains("quiet"));
+        assert!(!parsed.contains("foo"));
+        assert!(!parsed.contains("quux"));
+        assert_eq!(parsed.parse::<u8>("level"), Ok(Some(1)));
+        assert_eq!(parsed.parse::<u8>("foo"), Ok(None));
+        assert_eq!(parsed.parse::<u8>("quux"), Err(ArgError::NotFound("quux")));
+        assert_eq!(
+            parsed.parse::<bool>("verbose"),
+            Err(ArgError::BadValue("verbose", ""))
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                ArgError::UnknownShortOption('x', "-vxq", &parameters[..2])
+            ),
+            "Did not understand -x in \"-vxq\". Options are: --verbose"
+        );
+    }
+
+    #[test]
+    fn option_prefixes() {
+        let parameters = [
+            Parameter::named("ver"),
+            Parameter::named("verbose"),
+            Parameter::named("version"),
+            Parameter::named_value("level", "INT"),
+        ];
+        assert_eq!(
+            resolve_option(&parameters, "ver", false),
+            OptionMatch::Exact("ver")
+        );
+        assert_eq!(
+            resolve_option(&parameters, "verb", false),
+            OptionMatch::Prefix("verbose")
+        );
+        assert_eq!(
+            resolve_option(&parameters, "vers", false),
+            OptionMatch::Prefix("version")
+        );
+        assert_eq!(
+            resolve_option(&parameters, "l", true),
+            OptionMatch::Prefix("level")
+        );
+        // A prefix only matches the same kind of option
+        assert_eq!(
+            resolve_option(&parameters, "l", false),
+            OptionMatch::Unknown
+        );
+        assert_eq!(
+            resolve_option(&parameters, "verbo", true),
+            OptionMatch::Unknown
+        );
+        assert_eq!(resolve_option(&parameters, "", false), OptionMatch::Unknown);
+
+        let lenient = ArgumentParser::new(&parameters).allow_prefixes(true);
+        let mut slots = [""; 4];
+        let parsed = lenient.parse("--verb --l=3", &mut slots).unwrap();
+        assert_eq!(parsed.get("verbose"), Ok(Some("")));
+        assert_eq!(parsed.get("version"), Ok(None));
+        assert_eq!(parsed.get("ver"), Ok(None));
+        assert_eq!(parsed.get("level"), Ok(Some("3")));
+        let mut slots = [""; 4];
+        let parsed = lenient.parse("--ver", &mut slots).unwrap();
+        assert_eq!(parsed.get("ver"), Ok(Some("")));
+        assert_eq!(parsed.get("verbose"), Ok(None));
+
+        let mut slots = [""; 4];
+        assert_eq!(
+            ArgumentParser::new(&parameters)
+                .parse("--verb", &mut slots)
+                .unwrap_err(),
+            ArgError::UnknownOption("--verb", &parameters)
+        );
+        let mut slots = [""; 4];
+        let err = lenient.parse("--verbs", &mut slots).unwrap_err();
+        assert_eq!(err, ArgError::UnknownOption("--verbs", &parameters));
+        assert_eq!(
+            format!("{}", err),
+            "Did not understand \"--verbs\". \
+             Options are: --ver, --verbose, --version, --level=INT"
+        );
+        let parameters = &parameters[1..];
+        let lenient = ArgumentParser::new(parameters).allow_prefixes(true);
+        let mut slots = [""; 4];
+        let err = lenient.parse("--ver", &mut slots).unwrap_err();
+        assert_eq!(err, ArgError::AmbiguousOption("--ver", parameters));
+        assert_eq!(
+            format!("{}", err),
+            "\"--ver\" could be --verbose or --version"
+        );
+    }
+
+    #[test]
+    fn standalone_lookups() {
+        let parameters = [
+            Parameter::mandatory("channel"),
+            Parameter::optional("count"),
+            Parameter::named_value("level", "INT"),
+        ];
+        let mut slots = [""; 4];
+        let parsed = ArgumentParser::new(&parameters)
+            .parse("3 --level=x ten", &mut slots)
+            .unwrap();
+        assert_eq!(parsed.arguments(), &["3", "--level=x", "ten"]);
+        assert_eq!(parsed.positional(0), Some("3"));
+        assert_eq!(parsed.positional(1), Some("ten"));
+        assert_eq!(parsed.positional(2), None);
+        assert_eq!(parsed.get_as::<u8>("channel"), Some(Ok(3)));
+        assert!(parsed.get_as::<u8>("count").unwrap().is_err());
+        assert!(parsed.get_as::<u8>("level").unwrap().is_err());
+        assert_eq!(parsed.get_as::<u8>("nothing"), None);
+    }
+
+    #[test]
+    fn standalone_slots() {
+        let parameters = [Parameter::mandatory("a"), Parameter::optional("b")];
+        let mut slots = [""; 2];
+        let parsed = ArgumentParser::new(&parameters)
+            .parse("1 2", &mut slots)
+            .unwrap();
+        assert_eq!(parsed.arguments(), &["1", "2"]);
+        let mut slots = [""; 2];
+        assert_eq!(
+            ArgumentParser::new(&parameters)
+                .parse("1 --x 3", &mut slots)
+                .unwrap_err(),
+            ArgError::OutOfSlots(2)
+        );
+        assert_eq!(
+            format!("{}", ArgError::OutOfSlots(2)),
+            "Too many arguments (limit 2)"
+        );
+        let mut slots = [];
+        assert_eq!(
+            ArgumentParser::new(&parameters)
+                .parse("1", &mut slots)
+                .unwrap_err(),
+            ArgError::OutOfSlots(0)
+        );
+        assert_eq!(
+            ArgumentParser::new(&parameters)
+                .parse(" ", &mut slots)
+                .unwrap_err(),
+            ArgError::InsufficientArguments("a")
+        );
+    }
+
+    #[test]
+    fn tokens_roundtrip() {
+        let mut rng = Rng(1);
+        for _ in 0..1000 {
+            let line = random_line(&mut rng);
+            let tokens: Vec<&str> = tokenize(&line).collect();
+            for token in &tokens {
+                assert!(!token.is_empty());
+                assert!(!token.contains(char::is_whitespace));
+            }
+            let joined = tokens.join(" ");
+            assert_eq!(tokenize(&joined).collect::<Vec<_>>(), tokens);
+        }
+    }
+
+    #[test]
+    fn classify_roundtrip() {
+        let mut rng = Rng(2);
+        for _ in 0..1000 {
+            let line = random_line(&mut rng);
+            for token in tokenize(&line) {
+                let rebuilt = match classify(token) {
+                    Token::Positional(arg) => String::from(arg),
+                    Token::Named(name) => format!("--{}", name),
+                    Token::NamedValue(name, value) => format!("--{}={}", name, value),
+                    Token::Short(shorts) => format!("-{}", shorts),
+                    Token::ShortValue(shorts, value) => format!("-{}={}", shorts, value),
+                    Token::EndOfOptions => String::from("--"),
+                };
+                assert_eq!(rebuilt, token);
+            }
+        }
+    }
+
+    #[test]
+    fn positional_binds_at_most_once() {
+        let mut rng = Rng(3);
+        for _ in 0..1000 {
+            let line = random_line(&mut rng);
+            let args: Vec<&str> = tokenize(&line).collect();
+            let Ok(parsed) = match_parameters(PARAMETERS, &args) else {
+                continue;
+            };
+            for arg in
+                read_arguments(PARAMETERS, args.iter().copied(), false).filter_map(positional)
+            {
+                let bound = ["a", "b", "c"]
+                    .iter()
+                    .filter(|name| {
+                        parsed
+                            .get(name)
+                            .unwrap()
+                            .is_some_and(|v| core::ptr::eq(v, arg))
+                    })
+                    .count();
+                assert_eq!(bound, 1, "{:?} in {:?}", arg, args);
+            }
+            // Everything mandatory was given
+            assert!(parsed.get("a").unwrap().is_some());
+        }
+    }
+}
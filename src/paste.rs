@@ -0,0 +1,140 @@
+//! Bracketed paste, where the terminal marks the start and end of whatever
+//! is pasted, so it can be run as a whole once it has all arrived.
+#![deny(missing_docs)]
+
+use super::input::EscapeMatcher;
+
+/// Asks the terminal to mark pastes.
+pub(crate) const ENABLE: &str = "\x1b[?2004h";
+/// Asks the terminal to stop marking pastes.
+pub(crate) const DISABLE: &str = "\x1b[?2004l";
+/// Comes before a paste.
+const START: &[u8] = b"\x1b[200~";
+/// Comes after a paste.
+const END: &[u8] = b"\x1b[201~";
+
+/// Where we are with bracketed paste, once it's been turned on.
+///
+/// What's pasted is kept in the line buffer, after the line being typed,
+/// and then moved to the end of the buffer and typed in from there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Paste {
+    /// Watching what's typed for the start of a paste
+    Waiting(EscapeMatcher),
+    /// Keeping what's pasted until the end of the paste
+    Pasting {
+        end: EscapeMatcher,
+        /// How many bytes have been kept
+        len: usize,
+        /// The last byte pasted was a carriage return
+        after_cr: bool,
+        /// Some didn't fit
+        overflowed: bool,
+    },
+    /// Typing in what was pasted, from `next` on in the buffer
+    Typing { next: usize },
+}
+
+impl Paste {
+    pub(crate) const fn new() -> Self {
+        Paste::Waiting(EscapeMatcher::new(START))
+    }
+
+    /// Look at a byte typed while waiting. Returns `true` if it starts a
+    /// paste, and we're pasting now.
+    pub(crate) fn watch(&mut self, byte: u8) -> bool {
+        let Paste::Waiting(start) = self else {
+            return false;
+        };
+        if !start.feed(byte, |_| {}) {
+            return false;
+        }
+        *self = Paste::Pasting {
+            end: EscapeMatcher::new(END),
+            len: 0,
+            after_cr: false,
+            overflowed: false,
+        };
+        true
+    }
+
+    /// Keep a byte pasted, in `room`. Returns `true` once the paste has
+    /// ended.
+    pub(crate) fn keep(&mut self, byte: u8, room: &mut [u8]) -> bool {
+        let Paste::Pasting {
+            end,
+            len,
+            after_cr,
+            overflowed,
+        } = self
+        else {
+            return false;
+        };
+        end.feed(byte, |bytes| {
+            for &byte in bytes {
+                let typed = match byte {
+                    // A line feed ends a line, unless it's part of CR LF
+                    b'\n' if *after_cr => None,
+                    b'\r' | b'\n' => Some(b'\r'),
+                    // Tab would complete, rather than separate words
+                    b'\t' => Some(b' '),
+                    0x00..=0x1F | 0x7F => None,
+                    byte => Some(byte),
+                };
+                *after_cr = byte == b'\r';
+                match (typed, room.get_mut(*len)) {
+                    (None, _) => {}
+                    (Some(typed), Some(slot)) => {
+                        *slot = typed;
+                        *len += 1;
+                    }
+                    (Some(_), None) => *overflowed = true,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paste(bytes: &[u8], room: &mut [u8]) -> Option<(usize, bool)> {
+        let mut paste = Paste::new();
+        let mut bytes = bytes.iter();
+        for &byte in bytes.by_ref() {
+            if paste.watch(byte) {
+                break;
+            }
+        }
+        for &byte in bytes {
+            if paste.keep(byte, room) {
+                let Paste::Pasting {
+                    len, overflowed, ..
+                } = paste
+                else {
+                    unreachable!();
+                };
+                return Some((len, overflowed));
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn keeps_what_would_be_typed() {
+        let mut room = [0u8; 32];
+        assert_eq!(
+            paste(b"x\x1b[200~a\tb\r\nc\n\nd\x1b\x1b[201~", &mut room),
+            Some((8, false))
+        );
+        assert_eq!(&room[..8], b"a b\rc\r\rd");
+        // What doesn't fit is noticed
+        let mut room = [0u8; 4];
+        assert_eq!(
+            paste(b"\x1b[200~abcde\x1b[201~", &mut room),
+            Some((4, true))
+        );
+        assert_eq!(paste(b"\x1b[200abc\x1b[201~", &mut room), None);
+    }
+}
@@ -0,0 +1,330 @@
+//! Helpers for menus with one item per hardware register, such as menus
+//! generated from a register description.
+//!
+//! Describe each register with a [`RegisterDesc`], implement [`RegBus`] for
+//! the menu's context, and build each item with [`register`]. They all share
+//! [`read_write_callback`], which reads the register when its command is
+//! entered on its own, and writes it when given a value:
+//!
+//! ```text
+//! > ctrl
+//! 0x0000_0001
+//!
+//! > ctrl 0x11
+//! ```
+//!
+//! `help <command>` shows the register's address, width and access, like
+//! `RO, 16-bit @ 0x4001_0004`.
+//!
+//! The `regmap_gen` example turns a CSV file into a list of these items.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::{CommandContext, Item, Menu, Parameter};
+
+/// How wide a register is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    /// 8 bits
+    Bits8,
+    /// 16 bits
+    Bits16,
+    /// 32 bits
+    Bits32,
+}
+
+impl Width {
+    /// How many bits wide.
+    pub const fn bits(self) -> u32 {
+        match self {
+            Width::Bits8 => 8,
+            Width::Bits16 => 16,
+            Width::Bits32 => 32,
+        }
+    }
+
+    /// The largest value which fits.
+    pub const fn max(self) -> u32 {
+        u32::MAX >> (32 - self.bits())
+    }
+}
+
+/// Whether a register can be read, written or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Can only be read
+    ReadOnly,
+    /// Can only be written
+    WriteOnly,
+    /// Can be read and written
+    ReadWrite,
+}
+
+impl Access {
+    /// Can the register be read?
+    pub const fn readable(self) -> bool {
+        !matches!(self, Access::WriteOnly)
+    }
+
+    /// Can the register be written?
+    pub const fn writable(self) -> bool {
+        !matches!(self, Access::ReadOnly)
+    }
+}
+
+/// Where a register is, and how it can be used. Attach one to an item with
+/// [`register`], or [`Item::data`].
+///
+/// Displays as it's shown by `help`, like `RO, 16-bit @ 0x4001_0004`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDesc {
+    /// The register's address on the bus
+    pub addr: u32,
+    /// How wide the register is
+    pub width: Width,
+    /// Whether it can be read, written or both
+    pub access: Access,
+}
+
+impl RegisterDesc {
+    /// Describe a register.
+    pub const fn new(addr: u32, width: Width, access: Access) -> Self {
+        RegisterDesc {
+            addr,
+            width,
+            access,
+        }
+    }
+}
+
+impl core::fmt::Display for RegisterDesc {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let access = match self.access {
+            Access::ReadOnly => "RO",
+            Access::WriteOnly => "WO",
+            Access::ReadWrite => "RW",
+        };
+        write!(
+            f,
+            "{}, {}-bit @ {}",
+            access,
+            self.width.bits(),
+            Hex(self.addr, Width::Bits32)
+        )
+    }
+}
+
+/// Displays a value in hex, padded to the width of a register, with an
+/// underscore between each group of four digits.
+struct Hex(u32, Width);
+
+impl core::fmt::Display for Hex {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "0x")?;
+        let digits = self.1.bits() / 4;
+        for i in (0..digits).rev() {
+            write!(f, "{:X}", (self.0 >> (i * 4)) & 0xF)?;
+            if i != 0 && i % 4 == 0 {
+                write!(f, "_")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bus couldn't read or write a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusError;
+
+/// Reads and writes registers. Implement this for the context of a menu
+/// whose items are built with [`register`].
+pub trait RegBus {
+    /// Read `width` bits from `addr`.
+    fn read(&mut self, addr: u32, width: Width) -> Result<u32, BusError>;
+
+    /// Write `width` bits of `value` to `addr`. The value always fits.
+    fn write(&mut self, addr: u32, width: Width, value: u32) -> Result<(), BusError>;
+}
+
+/// The parameters of an item built with [`register`].
+pub const PARAMETERS: &[Parameter] = &[Parameter::optional("value")
+    .help("The value to write, in decimal or 0x hex. Leave out to read.")];
+
+/// Make an item which reads and writes the register described by `desc`,
+/// with [`read_write_callback`].
+pub const fn register<'a, I, T>(command: &'a str, desc: &'a RegisterDesc) -> Item<'a, I, T>
+where
+    I: Write,
+    T: RegBus,
+{
+    Item::new(command, read_write_callback::<I, T>, PARAMETERS).data(desc)
+}
+
+/// Reads the register described by the item's [`RegisterDesc`], or writes
+/// it if a value is given.
+///
+/// Reading prints the value in hex. Fails, with [`CommandContext::fail`], if
+/// the item has no `RegisterDesc`, the register can't be used that way, the
+/// value doesn't fit or the bus reports an error.
+pub fn read_write_callback<I, T>(
+    _menu: &Menu<I, T>,
+    item: &Item<I, T>,
+    args: &[&str],
+    interface: &mut CommandContext<I, T>,
+    context: &mut T,
+) where
+    I: Write,
+    T: RegBus,
+{
+    let Some(desc) = item.data_as::<RegisterDesc>() else {
+        interface.fail("Not a register");
+        return;
+    };
+    match args.first() {
+        None if !desc.access.readable() => interface.fail("Register is write-only"),
+        None => match context.read(desc.addr, desc.width) {
+            Ok(value) => {
+                writeln!(interface, "{}", Hex(value, desc.width)).ok();
+            }
+            Err(BusError) => interface.fail("Bus error"),
+        },
+        Some(_) if !desc.access.writable() => interface.fail("Register is read-only"),
+        Some(arg) => match parse_value(arg) {
+            Some(value) if value <= desc.width.max() => {
+                if context.write(desc.addr, desc.width, value).is_err() {
+                    interface.fail("Bus error");
+                }
+            }
+            Some(_) => interface.fail("Value too big for register"),
+            None => interface.fail("Value must be a number"),
+        },
+    }
+}
+
+/// A number as [`parse_int`](crate::parser::parse_int) reads it, like `12`
+/// or `0x1F`.
+fn parse_value(arg: &str) -> Option<u32> {
+    crate::parser::parse_int(arg).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe() {
+        let desc = RegisterDesc::new(0x4001_0004, Width::Bits16, Access::ReadOnly);
+        assert_eq!(std::format!("{}", desc), "RO, 16-bit @ 0x4001_0004");
+        assert_eq!(std::format!("{}", Hex(0xAB, Width::Bits8)), "0xAB");
+        assert_eq!(Width::Bits16.max(), 0xFFFF);
+        assert_eq!(parse_value("0x1F"), Some(31));
+        assert_eq!(parse_value("12"), Some(12));
+        assert_eq!(parse_value("x"), None);
+        assert_eq!(parse_value("0b1010_0101"), Some(0xA5));
+    }
+
+    #[cfg(feature = "echo")]
+    mod transcripts {
+        use super::*;
+        use crate::test_util::{assert_transcript, run_script, MockConsole};
+        use std::vec::Vec;
+
+        /// Registers at addresses 0 to 3, and a log of writes.
+        struct MockBus {
+            registers: [u32; 4],
+            writes: Vec<(u32, Width, u32)>,
+        }
+
+        impl RegBus for MockBus {
+            fn read(&mut self, addr: u32, _width: Width) -> Result<u32, BusError> {
+                self.registers.get(addr as usize).copied().ok_or(BusError)
+            }
+
+            fn write(&mut self, addr: u32, width: Width, value: u32) -> Result<(), BusError> {
+                let register = self.registers.get_mut(addr as usize).ok_or(BusError)?;
+                *register = value;
+                self.writes.push((addr, width, value));
+                Ok(())
+            }
+        }
+
+        const MENU: Menu<MockConsole, MockBus> = Menu::new(
+            "root",
+            &[
+                &register(
+                    "ctrl",
+                    &RegisterDesc::new(0, Width::Bits32, Access::ReadWrite),
+                ),
+                &register(
+                    "status",
+                    &RegisterDesc::new(1, Width::Bits16, Access::ReadOnly),
+                )
+                .help("What's going on"),
+                &register(
+                    "cmd",
+                    &RegisterDesc::new(2, Width::Bits8, Access::WriteOnly),
+                ),
+                &register(
+                    "gone",
+                    &RegisterDesc::new(8, Width::Bits8, Access::ReadWrite),
+                ),
+                &Item::new("bare", read_write_callback, PARAMETERS),
+            ],
+        );
+
+        #[test]
+        fn read_and_write() {
+            let mut bus = MockBus {
+                registers: [1, 0x1234, 0, 0],
+                writes: Vec::new(),
+            };
+            let script =
+                "ctrl\nctrl 0x11\nctrl\nstatus\nstatus 1\ncmd 300\ncmd 7\ncmd\ngone\nbare\n";
+            assert_transcript(
+                &run_script(MENU, script, &mut bus),
+                "
+> ctrl
+0x0000_0001
+
+> ctrl 0x11
+
+> ctrl
+0x0000_0011
+
+> status
+0x1234
+
+> status 1
+Error: Register is read-only
+
+> cmd 300
+Error: Value too big for register
+
+> cmd 7
+
+> cmd
+Error: Register is write-only
+
+> gone
+Error: Bus error
+
+> bare
+Error: Not a register
+
+>",
+            );
+            assert_eq!(bus.writes, [(0, Width::Bits32, 0x11), (2, Width::Bits8, 7)]);
+        }
+
+        #[test]
+        fn help() {
+            let mut bus = MockBus {
+                registers: [0; 4],
+                writes: Vec::new(),
+            };
+            let screen = run_script(MENU, "help status\n", &mut bus);
+            assert!(screen.contains("REGISTER:\n  RO, 16-bit @ 0x0000_0001\n\nDESCRIPTION:"));
+        }
+    }
+}
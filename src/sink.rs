@@ -0,0 +1,403 @@
+//! Lets the `Runner` write to something which can take fewer bytes than it's
+//! given, like a nearly full transmit ring.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+/// The longest escape sequence or character which is never split when the
+/// sink stops taking bytes part way through it.
+const MAX_UNIT: usize = 32;
+
+/// Something which takes bytes, but maybe not all of them at once, like
+/// `embedded_io::Write`.
+///
+/// Wrap it in a [`SinkWriter`] to use it as the `Runner`'s interface.
+pub trait ByteSink {
+    /// What goes wrong
+    type Error;
+
+    /// Write some of `bytes`, returning how many were written. Writing none
+    /// means the sink is full for now.
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error>;
+}
+
+/// The type of function a [`SinkWriter`] calls before trying again when its
+/// sink takes nothing. `attempt` counts up from 1.
+///
+/// This might wait a while, or service the transmitter.
+pub type BackoffFn<S> = fn(sink: &mut S, attempt: u32);
+
+/// Writes text to a [`ByteSink`], trying again when it's full.
+///
+/// If the sink still takes nothing after [`SinkWriter::max_attempts`] tries,
+/// the writer gives up and `write_str` returns an error. Writes by the
+/// `Runner` carry on regardless, while a callback sees the error from
+/// `write!`. Each time it gives up, [`SinkWriter::failures`] goes up by one.
+///
+/// An escape sequence or UTF-8 character is never cut short by giving up.
+/// Once part of one has been written, the rest is kept, and written before
+/// anything else. Whatever comes after is dropped, until the sink takes
+/// bytes again. Only the longest escape sequences, of more than 32 bytes,
+/// can still be split.
+pub struct SinkWriter<S> {
+    sink: S,
+    max_attempts: u32,
+    backoff: Option<BackoffFn<S>>,
+    /// The rest of an escape sequence or character, waiting to be written
+    pending: [u8; MAX_UNIT],
+    pending_len: usize,
+    /// Where we are in the text written so far, including what's pending
+    scanner: Scanner,
+    failures: u32,
+}
+
+impl<S> SinkWriter<S>
+where
+    S: ByteSink,
+{
+    /// Write to `sink`, trying up to 8 times when it takes nothing.
+    pub const fn new(sink: S) -> Self {
+        SinkWriter {
+            sink,
+            max_attempts: 8,
+            backoff: None,
+            pending: [0; MAX_UNIT],
+            pending_len: 0,
+            scanner: Scanner::new(),
+            failures: 0,
+        }
+    }
+
+    /// How many times to try writing before giving up. At least once.
+    pub const fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Call `backoff` between tries.
+    pub const fn backoff(mut self, backoff: BackoffFn<S>) -> Self {
+        self.backoff = Some(backoff);
+        self
+    }
+
+    /// How many times writing has been given up on.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Is there part of an escape sequence or character still to write?
+    pub fn has_pending(&self) -> bool {
+        self.pending_len > 0
+    }
+
+    /// Try writing what's pending again, without writing anything new.
+    pub fn flush(&mut self) -> core::fmt::Result {
+        if self.flush_pending() {
+            Ok(())
+        } else {
+            Err(core::fmt::Error)
+        }
+    }
+
+    /// Get at the sink.
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Get the sink back. Anything pending is lost.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// Write some of `bytes`, trying again while the sink takes nothing.
+    /// Returns `None` on giving up.
+    fn write_some(&mut self, bytes: &[u8]) -> Option<usize> {
+        let mut attempt = 0;
+        loop {
+            if let Ok(written @ 1..) = self.sink.write(bytes) {
+                return Some(written.min(bytes.len()));
+            }
+            attempt += 1;
+            if attempt >= self.max_attempts {
+                return None;
+            }
+            if let Some(backoff) = self.backoff {
+                backoff(&mut self.sink, attempt);
+            }
+        }
+    }
+
+    /// Write everything pending. Returns `false` if some is left.
+    fn flush_pending(&mut self) -> bool {
+        while self.pending_len > 0 {
+            let pending = self.pending;
+            let Some(written) = self.write_some(&pending[..self.pending_len]) else {
+                return false;
+            };
+            self.pending.copy_within(written..self.pending_len, 0);
+            self.pending_len -= written;
+        }
+        true
+    }
+}
+
+impl<S> Write for SinkWriter<S>
+where
+    S: ByteSink,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let mut rest = s.as_bytes();
+        while self.flush_pending() {
+            if rest.is_empty() {
+                return Ok(());
+            }
+            let Some(written) = self.write_some(rest) else {
+                break;
+            };
+            self.scanner.feed_all(&rest[..written]);
+            rest = &rest[written..];
+        }
+        // Keep whatever finishes off an escape sequence or character which
+        // has been started, and drop the rest
+        let keep = self.scanner.unit_end(rest).min(MAX_UNIT - self.pending_len);
+        self.pending[self.pending_len..][..keep].copy_from_slice(&rest[..keep]);
+        self.pending_len += keep;
+        self.scanner.feed_all(&rest[..keep]);
+        if keep == rest.len() {
+            return Ok(());
+        }
+        self.failures += 1;
+        Err(core::fmt::Error)
+    }
+}
+
+/// Tracks whether we're part way through an escape sequence or a UTF-8
+/// character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Scanner {
+    /// Continuation bytes still to come
+    utf8_left: u8,
+    escape: Escape,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    /// After the ESC
+    Start,
+    /// After `ESC [`, waiting for the final byte
+    Csi,
+    /// After `ESC ]`, waiting for BEL or `ESC \`
+    Osc,
+    /// An ESC in an OSC string
+    OscEsc,
+}
+
+impl Scanner {
+    const fn new() -> Self {
+        Scanner {
+            utf8_left: 0,
+            escape: Escape::None,
+        }
+    }
+
+    fn in_unit(&self) -> bool {
+        self.utf8_left > 0 || self.escape != Escape::None
+    }
+
+    fn feed(&mut self, byte: u8) {
+        match self.escape {
+            Escape::Start if byte == b'[' => self.escape = Escape::Csi,
+            Escape::Start if byte == b']' => self.escape = Escape::Osc,
+            Escape::Start => self.escape = Escape::None,
+            Escape::Csi => {
+                if (0x40..=0x7E).contains(&byte) {
+                    self.escape = Escape::None;
+                }
+            }
+            Escape::Osc => match byte {
+                0x07 => self.escape = Escape::None,
+                0x1B => self.escape = Escape::OscEsc,
+                _ => {}
+            },
+            Escape::OscEsc => self.escape = Escape::None,
+            Escape::None if self.utf8_left > 0 => self.utf8_left -= 1,
+            Escape::None => match byte {
+                0x1B => self.escape = Escape::Start,
+                0xF0.. => self.utf8_left = 3,
+                0xE0.. => self.utf8_left = 2,
+                0xC0.. => self.utf8_left = 1,
+                _ => {}
+            },
+        }
+    }
+
+    fn feed_all(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.feed(*byte);
+        }
+    }
+
+    /// How many of `bytes` it takes to finish what's been started.
+    fn unit_end(&self, bytes: &[u8]) -> usize {
+        let mut scanner = *self;
+        bytes
+            .iter()
+            .take_while(|byte| {
+                let more = scanner.in_unit();
+                scanner.feed(**byte);
+                more
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Takes at most `chunk` bytes per call, and nothing on the calls
+    /// `stall` picks.
+    #[derive(Default)]
+    struct MockSink {
+        written: Vec<u8>,
+        chunk: usize,
+        calls: usize,
+        stall: Option<fn(usize) -> bool>,
+        backoffs: usize,
+    }
+
+    impl ByteSink for MockSink {
+        type Error = ();
+
+        fn write(&mut self, bytes: &[u8]) -> Result<usize, ()> {
+            self.calls += 1;
+            if self.stall.is_some_and(|stall| stall(self.calls)) {
+                return Ok(0);
+            }
+            let len = bytes.len().min(self.chunk);
+            self.written.extend_from_slice(&bytes[..len]);
+            Ok(len)
+        }
+    }
+
+    /// Takes everything, for comparing against.
+    impl Write for MockSink {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            self.written.extend_from_slice(s.as_bytes());
+            Ok(())
+        }
+    }
+
+    const TEXT: &str = "\x1b]0;dev:/\x07\x1b[2K\rcafé \x1b[1;31mred\x1b[0m ✓\n";
+
+    #[test]
+    fn three_bytes_at_a_time() {
+        let mut writer = SinkWriter::new(MockSink {
+            chunk: 3,
+            ..Default::default()
+        });
+        write!(writer, "{}{}", TEXT, 42).unwrap();
+        assert_eq!(writer.failures(), 0);
+        let sink = writer.into_inner();
+        assert_eq!(sink.written, [TEXT, "42"].concat().as_bytes());
+    }
+
+    #[test]
+    fn sometimes_full() {
+        let mut writer = SinkWriter::new(MockSink {
+            chunk: 5,
+            stall: Some(|call| call % 3 != 0),
+            ..Default::default()
+        })
+        .backoff(|sink, _attempt| sink.backoffs += 1);
+        writer.write_str(TEXT).unwrap();
+        assert_eq!(writer.failures(), 0);
+        let sink = writer.into_inner();
+        assert_eq!(sink.written, TEXT.as_bytes());
+        assert!(sink.backoffs > 0);
+    }
+
+    #[test]
+    fn gives_up_between_units() {
+        // Takes 2 bytes, then nothing until call 10
+        let mut writer = SinkWriter::new(MockSink {
+            chunk: 2,
+            stall: Some(|call| (2..10).contains(&call)),
+            ..Default::default()
+        })
+        .max_attempts(3);
+        // Part of the escape sequence went, so the rest of it is kept
+        assert!(writer.write_str("\x1b[0mé").is_err());
+        assert_eq!(writer.failures(), 1);
+        assert!(writer.has_pending());
+        assert!(writer.flush().is_err());
+        // Finishing a sequence across writes is kept too
+        let mut writer = SinkWriter::new(MockSink {
+            chunk: 1,
+            stall: Some(|call| (2..14).contains(&call)),
+            ..Default::default()
+        })
+        .max_attempts(3);
+        writer.write_str("\x1b[").unwrap();
+        writer.write_str("12").unwrap();
+        assert!(writer.write_str("Dxyz").is_err());
+        assert!(writer.write_str("lost").is_err());
+        writer.write_str("é").unwrap();
+        assert_eq!(writer.into_inner().written, "\x1b[12Dé".as_bytes());
+    }
+
+    #[test]
+    fn units() {
+        let started = |bytes: &[u8]| {
+            let mut scanner = Scanner::new();
+            scanner.feed_all(bytes);
+            scanner
+        };
+        assert_eq!(started(b"\x1b[1").unit_end(b"2Dxy"), 2);
+        assert_eq!(started(b"\x1b]8;;").unit_end(b"http://x\x1b\\y"), 10);
+        assert_eq!(started(b"\x1b]0;").unit_end(b"t\x07y"), 2);
+        assert_eq!(started("é".as_bytes()).unit_end(b"ab"), 0);
+        assert_eq!(
+            started(&"✓".as_bytes()[..1]).unit_end(&"✓".as_bytes()[1..]),
+            2
+        );
+    }
+
+    #[test]
+    fn runner() {
+        use crate::{Item, Menu, Runner};
+
+        fn output<I: Write>(runner: Runner<I, ()>) -> I {
+            runner.interface
+        }
+
+        fn run<I: Write>(interface: I) -> I {
+            let item = Item::new(
+                "hello",
+                |_, _, _, interface, _| {
+                    writeln!(interface, "Héllo \x1b[1mwörld\x1b[0m").ok();
+                },
+                &[],
+            );
+            let items = [&item];
+            let menu = Menu::new("root", &items);
+            let mut buffer = [0u8; 32];
+            let mut runner = Runner::new(menu, &mut buffer, interface, &mut ());
+            for byte in b"help\nhello\n" {
+                runner.input_byte(*byte, &mut ());
+            }
+            output(runner)
+        }
+
+        let plain = run(MockSink::default());
+        let chunked = run(SinkWriter::new(MockSink {
+            chunk: 3,
+            stall: Some(|call| call % 4 == 0),
+            ..Default::default()
+        }));
+        assert_eq!(chunked.failures(), 0);
+        assert_eq!(chunked.into_inner().written, plain.written);
+    }
+}
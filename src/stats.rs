@@ -0,0 +1,38 @@
+//! Counters describing how much work the `Runner` has done.
+#![deny(missing_docs)]
+
+/// Counts the bytes going in and out of a `Runner`.
+///
+/// Only available with the `stats` feature.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub(crate) bytes_read: usize,
+    pub(crate) bytes_written: usize,
+    pub(crate) commands_executed: usize,
+    pub(crate) redraws: usize,
+}
+
+impl Stats {
+    /// How many bytes have been given to the `Runner`.
+    pub fn bytes_read(&self) -> usize {
+        self.bytes_read
+    }
+
+    /// How many bytes have been written to the interface, by the `Runner` or
+    /// by your callbacks through their [`CommandContext`](crate::CommandContext).
+    /// Anything written straight to the interface isn't counted.
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    /// How many non-empty command lines have been processed.
+    pub fn commands_executed(&self) -> usize {
+        self.commands_executed
+    }
+
+    /// How many times the prompt and input line have been re-drawn while
+    /// echoing.
+    pub fn redraws(&self) -> usize {
+        self.redraws
+    }
+}
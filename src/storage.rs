@@ -0,0 +1,150 @@
+//! Places to keep things while we work.
+//!
+//! By default the arguments to a command live in a fixed-size array, and the
+//! history lives in a buffer you lend the `Runner`. With the `alloc` feature,
+//! the arguments live in a `Vec` with as many slots as the command line needs,
+//! and the history can live in a `Vec` which grows as lines are added.
+
+/// The maximum number of arguments we can store without `alloc`.
+pub const MAX_ARGUMENTS: usize = 16;
+
+/// Make somewhere to keep the arguments to a command, with a slot for each
+/// argument we can store.
+#[cfg(not(feature = "alloc"))]
+pub(crate) fn argument_slots<'s>(_args: &str) -> impl AsMut<[&'s str]> {
+    [""; MAX_ARGUMENTS]
+}
+
+/// Make somewhere to keep the arguments to a command, with a slot for each
+/// argument we can store.
+#[cfg(feature = "alloc")]
+pub(crate) fn argument_slots<'s>(args: &str) -> impl AsMut<[&'s str]> {
+    alloc::vec![""; super::parser::tokenize(args).count()]
+}
+
+/// Bytes to keep things in, which might be able to grow.
+#[cfg(feature = "history")]
+pub(crate) trait Bytes {
+    /// All the bytes we have room for.
+    fn bytes(&self) -> &[u8];
+
+    /// All the bytes we have room for.
+    fn bytes_mut(&mut self) -> &mut [u8];
+
+    /// Try to make room for `len` bytes in all, and say whether there is.
+    fn make_room(&mut self, len: usize) -> bool;
+}
+
+#[cfg(feature = "history")]
+impl Bytes for &mut [u8] {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+
+    fn make_room(&mut self, len: usize) -> bool {
+        len <= self.len()
+    }
+}
+
+#[cfg(all(feature = "history", feature = "alloc"))]
+impl Bytes for alloc::vec::Vec<u8> {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self
+    }
+
+    fn make_room(&mut self, len: usize) -> bool {
+        if self.len() < len {
+            self.resize(len, 0);
+        }
+        true
+    }
+}
+
+/// Where the history is kept: a buffer that was lent to us.
+#[cfg(all(feature = "history", not(feature = "alloc")))]
+pub(crate) type Buffer<'a> = &'a mut [u8];
+
+/// Where the history is kept: a buffer that was lent to us, or one of our
+/// own which grows.
+#[cfg(all(feature = "history", feature = "alloc"))]
+pub(crate) enum Buffer<'a> {
+    Lent(&'a mut [u8]),
+    Growing(alloc::vec::Vec<u8>),
+}
+
+#[cfg(all(feature = "history", feature = "alloc"))]
+impl<'a> From<&'a mut [u8]> for Buffer<'a> {
+    fn from(buffer: &'a mut [u8]) -> Self {
+        Buffer::Lent(buffer)
+    }
+}
+
+#[cfg(all(feature = "history", feature = "alloc"))]
+impl Bytes for Buffer<'_> {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Buffer::Lent(buffer) => buffer.bytes(),
+            Buffer::Growing(buffer) => buffer.bytes(),
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        match self {
+            Buffer::Lent(buffer) => buffer.bytes_mut(),
+            Buffer::Growing(buffer) => buffer.bytes_mut(),
+        }
+    }
+
+    fn make_room(&mut self, len: usize) -> bool {
+        match self {
+            Buffer::Lent(buffer) => buffer.make_room(len),
+            Buffer::Growing(buffer) => buffer.make_room(len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "alloc"))]
+    #[test]
+    fn fixed_slots() {
+        assert_eq!(argument_slots("a b c").as_mut().len(), MAX_ARGUMENTS);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn a_slot_per_argument() {
+        let args = "a ".repeat(100);
+        assert_eq!(argument_slots(&args).as_mut().len(), 100);
+    }
+
+    #[cfg(feature = "history")]
+    #[test]
+    fn lent_bytes() {
+        let mut buffer = [0u8; 4];
+        let mut bytes = &mut buffer[..];
+        assert!(bytes.make_room(4));
+        assert!(!bytes.make_room(5));
+        assert_eq!(bytes.bytes().len(), 4);
+    }
+
+    #[cfg(all(feature = "history", feature = "alloc"))]
+    #[test]
+    fn growing_bytes() {
+        let mut bytes = Buffer::Growing(alloc::vec::Vec::new());
+        assert!(bytes.make_room(100));
+        assert_eq!(bytes.bytes().len(), 100);
+        assert!(bytes.make_room(10));
+        assert_eq!(bytes.bytes().len(), 100);
+    }
+}
@@ -0,0 +1,111 @@
+//! Takes telnet's option negotiation out of what's typed, for consoles
+//! served over a TCP socket.
+#![deny(missing_docs)]
+
+/// Interpret As Command, which starts every telnet command
+const IAC: u8 = 0xFF;
+const WILL: u8 = 0xFB;
+const WONT: u8 = 0xFC;
+const DO: u8 = 0xFD;
+const DONT: u8 = 0xFE;
+/// Subnegotiation Begin, which runs until `IAC SE`
+const SB: u8 = 0xFA;
+/// Subnegotiation End
+const SE: u8 = 0xF0;
+
+/// What to do with a byte read from a telnet client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Filtered {
+    /// It was typed, so use it
+    Typed(u8),
+    /// It's part of a command, so drop it
+    Dropped,
+    /// It finished a request for an option, which we refuse with this
+    Refuse([u8; 3]),
+}
+
+/// Where we are in a telnet command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Telnet {
+    /// Between commands
+    Data,
+    /// After a carriage return, which a NUL may follow
+    AfterCr,
+    /// After `IAC`
+    Iac,
+    /// After `IAC` and WILL, WONT, DO or DONT, waiting for the option
+    Option(u8),
+    /// In a subnegotiation
+    Sub,
+    /// After `IAC` in a subnegotiation
+    SubIac,
+}
+
+impl Telnet {
+    /// Look at the next byte from the client.
+    pub(crate) fn feed(&mut self, byte: u8) -> Filtered {
+        let (next, filtered) = match (*self, byte) {
+            (Telnet::Data | Telnet::AfterCr, IAC) => (Telnet::Iac, Filtered::Dropped),
+            // Enter is sent as CR NUL by some clients, and CR LF by others
+            (Telnet::AfterCr, 0) => (Telnet::Data, Filtered::Dropped),
+            (Telnet::Data | Telnet::AfterCr, b'\r') => (Telnet::AfterCr, Filtered::Typed(byte)),
+            (Telnet::Data | Telnet::AfterCr, _) => (Telnet::Data, Filtered::Typed(byte)),
+            // A doubled IAC is a 0xFF byte which was typed
+            (Telnet::Iac, IAC) => (Telnet::Data, Filtered::Typed(byte)),
+            (Telnet::Iac, WILL..=DONT) => (Telnet::Option(byte), Filtered::Dropped),
+            (Telnet::Iac, SB) => (Telnet::Sub, Filtered::Dropped),
+            (Telnet::Iac, _) => (Telnet::Data, Filtered::Dropped),
+            (Telnet::Option(WILL), _) => (Telnet::Data, Filtered::Refuse([IAC, DONT, byte])),
+            (Telnet::Option(DO), _) => (Telnet::Data, Filtered::Refuse([IAC, WONT, byte])),
+            // Nothing needs saying to an option being turned off
+            (Telnet::Option(_), _) => (Telnet::Data, Filtered::Dropped),
+            (Telnet::Sub, IAC) => (Telnet::SubIac, Filtered::Dropped),
+            (Telnet::Sub, _) => (Telnet::Sub, Filtered::Dropped),
+            (Telnet::SubIac, SE) => (Telnet::Data, Filtered::Dropped),
+            (Telnet::SubIac, _) => (Telnet::Sub, Filtered::Dropped),
+        };
+        *self = next;
+        filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// What's typed, and what's sent back, for `bytes`.
+    fn filter(bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut telnet = Telnet::Data;
+        let (mut typed, mut sent) = (Vec::new(), Vec::new());
+        for byte in bytes {
+            match telnet.feed(*byte) {
+                Filtered::Typed(byte) => typed.push(byte),
+                Filtered::Dropped => {}
+                Filtered::Refuse(reply) => sent.extend_from_slice(&reply),
+            }
+        }
+        (typed, sent)
+    }
+
+    #[test]
+    fn negotiation() {
+        // What PuTTY sends when it connects, give or take
+        let (typed, sent) = filter(b"\xff\xfb\x1f\xff\xfd\x03\xff\xfc\x01ls\r\0");
+        assert_eq!(typed, b"ls\r");
+        assert_eq!(sent, b"\xff\xfe\x1f\xff\xfc\x03");
+    }
+
+    #[test]
+    fn subnegotiation() {
+        let (typed, sent) = filter(b"a\xff\xfa\x18\x00xterm\xff\xff\xff\xf0b\xff\xf1c");
+        assert_eq!(typed, b"abc");
+        assert_eq!(sent, b"");
+    }
+
+    #[test]
+    fn typed_bytes() {
+        let (typed, _) = filter(b"\xff\xff\r\n\r\r\0\0");
+        assert_eq!(typed, b"\xff\r\n\r\r\0");
+    }
+}
@@ -0,0 +1,777 @@
+//! Helpers for testing menus, by typing a script at them and checking what
+//! appears on the screen.
+//!
+//! Only available with the `test-util` feature.
+#![deny(missing_docs)]
+// Failing an assertion is the whole point of some of these
+#![allow(clippy::panic)]
+
+use super::{InputSource, KeyPollFn, Menu, Runner};
+use std::{collections::VecDeque, string::String, vec::Vec};
+
+/// An interface which remembers everything written to it.
+///
+/// It can also hold keys pressed while a command is running, for
+/// [`MockConsole::poll_key`] to return.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MockConsole {
+    output: String,
+    pending: VecDeque<u8>,
+}
+
+impl MockConsole {
+    /// Make an empty console.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, exactly as it was written.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// Take everything written so far, leaving the console empty.
+    pub fn take_output(&mut self) -> String {
+        core::mem::take(&mut self.output)
+    }
+
+    /// Press some keys, written as for [`script_bytes`], for
+    /// [`MockConsole::poll_key`] to return later.
+    pub fn press_keys(&mut self, script: &str) {
+        self.pending.extend(script_bytes(script));
+    }
+
+    /// Take the oldest key given to [`MockConsole::press_keys`], if any. This
+    /// is a [`KeyPollFn`], for
+    /// [`Runner::set_key_poll`](crate::Runner::set_key_poll).
+    pub fn poll_key(&mut self) -> Option<u8> {
+        self.pending.pop_front()
+    }
+}
+
+impl core::fmt::Write for MockConsole {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.output.push_str(s);
+        Ok(())
+    }
+}
+
+/// Turn a script into the bytes a terminal would send.
+///
+/// A newline in the script presses Enter (sending a carriage return). Keys
+/// without a printable character are written in angle brackets:
+///
+/// * `<CR>`, `<LF>`, `<TAB>`, `<BS>`, `<DEL>` and `<ESC>`
+/// * `<UP>`, `<DOWN>`, `<LEFT>`, `<RIGHT>`, `<HOME>`, `<END>`, `<INS>` and
+///   `<FDEL>` (the forward delete key) send the usual VT100 sequences
+/// * `<F1>` to `<F4>` send `ESC O P` to `ESC O S`
+/// * `<C-x>` sends Ctrl-X, for any letter `x`
+/// * `<xNN>` sends the byte with hex value `NN`
+///
+/// Anything else in angle brackets is sent as typed.
+pub fn script_bytes(script: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut rest = script;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                if let Some(key) = key_bytes(&rest[1..end]) {
+                    bytes.extend_from_slice(&key);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        if c == '\n' {
+            bytes.push(b'\r');
+        } else {
+            let mut buf = [0u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    bytes
+}
+
+fn key_bytes(name: &str) -> Option<Vec<u8>> {
+    let bytes: &[u8] = match name {
+        "CR" => b"\r",
+        "LF" => b"\n",
+        "TAB" => b"\t",
+        "BS" => b"\x08",
+        "DEL" => b"\x7F",
+        "ESC" => b"\x1B",
+        "UP" => b"\x1B[A",
+        "DOWN" => b"\x1B[B",
+        "RIGHT" => b"\x1B[C",
+        "LEFT" => b"\x1B[D",
+        "HOME" => b"\x1B[H",
+        "END" => b"\x1B[F",
+        "INS" => b"\x1B[2~",
+        "FDEL" => b"\x1B[3~",
+        "F1" => b"\x1BOP",
+        "F2" => b"\x1BOQ",
+        "F3" => b"\x1BOR",
+        "F4" => b"\x1BOS",
+        _ => {
+            if let Some(letter) = name.strip_prefix("C-") {
+                let mut chars = letter.chars();
+                let c = chars.next()?;
+                if chars.next().is_some() || !c.is_ascii_alphabetic() {
+                    return None;
+                }
+                return Some(std::vec![c.to_ascii_uppercase() as u8 - b'@']);
+            }
+            if let Some(hex) = name.strip_prefix('x') {
+                if hex.len() == 2 {
+                    return u8::from_str_radix(hex, 16).ok().map(|b| std::vec![b]);
+                }
+            }
+            return None;
+        }
+    };
+    Some(bytes.to_vec())
+}
+
+/// Work out what a simple terminal would show after receiving `output`.
+///
+/// Carriage returns, backspaces and the cursor movement and erase sequences
+/// the `Runner` uses are applied, other escape sequences are dropped, and
+/// trailing spaces are removed from each line. Lines are joined with `\n`.
+pub fn normalise(output: &str) -> String {
+    let mut lines: Vec<Vec<char>> = std::vec![Vec::new()];
+    let mut row = 0;
+    let mut col: usize = 0;
+    let mut chars = output.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                row += 1;
+                col = 0;
+                if lines.len() <= row {
+                    lines.push(Vec::new());
+                }
+            }
+            '\r' => col = 0,
+            '\x08' => col = col.saturating_sub(1),
+            '\x07' => {}
+            '\x1B' => {
+                match chars.peek() {
+                    Some('[') => {
+                        chars.next();
+                        let mut params = String::new();
+                        let mut command = None;
+                        for c in chars.by_ref() {
+                            if ('\x40'..='\x7E').contains(&c) {
+                                command = Some(c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                        let n = params.parse::<usize>().unwrap_or(1);
+                        match command {
+                            Some('C') => col += n,
+                            Some('D') => col = col.saturating_sub(n),
+                            Some('K') => lines[row].truncate(col),
+                            Some('H') if params.is_empty() => {
+                                row = 0;
+                                col = 0;
+                            }
+                            Some('J') if params == "2" => {
+                                lines = std::vec![Vec::new()];
+                                row = 0;
+                                col = 0;
+                            }
+                            _ => {}
+                        }
+                    }
+                    Some(']') => {
+                        // Operating system command, terminated by BEL or ST
+                        while let Some(c) = chars.next() {
+                            if c == '\x07' {
+                                break;
+                            }
+                            if c == '\x1B' && chars.peek() == Some(&'\\') {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            c => {
+                let line = &mut lines[row];
+                while line.len() < col {
+                    line.push(' ');
+                }
+                if col < line.len() {
+                    line[col] = c;
+                } else {
+                    line.push(c);
+                }
+                col += 1;
+            }
+        }
+    }
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|l| l.iter().collect::<String>().trim_end().into())
+        .collect();
+    lines.join("\n")
+}
+
+/// Compare two transcripts, describing the lines that don't match.
+///
+/// Returns `None` if they are the same.
+pub fn transcript_diff(actual: &str, expected: &str) -> Option<String> {
+    if actual == expected {
+        return None;
+    }
+    let mut message = String::from("transcripts differ (- expected, + actual):\n");
+    let actual_lines: Vec<&str> = actual.split('\n').collect();
+    let expected_lines: Vec<&str> = expected.split('\n').collect();
+    for i in 0..actual_lines.len().max(expected_lines.len()) {
+        match (expected_lines.get(i), actual_lines.get(i)) {
+            (Some(e), Some(a)) if e == a => {
+                message.push_str("  ");
+                message.push_str(e);
+            }
+            (e, a) => {
+                if let Some(e) = e {
+                    message.push_str("- ");
+                    message.push_str(e);
+                    if a.is_some() {
+                        message.push('\n');
+                    }
+                }
+                if let Some(a) = a {
+                    message.push_str("+ ");
+                    message.push_str(a);
+                }
+            }
+        }
+        message.push('\n');
+    }
+    Some(message)
+}
+
+/// Panic if two transcripts differ, showing the lines that don't match.
+#[track_caller]
+pub fn assert_transcript(actual: &str, expected: &str) {
+    if let Some(message) = transcript_diff(actual, expected) {
+        panic!("{}", message);
+    }
+}
+
+/// Drives a `Runner` from a script, and records what appears on the screen.
+pub struct Transcript<'a, T> {
+    runner: Runner<'a, MockConsole, T>,
+}
+
+impl<'a, T> Transcript<'a, T> {
+    /// Start a `Runner` on a `MockConsole`.
+    pub fn new(menu: Menu<'a, MockConsole, T>, buffer: &'a mut [u8], context: &mut T) -> Self {
+        Transcript {
+            runner: Runner::new(menu, buffer, MockConsole::new(), context),
+        }
+    }
+
+    /// Type a script (see [`script_bytes`]) into the `Runner`.
+    pub fn type_script(&mut self, script: &str, context: &mut T) {
+        for b in script_bytes(script) {
+            self.runner.input_byte(b, context);
+        }
+    }
+
+    /// Everything on the screen so far, normalised with [`normalise`].
+    pub fn screen(&self) -> String {
+        normalise(self.runner.interface.output())
+    }
+
+    /// Get at the `Runner`.
+    pub fn runner(&mut self) -> &mut Runner<'a, MockConsole, T> {
+        &mut self.runner
+    }
+}
+
+/// Run a script against a fresh `Runner` and return the normalised screen.
+pub fn run_script<T>(menu: Menu<MockConsole, T>, script: &str, context: &mut T) -> String {
+    let mut buffer = [0u8; 256];
+    let mut transcript = Transcript::new(menu, &mut buffer, context);
+    transcript.type_script(script, context);
+    transcript.screen()
+}
+
+/// One byte of input in a [`Recording`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedByte {
+    /// When it arrived, by the clock given to [`Recorder::new`]
+    pub time: u64,
+    /// How it arrived
+    pub source: InputSource,
+    /// The byte
+    pub byte: u8,
+}
+
+/// Every byte of input a `Runner` was given, in order, made by a
+/// [`Recorder`].
+///
+/// [`Recording::to_bytes`] saves it, to attach to a bug report say, and
+/// [`Recording::replay`] feeds it to another `Runner`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recording {
+    bytes: Vec<RecordedByte>,
+}
+
+impl Recording {
+    /// How many bytes each one takes in [`Recording::to_bytes`].
+    const RECORD_LEN: usize = 10;
+
+    /// Make an empty recording.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a byte to the end.
+    pub fn push(&mut self, byte: RecordedByte) {
+        self.bytes.push(byte);
+    }
+
+    /// The bytes recorded, oldest first.
+    pub fn bytes(&self) -> &[RecordedByte] {
+        &self.bytes
+    }
+
+    /// Save the recording.
+    ///
+    /// It's the number of bytes recorded, as a little-endian `u32`, then for
+    /// each one its time as a little-endian `u64`, a `0` if it was typed or
+    /// a `1` if it was polled, and the byte itself.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + self.bytes.len() * Self::RECORD_LEN);
+        let count = u32::try_from(self.bytes.len()).unwrap_or(u32::MAX);
+        out.extend_from_slice(&count.to_le_bytes());
+        for recorded in self.bytes.iter().take(count as usize) {
+            out.extend_from_slice(&recorded.time.to_le_bytes());
+            out.push(match recorded.source {
+                InputSource::Typed => 0,
+                InputSource::Polled => 1,
+            });
+            out.push(recorded.byte);
+        }
+        out
+    }
+
+    /// Load a recording saved with [`Recording::to_bytes`], or give `None`
+    /// if `data` isn't one.
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let (count, rest) = data.split_first_chunk::<4>()?;
+        let count = u32::from_le_bytes(*count) as usize;
+        if rest.len() != count.checked_mul(Self::RECORD_LEN)? {
+            return None;
+        }
+        let mut bytes = Vec::with_capacity(count);
+        for record in rest.chunks_exact(Self::RECORD_LEN) {
+            let (time, tail) = record.split_first_chunk::<8>()?;
+            let source = match tail[0] {
+                0 => InputSource::Typed,
+                1 => InputSource::Polled,
+                _ => return None,
+            };
+            bytes.push(RecordedByte {
+                time: u64::from_le_bytes(*time),
+                source,
+                byte: tail[1],
+            });
+        }
+        Some(Recording { bytes })
+    }
+
+    /// Feed the recording to `runner`, as it was fed to the one recorded.
+    ///
+    /// Typed bytes go to [`Runner::input_byte`], and the polled bytes after
+    /// each are waiting for its callbacks to read with
+    /// [`Recorder::poll_key`]. Set the `Runner` up as the one recorded was,
+    /// with the same menu and options, and the same output follows.
+    pub fn replay<I, T>(&self, runner: &mut Runner<Recorder<I>, T>, context: &mut T)
+    where
+        I: core::fmt::Write,
+    {
+        for (i, recorded) in self.bytes.iter().enumerate() {
+            if recorded.source == InputSource::Polled {
+                continue;
+            }
+            let polled = self.bytes[i + 1..]
+                .iter()
+                .take_while(|later| later.source == InputSource::Polled)
+                .map(|later| later.byte);
+            runner.interface.replay_keys.clear();
+            runner.interface.replay_keys.extend(polled);
+            runner.input_byte(recorded.byte, context);
+        }
+        runner.interface.replay_keys.clear();
+    }
+}
+
+/// An interface which records every byte of input given to the `Runner`,
+/// however it arrived.
+///
+/// Give [`Recorder::tap`] to
+/// [`Runner::set_input_tap`](crate::Runner::set_input_tap), and if
+/// callbacks poll for keys, [`Recorder::poll_key`] to
+/// [`Runner::set_key_poll`](crate::Runner::set_key_poll). Everything
+/// written goes to the interface inside.
+pub struct Recorder<I> {
+    /// The interface being recorded
+    pub interface: I,
+    clock: fn() -> u64,
+    key_poll: Option<KeyPollFn<I>>,
+    recording: Recording,
+    /// Keys for callbacks to read, while replaying
+    replay_keys: VecDeque<u8>,
+}
+
+impl<I> Recorder<I> {
+    /// Record input to `interface`, timing it with `clock`.
+    pub fn new(interface: I, clock: fn() -> u64) -> Self {
+        Recorder {
+            interface,
+            clock,
+            key_poll: None,
+            recording: Recording::new(),
+            replay_keys: VecDeque::new(),
+        }
+    }
+
+    /// Use `poll` to check for a key waiting on the interface inside, for
+    /// [`Recorder::poll_key`].
+    pub fn set_key_poll(&mut self, poll: KeyPollFn<I>) {
+        self.key_poll = Some(poll);
+    }
+
+    /// Record a byte. This is an [`InputTapFn`](crate::InputTapFn).
+    pub fn tap(&mut self, byte: u8, source: InputSource) {
+        let time = (self.clock)();
+        self.recording.push(RecordedByte { time, source, byte });
+    }
+
+    /// Take a key waiting to be read, during [`Recording::replay`], or from
+    /// the interface inside. This is a [`KeyPollFn`].
+    pub fn poll_key(&mut self) -> Option<u8> {
+        if let Some(key) = self.replay_keys.pop_front() {
+            return Some(key);
+        }
+        self.key_poll.and_then(|poll| poll(&mut self.interface))
+    }
+
+    /// Everything recorded so far.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Take everything recorded so far, and start again.
+    pub fn take_recording(&mut self) -> Recording {
+        core::mem::take(&mut self.recording)
+    }
+}
+
+impl<I> core::fmt::Write for Recorder<I>
+where
+    I: core::fmt::Write,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.interface.write_str(s)
+    }
+}
+
+/// What [`check_command`] expects of a command's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputCheck<'e> {
+    /// The output is exactly this
+    Equals(&'e str),
+    /// The output contains this somewhere
+    Contains(&'e str),
+    /// A line of the output starts with `Error: ` and contains this
+    Error(&'e str),
+}
+
+/// Run a command line with [`Runner::run_command`], and return what it
+/// wrote, normalised with [`normalise`].
+pub fn command_output<T>(
+    runner: &mut Runner<MockConsole, T>,
+    context: &mut T,
+    command_line: &str,
+) -> String {
+    let start = runner.interface.output().len();
+    runner.run_command(command_line, context);
+    normalise(&runner.interface.output()[start..])
+}
+
+/// Run a command line, and check what it wrote.
+///
+/// If the check fails, the message says why, and shows the command's output
+/// and everything the `Runner` has written so far.
+pub fn check_command<T>(
+    runner: &mut Runner<MockConsole, T>,
+    context: &mut T,
+    command_line: &str,
+    check: OutputCheck,
+) -> Result<(), String> {
+    let output = command_output(runner, context, command_line);
+    let failure = match check {
+        OutputCheck::Equals(expected) => transcript_diff(&output, expected),
+        OutputCheck::Contains(expected) => (!output.contains(expected))
+            .then(|| std::format!("output does not contain {:?}\n", expected)),
+        OutputCheck::Error(expected) => (!output
+            .lines()
+            .any(|line| line.starts_with("Error: ") && line.contains(expected)))
+        .then(|| std::format!("output has no error containing {:?}\n", expected)),
+    };
+    match failure {
+        None => Ok(()),
+        Some(failure) => Err(std::format!(
+            "command {:?} failed its check: {}\noutput:\n{}\n\nfull transcript:\n{}",
+            command_line,
+            failure,
+            output,
+            normalise(runner.interface.output())
+        )),
+    }
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_command<T>(
+    runner: &mut Runner<MockConsole, T>,
+    context: &mut T,
+    command_line: &str,
+    check: OutputCheck,
+) {
+    if let Err(message) = check_command(runner, context, command_line, check) {
+        panic!("{}", message);
+    }
+}
+
+/// Run a command line on a `Runner<MockConsole, _>` and check its output,
+/// either exactly or with `contains`.
+///
+/// ```rust,ignore
+/// assert_command_output!(runner, ctx, "status --verbose", contains "uptime");
+/// assert_command_output!(runner, ctx, "version", "v1.0\n");
+/// ```
+///
+/// Only available with the `test-util` feature.
+#[macro_export]
+macro_rules! assert_command_output {
+    ($runner:expr, $context:expr, $command_line:expr, contains $expected:expr $(,)?) => {
+        $crate::test_util::assert_command(
+            &mut $runner,
+            &mut $context,
+            $command_line,
+            $crate::test_util::OutputCheck::Contains($expected),
+        )
+    };
+    ($runner:expr, $context:expr, $command_line:expr, $expected:expr $(,)?) => {
+        $crate::test_util::assert_command(
+            &mut $runner,
+            &mut $context,
+            $command_line,
+            $crate::test_util::OutputCheck::Equals($expected),
+        )
+    };
+}
+
+/// Run a command line on a `Runner<MockConsole, _>` and check that it printed
+/// an error containing the given text.
+///
+/// ```rust,ignore
+/// assert_command_error!(runner, ctx, "status --bogus", "Did not understand");
+/// ```
+///
+/// Only available with the `test-util` feature.
+#[macro_export]
+macro_rules! assert_command_error {
+    ($runner:expr, $context:expr, $command_line:expr, $expected:expr $(,)?) => {
+        $crate::test_util::assert_command(
+            &mut $runner,
+            &mut $context,
+            $command_line,
+            $crate::test_util::OutputCheck::Error($expected),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CommandContext, Item, Parameter};
+
+    #[test]
+    fn script() {
+        assert_eq!(script_bytes("ab\n"), b"ab\r");
+        assert_eq!(script_bytes("<UP><C-c><TAB><x7f>"), b"\x1B[A\x03\t\x7F");
+        assert_eq!(script_bytes("<foo> <C-cc>"), b"<foo> <C-cc>");
+        assert_eq!(script_bytes("é"), "é".as_bytes());
+    }
+
+    #[test]
+    fn terminal() {
+        assert_eq!(normalise("> a\r> ab\r> abc"), "> abc");
+        assert_eq!(normalise("> abc\x08 \x08"), "> ab");
+        assert_eq!(normalise("one\ntwo\n"), "one\ntwo\n");
+        assert_eq!(normalise("abc\x1B[2D\x1B[K"), "a");
+        assert_eq!(normalise("abc\x1B[2J\x1B[Hx"), "x");
+        assert_eq!(normalise("\x1B]0;title\x07x"), "x");
+    }
+
+    #[test]
+    #[should_panic(expected = "- two\n+ three")]
+    fn mismatch() {
+        assert_transcript("one\nthree", "one\ntwo");
+    }
+
+    fn status(
+        _menu: &Menu<MockConsole, u32>,
+        _item: &Item<MockConsole, u32>,
+        args: &[&str],
+        interface: &mut CommandContext<MockConsole, u32>,
+        context: &mut u32,
+    ) {
+        use core::fmt::Write;
+        *context += 1;
+        writeln!(interface, "calls: {}", context).unwrap();
+        if args.contains(&"--verbose") {
+            writeln!(interface, "uptime: 5s").unwrap();
+        }
+    }
+
+    static STATUS_MENU: Menu<MockConsole, u32> = Menu::new(
+        "root",
+        &[&Item::new("status", status, &[Parameter::named("verbose")])],
+    );
+
+    #[test]
+    fn command_assertions() {
+        let mut buffer = [0u8; 64];
+        let mut ctx = 0;
+        let mut runner = Runner::new(STATUS_MENU, &mut buffer, MockConsole::new(), &mut ctx);
+        assert_eq!(
+            command_output(&mut runner, &mut ctx, "status"),
+            "calls: 1\n"
+        );
+        assert_command_output!(runner, ctx, "status --verbose", contains "uptime");
+        assert_command_output!(runner, ctx, "status", "calls: 3\n");
+        assert_command_error!(runner, ctx, "status --bogus", "Did not understand");
+        assert_eq!(ctx, 3);
+    }
+
+    #[test]
+    fn command_assertion_failures() {
+        let mut buffer = [0u8; 64];
+        let mut ctx = 0;
+        let mut runner = Runner::new(STATUS_MENU, &mut buffer, MockConsole::new(), &mut ctx);
+        let message = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            assert_command_output!(runner, ctx, "status", contains "uptime");
+        }))
+        .unwrap_err()
+        .downcast::<String>()
+        .unwrap();
+        assert_eq!(
+            *message,
+            "command \"status\" failed its check: output does not contain \"uptime\"\n\
+             \noutput:\ncalls: 1\n\
+             \n\nfull transcript:\n\n> calls: 1\n"
+        );
+
+        let message = check_command(
+            &mut runner,
+            &mut ctx,
+            "status",
+            OutputCheck::Equals("calls: 1\n"),
+        );
+        assert!(message.unwrap_err().contains("- calls: 1\n+ calls: 2\n"));
+        let message = check_command(&mut runner, &mut ctx, "status", OutputCheck::Error("calls"));
+        assert!(message
+            .unwrap_err()
+            .contains("output has no error containing \"calls\""));
+    }
+
+    fn watch(
+        _menu: &Menu<Recorder<MockConsole>, u32>,
+        _item: &Item<Recorder<MockConsole>, u32>,
+        _args: &[&str],
+        interface: &mut CommandContext<Recorder<MockConsole>, u32>,
+        context: &mut u32,
+    ) {
+        use core::fmt::Write;
+        while let Some(key) = interface.poll_key() {
+            *context += 1;
+            writeln!(interface, "key {:?}", key as char).unwrap();
+        }
+    }
+
+    static WATCH_MENU: Menu<Recorder<MockConsole>, u32> =
+        Menu::new("root", &[&Item::new("watch", watch, &[])]);
+
+    fn clock() -> u64 {
+        use std::sync::atomic::{AtomicU64, Ordering};
+        static NOW: AtomicU64 = AtomicU64::new(0);
+        NOW.fetch_add(10, Ordering::Relaxed)
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let session = |recorder: Recorder<MockConsole>, recording: Option<&Recording>| {
+            let mut buffer = [0u8; 64];
+            let mut ctx = 0;
+            let mut runner = Runner::new(WATCH_MENU, &mut buffer, recorder, &mut ctx);
+            runner.set_input_tap(Recorder::tap);
+            runner.set_key_poll(Recorder::poll_key);
+            match recording {
+                Some(recording) => recording.replay(&mut runner, &mut ctx),
+                None => {
+                    for b in script_bytes("wacth<BS><BS><BS>tch<C-w>wat<C-u>watch\n") {
+                        runner.input_byte(b, &mut ctx);
+                    }
+                    runner.interface.interface.press_keys("ab");
+                    for b in script_bytes("watch\nnope\n") {
+                        runner.input_byte(b, &mut ctx);
+                    }
+                }
+            }
+            assert_eq!(ctx, 2);
+            runner.interface
+        };
+        let mut recorder = Recorder::new(MockConsole::new(), clock);
+        recorder.set_key_poll(MockConsole::poll_key);
+        let mut recorded = session(recorder, None);
+        let screen = normalise(recorded.interface.output());
+        assert!(screen.contains("watch\nkey 'a'\nkey 'b'\n"), "{}", screen);
+        let recording = recorded.take_recording();
+        let polled: Vec<u8> = recording
+            .bytes()
+            .iter()
+            .filter(|b| b.source == InputSource::Polled)
+            .map(|b| b.byte)
+            .collect();
+        assert_eq!(polled, b"ab");
+        assert!(recording.bytes().windows(2).all(|w| w[0].time < w[1].time));
+
+        let saved = recording.to_bytes();
+        assert_eq!(saved.len(), 4 + recording.bytes().len() * 10);
+        let loaded = Recording::from_bytes(&saved).unwrap();
+        assert_eq!(loaded, recording);
+        assert_eq!(Recording::from_bytes(&saved[..saved.len() - 1]), None);
+
+        // The keys come from the recording, not the console
+        let mut replayed = session(Recorder::new(MockConsole::new(), clock), Some(&loaded));
+        assert_eq!(replayed.interface.output(), recorded.interface.output());
+        let again = replayed.take_recording();
+        assert!(again
+            .bytes()
+            .iter()
+            .map(|b| (b.source, b.byte))
+            .eq(recording.bytes().iter().map(|b| (b.source, b.byte))));
+    }
+}
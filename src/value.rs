@@ -0,0 +1,316 @@
+//! Helpers for items which each hold one setting, like `brightness`.
+//!
+//! Describe each setting with a [`Value`], and build its item with
+//! [`value`]. Entering the command on its own shows the setting, and giving
+//! a value changes it, confirming the old and new values:
+//!
+//! ```text
+//! > brightness
+//! brightness: 50
+//!
+//! > brightness 75
+//! brightness: 50 -> 75
+//! ```
+//!
+//! With the `builtins-extra` feature, call
+//! [`Runner::enable_show`](crate::Runner::enable_show) for a `show` command,
+//! which lists every setting in the current menu.
+#![deny(missing_docs)]
+
+use core::fmt::Write;
+
+use super::{CommandContext, Item, Menu, Parameter};
+
+/// The longest old value shown when a setting is changed. Longer ones end
+/// with `...`.
+const MAX_OLD_VALUE: usize = 32;
+
+/// How to read and change one setting in the context. Attach one to an item
+/// with [`value`], or [`Item::data`].
+pub struct Value<T> {
+    /// Write the setting's current value
+    pub get: fn(context: &T, out: &mut dyn Write) -> core::fmt::Result,
+    /// Change the setting to the value typed in. An `Err` is printed as an
+    /// error.
+    pub set: fn(context: &mut T, value: &str) -> Result<(), &'static str>,
+}
+
+impl<T> Value<T> {
+    /// Describe a setting.
+    pub const fn new(
+        get: fn(context: &T, out: &mut dyn Write) -> core::fmt::Result,
+        set: fn(context: &mut T, value: &str) -> Result<(), &'static str>,
+    ) -> Self {
+        Value { get, set }
+    }
+}
+
+/// The parameters of an item built with [`value`].
+pub const PARAMETERS: &[Parameter] =
+    &[Parameter::optional("value").help("The new value. Leave out to see the current one.")];
+
+/// Make an item which shows and changes the setting described by `value`,
+/// with [`value_callback`].
+pub const fn value<'a, I, T>(command: &'a str, value: &'a Value<T>) -> Item<'a, I, T>
+where
+    I: Write,
+    T: 'static,
+{
+    Item::new(command, value_callback::<I, T>, PARAMETERS).data(value)
+}
+
+/// Shows the setting described by the item's [`Value`], or changes it if a
+/// value is given.
+///
+/// Fails, with [`CommandContext::fail`], if the item has no `Value` or the
+/// setting refuses the new value.
+pub fn value_callback<I, T>(
+    _menu: &Menu<I, T>,
+    item: &Item<I, T>,
+    args: &[&str],
+    interface: &mut CommandContext<I, T>,
+    context: &mut T,
+) where
+    I: Write,
+    T: 'static,
+{
+    let Some(value) = item.data_as::<Value<T>>() else {
+        interface.fail("Not a setting");
+        return;
+    };
+    let Some(arg) = args.first() else {
+        write!(interface, "{}: ", item.command).ok();
+        (value.get)(context, interface).ok();
+        writeln!(interface).ok();
+        return;
+    };
+    let mut old = Snapshot::new();
+    (value.get)(context, &mut old).ok();
+    if let Err(e) = (value.set)(context, arg) {
+        interface.fail(e);
+        return;
+    }
+    write!(interface, "{}: {} -> ", item.command, old).ok();
+    (value.get)(context, interface).ok();
+    writeln!(interface).ok();
+}
+
+/// Lists every setting in `items`, with the values lined up.
+#[cfg(feature = "builtins-extra")]
+pub(crate) fn show<I, T>(
+    out: &mut dyn Write,
+    items: &[&Item<I, T>],
+    context: &T,
+) -> core::fmt::Result
+where
+    T: 'static,
+{
+    let settings = || {
+        items
+            .iter()
+            .filter(|item| !item.hidden)
+            .filter_map(|item| Some((item.command, item.data_as::<Value<T>>()?)))
+    };
+    let width = settings().map(|(command, _)| command.len()).max();
+    let Some(width) = width else {
+        return writeln!(out, "No settings here");
+    };
+    for (command, value) in settings() {
+        write!(out, "  {:width$}  ", command, width = width)?;
+        (value.get)(context, out)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// The type of [`show`], once the `Runner` knows the context can be
+/// looked for in an item's data.
+#[cfg(feature = "builtins-extra")]
+pub(crate) type ShowFn<I, T> =
+    fn(out: &mut dyn Write, items: &[&Item<I, T>], context: &T) -> core::fmt::Result;
+
+/// Keeps the start of what's written to it, in whole characters.
+struct Snapshot {
+    buffer: [u8; MAX_OLD_VALUE],
+    len: usize,
+    truncated: bool,
+}
+
+impl Snapshot {
+    fn new() -> Self {
+        Snapshot {
+            buffer: [0; MAX_OLD_VALUE],
+            len: 0,
+            truncated: false,
+        }
+    }
+}
+
+impl Write for Snapshot {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            let end = self.len + c.len_utf8();
+            if self.truncated || end > self.buffer.len() {
+                self.truncated = true;
+                break;
+            }
+            c.encode_utf8(&mut self.buffer[self.len..end]);
+            self.len = end;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // We only ever store whole characters
+        f.write_str(core::str::from_utf8(&self.buffer[..self.len]).unwrap_or(""))?;
+        if self.truncated {
+            f.write_str("...")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot() {
+        let mut old = Snapshot::new();
+        write!(old, "{}", "é".repeat(20)).unwrap();
+        assert_eq!(
+            std::format!("{}", old),
+            std::format!("{}...", "é".repeat(16))
+        );
+    }
+
+    #[cfg(all(feature = "echo", feature = "builtins-extra"))]
+    mod transcripts {
+        use super::*;
+        use crate::test_util::{assert_transcript, MockConsole, Transcript};
+
+        struct Lamp {
+            brightness: u8,
+            mode: &'static str,
+        }
+
+        const BRIGHTNESS: Value<Lamp> = Value::new(
+            |lamp, out| write!(out, "{}", lamp.brightness),
+            |lamp, value| {
+                lamp.brightness = value.parse().map_err(|_| "Must be 0 to 255")?;
+                Ok(())
+            },
+        );
+
+        const MODE: Value<Lamp> = Value::new(
+            |lamp, out| out.write_str(lamp.mode),
+            |lamp, value| {
+                lamp.mode = match value {
+                    "calm" => "slow and quiet",
+                    "party" => "fast and loud",
+                    _ => return Err("Mode must be calm or party"),
+                };
+                Ok(())
+            },
+        );
+
+        const MENU: Menu<MockConsole, Lamp> = Menu::new(
+            "root",
+            &[
+                &value("brightness", &BRIGHTNESS),
+                &Item::new("toggle", |_, _, _, _, _| {}, &[]),
+                &value("mode", &MODE),
+                &Item::new("reset", |_, _, _, _, _| {}, &[]).hidden(),
+                &Item::new("bare", value_callback, PARAMETERS),
+            ],
+        );
+
+        #[test]
+        fn settings() {
+            let mut lamp = Lamp {
+                brightness: 50,
+                mode: "slow and quiet",
+            };
+            let mut buffer = [0u8; 64];
+            let mut transcript = Transcript::new(MENU, &mut buffer, &mut lamp);
+            transcript.runner().enable_show();
+            transcript.type_script(
+                "brightness\nbrightness 75\nbrightness 300\nmode party\nshow\nbare\n",
+                &mut lamp,
+            );
+            assert_transcript(
+                &transcript.screen(),
+                "
+> brightness
+brightness: 50
+
+> brightness 75
+brightness: 50 -> 75
+
+> brightness 300
+Error: Must be 0 to 255
+
+> mode party
+mode: slow and quiet -> fast and loud
+
+> show
+  brightness  75
+  mode        fast and loud
+
+> bare
+Error: Not a setting
+
+>",
+            );
+            assert_eq!(lamp.brightness, 75);
+        }
+
+        #[test]
+        fn show_in_help() {
+            let mut lamp = Lamp {
+                brightness: 50,
+                mode: "slow and quiet",
+            };
+            let mut buffer = [0u8; 64];
+            let mut transcript = Transcript::new(MENU, &mut buffer, &mut lamp);
+            transcript.runner().enable_show();
+            transcript.type_script("help\n", &mut lamp);
+            assert!(transcript.screen().contains("\n  show\n  help ["));
+        }
+
+        #[test]
+        fn show_yields_to_items() {
+            const SHOW_MENU: Menu<MockConsole, Lamp> = Menu::new(
+                "root",
+                &[
+                    &value("brightness", &BRIGHTNESS),
+                    &Item::new("show", |_, _, _, out, _| write!(out, "Mine").unwrap(), &[]),
+                ],
+            );
+            let mut lamp = Lamp {
+                brightness: 50,
+                mode: "slow and quiet",
+            };
+            let mut buffer = [0u8; 64];
+            let mut transcript = Transcript::new(SHOW_MENU, &mut buffer, &mut lamp);
+            transcript.runner().enable_show();
+            transcript.type_script("help\nshow\n", &mut lamp);
+            assert_transcript(
+                &transcript.screen(),
+                "
+> help
+AVAILABLE ITEMS:
+  brightness [ <value> ]
+  show
+  help [ <command> ]
+
+> show
+Mine
+
+>",
+            );
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Runs a menu generated by `#[derive(MenuCommands)]` through a `Runner`.
+#![cfg(feature = "derive")]
+
+use menu::*;
+
+#[derive(Debug, PartialEq)]
+enum Mode {
+    Fast,
+    Slow,
+}
+
+impl core::str::FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "fast" => Ok(Mode::Fast),
+            "slow" => Ok(Mode::Slow),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, MenuCommands)]
+#[menu(interface = String, context = Vec<Command>, handler = handle)]
+enum Command {
+    /// Show the status
+    Status,
+    /// Change the level
+    SetLevel {
+        /// The new level
+        level: u8,
+        /// Say more
+        verbose: bool,
+        /// How quickly to change
+        speed: Option<u32>,
+        /// How to change
+        #[menu(named)]
+        mode: Option<Mode>,
+    },
+    #[menu(name = "reboot-now")]
+    Reboot,
+}
+
+fn handle(
+    command: Command,
+    _interface: &mut CommandContext<String, Vec<Command>>,
+    context: &mut Vec<Command>,
+) {
+    context.push(command);
+}
+
+fn run(lines: &[&str]) -> (String, Vec<Command>) {
+    let mut buffer = [0u8; 64];
+    let mut context = Vec::new();
+    let mut r = Runner::new(
+        Command::menu("root"),
+        &mut buffer,
+        String::new(),
+        &mut context,
+    );
+    for line in lines {
+        for b in line.bytes() {
+            r.input_byte(b, &mut context);
+        }
+        r.input_byte(b'\r', &mut context);
+    }
+    (r.interface, context)
+}
+
+#[test]
+fn items_generated() {
+    assert_eq!(Command::ITEMS.len(), 3);
+    assert_eq!(Command::ITEMS[0].command, "status");
+    assert_eq!(Command::ITEMS[0].help, Some("Show the status"));
+    assert_eq!(Command::ITEMS[1].command, "set-level");
+    assert_eq!(Command::ITEMS[2].command, "reboot-now");
+    assert_eq!(Command::ITEMS[2].help, None);
+    let ItemType::Callback { parameters, .. } = Command::ITEMS[1].item_type else {
+        panic!("Not a callback");
+    };
+    assert!(matches!(
+        parameters,
+        [
+            Parameter::Mandatory {
+                parameter_name: "level",
+                help: Some("The new level"),
+                display: None,
+            },
+            Parameter::Named {
+                parameter_name: "verbose",
+                ..
+            },
+            Parameter::Optional {
+                parameter_name: "speed",
+                ..
+            },
+            Parameter::NamedValue {
+                parameter_name: "mode",
+                argument_name: "MODE",
+                ..
+            },
+        ]
+    ));
+}
+
+#[test]
+fn round_trip() {
+    let (_output, commands) = run(&[
+        "status",
+        "set-level 3",
+        "set-level 4 100 --verbose --mode=slow",
+        "reboot-now",
+    ]);
+    assert_eq!(
+        commands,
+        vec![
+            Command::Status,
+            Command::SetLevel {
+                level: 3,
+                verbose: false,
+                speed: None,
+                mode: None
+            },
+            Command::SetLevel {
+                level: 4,
+                verbose: true,
+                speed: Some(100),
+                mode: Some(Mode::Slow)
+            },
+            Command::Reboot,
+        ]
+    );
+}
+
+#[test]
+fn bad_value() {
+    let (output, commands) = run(&["set-level 300", "set-level 1 --mode=medium"]);
+    assert!(commands.is_empty());
+    assert!(output.contains("Error: Invalid value for \"level\""));
+    assert!(output.contains("Error: Invalid value for \"mode\""));
+
+    let mut buffer = [0u8; 64];
+    let mut context = Vec::new();
+    let mut r = Runner::new(
+        Command::menu("root"),
+        &mut buffer,
+        String::new(),
+        &mut context,
+    );
+    assert_eq!(
+        r.run_command("set-level 300", &mut context),
+        CommandOutcome::Failed("Invalid value for \"level\"")
+    );
+    r.set_machine_mode(true);
+    r.run_command("set-level 1 --mode=medium", &mut context);
+    assert!(r
+        .interface
+        .contains("status=err reason=\"Invalid value for \\\"mode\\\"\""));
+    assert!(context.is_empty());
+}
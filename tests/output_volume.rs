@@ -0,0 +1,139 @@
+//! Checks we don't accidentally start writing a lot more bytes than we used
+//! to. This matters on a 9600 baud UART.
+
+use core::fmt::Write;
+use menu::*;
+
+fn dummy(
+    _menu: &Menu<String, ()>,
+    _item: &Item<String, ()>,
+    _args: &[&str],
+    _interface: &mut CommandContext<String, ()>,
+    _context: &mut (),
+) {
+}
+
+const ROOT_MENU: Menu<String, ()> = Menu::new(
+    "root",
+    &[
+        &Item::new("one", dummy, &[]).help("The first item"),
+        &Item::new("two", dummy, &[Parameter::mandatory("a")]).help("The second item"),
+        &Item::new("three", dummy, &[Parameter::named("verbose")]).help("The third item"),
+        &Item::new("four", dummy, &[]).help("The fourth item"),
+        &Item::new("five", dummy, &[]).help("The fifth item"),
+    ],
+);
+
+/// Type `line` followed by Enter, and return how many bytes that wrote.
+fn bytes_for_line(line: &str) -> usize {
+    let mut buffer = [0u8; 64];
+    let mut r = Runner::new(ROOT_MENU, &mut buffer, String::new(), &mut ());
+    r.interface.clear();
+    for b in line.bytes() {
+        r.input_byte(b, &mut ());
+    }
+    r.input_byte(b'\r', &mut ());
+    #[cfg(feature = "stats")]
+    {
+        assert_eq!(r.stats().bytes_written(), r.interface.len() + "\n> ".len());
+        assert_eq!(r.stats().bytes_read(), line.len() + 1);
+        assert_eq!(r.stats().commands_executed(), 1);
+    }
+    r.interface.len()
+}
+
+#[test]
+fn typing_a_long_command() {
+    // 20 characters, which isn't a valid command
+    let written = bytes_for_line("abcdefghijklmnopqrst");
+    assert!(written <= 90, "wrote {} bytes", written);
+}
+
+#[test]
+fn help_on_five_items() {
+    let written = bytes_for_line("help");
+    assert!(written <= 120, "wrote {} bytes", written);
+}
+
+/// An interface which only counts what's written to it.
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+fn no_op(
+    _menu: &Menu<CountingWriter, ()>,
+    _item: &Item<CountingWriter, ()>,
+    _args: &[&str],
+    _interface: &mut CommandContext<CountingWriter, ()>,
+    _context: &mut (),
+) {
+}
+
+#[test]
+fn echo_is_one_byte_per_key() {
+    const MENU: Menu<CountingWriter, ()> = Menu::new("root", &[&Item::new("x", no_op, &[])]);
+    let mut buffer = [0u8; 128];
+    let mut r = Runner::new(MENU, &mut buffer, CountingWriter(0), &mut ());
+    r.set_echo(Echo::On);
+    // The first redraws the prompt too, but however long the line gets,
+    // each key typed after that costs the same
+    r.input_byte(b'x', &mut ());
+    for b in "x".repeat(100).bytes() {
+        r.interface.0 = 0;
+        r.input_byte(b, &mut ());
+        assert_eq!(r.interface.0, 1);
+    }
+    // Completing a character with more than one byte redraws the line
+    r.interface.0 = 0;
+    for b in "€".bytes() {
+        r.input_byte(b, &mut ());
+    }
+    assert!(r.interface.0 > 100);
+    r.interface.0 = 0;
+    r.input_byte(b'y', &mut ());
+    assert_eq!(r.interface.0, 1);
+}
+
+#[cfg(feature = "stats")]
+fn hello(
+    _menu: &Menu<String, ()>,
+    _item: &Item<String, ()>,
+    _args: &[&str],
+    interface: &mut CommandContext<String, ()>,
+    _context: &mut (),
+) {
+    writeln!(interface, "Hello, world!").unwrap();
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn callback_output_counted() {
+    const MENU: Menu<String, ()> = Menu::new("root", &[&Item::new("hello", hello, &[])]);
+    let mut buffer = [0u8; 64];
+    let mut r = Runner::new(MENU, &mut buffer, String::new(), &mut ());
+    r.run_command("hello", &mut ());
+    assert!(r.interface.contains("Hello, world!\n"));
+    assert_eq!(r.stats().bytes_written(), r.interface.len());
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn redraws_counted() {
+    let mut buffer = [0u8; 64];
+    let mut r = Runner::new(ROOT_MENU, &mut buffer, String::new(), &mut ());
+    for b in "one".bytes() {
+        r.input_byte(b, &mut ());
+    }
+    // Only the first character redraws the line
+    #[cfg(feature = "echo")]
+    assert_eq!(r.stats().redraws(), 1);
+    #[cfg(not(feature = "echo"))]
+    assert_eq!(r.stats().redraws(), 0);
+    r.reset_stats();
+    assert_eq!(r.stats(), &Stats::default());
+}
@@ -0,0 +1,42 @@
+//! Checks the time taken to handle a typed byte doesn't grow faster than the
+//! length of the line. `examples/bench.rs` gives the actual numbers.
+
+use menu::*;
+use std::time::{Duration, Instant};
+
+const MENU: Menu<String, ()> = Menu::new("root", &[]);
+
+/// The quickest of many goes at typing one byte onto a line which then has
+/// `len` bytes. The quickest is the one least upset by whatever else the
+/// machine is doing.
+fn quickest_typed_byte(len: usize) -> Duration {
+    let mut buffer = [0u8; 256];
+    let mut r = Runner::new(MENU, &mut buffer, String::new(), &mut ());
+    for _ in 0..len {
+        r.input_byte(b'x', &mut ());
+    }
+    (0..500)
+        .map(|_| {
+            r.input_byte(0x08, &mut ());
+            r.interface.clear();
+            let start = Instant::now();
+            r.input_byte(b'y', &mut ());
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+#[test]
+fn typed_byte_is_no_worse_than_linear() {
+    let short = quickest_typed_byte(8);
+    let long = quickest_typed_byte(128);
+    // The line is 16 times longer, and there's plenty of room for noise on
+    // top of that
+    assert!(
+        long <= short.max(Duration::from_micros(1)) * 16 * 4,
+        "8 bytes took {:?} but 128 bytes took {:?}",
+        short,
+        long
+    );
+}